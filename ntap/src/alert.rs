@@ -0,0 +1,559 @@
+use crate::config::{AlertCondition, AlertConfig, AlertDirection, AlertRule};
+use crate::net::socket::LocalSocket;
+use crate::net::stat::NetStatData;
+use crate::net::traffic::TrafficInfo;
+use crate::notification::{Notification, NotificationType};
+use crate::thread_log;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Traffic of one tick, taken before it is merged into the cumulative NetStatData.
+#[derive(Debug, Clone)]
+pub struct TickTraffic {
+    pub total: TrafficInfo,
+    /// Interface name -> TrafficInfo
+    pub interfaces: HashMap<String, TrafficInfo>,
+    /// Connections with packets in the tick
+    pub connections: usize,
+}
+
+impl TickTraffic {
+    pub fn from_netstat_data(netstat_data: &NetStatData) -> Self {
+        TickTraffic {
+            total: netstat_data.traffic.clone(),
+            interfaces: netstat_data
+                .interface_traffic
+                .iter()
+                .map(|(name, interface_traffic)| (name.clone(), interface_traffic.traffic.clone()))
+                .collect(),
+            connections: netstat_data.connection_map.len(),
+        }
+    }
+    fn bytes(&self, interface: &Option<String>, direction: AlertDirection) -> usize {
+        let traffic = match interface {
+            Some(name) => match self.interfaces.get(name) {
+                Some(traffic) => traffic,
+                None => return 0,
+            },
+            None => &self.total,
+        };
+        match direction {
+            AlertDirection::Ingress => traffic.bytes_received,
+            AlertDirection::Egress => traffic.bytes_sent,
+            AlertDirection::Total => traffic.total_bytes(),
+        }
+    }
+}
+
+/// Evaluation state of a rule.
+#[derive(Debug, Clone)]
+struct RuleState {
+    /// When the threshold was first exceeded
+    exceeded_since: Option<Instant>,
+    /// The condition has fired and not yet cleared
+    active: bool,
+    /// Keys already notified (country codes, remote addresses)
+    notified: HashSet<String>,
+}
+
+impl RuleState {
+    fn new() -> Self {
+        RuleState {
+            exceeded_since: None,
+            active: false,
+            notified: HashSet::new(),
+        }
+    }
+}
+
+/// Evaluates the configured alert rules and dispatches the notifications.
+pub struct AlertEngine {
+    rules: Vec<(AlertRule, RuleState)>,
+    log_file_path: Option<PathBuf>,
+    webhook_command: Option<String>,
+}
+
+impl AlertEngine {
+    pub fn new(config: &AlertConfig) -> Self {
+        AlertEngine {
+            rules: config
+                .rules
+                .iter()
+                .map(|rule| (rule.clone(), RuleState::new()))
+                .collect(),
+            log_file_path: config.log_file_path.as_ref().map(PathBuf::from),
+            webhook_command: config.webhook_command.clone(),
+        }
+    }
+    /// Evaluate the rules against the traffic of the last tick and the cumulative data.
+    /// New notifications are written to the log file and passed to the webhook command.
+    pub fn evaluate(
+        &mut self,
+        tick: &TickTraffic,
+        netstat_data: &NetStatData,
+        interval: Duration,
+    ) -> Vec<Notification> {
+        let notifications = self.check_rules(tick, netstat_data, interval, Instant::now());
+        for notification in &notifications {
+            self.dispatch(notification);
+        }
+        notifications
+    }
+    /// Evaluate the rules at the time now, without dispatching the notifications.
+    fn check_rules(
+        &mut self,
+        tick: &TickTraffic,
+        netstat_data: &NetStatData,
+        interval: Duration,
+        now: Instant,
+    ) -> Vec<Notification> {
+        let mut notifications: Vec<Notification> = Vec::new();
+        for (rule, state) in self.rules.iter_mut() {
+            match &rule.condition {
+                AlertCondition::Bandwidth {
+                    interface,
+                    direction,
+                    bytes_per_sec,
+                    duration_secs,
+                } => {
+                    let secs = interval.as_secs_f64().max(0.001);
+                    let rate = (tick.bytes(interface, *direction) as f64 / secs) as u64;
+                    if rate > *bytes_per_sec {
+                        let since = *state.exceeded_since.get_or_insert(now);
+                        if !state.active
+                            && now.saturating_duration_since(since).as_secs() >= *duration_secs
+                        {
+                            state.active = true;
+                            let direction_name = match direction {
+                                AlertDirection::Ingress => "ingress",
+                                AlertDirection::Egress => "egress",
+                                AlertDirection::Total => "total",
+                            };
+                            notifications.push(Notification::new(
+                                rule.name.clone(),
+                                format!(
+                                    "{} {} bandwidth {}ps above {}ps for {}s",
+                                    interface.as_deref().unwrap_or("all interfaces"),
+                                    direction_name,
+                                    TrafficInfo::format_bytes(rate as usize),
+                                    TrafficInfo::format_bytes(*bytes_per_sec as usize),
+                                    duration_secs
+                                ),
+                                NotificationType::Traffic,
+                            ));
+                        }
+                    } else {
+                        state.exceeded_since = None;
+                        state.active = false;
+                    }
+                }
+                AlertCondition::NewCountry { known_countries } => {
+                    for host in netstat_data.remote_hosts.values() {
+                        if host.country_code.is_empty()
+                            || known_countries
+                                .iter()
+                                .any(|code| code.eq_ignore_ascii_case(&host.country_code))
+                            || state.notified.contains(&host.country_code)
+                        {
+                            continue;
+                        }
+                        state.notified.insert(host.country_code.clone());
+                        notifications.push(Notification::new(
+                            rule.name.clone(),
+                            format!(
+                                "New remote country {} ({}): {}",
+                                host.country_name, host.country_code, host.ip_addr
+                            ),
+                            NotificationType::RemoteHost,
+                        ));
+                    }
+                }
+                AlertCondition::ProcessAsn { process, asn } => {
+                    let mut current: HashSet<String> = HashSet::new();
+                    for conn in netstat_data.connection_map.keys() {
+                        let host = match netstat_data.remote_hosts.get(&conn.remote_ip_addr) {
+                            Some(host) => host,
+                            None => continue,
+                        };
+                        if host.asn != *asn {
+                            continue;
+                        }
+                        let local_socket = LocalSocket {
+                            interface_name: conn.interface_name.clone(),
+                            port: conn.local_port,
+                            protocol: conn.protocol,
+                        };
+                        let socket_process = match netstat_data.local_socket_map.get(&local_socket)
+                        {
+                            Some(socket_process) => socket_process,
+                            None => continue,
+                        };
+                        let process_info = match &socket_process.process {
+                            Some(process_info) => process_info,
+                            None => continue,
+                        };
+                        if process_info.name != *process {
+                            continue;
+                        }
+                        let key = format!("{}:{}", process_info.pid, conn.remote_ip_addr);
+                        current.insert(key.clone());
+                        if !state.notified.insert(key) {
+                            continue;
+                        }
+                        notifications.push(Notification::new(
+                            rule.name.clone(),
+                            format!(
+                                "{} (PID {}) connected to {}:{} AS{} {}",
+                                process_info.name,
+                                process_info.pid,
+                                conn.remote_ip_addr,
+                                conn.remote_port,
+                                host.asn,
+                                host.as_name
+                            ),
+                            NotificationType::RemoteHost,
+                        ));
+                    }
+                    // Forget the connections that have timed out, so the set stays small.
+                    // The process notifies again if it connects to the address later.
+                    state.notified.retain(|key| current.contains(key));
+                }
+                AlertCondition::ConnectionCount { max } => {
+                    let count = tick.connections;
+                    if count > *max {
+                        if !state.active {
+                            state.active = true;
+                            notifications.push(Notification::new(
+                                rule.name.clone(),
+                                format!(
+                                    "{} connections with traffic in the last tick (limit {})",
+                                    count, max
+                                ),
+                                NotificationType::Traffic,
+                            ));
+                        }
+                    } else {
+                        state.active = false;
+                    }
+                }
+            }
+        }
+        notifications
+    }
+    fn dispatch(&self, notification: &Notification) {
+        thread_log!(
+            info,
+            "notification {}: {}",
+            notification.title,
+            notification.body
+        );
+        let json = match serde_json::to_string(notification) {
+            Ok(json) => json,
+            Err(e) => {
+                thread_log!(error, "notification serialize error: {:?}", e);
+                return;
+            }
+        };
+        if let Some(path) = &self.log_file_path {
+            let result = File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", json));
+            if let Err(e) = result {
+                thread_log!(error, "notification log error: {:?}", e);
+            }
+        }
+        if let Some(command) = &self.webhook_command {
+            run_webhook_command(command.clone(), notification, json);
+        }
+    }
+}
+
+/// Run the webhook command in the background so that a slow command does not block the tick.
+fn run_webhook_command(command: String, notification: &Notification, json: String) {
+    let notification_type = notification.notification_type.name();
+    let title = notification.title.clone();
+    let body = notification.body.clone();
+    let result = thread::Builder::new()
+        .name(String::from("notification-webhook"))
+        .spawn(move || {
+            let mut child_command = if cfg!(windows) {
+                let mut child_command = Command::new("cmd");
+                child_command.arg("/C").arg(&command);
+                child_command
+            } else {
+                let mut child_command = Command::new("sh");
+                child_command.arg("-c").arg(&command);
+                child_command
+            };
+            let child = child_command
+                .env("NTAP_NOTIFICATION_TYPE", notification_type)
+                .env("NTAP_NOTIFICATION_TITLE", title)
+                .env("NTAP_NOTIFICATION_BODY", body)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            match child {
+                Ok(mut child) => {
+                    if let Some(mut stdin) = child.stdin.take() {
+                        let _ = stdin.write_all(json.as_bytes());
+                    }
+                    match child.wait() {
+                        Ok(status) => {
+                            if !status.success() {
+                                thread_log!(error, "webhook command exited with {}", status);
+                            }
+                        }
+                        Err(e) => {
+                            thread_log!(error, "webhook command error: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    thread_log!(error, "webhook command error: {:?}", e);
+                }
+            }
+        });
+    if let Err(e) = result {
+        thread_log!(error, "webhook thread error: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::host::RemoteHostInfo;
+    use crate::net::socket::{SocketConnection, SocketProcess, SocketStatus, TransportProtocol};
+    use crate::net::traffic::InterfaceTrafficInfo;
+    use crate::process::ProcessInfo;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn engine(condition: AlertCondition) -> AlertEngine {
+        let mut config = AlertConfig::new();
+        config.rules.push(AlertRule {
+            name: String::from("test"),
+            condition,
+        });
+        AlertEngine::new(&config)
+    }
+
+    // Above 1000 B/s in total on all interfaces, without a hold-down
+    fn all_interfaces_engine() -> AlertEngine {
+        engine(AlertCondition::Bandwidth {
+            interface: None,
+            direction: AlertDirection::Total,
+            bytes_per_sec: 1000,
+            duration_secs: 0,
+        })
+    }
+
+    // One tick with bytes_received on eth0 and no TCP/UDP connection (e.g. ICMP)
+    fn eth0_tick(bytes_received: usize) -> TickTraffic {
+        let mut delta = NetStatData::new();
+        delta.traffic.bytes_received = bytes_received;
+        let mut eth0 = InterfaceTrafficInfo::new();
+        eth0.traffic.bytes_received = bytes_received;
+        delta.interface_traffic.insert(String::from("eth0"), eth0);
+        TickTraffic::from_netstat_data(&delta)
+    }
+
+    fn remote_host(ip_addr: Ipv4Addr, country_code: &str, asn: u32) -> RemoteHostInfo {
+        let mut host = RemoteHostInfo::new(String::new(), IpAddr::V4(ip_addr));
+        host.country_code = country_code.to_string();
+        host.country_name = country_code.to_string();
+        host.asn = asn;
+        host
+    }
+
+    fn connection(local_port: u16, remote_ip_addr: Ipv4Addr) -> SocketConnection {
+        SocketConnection {
+            interface_name: String::from("eth0"),
+            local_ip_addr: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)),
+            local_port,
+            remote_ip_addr: IpAddr::V4(remote_ip_addr),
+            remote_port: 443,
+            protocol: TransportProtocol::TCP,
+        }
+    }
+
+    #[test]
+    fn bandwidth_hold_down_and_rearm() {
+        let mut engine = engine(AlertCondition::Bandwidth {
+            interface: Some(String::from("eth0")),
+            direction: AlertDirection::Ingress,
+            bytes_per_sec: 1000,
+            duration_secs: 10,
+        });
+        let data = NetStatData::new();
+        let high = eth0_tick(2000);
+        let low = eth0_tick(500);
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        // Above the threshold, but not for long enough
+        assert!(engine.check_rules(&high, &data, SECOND, at(0)).is_empty());
+        assert!(engine.check_rules(&high, &data, SECOND, at(9)).is_empty());
+        let notifications = engine.check_rules(&high, &data, SECOND, at(10));
+        assert_eq!(notifications.len(), 1);
+        assert!(notifications[0].body.starts_with("eth0 ingress bandwidth"));
+        // Once while it stays above
+        assert!(engine.check_rules(&high, &data, SECOND, at(11)).is_empty());
+        // Dropping below clears it, and the hold-down starts again
+        assert!(engine.check_rules(&low, &data, SECOND, at(12)).is_empty());
+        assert!(engine.check_rules(&high, &data, SECOND, at(13)).is_empty());
+        assert!(engine.check_rules(&high, &data, SECOND, at(22)).is_empty());
+        assert_eq!(engine.check_rules(&high, &data, SECOND, at(23)).len(), 1);
+        // The rate is per second of the tick
+        let mut engine = all_interfaces_engine();
+        assert!(engine
+            .check_rules(&high, &data, Duration::from_secs(4), at(0))
+            .is_empty());
+    }
+
+    #[test]
+    fn bandwidth_unknown_interface() {
+        let mut engine = engine(AlertCondition::Bandwidth {
+            interface: Some(String::from("eth1")),
+            direction: AlertDirection::Total,
+            bytes_per_sec: 1000,
+            duration_secs: 0,
+        });
+        let data = NetStatData::new();
+        let tick = eth0_tick(2000);
+        assert!(engine
+            .check_rules(&tick, &data, SECOND, Instant::now())
+            .is_empty());
+        assert_eq!(
+            all_interfaces_engine()
+                .check_rules(&tick, &data, SECOND, Instant::now())
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn new_country_once() {
+        let mut engine = engine(AlertCondition::NewCountry {
+            known_countries: vec![String::from("us")],
+        });
+        let tick = eth0_tick(0);
+        let now = Instant::now();
+        let mut data = NetStatData::new();
+        for (ip_addr, country_code) in [
+            (Ipv4Addr::new(8, 8, 8, 8), "US"),
+            (Ipv4Addr::new(133, 1, 1, 1), "JP"),
+            (Ipv4Addr::new(10, 0, 0, 1), ""),
+        ] {
+            data.remote_hosts
+                .insert(IpAddr::V4(ip_addr), remote_host(ip_addr, country_code, 0));
+        }
+        let notifications = engine.check_rules(&tick, &data, SECOND, now);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(
+            notifications[0].body,
+            "New remote country JP (JP): 133.1.1.1"
+        );
+        assert!(engine.check_rules(&tick, &data, SECOND, now).is_empty());
+        // Another host in a notified country
+        let ip_addr = Ipv4Addr::new(133, 2, 2, 2);
+        data.remote_hosts
+            .insert(IpAddr::V4(ip_addr), remote_host(ip_addr, "JP", 0));
+        assert!(engine.check_rules(&tick, &data, SECOND, now).is_empty());
+        let ip_addr = Ipv4Addr::new(85, 1, 1, 1);
+        data.remote_hosts
+            .insert(IpAddr::V4(ip_addr), remote_host(ip_addr, "DE", 0));
+        assert_eq!(engine.check_rules(&tick, &data, SECOND, now).len(), 1);
+    }
+
+    #[test]
+    fn process_asn_once_per_connection() {
+        let mut engine = engine(AlertCondition::ProcessAsn {
+            process: String::from("curl"),
+            asn: 13335,
+        });
+        let tick = eth0_tick(0);
+        let now = Instant::now();
+        let remote_ip_addr = Ipv4Addr::new(1, 1, 1, 1);
+        let mut data = NetStatData::new();
+        data.remote_hosts.insert(
+            IpAddr::V4(remote_ip_addr),
+            remote_host(remote_ip_addr, "US", 13335),
+        );
+        for (port, pid, name) in [(50000, 100, "curl"), (50001, 200, "wget")] {
+            data.local_socket_map.insert(
+                LocalSocket {
+                    interface_name: String::from("eth0"),
+                    port,
+                    protocol: TransportProtocol::TCP,
+                },
+                SocketProcess {
+                    socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), port),
+                    protocol: TransportProtocol::TCP,
+                    status: SocketStatus::Established,
+                    process: Some(ProcessInfo::new(pid, name.to_string())),
+                },
+            );
+            data.connection_map
+                .insert(connection(port, remote_ip_addr), TrafficInfo::new());
+        }
+        let notifications = engine.check_rules(&tick, &data, SECOND, now);
+        assert_eq!(notifications.len(), 1);
+        assert!(notifications[0]
+            .body
+            .starts_with("curl (PID 100) connected to 1.1.1.1:443"));
+        assert!(engine.check_rules(&tick, &data, SECOND, now).is_empty());
+        // The connection times out and is forgotten
+        data.connection_map
+            .remove(&connection(50000, remote_ip_addr));
+        assert!(engine.check_rules(&tick, &data, SECOND, now).is_empty());
+        assert!(engine.rules[0].1.notified.is_empty());
+        data.connection_map
+            .insert(connection(50000, remote_ip_addr), TrafficInfo::new());
+        assert_eq!(engine.check_rules(&tick, &data, SECOND, now).len(), 1);
+    }
+
+    #[test]
+    fn connection_count_per_tick() {
+        let mut engine = engine(AlertCondition::ConnectionCount { max: 2 });
+        let now = Instant::now();
+        // Many connections seen earlier in the session
+        let mut data = NetStatData::new();
+        for port in 50000..50010 {
+            data.connection_map.insert(
+                connection(port, Ipv4Addr::new(1, 1, 1, 1)),
+                TrafficInfo::new(),
+            );
+        }
+        let mut delta = NetStatData::new();
+        delta.connection_map.insert(
+            connection(50000, Ipv4Addr::new(1, 1, 1, 1)),
+            TrafficInfo::new(),
+        );
+        let quiet = TickTraffic::from_netstat_data(&delta);
+        assert!(engine.check_rules(&quiet, &data, SECOND, now).is_empty());
+        for port in 50001..50003 {
+            delta.connection_map.insert(
+                connection(port, Ipv4Addr::new(1, 1, 1, 1)),
+                TrafficInfo::new(),
+            );
+        }
+        let busy = TickTraffic::from_netstat_data(&delta);
+        let notifications = engine.check_rules(&busy, &data, SECOND, now);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(
+            notifications[0].body,
+            "3 connections with traffic in the last tick (limit 2)"
+        );
+        assert!(engine.check_rules(&busy, &data, SECOND, now).is_empty());
+        assert!(engine.check_rules(&quiet, &data, SECOND, now).is_empty());
+        assert_eq!(engine.check_rules(&busy, &data, SECOND, now).len(), 1);
+    }
+}
@@ -5,6 +5,7 @@ use crate::thread_log;
 use crate::thread_log::LogLevel;
 use crate::thread_log::DEFAULT_LOG_FILE_PATH;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 pub const NTAP_CONFIG_FILE_NAME: &str = "ntap-config.json";
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -17,6 +18,27 @@ pub struct AppConfig {
     pub display: DisplayConfig,
     /// Privacy configuration.
     pub privacy: PrivacyConfig,
+    /// Metrics exporter configuration.
+    #[serde(default = "MetricsConfig::new")]
+    pub metrics: MetricsConfig,
+    /// Persistent traffic accounting configuration.
+    #[serde(default = "AccountingConfig::new")]
+    pub accounting: AccountingConfig,
+    /// Alert rules configuration.
+    #[serde(default = "AlertConfig::new")]
+    pub alerts: AlertConfig,
+    /// Database update configuration.
+    #[serde(default = "UpdateConfig::new")]
+    pub update: UpdateConfig,
+    /// User-defined network labels.
+    #[serde(default = "LabelConfig::new")]
+    pub labels: LabelConfig,
+    /// Live packet capture configuration.
+    #[serde(default = "CaptureConfig::new")]
+    pub capture: CaptureConfig,
+    /// NetFlow v9 / IPFIX flow export configuration.
+    #[serde(default = "ExportConfig::new")]
+    pub export: ExportConfig,
 }
 
 impl AppConfig {
@@ -26,6 +48,13 @@ impl AppConfig {
             network: NetworkConfig::new(),
             display: DisplayConfig::new(),
             privacy: PrivacyConfig::new(),
+            metrics: MetricsConfig::new(),
+            accounting: AccountingConfig::new(),
+            alerts: AlertConfig::new(),
+            update: UpdateConfig::new(),
+            labels: LabelConfig::new(),
+            capture: CaptureConfig::new(),
+            export: ExportConfig::new(),
         }
     }
     pub fn load() -> AppConfig {
@@ -127,6 +156,13 @@ pub struct DisplayConfig {
     /// true: bandwidth, false: total.
     /// Default is total.
     pub show_bandwidth: bool,
+    /// Search filters pinned at startup. Rows must contain every filter.
+    /// Example: ["firefox", "443"]
+    #[serde(default)]
+    pub pinned_filters: Vec<String>,
+    /// Default sort of the TUI tables. Tables without the key sort by total bytes.
+    #[serde(default = "SortConfig::new")]
+    pub default_sort: SortConfig,
 }
 
 impl DisplayConfig {
@@ -136,10 +172,83 @@ impl DisplayConfig {
             connection_count: 20,
             tick_rate: 1000,
             show_bandwidth: false,
+            pinned_filters: Vec::new(),
+            default_sort: SortConfig::new(),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct SortConfig {
+    pub key: SortKey,
+    pub order: SortOrder,
+}
+
+impl SortConfig {
+    pub fn new() -> SortConfig {
+        SortConfig {
+            key: SortKey::Total,
+            order: SortOrder::Descending,
+        }
+    }
+}
+
+/// Sort column of the TUI tables.
+/// Total, bytes received and bytes sent follow the display mode (total/bandwidth).
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Total,
+    BytesReceived,
+    BytesSent,
+    /// Current bandwidth (ingress + egress)
+    Rate,
+    Packets,
+    FirstSeen,
+    LastSeen,
+    Country,
+    Asn,
+    ProcessName,
+    Hosts,
+    Connections,
+    /// Average TCP round-trip time
+    Rtt,
+}
+
+impl SortKey {
+    pub fn name(&self) -> &str {
+        match self {
+            SortKey::Total => "Total",
+            SortKey::BytesReceived => "Bytes received",
+            SortKey::BytesSent => "Bytes sent",
+            SortKey::Rate => "Rate",
+            SortKey::Packets => "Packets",
+            SortKey::FirstSeen => "First seen",
+            SortKey::LastSeen => "Last seen",
+            SortKey::Country => "Country",
+            SortKey::Asn => "ASN",
+            SortKey::ProcessName => "Process name",
+            SortKey::Hosts => "Hosts",
+            SortKey::Connections => "Connections",
+            SortKey::Rtt => "RTT",
+        }
+    }
+    /// Order used when the key is selected. Names ascending, numbers and times largest/latest first.
+    pub fn default_order(&self) -> SortOrder {
+        match self {
+            SortKey::Country | SortKey::Asn | SortKey::ProcessName => SortOrder::Ascending,
+            _ => SortOrder::Descending,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PrivacyConfig {
     /// Hide self private IP addresses by default.
@@ -157,6 +266,202 @@ impl PrivacyConfig {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    /// Maximum number of label values per metric (interface, ASN, country, process, service port).
+    /// Traffic for new label values beyond the limit is counted under "other".
+    pub max_label_values: usize,
+}
+
+impl MetricsConfig {
+    pub fn new() -> MetricsConfig {
+        MetricsConfig {
+            max_label_values: 100,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountingConfig {
+    /// Record hourly/daily/monthly traffic totals in the traffic database.
+    pub enabled: bool,
+    /// Days to keep hourly totals. 0 keeps them forever.
+    pub hourly_retention_days: u64,
+    /// Days to keep daily totals. 0 keeps them forever. Monthly totals are always kept.
+    pub daily_retention_days: u64,
+}
+
+impl AccountingConfig {
+    pub fn new() -> AccountingConfig {
+        AccountingConfig {
+            enabled: true,
+            hourly_retention_days: 31,
+            daily_retention_days: 400,
+        }
+    }
+    pub fn hourly_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.hourly_retention_days * 24 * 3600)
+    }
+    pub fn daily_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.daily_retention_days * 24 * 3600)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AlertConfig {
+    /// Rules evaluated on every tick in stat and monitor mode.
+    pub rules: Vec<AlertRule>,
+    /// Append each notification as a JSON line to this file.
+    pub log_file_path: Option<String>,
+    /// Run this command for each notification. The notification is passed as JSON on stdin
+    /// and as NTAP_NOTIFICATION_TYPE, NTAP_NOTIFICATION_TITLE and NTAP_NOTIFICATION_BODY.
+    pub webhook_command: Option<String>,
+    /// The number of recent notifications to keep for display.
+    pub max_notifications: usize,
+}
+
+impl AlertConfig {
+    pub fn new() -> AlertConfig {
+        AlertConfig {
+            rules: Vec::new(),
+            log_file_path: None,
+            webhook_command: None,
+            max_notifications: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AlertRule {
+    /// Name of the rule. Used as the notification title.
+    pub name: String,
+    pub condition: AlertCondition,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Bandwidth on an interface (or all interfaces if None) above bytes_per_sec for duration_secs.
+    Bandwidth {
+        interface: Option<String>,
+        direction: AlertDirection,
+        bytes_per_sec: u64,
+        duration_secs: u64,
+    },
+    /// Traffic with a country not seen before in this session and not in known_countries.
+    NewCountry { known_countries: Vec<String> },
+    /// A process talks to a remote host in the AS.
+    ProcessAsn { process: String, asn: u32 },
+    /// More than max connections with traffic in one tick.
+    ConnectionCount { max: usize },
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertDirection {
+    Ingress,
+    Egress,
+    Total,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateConfig {
+    /// Base URL (http/https) or local directory with manifest.json and the database files.
+    /// None uses the default download server. The --mirror option overrides this.
+    pub mirror: Option<String>,
+}
+
+impl UpdateConfig {
+    pub fn new() -> UpdateConfig {
+        UpdateConfig { mirror: None }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LabelConfig {
+    /// CIDR or IP address to label. Example: {"10.20.0.0/16": "k8s-prod", "192.0.2.10": "db-primary"}
+    #[serde(default)]
+    pub networks: BTreeMap<String, String>,
+    /// Optional labels file with one `CIDR = "label"` entry per line. # starts a comment.
+    /// Entries in networks take precedence over the file.
+    #[serde(default)]
+    pub file_path: Option<String>,
+}
+
+impl LabelConfig {
+    pub fn new() -> LabelConfig {
+        LabelConfig {
+            networks: BTreeMap::new(),
+            file_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CaptureConfig {
+    /// Maximum number of raw bytes kept per packet in live mode for the hex dump.
+    /// 0 keeps no raw bytes. The --snaplen option overrides this.
+    pub snaplen: usize,
+}
+
+impl CaptureConfig {
+    pub fn new() -> CaptureConfig {
+        CaptureConfig { snaplen: 65535 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowProtocol {
+    NetflowV9,
+    Ipfix,
+}
+
+impl FlowProtocol {
+    pub fn from_str(s: &str) -> Option<FlowProtocol> {
+        match s {
+            "netflow-v9" | "netflow_v9" => Some(FlowProtocol::NetflowV9),
+            "ipfix" => Some(FlowProtocol::Ipfix),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            FlowProtocol::NetflowV9 => "NetFlow v9",
+            FlowProtocol::Ipfix => "IPFIX",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExportConfig {
+    /// Collector address. Example: "192.0.2.10:4739". The --collector option overrides this.
+    pub collector: Option<String>,
+    /// Export format. The --protocol option overrides this.
+    pub protocol: FlowProtocol,
+    /// Seconds after which a long-lived flow is exported and a new one is started.
+    pub active_timeout: u64,
+    /// Seconds without traffic after which a flow is exported.
+    pub inactive_timeout: u64,
+    /// Seconds between template retransmissions.
+    pub template_interval: u64,
+    /// Source ID (NetFlow v9) or observation domain ID (IPFIX).
+    pub observation_domain_id: u32,
+}
+
+impl ExportConfig {
+    pub fn new() -> ExportConfig {
+        ExportConfig {
+            collector: None,
+            protocol: FlowProtocol::Ipfix,
+            active_timeout: 60,
+            inactive_timeout: 15,
+            template_interval: 60,
+            observation_domain_id: 0,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct DatabaseConfig {
     pub ipv4_asn_db_path: String,
@@ -0,0 +1,774 @@
+use ipnet::IpNet;
+use ntap_db_as::AutonomousSystem;
+use ntap_db_country::Country;
+use ntap_db_ipv4_asn::Ipv4Asn;
+use ntap_db_ipv4_country::Ipv4Country;
+use ntap_db_ipv6_asn::Ipv6Asn;
+use ntap_db_ipv6_country::Ipv6Country;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+/// Maximum number of overlaps listed in the error.
+const MAX_REPORTED_OVERLAPS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    /// iptoasn.com ip2asn-combined.tsv, ip2asn-v4.tsv, ip2asn-v6.tsv or ip2asn-v4-u32.tsv
+    IpToAsn,
+    /// DB-IP dbip-asn-lite.csv
+    DbipAsn,
+    /// DB-IP dbip-country-lite.csv
+    DbipCountry,
+    /// GeoLite2-ASN-Blocks-IPv4.csv and GeoLite2-ASN-Blocks-IPv6.csv
+    Geolite2Asn,
+    /// GeoLite2-Country-Blocks-IPv4.csv, GeoLite2-Country-Blocks-IPv6.csv and GeoLite2-Country-Locations-en.csv
+    Geolite2Country,
+    /// MaxMind DB (.mmdb) ASN, Country or City database
+    Mmdb,
+}
+
+impl SourceFormat {
+    pub fn from_str(s: &str) -> Option<SourceFormat> {
+        match s {
+            "iptoasn" => Some(SourceFormat::IpToAsn),
+            "dbip-asn" => Some(SourceFormat::DbipAsn),
+            "dbip-country" => Some(SourceFormat::DbipCountry),
+            "geolite2-asn" => Some(SourceFormat::Geolite2Asn),
+            "geolite2-country" => Some(SourceFormat::Geolite2Country),
+            "mmdb" => Some(SourceFormat::Mmdb),
+            _ => None,
+        }
+    }
+    pub fn names() -> [&'static str; 6] {
+        [
+            "iptoasn",
+            "dbip-asn",
+            "dbip-country",
+            "geolite2-asn",
+            "geolite2-country",
+            "mmdb",
+        ]
+    }
+}
+
+/// Range key of an IP range table. u32 for IPv4, u128 for IPv6.
+pub trait RangeKey: Copy + Ord {
+    fn next(self) -> Option<Self>;
+    fn to_ip_addr(self) -> IpAddr;
+}
+
+impl RangeKey for u32 {
+    fn next(self) -> Option<Self> {
+        self.checked_add(1)
+    }
+    fn to_ip_addr(self) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(self))
+    }
+}
+
+impl RangeKey for u128 {
+    fn next(self) -> Option<Self> {
+        self.checked_add(1)
+    }
+    fn to_ip_addr(self) -> IpAddr {
+        IpAddr::V6(Ipv6Addr::from(self))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RangeOverlap {
+    pub table: &'static str,
+    pub first: (IpAddr, IpAddr),
+    pub second: (IpAddr, IpAddr),
+}
+
+impl fmt::Display for RangeOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}-{} overlaps {}-{}",
+            self.table, self.first.0, self.first.1, self.second.0, self.second.1
+        )
+    }
+}
+
+/// Inclusive IP ranges (ip_from, ip_to, value)
+#[derive(Debug, Clone)]
+pub struct RangeTable<K, V> {
+    pub name: &'static str,
+    pub ranges: Vec<(K, K, V)>,
+}
+
+impl<K: RangeKey, V: Clone + PartialEq> RangeTable<K, V> {
+    pub fn new(name: &'static str) -> Self {
+        RangeTable {
+            name,
+            ranges: Vec::new(),
+        }
+    }
+    pub fn push(&mut self, ip_from: K, ip_to: K, value: V) {
+        self.ranges.push((ip_from, ip_to, value));
+    }
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+    /// Sort the ranges and return the overlapping ranges.
+    /// Each range that starts inside an earlier range is reported once,
+    /// paired with the earlier range that reaches furthest.
+    pub fn find_overlaps(&mut self) -> Vec<RangeOverlap> {
+        self.ranges
+            .sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+        let mut overlaps: Vec<RangeOverlap> = Vec::new();
+        // Index of the range with the largest ip_to so far
+        let mut widest: usize = 0;
+        for i in 1..self.ranges.len() {
+            let (first, current) = (&self.ranges[widest], &self.ranges[i]);
+            if current.0 <= first.1 {
+                overlaps.push(RangeOverlap {
+                    table: self.name,
+                    first: (first.0.to_ip_addr(), first.1.to_ip_addr()),
+                    second: (current.0.to_ip_addr(), current.1.to_ip_addr()),
+                });
+            }
+            if current.1 > first.1 {
+                widest = i;
+            }
+        }
+        overlaps
+    }
+    /// Merge adjacent ranges with the same value. The ranges must be sorted.
+    pub fn merge_adjacent(&mut self) {
+        let mut merged: Vec<(K, K, V)> = Vec::with_capacity(self.ranges.len());
+        for range in self.ranges.drain(..) {
+            if let Some(last) = merged.last_mut() {
+                if last.2 == range.2 && last.1.next() == Some(range.0) {
+                    last.1 = range.1;
+                    continue;
+                }
+            }
+            merged.push(range);
+        }
+        self.ranges = merged;
+    }
+}
+
+/// Records imported from the source files.
+#[derive(Debug, Clone)]
+pub struct ImportedData {
+    pub ipv4_asn: RangeTable<u32, u32>,
+    pub ipv6_asn: RangeTable<u128, u32>,
+    pub ipv4_country: RangeTable<u32, String>,
+    pub ipv6_country: RangeTable<u128, String>,
+    /// ASN -> AS name
+    pub as_names: BTreeMap<u32, String>,
+    /// Country code -> Country name
+    pub country_names: BTreeMap<String, String>,
+    /// Lines or networks that could not be used
+    pub skipped: usize,
+}
+
+/// A database file written by `ImportedData::write`.
+#[derive(Debug, Clone)]
+pub struct WrittenFile {
+    pub path: PathBuf,
+    pub imported: usize,
+    pub records: usize,
+}
+
+impl ImportedData {
+    pub fn new() -> Self {
+        ImportedData {
+            ipv4_asn: RangeTable::new(ntap_db_ipv4_asn::IPV4_ASN_BIN_NAME),
+            ipv6_asn: RangeTable::new(ntap_db_ipv6_asn::IPV6_ASN_BIN_NAME),
+            ipv4_country: RangeTable::new(ntap_db_ipv4_country::IPV4_COUNTRY_BIN_NAME),
+            ipv6_country: RangeTable::new(ntap_db_ipv6_country::IPV6_COUNTRY_BIN_NAME),
+            as_names: BTreeMap::new(),
+            country_names: BTreeMap::new(),
+            skipped: 0,
+        }
+    }
+    pub fn import(
+        &mut self,
+        format: SourceFormat,
+        files: &[PathBuf],
+    ) -> Result<(), Box<dyn Error>> {
+        match format {
+            SourceFormat::IpToAsn => {
+                for file in files {
+                    self.import_iptoasn(file)?;
+                }
+            }
+            SourceFormat::DbipAsn => {
+                for file in files {
+                    self.import_dbip_asn(file)?;
+                }
+            }
+            SourceFormat::DbipCountry => {
+                for file in files {
+                    self.import_dbip_country(file)?;
+                }
+            }
+            SourceFormat::Geolite2Asn => {
+                for file in files {
+                    self.import_geolite2_asn(file)?;
+                }
+            }
+            SourceFormat::Geolite2Country => {
+                self.import_geolite2_country(files)?;
+            }
+            SourceFormat::Mmdb => {
+                for file in files {
+                    self.import_mmdb(file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn add_asn_range(&mut self, ip_from: IpAddr, ip_to: IpAddr, asn: u32) {
+        match (ip_from, ip_to) {
+            (IpAddr::V4(ip_from), IpAddr::V4(ip_to)) if ip_from <= ip_to => {
+                self.ipv4_asn
+                    .push(u32::from(ip_from), u32::from(ip_to), asn);
+            }
+            (IpAddr::V6(ip_from), IpAddr::V6(ip_to)) if ip_from <= ip_to => {
+                self.ipv6_asn
+                    .push(u128::from(ip_from), u128::from(ip_to), asn);
+            }
+            _ => {
+                self.skipped += 1;
+            }
+        }
+    }
+    fn add_country_range(&mut self, ip_from: IpAddr, ip_to: IpAddr, country_code: String) {
+        match (ip_from, ip_to) {
+            (IpAddr::V4(ip_from), IpAddr::V4(ip_to)) if ip_from <= ip_to => {
+                self.ipv4_country
+                    .push(u32::from(ip_from), u32::from(ip_to), country_code);
+            }
+            (IpAddr::V6(ip_from), IpAddr::V6(ip_to)) if ip_from <= ip_to => {
+                self.ipv6_country
+                    .push(u128::from(ip_from), u128::from(ip_to), country_code);
+            }
+            _ => {
+                self.skipped += 1;
+            }
+        }
+    }
+    fn add_as_name(&mut self, asn: u32, as_name: &str) {
+        if !as_name.is_empty() {
+            self.as_names
+                .entry(asn)
+                .or_insert_with(|| as_name.to_string());
+        }
+    }
+    /// range_start, range_end, AS_number, country_code, AS_description (tab separated, no header)
+    fn import_iptoasn(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(b'\t')
+            .quoting(false)
+            .flexible(true)
+            .from_path(path)?;
+        for result in reader.records() {
+            let record = result?;
+            let (ip_from, ip_to, asn) = match (
+                record.get(0).and_then(parse_iptoasn_addr),
+                record.get(1).and_then(parse_iptoasn_addr),
+                record.get(2).and_then(|asn| asn.trim().parse::<u32>().ok()),
+            ) {
+                (Some(ip_from), Some(ip_to), Some(asn)) => (ip_from, ip_to, asn),
+                _ => {
+                    self.skipped += 1;
+                    continue;
+                }
+            };
+            // ASN 0 is "Not routed"
+            if asn == 0 {
+                continue;
+            }
+            self.add_asn_range(ip_from, ip_to, asn);
+            self.add_as_name(asn, record.get(4).unwrap_or("").trim());
+        }
+        Ok(())
+    }
+    /// ip_start, ip_end, asn, as_organization (no header)
+    fn import_dbip_asn(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)?;
+        for result in reader.records() {
+            let record = result?;
+            let (ip_from, ip_to, asn) = match (
+                record
+                    .get(0)
+                    .and_then(|ip| ip.trim().parse::<IpAddr>().ok()),
+                record
+                    .get(1)
+                    .and_then(|ip| ip.trim().parse::<IpAddr>().ok()),
+                record.get(2).and_then(|asn| asn.trim().parse::<u32>().ok()),
+            ) {
+                (Some(ip_from), Some(ip_to), Some(asn)) => (ip_from, ip_to, asn),
+                _ => {
+                    self.skipped += 1;
+                    continue;
+                }
+            };
+            self.add_asn_range(ip_from, ip_to, asn);
+            self.add_as_name(asn, record.get(3).unwrap_or("").trim());
+        }
+        Ok(())
+    }
+    /// ip_start, ip_end, country (no header)
+    fn import_dbip_country(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)?;
+        for result in reader.records() {
+            let record = result?;
+            let (ip_from, ip_to, country_code) = match (
+                record
+                    .get(0)
+                    .and_then(|ip| ip.trim().parse::<IpAddr>().ok()),
+                record
+                    .get(1)
+                    .and_then(|ip| ip.trim().parse::<IpAddr>().ok()),
+                record.get(2).map(|code| code.trim().to_uppercase()),
+            ) {
+                (Some(ip_from), Some(ip_to), Some(country_code)) => (ip_from, ip_to, country_code),
+                _ => {
+                    self.skipped += 1;
+                    continue;
+                }
+            };
+            // ZZ is "Unknown or unspecified"
+            if country_code.is_empty() || country_code == "ZZ" {
+                continue;
+            }
+            self.add_country_range(ip_from, ip_to, country_code);
+        }
+        Ok(())
+    }
+    /// network, autonomous_system_number, autonomous_system_organization (with header)
+    fn import_geolite2_asn(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let headers = reader.headers()?.clone();
+        let network_index = header_index(&headers, "network", path)?;
+        let asn_index = header_index(&headers, "autonomous_system_number", path)?;
+        let org_index = header_index(&headers, "autonomous_system_organization", path)?;
+        for result in reader.records() {
+            let record = result?;
+            let (network, asn) = match (
+                record
+                    .get(network_index)
+                    .and_then(|network| network.parse::<IpNet>().ok()),
+                record
+                    .get(asn_index)
+                    .and_then(|asn| asn.parse::<u32>().ok()),
+            ) {
+                (Some(network), Some(asn)) => (network, asn),
+                _ => {
+                    self.skipped += 1;
+                    continue;
+                }
+            };
+            self.add_asn_range(network.network(), network.broadcast(), asn);
+            self.add_as_name(asn, record.get(org_index).unwrap_or("").trim());
+        }
+        Ok(())
+    }
+    /// Blocks: network, geoname_id, registered_country_geoname_id, ... (with header)
+    /// Locations: geoname_id, locale_code, continent_code, continent_name, country_iso_code, country_name, ... (with header)
+    fn import_geolite2_country(&mut self, files: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let mut block_files: Vec<&PathBuf> = Vec::new();
+        // geoname_id -> country_iso_code
+        let mut geoname_map: HashMap<String, String> = HashMap::new();
+        for path in files {
+            let mut reader = csv::Reader::from_path(path)?;
+            let headers = reader.headers()?.clone();
+            if headers.iter().any(|header| header == "network") {
+                block_files.push(path);
+                continue;
+            }
+            let geoname_index = header_index(&headers, "geoname_id", path)?;
+            let code_index = header_index(&headers, "country_iso_code", path)?;
+            let name_index = header_index(&headers, "country_name", path)?;
+            for result in reader.records() {
+                let record = result?;
+                let country_code = record.get(code_index).unwrap_or("").trim();
+                // Continents have no country code
+                if country_code.is_empty() {
+                    continue;
+                }
+                geoname_map.insert(
+                    record.get(geoname_index).unwrap_or("").to_string(),
+                    country_code.to_string(),
+                );
+                let country_name = record.get(name_index).unwrap_or("").trim();
+                if !country_name.is_empty() {
+                    self.country_names
+                        .insert(country_code.to_string(), country_name.to_string());
+                }
+            }
+        }
+        if geoname_map.is_empty() {
+            return Err(
+                "GeoLite2 country import needs the GeoLite2-Country-Locations CSV file".into(),
+            );
+        }
+        for path in block_files {
+            let mut reader = csv::Reader::from_path(path)?;
+            let headers = reader.headers()?.clone();
+            let network_index = header_index(&headers, "network", path)?;
+            let geoname_index = header_index(&headers, "geoname_id", path)?;
+            let registered_index = header_index(&headers, "registered_country_geoname_id", path)?;
+            for result in reader.records() {
+                let record = result?;
+                let network = match record
+                    .get(network_index)
+                    .and_then(|network| network.parse::<IpNet>().ok())
+                {
+                    Some(network) => network,
+                    None => {
+                        self.skipped += 1;
+                        continue;
+                    }
+                };
+                // Anonymous proxies and satellite providers only have the registered country
+                let geoname_id = match record.get(geoname_index) {
+                    Some(geoname_id) if !geoname_id.is_empty() => geoname_id,
+                    _ => record.get(registered_index).unwrap_or(""),
+                };
+                match geoname_map.get(geoname_id) {
+                    Some(country_code) => {
+                        self.add_country_range(
+                            network.network(),
+                            network.broadcast(),
+                            country_code.clone(),
+                        );
+                    }
+                    None => {
+                        self.skipped += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// GeoLite2/GeoIP2 ASN, Country or City database
+    fn import_mmdb(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let reader = maxminddb::Reader::open_readfile(path)?;
+        let database_type = reader.metadata.database_type.clone();
+        let is_asn = database_type.contains("ASN");
+        // Networks under ::/96 of an IPv6 tree are returned as IPv4 networks
+        let network: ipnetwork::IpNetwork = if reader.metadata.ip_version == 6 {
+            "::/0".parse()?
+        } else {
+            "0.0.0.0/0".parse()?
+        };
+        if is_asn {
+            let items = reader.within::<maxminddb::geoip2::Asn>(network)?;
+            for item in items {
+                let item = item?;
+                let (ip_from, ip_to) = mmdb_network_range(&item.ip_net);
+                let asn = match item.info.autonomous_system_number {
+                    Some(asn) => asn,
+                    None => {
+                        self.skipped += 1;
+                        continue;
+                    }
+                };
+                self.add_asn_range(ip_from, ip_to, asn);
+                self.add_as_name(asn, item.info.autonomous_system_organization.unwrap_or(""));
+            }
+        } else {
+            // City databases share the country fields
+            let items = reader.within::<maxminddb::geoip2::Country>(network)?;
+            for item in items {
+                let item = item?;
+                let (ip_from, ip_to) = mmdb_network_range(&item.ip_net);
+                let country = match item.info.country.or(item.info.registered_country) {
+                    Some(country) => country,
+                    None => {
+                        self.skipped += 1;
+                        continue;
+                    }
+                };
+                let country_code = match country.iso_code {
+                    Some(country_code) => country_code.to_string(),
+                    None => {
+                        self.skipped += 1;
+                        continue;
+                    }
+                };
+                if let Some(country_name) = country.names.as_ref().and_then(|names| names.get("en"))
+                {
+                    self.country_names
+                        .entry(country_code.clone())
+                        .or_insert_with(|| country_name.to_string());
+                }
+                self.add_country_range(ip_from, ip_to, country_code);
+            }
+        }
+        Ok(())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ipv4_asn.is_empty()
+            && self.ipv6_asn.is_empty()
+            && self.ipv4_country.is_empty()
+            && self.ipv6_country.is_empty()
+    }
+    /// Check all tables for overlapping ranges.
+    pub fn validate(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut overlaps: Vec<RangeOverlap> = Vec::new();
+        overlaps.extend(self.ipv4_asn.find_overlaps());
+        overlaps.extend(self.ipv6_asn.find_overlaps());
+        overlaps.extend(self.ipv4_country.find_overlaps());
+        overlaps.extend(self.ipv6_country.find_overlaps());
+        if overlaps.is_empty() {
+            return Ok(());
+        }
+        let mut message = format!("{} overlapping ranges found", overlaps.len());
+        for overlap in overlaps.iter().take(MAX_REPORTED_OVERLAPS) {
+            message.push_str(&format!("\n  {}", overlap));
+        }
+        if overlaps.len() > MAX_REPORTED_OVERLAPS {
+            message.push_str(&format!(
+                "\n  ... and {} more",
+                overlaps.len() - MAX_REPORTED_OVERLAPS
+            ));
+        }
+        Err(message.into())
+    }
+    /// Write the non-empty tables to the database directory. Call validate first.
+    pub fn write(&mut self, output_dir: &Path) -> Result<Vec<WrittenFile>, Box<dyn Error>> {
+        if !output_dir.exists() {
+            std::fs::create_dir_all(output_dir)?;
+        }
+        let mut written_files: Vec<WrittenFile> = Vec::new();
+        if !self.ipv4_asn.is_empty() {
+            let imported = self.ipv4_asn.len();
+            self.ipv4_asn.merge_adjacent();
+            let records: Vec<Ipv4Asn> = self
+                .ipv4_asn
+                .ranges
+                .iter()
+                .map(|(ip_from, ip_to, asn)| Ipv4Asn {
+                    ip_from: *ip_from,
+                    ip_to: *ip_to,
+                    asn: *asn,
+                })
+                .collect();
+            written_files.push(write_records(
+                output_dir,
+                ntap_db_ipv4_asn::IPV4_ASN_BIN_NAME,
+                imported,
+                &records,
+            )?);
+        }
+        if !self.ipv6_asn.is_empty() {
+            let imported = self.ipv6_asn.len();
+            self.ipv6_asn.merge_adjacent();
+            let records: Vec<Ipv6Asn> = self
+                .ipv6_asn
+                .ranges
+                .iter()
+                .map(|(ip_from, ip_to, asn)| Ipv6Asn {
+                    ip_from: *ip_from,
+                    ip_to: *ip_to,
+                    asn: *asn,
+                })
+                .collect();
+            written_files.push(write_records(
+                output_dir,
+                ntap_db_ipv6_asn::IPV6_ASN_BIN_NAME,
+                imported,
+                &records,
+            )?);
+        }
+        if !self.ipv4_country.is_empty() {
+            let imported = self.ipv4_country.len();
+            self.ipv4_country.merge_adjacent();
+            let records: Vec<Ipv4Country> = self
+                .ipv4_country
+                .ranges
+                .iter()
+                .map(|(ip_from, ip_to, country_code)| Ipv4Country {
+                    ip_from: *ip_from,
+                    ip_to: *ip_to,
+                    country_code: country_code.clone(),
+                })
+                .collect();
+            written_files.push(write_records(
+                output_dir,
+                ntap_db_ipv4_country::IPV4_COUNTRY_BIN_NAME,
+                imported,
+                &records,
+            )?);
+        }
+        if !self.ipv6_country.is_empty() {
+            let imported = self.ipv6_country.len();
+            self.ipv6_country.merge_adjacent();
+            let records: Vec<Ipv6Country> = self
+                .ipv6_country
+                .ranges
+                .iter()
+                .map(|(ip_from, ip_to, country_code)| Ipv6Country {
+                    ip_from: *ip_from,
+                    ip_to: *ip_to,
+                    country_code: country_code.clone(),
+                })
+                .collect();
+            written_files.push(write_records(
+                output_dir,
+                ntap_db_ipv6_country::IPV6_COUNTRY_BIN_NAME,
+                imported,
+                &records,
+            )?);
+        }
+        if !self.as_names.is_empty() {
+            let records: Vec<AutonomousSystem> = self
+                .as_names
+                .iter()
+                .map(|(asn, as_name)| AutonomousSystem {
+                    asn: *asn,
+                    as_name: as_name.clone(),
+                })
+                .collect();
+            written_files.push(write_records(
+                output_dir,
+                ntap_db_as::AS_BIN_NAME,
+                records.len(),
+                &records,
+            )?);
+        }
+        if !self.country_names.is_empty() {
+            let records: Vec<Country> = self
+                .country_names
+                .iter()
+                .map(|(country_code, country_name)| Country {
+                    country_code: country_code.clone(),
+                    country_name: country_name.clone(),
+                })
+                .collect();
+            written_files.push(write_records(
+                output_dir,
+                ntap_db_country::COUNTRY_BIN_NAME,
+                records.len(),
+                &records,
+            )?);
+        }
+        Ok(written_files)
+    }
+}
+
+/// Parse a dotted/colon address, or a decimal u32 as in ip2asn-v4-u32.tsv
+fn parse_iptoasn_addr(s: &str) -> Option<IpAddr> {
+    let s = s.trim();
+    match s.parse::<IpAddr>() {
+        Ok(ip_addr) => Some(ip_addr),
+        Err(_) => s.parse::<u32>().ok().map(|n| IpAddr::V4(Ipv4Addr::from(n))),
+    }
+}
+
+fn header_index(
+    headers: &csv::StringRecord,
+    name: &str,
+    path: &Path,
+) -> Result<usize, Box<dyn Error>> {
+    match headers.iter().position(|header| header == name) {
+        Some(index) => Ok(index),
+        None => Err(format!("{}: missing column {}", path.display(), name).into()),
+    }
+}
+
+/// First and last address of a network from an mmdb file.
+fn mmdb_network_range(network: &ipnetwork::IpNetwork) -> (IpAddr, IpAddr) {
+    match network {
+        ipnetwork::IpNetwork::V4(network) => (
+            IpAddr::V4(network.network()),
+            IpAddr::V4(network.broadcast()),
+        ),
+        ipnetwork::IpNetwork::V6(network) => {
+            let ip_from = u128::from(network.network());
+            let host_bits = 128 - network.prefix() as u32;
+            let ip_to = if host_bits >= 128 {
+                u128::MAX
+            } else {
+                ip_from | ((1u128 << host_bits) - 1)
+            };
+            (
+                IpAddr::V6(Ipv6Addr::from(ip_from)),
+                IpAddr::V6(Ipv6Addr::from(ip_to)),
+            )
+        }
+    }
+}
+
+/// Serialize the records with bincode. Write to a temporary file first so that a running ntap
+/// never reads a partial file.
+fn write_records<T: Serialize>(
+    output_dir: &Path,
+    file_name: &str,
+    imported: usize,
+    records: &Vec<T>,
+) -> Result<WrittenFile, Box<dyn Error>> {
+    let path = output_dir.join(file_name);
+    let tmp_path = output_dir.join(format!("{}.tmp", file_name));
+    let file = File::create(&tmp_path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    bincode::serialize_into(&mut writer, records)?;
+    std::io::Write::flush(&mut writer)?;
+    drop(writer);
+    std::fs::rename(&tmp_path, &path)?;
+    Ok(WrittenFile {
+        path,
+        imported,
+        records: records.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_overlaps_wide_range() {
+        let mut table: RangeTable<u32, u32> = RangeTable::new("test");
+        // 10.0.0.0/8 covers the next three ranges, 10.3.0.0/16 is disjoint from its neighbour
+        table.push(0x0A00_0000, 0x0AFF_FFFF, 1);
+        table.push(0x0A01_0000, 0x0A01_FFFF, 2);
+        table.push(0x0A02_0000, 0x0A02_FFFF, 3);
+        table.push(0x0A03_0000, 0x0A03_FFFF, 4);
+        table.push(0x0B00_0000, 0x0B00_00FF, 5);
+        let overlaps = table.find_overlaps();
+        assert_eq!(overlaps.len(), 3);
+        let wide = (
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)),
+            IpAddr::V4(Ipv4Addr::new(10, 255, 255, 255)),
+        );
+        assert!(overlaps.iter().all(|overlap| overlap.first == wide));
+        assert_eq!(overlaps[2].second.0, IpAddr::V4(Ipv4Addr::new(10, 3, 0, 0)));
+    }
+
+    #[test]
+    fn find_overlaps_adjacent() {
+        let mut table: RangeTable<u32, u32> = RangeTable::new("test");
+        table.push(20, 29, 2);
+        table.push(0, 9, 1);
+        table.push(10, 19, 1);
+        table.push(25, 40, 3);
+        let overlaps = table.find_overlaps();
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].second.0, IpAddr::V4(Ipv4Addr::from(25)));
+    }
+}
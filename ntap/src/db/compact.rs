@@ -0,0 +1,751 @@
+use super::ip::{split_ranges, IpBlock, IpDatabase, IpInfo};
+use crate::thread_log;
+use memmap2::Mmap;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+/// Compact IP database built from the ipv4/ipv6 asn/country, as and country .bin files.
+pub const COMPACT_IPDB_FILE_NAME: &str = "ipdb.ntdb";
+const COMPACT_IPDB_MAGIC: &[u8; 8] = b"NTAPIPDB";
+const COMPACT_IPDB_VERSION: u32 = 1;
+
+/// magic (8), version (4), reserved (4), then (offset: u64, count: u64) for each section
+const HEADER_SIZE: usize = 16 + SECTION_COUNT * 16;
+const SECTION_COUNT: usize = 7;
+
+/// Source files of the compact database. It is rebuilt when one of them is newer.
+const SOURCE_FILE_NAMES: [&str; 6] = [
+    ntap_db_as::AS_BIN_NAME,
+    ntap_db_country::COUNTRY_BIN_NAME,
+    ntap_db_ipv4_asn::IPV4_ASN_BIN_NAME,
+    ntap_db_ipv4_country::IPV4_COUNTRY_BIN_NAME,
+    ntap_db_ipv6_asn::IPV6_ASN_BIN_NAME,
+    ntap_db_ipv6_country::IPV6_COUNTRY_BIN_NAME,
+];
+
+/// Sections of the file. All integers are little endian and all tables are sorted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    /// ip_from: u32, ip_to: u32, asn: u32
+    Ipv4Asn,
+    /// ip_from: u32, ip_to: u32, country index: u32
+    Ipv4Country,
+    /// ip_from: u128, ip_to: u128, asn: u32
+    Ipv6Asn,
+    /// ip_from: u128, ip_to: u128, country index: u32
+    Ipv6Country,
+    /// asn: u32, name offset: u32, name length: u32
+    AsNames,
+    /// code offset: u32, code length: u32, name offset: u32, name length: u32
+    Countries,
+    /// Interned UTF-8 strings. The count is the length in bytes.
+    Strings,
+}
+
+impl Section {
+    const ALL: [Section; SECTION_COUNT] = [
+        Section::Ipv4Asn,
+        Section::Ipv4Country,
+        Section::Ipv6Asn,
+        Section::Ipv6Country,
+        Section::AsNames,
+        Section::Countries,
+        Section::Strings,
+    ];
+    fn record_size(&self) -> usize {
+        match self {
+            Section::Ipv4Asn | Section::Ipv4Country => 12,
+            Section::Ipv6Asn | Section::Ipv6Country => 36,
+            Section::AsNames => 12,
+            Section::Countries => 16,
+            Section::Strings => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SectionRange {
+    offset: usize,
+    count: usize,
+}
+
+#[derive(Debug)]
+enum CompactData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl CompactData {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            CompactData::Mapped(mmap) => mmap,
+            CompactData::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// Read-only IP database on a memory-mapped file.
+/// Lookups are binary searches over fixed-width records, so the reader is Send + Sync and
+/// needs no lock. Results are the same as IpDatabase built from the same .bin files.
+#[derive(Debug)]
+pub struct CompactIpDatabase {
+    data: CompactData,
+    sections: [SectionRange; SECTION_COUNT],
+}
+
+impl CompactIpDatabase {
+    /// Open the compact database in the database directory.
+    /// It is built from the .bin files if it is missing, invalid or older than them.
+    #[cfg(not(feature = "bundle"))]
+    pub fn load() -> Result<CompactIpDatabase, Box<dyn Error>> {
+        let path = match crate::sys::get_db_file_path(COMPACT_IPDB_FILE_NAME) {
+            Some(path) => path,
+            None => {
+                return Err("Could not get database directory path".into());
+            }
+        };
+        if !is_stale(&path) {
+            match CompactIpDatabase::open(&path) {
+                Ok(ipdb) => return Ok(ipdb),
+                Err(e) => {
+                    thread_log!(warn, "{} is invalid, rebuilding: {:?}", path.display(), e);
+                }
+            }
+        }
+        CompactIpDatabase::build(&path)
+    }
+    /// Build the compact database in memory from the bundled maps.
+    #[cfg(feature = "bundle")]
+    pub fn load() -> Result<CompactIpDatabase, Box<dyn Error>> {
+        let ip_db = IpDatabase::load()?;
+        CompactIpDatabase::from_bytes(CompactIpDatabase::to_bytes(&ip_db))
+    }
+    /// Build the file from the .bin files and open it.
+    /// Falls back to the in-memory data if the file cannot be written.
+    pub fn build(path: &Path) -> Result<CompactIpDatabase, Box<dyn Error>> {
+        thread_log!(info, "Building {}", path.display());
+        let ip_db = IpDatabase::load()?;
+        let bytes = CompactIpDatabase::to_bytes(&ip_db);
+        drop(ip_db);
+        let tmp_path = path.with_extension("tmp");
+        let result =
+            std::fs::write(&tmp_path, &bytes).and_then(|_| std::fs::rename(&tmp_path, path));
+        if let Err(e) = result {
+            thread_log!(error, "{} write error: {:?}", path.display(), e);
+            return CompactIpDatabase::from_bytes(bytes);
+        }
+        CompactIpDatabase::open(path)
+    }
+    pub fn open(path: &Path) -> Result<CompactIpDatabase, Box<dyn Error>> {
+        let file = File::open(path)?;
+        // The file is only ever replaced by rename, never modified in place
+        let mmap = unsafe { Mmap::map(&file)? };
+        CompactIpDatabase::from_data(CompactData::Mapped(mmap))
+    }
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<CompactIpDatabase, Box<dyn Error>> {
+        CompactIpDatabase::from_data(CompactData::Owned(bytes))
+    }
+    fn from_data(data: CompactData) -> Result<CompactIpDatabase, Box<dyn Error>> {
+        let bytes = data.bytes();
+        if bytes.len() < HEADER_SIZE || &bytes[0..8] != COMPACT_IPDB_MAGIC {
+            return Err("Not a compact IP database".into());
+        }
+        let version = read_u32(bytes, 8);
+        if version != COMPACT_IPDB_VERSION {
+            return Err(format!("Unsupported compact IP database version {}", version).into());
+        }
+        let mut sections = [SectionRange::default(); SECTION_COUNT];
+        for (i, section) in Section::ALL.iter().enumerate() {
+            let offset = read_u64(bytes, 16 + i * 16) as usize;
+            let count = read_u64(bytes, 24 + i * 16) as usize;
+            let end = count
+                .checked_mul(section.record_size())
+                .and_then(|size| size.checked_add(offset));
+            match end {
+                Some(end) if offset >= HEADER_SIZE && end <= bytes.len() => {}
+                _ => {
+                    return Err(format!("Invalid section {:?}", section).into());
+                }
+            }
+            sections[i] = SectionRange { offset, count };
+        }
+        Ok(CompactIpDatabase { data, sections })
+    }
+    /// Serialize the maps. Each RangeInclusiveMap iterates its ranges sorted and coalesced,
+    /// so the records keep the exact lookup results of the maps.
+    pub fn to_bytes(ip_db: &IpDatabase) -> Vec<u8> {
+        let mut strings = StringTable::new();
+        // Countries in the ranges and in the country names
+        let mut country_codes: BTreeSet<&String> = ip_db.country_map.keys().collect();
+        country_codes.extend(ip_db.ipv4_country_map.iter().map(|(_, code)| code));
+        country_codes.extend(ip_db.ipv6_country_map.iter().map(|(_, code)| code));
+        let mut country_index: HashMap<&String, u32> = HashMap::new();
+        let mut countries: Vec<u8> = Vec::new();
+        for (i, code) in country_codes.iter().enumerate() {
+            country_index.insert(code, i as u32);
+            let (code_offset, code_len) = strings.intern(code);
+            let (name_offset, name_len) = match ip_db.country_map.get(*code) {
+                Some(name) => strings.intern(name),
+                None => (0, 0),
+            };
+            for n in [code_offset, code_len, name_offset, name_len] {
+                countries.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+        let mut asns: Vec<(&u32, &String)> = ip_db.autonomous_map.iter().collect();
+        asns.sort();
+        let mut as_names: Vec<u8> = Vec::new();
+        for (asn, name) in &asns {
+            let (name_offset, name_len) = strings.intern(name);
+            for n in [**asn, name_offset, name_len] {
+                as_names.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+        let mut ipv4_asn: Vec<u8> = Vec::new();
+        for (range, asn) in ip_db.ipv4_asn_map.iter() {
+            ipv4_asn.extend_from_slice(&range.start().to_le_bytes());
+            ipv4_asn.extend_from_slice(&range.end().to_le_bytes());
+            ipv4_asn.extend_from_slice(&asn.to_le_bytes());
+        }
+        let mut ipv4_country: Vec<u8> = Vec::new();
+        for (range, code) in ip_db.ipv4_country_map.iter() {
+            ipv4_country.extend_from_slice(&range.start().to_le_bytes());
+            ipv4_country.extend_from_slice(&range.end().to_le_bytes());
+            ipv4_country.extend_from_slice(&country_index[code].to_le_bytes());
+        }
+        let mut ipv6_asn: Vec<u8> = Vec::new();
+        for (range, asn) in ip_db.ipv6_asn_map.iter() {
+            ipv6_asn.extend_from_slice(&range.start().to_le_bytes());
+            ipv6_asn.extend_from_slice(&range.end().to_le_bytes());
+            ipv6_asn.extend_from_slice(&asn.to_le_bytes());
+        }
+        let mut ipv6_country: Vec<u8> = Vec::new();
+        for (range, code) in ip_db.ipv6_country_map.iter() {
+            ipv6_country.extend_from_slice(&range.start().to_le_bytes());
+            ipv6_country.extend_from_slice(&range.end().to_le_bytes());
+            ipv6_country.extend_from_slice(&country_index[code].to_le_bytes());
+        }
+        let section_data: [&Vec<u8>; SECTION_COUNT] = [
+            &ipv4_asn,
+            &ipv4_country,
+            &ipv6_asn,
+            &ipv6_country,
+            &as_names,
+            &countries,
+            &strings.bytes,
+        ];
+        let mut bytes: Vec<u8> = Vec::with_capacity(
+            HEADER_SIZE + section_data.iter().map(|data| data.len()).sum::<usize>(),
+        );
+        bytes.extend_from_slice(COMPACT_IPDB_MAGIC);
+        bytes.extend_from_slice(&COMPACT_IPDB_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        let mut offset = HEADER_SIZE;
+        for (section, data) in Section::ALL.iter().zip(section_data.iter()) {
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&((data.len() / section.record_size()) as u64).to_le_bytes());
+            offset += data.len();
+        }
+        for data in section_data {
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+    fn record(&self, section: Section, index: usize) -> &[u8] {
+        let range = self.sections[section as usize];
+        let size = section.record_size();
+        let start = range.offset + index * size;
+        &self.data.bytes()[start..start + size]
+    }
+    fn count(&self, section: Section) -> usize {
+        self.sections[section as usize].count
+    }
+    fn string(&self, offset: u32, len: u32) -> &str {
+        let range = self.sections[Section::Strings as usize];
+        let start = offset as usize;
+        let end = start + len as usize;
+        if end > range.count {
+            return "";
+        }
+        let bytes = &self.data.bytes()[range.offset + start..range.offset + end];
+        std::str::from_utf8(bytes).unwrap_or("")
+    }
+    /// Index of the first record whose ip_from is greater than the key.
+    fn upper_bound<K: Ord + Copy>(
+        &self,
+        section: Section,
+        key: K,
+        decode: fn(&[u8]) -> (K, K, u32),
+    ) -> usize {
+        let mut low = 0;
+        let mut high = self.count(section);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if decode(self.record(section, mid)).0 <= key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+    fn find<K: Ord + Copy>(
+        &self,
+        section: Section,
+        key: K,
+        decode: fn(&[u8]) -> (K, K, u32),
+    ) -> Option<u32> {
+        let index = self.upper_bound(section, key, decode);
+        if index == 0 {
+            return None;
+        }
+        let (_, ip_to, value) = decode(self.record(section, index - 1));
+        if key <= ip_to {
+            Some(value)
+        } else {
+            None
+        }
+    }
+    fn overlapping<K: Ord + Copy>(
+        &self,
+        section: Section,
+        ip_from: K,
+        ip_to: K,
+        decode: fn(&[u8]) -> (K, K, u32),
+    ) -> Vec<(K, K, u32)> {
+        let mut index = self.upper_bound(section, ip_from, decode);
+        if index > 0 && decode(self.record(section, index - 1)).1 >= ip_from {
+            index -= 1;
+        }
+        let mut ranges: Vec<(K, K, u32)> = Vec::new();
+        while index < self.count(section) {
+            let range = decode(self.record(section, index));
+            if range.0 > ip_to {
+                break;
+            }
+            ranges.push(range);
+            index += 1;
+        }
+        ranges
+    }
+    fn get_as_name(&self, asn: u32) -> &str {
+        let mut low = 0;
+        let mut high = self.count(Section::AsNames);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let record = self.record(Section::AsNames, mid);
+            match read_u32(record, 0).cmp(&asn) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => {
+                    return self.string(read_u32(record, 4), read_u32(record, 8));
+                }
+            }
+        }
+        ""
+    }
+    /// (country_code, country_name)
+    fn get_country(&self, index: u32) -> (&str, &str) {
+        if index as usize >= self.count(Section::Countries) {
+            return ("", "");
+        }
+        let record = self.record(Section::Countries, index as usize);
+        (
+            self.string(read_u32(record, 0), read_u32(record, 4)),
+            self.string(read_u32(record, 8), read_u32(record, 12)),
+        )
+    }
+    pub fn get_ipv4_info(&self, ipv4_addr: Ipv4Addr) -> Option<IpInfo> {
+        let mut ip_info = IpInfo::new(IpAddr::V4(ipv4_addr));
+        let ip_addr_int: u32 = u32::from(ipv4_addr);
+        ip_info.ip_version = String::from("v4");
+        ip_info.ip_addr_dec = ip_addr_int.to_string();
+        if let Some(index) = self.find(Section::Ipv4Country, ip_addr_int, decode_ipv4) {
+            let (country_code, country_name) = self.get_country(index);
+            ip_info.country_code = country_code.to_string();
+            ip_info.country_name = country_name.to_string();
+        }
+        if let Some(asn) = self.find(Section::Ipv4Asn, ip_addr_int, decode_ipv4) {
+            ip_info.asn = asn;
+            ip_info.as_name = self.get_as_name(asn).to_string();
+        }
+        filter_ip_info(ip_info)
+    }
+    pub fn get_ipv6_info(&self, ipv6_addr: Ipv6Addr) -> Option<IpInfo> {
+        let mut ip_info = IpInfo::new(IpAddr::V6(ipv6_addr));
+        let ip_addr_int: u128 = u128::from(ipv6_addr);
+        ip_info.ip_version = String::from("v6");
+        ip_info.ip_addr_dec = ip_addr_int.to_string();
+        if let Some(index) = self.find(Section::Ipv6Country, ip_addr_int, decode_ipv6) {
+            let (country_code, country_name) = self.get_country(index);
+            ip_info.country_code = country_code.to_string();
+            ip_info.country_name = country_name.to_string();
+        }
+        if let Some(asn) = self.find(Section::Ipv6Asn, ip_addr_int, decode_ipv6) {
+            ip_info.asn = asn;
+            ip_info.as_name = self.get_as_name(asn).to_string();
+        }
+        filter_ip_info(ip_info)
+    }
+    /// Split the IPv4 range into the distinct AS/country blocks it overlaps.
+    pub fn get_ipv4_blocks(&self, ip_from: Ipv4Addr, ip_to: Ipv4Addr) -> Vec<IpBlock> {
+        let (ip_from, ip_to) = (u32::from(ip_from), u32::from(ip_to));
+        let asn_ranges = self.overlapping(Section::Ipv4Asn, ip_from, ip_to, decode_ipv4);
+        let country_ranges = self.overlapping(Section::Ipv4Country, ip_from, ip_to, decode_ipv4);
+        split_ranges(
+            ip_from,
+            ip_to,
+            &asn_ranges,
+            &country_ranges,
+            |n| n.checked_add(1),
+            |n| n - 1,
+        )
+        .into_iter()
+        .map(|(start, end, asn, country_index)| {
+            self.new_ip_block(
+                IpAddr::V4(Ipv4Addr::from(start)),
+                IpAddr::V4(Ipv4Addr::from(end)),
+                asn,
+                country_index,
+            )
+        })
+        .collect()
+    }
+    /// Split the IPv6 range into the distinct AS/country blocks it overlaps.
+    pub fn get_ipv6_blocks(&self, ip_from: Ipv6Addr, ip_to: Ipv6Addr) -> Vec<IpBlock> {
+        let (ip_from, ip_to) = (u128::from(ip_from), u128::from(ip_to));
+        let asn_ranges = self.overlapping(Section::Ipv6Asn, ip_from, ip_to, decode_ipv6);
+        let country_ranges = self.overlapping(Section::Ipv6Country, ip_from, ip_to, decode_ipv6);
+        split_ranges(
+            ip_from,
+            ip_to,
+            &asn_ranges,
+            &country_ranges,
+            |n| n.checked_add(1),
+            |n| n - 1,
+        )
+        .into_iter()
+        .map(|(start, end, asn, country_index)| {
+            self.new_ip_block(
+                IpAddr::V6(Ipv6Addr::from(start)),
+                IpAddr::V6(Ipv6Addr::from(end)),
+                asn,
+                country_index,
+            )
+        })
+        .collect()
+    }
+    fn new_ip_block(
+        &self,
+        ip_from: IpAddr,
+        ip_to: IpAddr,
+        asn: Option<u32>,
+        country_index: Option<u32>,
+    ) -> IpBlock {
+        let asn = asn.unwrap_or(0);
+        let (country_code, country_name) = match country_index {
+            Some(index) => self.get_country(index),
+            None => ("", ""),
+        };
+        IpBlock {
+            ip_from,
+            ip_to,
+            asn,
+            as_name: self.get_as_name(asn).to_string(),
+            country_code: country_code.to_string(),
+            country_name: country_name.to_string(),
+        }
+    }
+}
+
+/// Deduplicated string storage for the Strings section.
+struct StringTable {
+    bytes: Vec<u8>,
+    /// String -> (offset, length)
+    index: HashMap<String, (u32, u32)>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable {
+            bytes: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+    fn intern(&mut self, s: &str) -> (u32, u32) {
+        if let Some(entry) = self.index.get(s) {
+            return *entry;
+        }
+        let entry = (self.bytes.len() as u32, s.len() as u32);
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.index.insert(s.to_string(), entry);
+        entry
+    }
+}
+
+/// Same rule as IpDatabase: no info without a known country or ASN.
+fn filter_ip_info(ip_info: IpInfo) -> Option<IpInfo> {
+    if (ip_info.country_code.is_empty()
+        || ip_info.country_code == "ZZ"
+        || ip_info.country_code == "-")
+        && ip_info.asn == 0
+    {
+        None
+    } else {
+        Some(ip_info)
+    }
+}
+
+/// The compact database is stale if it is missing or older than one of the .bin files.
+#[cfg(not(feature = "bundle"))]
+fn is_stale(path: &Path) -> bool {
+    let modified = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return true,
+    };
+    let db_dir = match path.parent() {
+        Some(db_dir) => db_dir,
+        None => return true,
+    };
+    SOURCE_FILE_NAMES.iter().any(|name| {
+        match std::fs::metadata(db_dir.join(name)).and_then(|metadata| metadata.modified()) {
+            Ok(source_modified) => source_modified > modified,
+            Err(_) => false,
+        }
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(buf)
+}
+
+fn read_u128(bytes: &[u8], offset: usize) -> u128 {
+    let mut buf = [0u8; 16];
+    buf.copy_from_slice(&bytes[offset..offset + 16]);
+    u128::from_le_bytes(buf)
+}
+
+fn decode_ipv4(record: &[u8]) -> (u32, u32, u32) {
+    (
+        read_u32(record, 0),
+        read_u32(record, 4),
+        read_u32(record, 8),
+    )
+}
+
+fn decode_ipv6(record: &[u8]) -> (u128, u128, u32) {
+    (
+        read_u128(record, 0),
+        read_u128(record, 16),
+        read_u32(record, 32),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(s: &str) -> u32 {
+        u32::from(s.parse::<Ipv4Addr>().unwrap())
+    }
+
+    fn v6(s: &str) -> u128 {
+        u128::from(s.parse::<Ipv6Addr>().unwrap())
+    }
+
+    fn test_database() -> IpDatabase {
+        let mut ip_db = IpDatabase::new();
+        for (from, to, country_code) in [
+            ("0.0.0.0", "0.0.0.255", "JP"),
+            ("1.0.0.0", "1.0.0.255", "AU"),
+            ("1.0.1.0", "1.0.3.255", "CN"),
+            ("8.8.8.0", "8.8.8.255", "US"),
+            ("10.0.0.0", "10.255.255.255", "ZZ"),
+            ("255.255.255.0", "255.255.255.255", "DE"),
+        ] {
+            ip_db
+                .ipv4_country_map
+                .insert(v4(from)..=v4(to), country_code.to_string());
+        }
+        for (from, to, asn) in [
+            ("1.0.0.0", "1.0.0.255", 13335),
+            ("1.0.2.0", "1.0.2.255", 4134),
+            ("8.8.8.0", "8.8.8.127", 15169),
+            ("9.9.9.0", "9.9.9.255", 19281),
+            ("255.255.255.128", "255.255.255.255", 64512),
+        ] {
+            ip_db.ipv4_asn_map.insert(v4(from)..=v4(to), asn);
+        }
+        for (from, to, country_code) in [
+            ("::", "::ff", "NL"),
+            ("2001:db8::", "2001:db8:ffff:ffff:ffff:ffff:ffff:ffff", "US"),
+            ("2400::", "240f:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "JP"),
+            ("ffff::", "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff", "SE"),
+        ] {
+            ip_db
+                .ipv6_country_map
+                .insert(v6(from)..=v6(to), country_code.to_string());
+        }
+        for (from, to, asn) in [
+            ("2001:db8::", "2001:db8:0:ffff:ffff:ffff:ffff:ffff", 64500),
+            (
+                "2404:6800::",
+                "2404:6800:ffff:ffff:ffff:ffff:ffff:ffff",
+                15169,
+            ),
+            (
+                "ffff:ffff::",
+                "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+                64512,
+            ),
+        ] {
+            ip_db.ipv6_asn_map.insert(v6(from)..=v6(to), asn);
+        }
+        // DE, SE and AS64512 have no name
+        for (country_code, country_name) in [
+            ("JP", "Japan"),
+            ("AU", "Australia"),
+            ("CN", "China"),
+            ("US", "United States"),
+            ("NL", "Netherlands"),
+        ] {
+            ip_db
+                .country_map
+                .insert(country_code.to_string(), country_name.to_string());
+        }
+        for (asn, as_name) in [
+            (13335, "CLOUDFLARENET"),
+            (4134, "CHINANET-BACKBONE"),
+            (15169, "GOOGLE"),
+            (19281, "QUAD9-AS-1"),
+            (64500, "DOC-AS"),
+        ] {
+            ip_db.autonomous_map.insert(asn, as_name.to_string());
+        }
+        ip_db
+    }
+
+    // Start, end and both neighbours of every range, so edges, gaps and the first and last ranges are covered
+    fn probes<K: Copy + Ord>(
+        ranges: Vec<(K, K)>,
+        prev: fn(K) -> Option<K>,
+        next: fn(K) -> Option<K>,
+    ) -> BTreeSet<K> {
+        let mut probes: BTreeSet<K> = BTreeSet::new();
+        for (start, end) in ranges {
+            probes.extend(
+                [Some(start), Some(end), prev(start), next(end)]
+                    .into_iter()
+                    .flatten(),
+            );
+        }
+        probes
+    }
+
+    fn to_json<T: serde::Serialize>(value: &T) -> String {
+        serde_json::to_string(value).unwrap()
+    }
+
+    #[test]
+    fn compact_lookups_match_ip_database() {
+        let ip_db = test_database();
+        let compact_db =
+            CompactIpDatabase::from_bytes(CompactIpDatabase::to_bytes(&ip_db)).unwrap();
+
+        let ipv4_ranges: Vec<(u32, u32)> = ip_db
+            .ipv4_country_map
+            .iter()
+            .map(|(range, _)| (*range.start(), *range.end()))
+            .chain(
+                ip_db
+                    .ipv4_asn_map
+                    .iter()
+                    .map(|(range, _)| (*range.start(), *range.end())),
+            )
+            .collect();
+        let ipv4_probes = probes(ipv4_ranges, |k| k.checked_sub(1), |k| k.checked_add(1));
+        assert!(ipv4_probes.contains(&0) && ipv4_probes.contains(&u32::MAX));
+        let mut found = 0;
+        for ip in ipv4_probes {
+            let ipv4_addr = Ipv4Addr::from(ip);
+            let expected = ip_db.get_ipv4_info(ipv4_addr);
+            found += expected.is_some() as usize;
+            assert_eq!(
+                to_json(&compact_db.get_ipv4_info(ipv4_addr)),
+                to_json(&expected),
+                "{}",
+                ipv4_addr
+            );
+        }
+        assert!(found > 10);
+
+        let ipv6_ranges: Vec<(u128, u128)> = ip_db
+            .ipv6_country_map
+            .iter()
+            .map(|(range, _)| (*range.start(), *range.end()))
+            .chain(
+                ip_db
+                    .ipv6_asn_map
+                    .iter()
+                    .map(|(range, _)| (*range.start(), *range.end())),
+            )
+            .collect();
+        let ipv6_probes = probes(ipv6_ranges, |k| k.checked_sub(1), |k| k.checked_add(1));
+        assert!(ipv6_probes.contains(&0) && ipv6_probes.contains(&u128::MAX));
+        for ip in ipv6_probes {
+            let ipv6_addr = Ipv6Addr::from(ip);
+            assert_eq!(
+                to_json(&compact_db.get_ipv6_info(ipv6_addr)),
+                to_json(&ip_db.get_ipv6_info(ipv6_addr)),
+                "{}",
+                ipv6_addr
+            );
+        }
+    }
+
+    #[test]
+    fn compact_blocks_match_ip_database() {
+        let ip_db = test_database();
+        let compact_db =
+            CompactIpDatabase::from_bytes(CompactIpDatabase::to_bytes(&ip_db)).unwrap();
+        for (from, to) in [
+            ("0.0.0.0", "255.255.255.255"),
+            ("1.0.0.128", "1.0.2.127"),
+            ("8.8.8.100", "9.9.9.9"),
+            ("11.0.0.0", "12.0.0.0"),
+        ] {
+            let (from, to) = (from.parse().unwrap(), to.parse().unwrap());
+            assert_eq!(
+                to_json(&compact_db.get_ipv4_blocks(from, to)),
+                to_json(&ip_db.get_ipv4_blocks(from, to)),
+                "{} - {}",
+                from,
+                to
+            );
+        }
+        for (from, to) in [
+            ("::", "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff"),
+            ("2001:db8::", "2001:db8:1::"),
+        ] {
+            let (from, to) = (from.parse().unwrap(), to.parse().unwrap());
+            assert_eq!(
+                to_json(&compact_db.get_ipv6_blocks(from, to)),
+                to_json(&ip_db.get_ipv6_blocks(from, to)),
+                "{} - {}",
+                from,
+                to
+            );
+        }
+    }
+}
@@ -255,6 +255,165 @@ impl IpDatabase {
             Some(ip_info)
         }
     }
+    /// Split the IPv4 range into the distinct AS/country blocks it overlaps.
+    pub fn get_ipv4_blocks(&self, ip_from: Ipv4Addr, ip_to: Ipv4Addr) -> Vec<IpBlock> {
+        let range = u32::from(ip_from)..=u32::from(ip_to);
+        let asn_ranges: Vec<(u32, u32, u32)> = self
+            .ipv4_asn_map
+            .overlapping(&range)
+            .map(|(r, asn)| (*r.start(), *r.end(), *asn))
+            .collect();
+        let country_ranges: Vec<(u32, u32, String)> = self
+            .ipv4_country_map
+            .overlapping(&range)
+            .map(|(r, country_code)| (*r.start(), *r.end(), country_code.clone()))
+            .collect();
+        let segments = split_ranges(
+            *range.start(),
+            *range.end(),
+            &asn_ranges,
+            &country_ranges,
+            |n| n.checked_add(1),
+            |n| n - 1,
+        );
+        segments
+            .into_iter()
+            .map(|(start, end, asn, country_code)| {
+                self.new_ip_block(
+                    IpAddr::V4(Ipv4Addr::from(start)),
+                    IpAddr::V4(Ipv4Addr::from(end)),
+                    asn,
+                    country_code,
+                )
+            })
+            .collect()
+    }
+    /// Split the IPv6 range into the distinct AS/country blocks it overlaps.
+    pub fn get_ipv6_blocks(&self, ip_from: Ipv6Addr, ip_to: Ipv6Addr) -> Vec<IpBlock> {
+        let range = u128::from(ip_from)..=u128::from(ip_to);
+        let asn_ranges: Vec<(u128, u128, u32)> = self
+            .ipv6_asn_map
+            .overlapping(&range)
+            .map(|(r, asn)| (*r.start(), *r.end(), *asn))
+            .collect();
+        let country_ranges: Vec<(u128, u128, String)> = self
+            .ipv6_country_map
+            .overlapping(&range)
+            .map(|(r, country_code)| (*r.start(), *r.end(), country_code.clone()))
+            .collect();
+        let segments = split_ranges(
+            *range.start(),
+            *range.end(),
+            &asn_ranges,
+            &country_ranges,
+            |n| n.checked_add(1),
+            |n| n - 1,
+        );
+        segments
+            .into_iter()
+            .map(|(start, end, asn, country_code)| {
+                self.new_ip_block(
+                    IpAddr::V6(Ipv6Addr::from(start)),
+                    IpAddr::V6(Ipv6Addr::from(end)),
+                    asn,
+                    country_code,
+                )
+            })
+            .collect()
+    }
+    fn new_ip_block(
+        &self,
+        ip_from: IpAddr,
+        ip_to: IpAddr,
+        asn: Option<u32>,
+        country_code: Option<String>,
+    ) -> IpBlock {
+        let asn = asn.unwrap_or(0);
+        let country_code = country_code.unwrap_or_default();
+        IpBlock {
+            ip_from,
+            ip_to,
+            asn,
+            as_name: self.autonomous_map.get(&asn).cloned().unwrap_or_default(),
+            country_name: self
+                .country_map
+                .get(&country_code)
+                .cloned()
+                .unwrap_or_default(),
+            country_code,
+        }
+    }
+}
+
+/// Segments (start, end, asn, country_code) of [ip_from, ip_to] with a constant ASN and country.
+/// The ranges must be sorted and not overlap each other. Adjacent segments with the same values are merged.
+pub fn split_ranges<K: Ord + Copy, C: Clone + PartialEq>(
+    ip_from: K,
+    ip_to: K,
+    asn_ranges: &[(K, K, u32)],
+    country_ranges: &[(K, K, C)],
+    next: fn(K) -> Option<K>,
+    prev: fn(K) -> K,
+) -> Vec<(K, K, Option<u32>, Option<C>)> {
+    // Start points of the segments
+    let mut starts: Vec<K> = vec![ip_from];
+    for (start, end, _) in asn_ranges {
+        starts.push(*start);
+        if let Some(n) = next(*end) {
+            starts.push(n);
+        }
+    }
+    for (start, end, _) in country_ranges {
+        starts.push(*start);
+        if let Some(n) = next(*end) {
+            starts.push(n);
+        }
+    }
+    starts.retain(|start| *start >= ip_from && *start <= ip_to);
+    starts.sort();
+    starts.dedup();
+    let mut segments: Vec<(K, K, Option<u32>, Option<C>)> = Vec::new();
+    for (i, start) in starts.iter().enumerate() {
+        let end = match starts.get(i + 1) {
+            Some(next_start) => prev(*next_start),
+            None => ip_to,
+        };
+        let asn = range_value(asn_ranges, *start);
+        let country_code = range_value(country_ranges, *start);
+        if let Some(last) = segments.last_mut() {
+            if last.2 == asn && last.3 == country_code {
+                last.1 = end;
+                continue;
+            }
+        }
+        segments.push((*start, end, asn, country_code));
+    }
+    segments
+}
+
+/// Value of the range containing the key.
+fn range_value<K: Ord + Copy, V: Clone>(ranges: &[(K, K, V)], key: K) -> Option<V> {
+    let i = ranges.partition_point(|range| range.0 <= key);
+    if i == 0 {
+        return None;
+    }
+    let range = &ranges[i - 1];
+    if key <= range.1 {
+        Some(range.2.clone())
+    } else {
+        None
+    }
+}
+
+/// IP range with the same ASN and country.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IpBlock {
+    pub ip_from: IpAddr,
+    pub ip_to: IpAddr,
+    pub asn: u32,
+    pub as_name: String,
+    pub country_code: String,
+    pub country_name: String,
 }
 
 #[derive(Serialize, Deserialize)]
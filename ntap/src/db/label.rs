@@ -0,0 +1,164 @@
+use crate::config::LabelConfig;
+use crate::thread_log;
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// User-defined labels for IP networks. The longest matching prefix wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelDatabase {
+    /// (prefix length, network address -> label), longest prefix first
+    pub ipv4_maps: Vec<(u8, HashMap<u32, String>)>,
+    pub ipv6_maps: Vec<(u8, HashMap<u128, String>)>,
+}
+
+impl LabelDatabase {
+    pub fn new() -> LabelDatabase {
+        LabelDatabase {
+            ipv4_maps: Vec::new(),
+            ipv6_maps: Vec::new(),
+        }
+    }
+    /// Build from the networks in the config and the labels file.
+    /// Invalid entries are logged and skipped.
+    pub fn from_config(config: &LabelConfig) -> LabelDatabase {
+        let mut label_db = LabelDatabase::new();
+        if let Some(file_path) = &config.file_path {
+            match std::fs::read_to_string(Path::new(file_path)) {
+                Ok(content) => {
+                    for (i, line) in content.lines().enumerate() {
+                        let line = line.trim();
+                        if line.is_empty() || line.starts_with('#') {
+                            continue;
+                        }
+                        match parse_label_line(line) {
+                            Some((network, label)) => {
+                                label_db.add(network, label);
+                            }
+                            None => {
+                                thread_log!(
+                                    error,
+                                    "{}:{}: invalid label entry: {}",
+                                    file_path,
+                                    i + 1,
+                                    line
+                                );
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    thread_log!(error, "labels file {} error: {:?}", file_path, e);
+                }
+            }
+        }
+        // The config entries take precedence over the file
+        for (network, label) in &config.networks {
+            match parse_network(network) {
+                Some(network) => {
+                    label_db.add(network, label.clone());
+                }
+                None => {
+                    thread_log!(error, "invalid label network: {}", network);
+                }
+            }
+        }
+        label_db
+    }
+    pub fn add(&mut self, network: IpNet, label: String) {
+        match network {
+            IpNet::V4(network) => {
+                let prefix_len = network.prefix_len();
+                let key = u32::from(network.network());
+                match self
+                    .ipv4_maps
+                    .iter_mut()
+                    .find(|(len, _)| *len == prefix_len)
+                {
+                    Some((_, map)) => {
+                        map.insert(key, label);
+                    }
+                    None => {
+                        self.ipv4_maps
+                            .push((prefix_len, HashMap::from([(key, label)])));
+                        self.ipv4_maps
+                            .sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+                    }
+                }
+            }
+            IpNet::V6(network) => {
+                let prefix_len = network.prefix_len();
+                let key = u128::from(network.network());
+                match self
+                    .ipv6_maps
+                    .iter_mut()
+                    .find(|(len, _)| *len == prefix_len)
+                {
+                    Some((_, map)) => {
+                        map.insert(key, label);
+                    }
+                    None => {
+                        self.ipv6_maps
+                            .push((prefix_len, HashMap::from([(key, label)])));
+                        self.ipv6_maps
+                            .sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+                    }
+                }
+            }
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ipv4_maps.is_empty() && self.ipv6_maps.is_empty()
+    }
+    pub fn get_label(&self, ip_addr: &IpAddr) -> Option<String> {
+        match ip_addr {
+            IpAddr::V4(ipv4_addr) => {
+                let value = u32::from(*ipv4_addr);
+                for (prefix_len, map) in &self.ipv4_maps {
+                    let mask = u32::MAX.checked_shl(32 - *prefix_len as u32).unwrap_or(0);
+                    if let Some(label) = map.get(&(value & mask)) {
+                        return Some(label.clone());
+                    }
+                }
+                None
+            }
+            IpAddr::V6(ipv6_addr) => {
+                let value = u128::from(*ipv6_addr);
+                for (prefix_len, map) in &self.ipv6_maps {
+                    let mask = u128::MAX.checked_shl(128 - *prefix_len as u32).unwrap_or(0);
+                    if let Some(label) = map.get(&(value & mask)) {
+                        return Some(label.clone());
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// CIDR or a single IP address (host route).
+fn parse_network(s: &str) -> Option<IpNet> {
+    let s = s.trim();
+    match s.parse::<IpNet>() {
+        Ok(network) => Some(network.trunc()),
+        Err(_) => match s.parse::<IpAddr>() {
+            Ok(ip_addr) => Some(IpNet::from(ip_addr)),
+            Err(_) => None,
+        },
+    }
+}
+
+/// `10.20.0.0/16 = "k8s-prod"` or `192.0.2.10 db-primary`
+fn parse_label_line(line: &str) -> Option<(IpNet, String)> {
+    let (network, label) = match line.split_once('=') {
+        Some((network, label)) => (network, label),
+        None => line.split_once(char::is_whitespace)?,
+    };
+    let network = parse_network(network)?;
+    let label = label.trim().trim_matches('"').trim();
+    if label.is_empty() {
+        return None;
+    }
+    Some((network, label.to_string()))
+}
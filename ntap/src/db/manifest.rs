@@ -0,0 +1,166 @@
+use crate::thread_log;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const DB_MANIFEST_FILE_NAME: &str = "manifest.json";
+/// Default update source. The *_R2_URL constants of the ntap-db crates point to this server.
+pub const DEFAULT_DB_MIRROR: &str = "https://r2.ntap.io";
+
+/// Database files that can be listed in a manifest.
+pub const DB_FILE_NAMES: [&str; 9] = [
+    ntap_db_as::AS_BIN_NAME,
+    ntap_db_country::COUNTRY_BIN_NAME,
+    ntap_db_ipv4_asn::IPV4_ASN_BIN_NAME,
+    ntap_db_ipv4_country::IPV4_COUNTRY_BIN_NAME,
+    ntap_db_ipv6_asn::IPV6_ASN_BIN_NAME,
+    ntap_db_ipv6_country::IPV6_COUNTRY_BIN_NAME,
+    ntap_db_oui::OUI_BIN_NAME,
+    ntap_db_tcp_service::TCP_SERVICE_BIN_NAME,
+    ntap_db_udp_service::UDP_SERVICE_BIN_NAME,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DbFileEntry {
+    /// File name in the database directory. Example: ipv4-asn.bin
+    pub name: String,
+    /// Dataset version. Example: 2024-06-01
+    pub version: String,
+    /// Lowercase hex SHA-256 of the file
+    pub sha256: String,
+    /// File size in bytes
+    pub size: u64,
+}
+
+/// List of database files with their versions and hashes.
+/// The update source serves it as manifest.json next to the files,
+/// and the installed copy is kept in the database directory.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DbManifest {
+    /// When the manifest was generated (RFC 3339)
+    #[serde(default)]
+    pub generated_at: String,
+    pub files: Vec<DbFileEntry>,
+}
+
+impl DbManifest {
+    pub fn new() -> DbManifest {
+        DbManifest {
+            generated_at: String::new(),
+            files: Vec::new(),
+        }
+    }
+    /// Parse and validate a manifest.
+    pub fn from_json(json: &str) -> Result<DbManifest, Box<dyn Error>> {
+        let manifest: DbManifest = serde_json::from_str(json)?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+    /// Load the installed manifest. Returns None if there is none.
+    pub fn load_installed(db_dir: &Path) -> Option<DbManifest> {
+        let path = db_dir.join(DB_MANIFEST_FILE_NAME);
+        match std::fs::read_to_string(path) {
+            Ok(json) => match DbManifest::from_json(&json) {
+                Ok(manifest) => Some(manifest),
+                Err(e) => {
+                    thread_log!(error, "manifest error: {:?}", e);
+                    None
+                }
+            },
+            Err(_) => None,
+        }
+    }
+    /// Write the manifest to the directory through a temporary file.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let path = dir.join(DB_MANIFEST_FILE_NAME);
+        let tmp_path = dir.join(format!("{}.tmp", DB_MANIFEST_FILE_NAME));
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(path)
+    }
+    pub fn get(&self, name: &str) -> Option<&DbFileEntry> {
+        self.files.iter().find(|entry| entry.name == name)
+    }
+    /// Only known file names are allowed so that a manifest cannot write outside the database directory.
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for entry in &self.files {
+            if !DB_FILE_NAMES.contains(&entry.name.as_str()) {
+                return Err(format!("Unknown database file in manifest: {}", entry.name).into());
+            }
+            if entry.sha256.len() != 64 || !entry.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("Invalid SHA-256 for {}: {}", entry.name, entry.sha256).into());
+            }
+        }
+        Ok(())
+    }
+    /// Build a manifest from the database files in the directory.
+    pub fn from_dir(dir: &Path, version: &str) -> Result<DbManifest, Box<dyn Error>> {
+        let mut manifest = DbManifest::new();
+        manifest.generated_at = chrono::Local::now().to_rfc3339();
+        for name in DB_FILE_NAMES {
+            let path = dir.join(name);
+            if !path.exists() {
+                continue;
+            }
+            manifest.files.push(DbFileEntry {
+                name: name.to_string(),
+                version: version.to_string(),
+                sha256: sha256_file(&path)?,
+                size: std::fs::metadata(&path)?.len(),
+            });
+        }
+        Ok(manifest)
+    }
+}
+
+/// Where database updates are fetched from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbSource {
+    /// Base URL. Files are fetched from <base>/<name>
+    Http(String),
+    /// Local directory
+    Dir(PathBuf),
+}
+
+impl DbSource {
+    /// http(s):// is a mirror URL, file:// or anything else is a local directory.
+    pub fn from_str(s: &str) -> DbSource {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            DbSource::Http(s.trim_end_matches('/').to_string())
+        } else {
+            DbSource::Dir(PathBuf::from(s.strip_prefix("file://").unwrap_or(s)))
+        }
+    }
+    pub fn default() -> DbSource {
+        DbSource::Http(DEFAULT_DB_MIRROR.to_string())
+    }
+    /// URL or path of a file in the source.
+    pub fn location(&self, name: &str) -> String {
+        match self {
+            DbSource::Http(base_url) => format!("{}/{}", base_url, name),
+            DbSource::Dir(dir) => dir.join(name).display().to_string(),
+        }
+    }
+}
+
+/// Lowercase hex SHA-256 of the file.
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buf[..n]);
+    }
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
@@ -1,4 +1,10 @@
 #![allow(unused)]
 
+pub mod build;
+pub mod compact;
 pub mod ip;
+pub mod label;
+pub mod manifest;
+pub mod oui;
 pub mod service;
+pub mod traffic;
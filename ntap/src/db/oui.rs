@@ -0,0 +1,113 @@
+use netdev::mac::MacAddr;
+use std::collections::HashMap;
+
+/// In-memory OUI (MAC vendor) database with hash maps.
+/// IEEE MA-L blocks are keyed by the first 24 bits, MA-M by 28 bits and MA-S by 36 bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OuiDatabase {
+    pub ma_l_map: HashMap<u64, String>,
+    pub ma_m_map: HashMap<u64, String>,
+    pub ma_s_map: HashMap<u64, String>,
+}
+
+impl OuiDatabase {
+    pub fn new() -> OuiDatabase {
+        OuiDatabase {
+            ma_l_map: HashMap::new(),
+            ma_m_map: HashMap::new(),
+            ma_s_map: HashMap::new(),
+        }
+    }
+    #[cfg(feature = "bundle")]
+    pub fn load() -> Result<OuiDatabase, Box<dyn std::error::Error>> {
+        Ok(OuiDatabase::from_map(ntap_db_oui::get_map()))
+    }
+    #[cfg(not(feature = "bundle"))]
+    pub fn load() -> Result<OuiDatabase, Box<dyn std::error::Error>> {
+        match crate::sys::get_db_file_path(ntap_db_oui::OUI_BIN_NAME) {
+            Some(file_path) => {
+                if !file_path.exists() {
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("{} not found", ntap_db_oui::OUI_BIN_NAME),
+                    )));
+                }
+                Ok(OuiDatabase::from_map(ntap_db_oui::get_map_from_file(
+                    file_path,
+                )))
+            }
+            None => Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Could not get database directory path",
+            ))),
+        }
+    }
+    /// Build from the (mac_prefix -> vendor_name) map.
+    /// The prefix is "00:1B:C5" for MA-L, or "00:1B:C5:00:10:00/36" for MA-M (/28) and MA-S (/36).
+    pub fn from_map(oui_map: HashMap<String, String>) -> OuiDatabase {
+        let mut oui_db = OuiDatabase::new();
+        for (mac_prefix, vendor_name) in oui_map {
+            let (prefix, prefix_len) = match mac_prefix.split_once('/') {
+                Some((prefix, prefix_len)) => match prefix_len.parse::<u32>() {
+                    Ok(prefix_len) => (prefix, prefix_len),
+                    Err(_) => continue,
+                },
+                None => (mac_prefix.as_str(), 24),
+            };
+            let value = match parse_mac_prefix(prefix) {
+                Some(value) => value,
+                None => continue,
+            };
+            match prefix_len {
+                24 => {
+                    oui_db.ma_l_map.insert(value >> 24, vendor_name);
+                }
+                28 => {
+                    oui_db.ma_m_map.insert(value >> 20, vendor_name);
+                }
+                36 => {
+                    oui_db.ma_s_map.insert(value >> 12, vendor_name);
+                }
+                _ => {}
+            }
+        }
+        oui_db
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ma_l_map.is_empty() && self.ma_m_map.is_empty() && self.ma_s_map.is_empty()
+    }
+    /// Get the vendor name of the MAC address. The longest matching block wins.
+    pub fn get_vendor_name(&self, mac_addr: &MacAddr) -> Option<String> {
+        let octets = mac_addr.octets();
+        let value = octets
+            .iter()
+            .fold(0u64, |value, octet| (value << 8) | *octet as u64);
+        if let Some(vendor_name) = self.ma_s_map.get(&(value >> 12)) {
+            return Some(vendor_name.clone());
+        }
+        if let Some(vendor_name) = self.ma_m_map.get(&(value >> 20)) {
+            return Some(vendor_name.clone());
+        }
+        self.ma_l_map.get(&(value >> 24)).cloned()
+    }
+    pub fn get_vendor_name_from_str(&self, mac_addr: &str) -> Option<String> {
+        match mac_addr.parse::<MacAddr>() {
+            Ok(mac_addr) => self.get_vendor_name(&mac_addr),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Parse "00:1B:C5" or "00:1B:C5:00:10:00" into a 48-bit value.
+fn parse_mac_prefix(prefix: &str) -> Option<u64> {
+    let mut value: u64 = 0;
+    let mut count = 0;
+    for part in prefix.split([':', '-']) {
+        value = (value << 8) | u8::from_str_radix(part, 16).ok()? as u64;
+        count += 1;
+    }
+    if count != 3 && count != 6 {
+        return None;
+    }
+    Some(value << ((6 - count) * 8))
+}
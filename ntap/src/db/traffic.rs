@@ -0,0 +1,861 @@
+use crate::net::socket::{LocalSocket, SocketProcess};
+use crate::net::stat::{NetStatData, NetStatStrage};
+use crate::net::traffic::TrafficInfo;
+use crate::sys;
+use crate::thread_log;
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike};
+use redb::{Database, DatabaseError, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const TRAFFIC_DB_FILE_NAME: &str = "traffic.redb";
+/// Interval between writes of the pending totals to the database.
+pub const TRAFFIC_DB_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+/// Maximum number of local sockets kept for process attribution.
+const LOCAL_SOCKET_MAP_CAPACITY: usize = 65536;
+const UNKNOWN_NAME: &str = "unknown";
+
+// Key: (bucket start unix time, dimension, name)
+// Value: (bytes_received, bytes_sent, packet_received, packet_sent)
+type TotalsTable = TableDefinition<'static, (i64, u8, &'static str), (u64, u64, u64, u64)>;
+const HOURLY_TABLE: TotalsTable = TableDefinition::new("hourly");
+const DAILY_TABLE: TotalsTable = TableDefinition::new("daily");
+const MONTHLY_TABLE: TotalsTable = TableDefinition::new("monthly");
+// Display names. (dimension, name) -> display name. e.g. (Asn, "13335") -> "CLOUDFLARENET"
+const NAMES_TABLE: TableDefinition<(u8, &str), &str> = TableDefinition::new("names");
+
+// Serialize access from this process. The file itself is locked by redb.
+static TRAFFIC_DB_LOCK: Mutex<()> = Mutex::new(());
+
+/// (hour start, dimension, name) -> total
+pub type TrafficTotals = HashMap<(i64, Dimension, String), TrafficTotal>;
+/// (dimension, name) -> display name
+pub type DisplayNames = HashMap<(Dimension, String), String>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Granularity {
+    Hour,
+    Day,
+    Month,
+}
+
+impl Granularity {
+    pub fn from_str(s: &str) -> Option<Granularity> {
+        match s {
+            "hour" | "hourly" => Some(Granularity::Hour),
+            "day" | "daily" => Some(Granularity::Day),
+            "month" | "monthly" => Some(Granularity::Month),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            Granularity::Hour => "hourly",
+            Granularity::Day => "daily",
+            Granularity::Month => "monthly",
+        }
+    }
+    /// Pick a granularity that gives a readable number of rows for the period.
+    pub fn for_period(period: Duration) -> Granularity {
+        if period <= Duration::from_secs(2 * 24 * 3600) {
+            Granularity::Hour
+        } else if period <= Duration::from_secs(62 * 24 * 3600) {
+            Granularity::Day
+        } else {
+            Granularity::Month
+        }
+    }
+    fn table(&self) -> TotalsTable {
+        match self {
+            Granularity::Hour => HOURLY_TABLE,
+            Granularity::Day => DAILY_TABLE,
+            Granularity::Month => MONTHLY_TABLE,
+        }
+    }
+    /// Start of the bucket containing the unix time, in local time.
+    pub fn bucket_start(&self, timestamp: i64) -> i64 {
+        let datetime: DateTime<Local> = match Local.timestamp_opt(timestamp, 0).earliest() {
+            Some(datetime) => datetime,
+            None => return timestamp - timestamp.rem_euclid(3600),
+        };
+        let date: NaiveDate = match self {
+            // Not through the local hour, which is repeated when the clock goes back
+            Granularity::Hour => {
+                return timestamp - (datetime.minute() * 60 + datetime.second()) as i64;
+            }
+            Granularity::Day => datetime.date_naive(),
+            Granularity::Month => {
+                NaiveDate::from_ymd_opt(datetime.year(), datetime.month(), 1).unwrap_or_default()
+            }
+        };
+        match date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        {
+            Some(start) => start.timestamp(),
+            None => timestamp - timestamp.rem_euclid(3600),
+        }
+    }
+    pub fn format_bucket(&self, bucket_start: i64) -> String {
+        let datetime: DateTime<Local> = match Local.timestamp_opt(bucket_start, 0).earliest() {
+            Some(datetime) => datetime,
+            None => return bucket_start.to_string(),
+        };
+        match self {
+            Granularity::Hour => datetime.format("%Y-%m-%d %H:00").to_string(),
+            Granularity::Day => datetime.format("%Y-%m-%d").to_string(),
+            Granularity::Month => datetime.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Dimension {
+    /// All captured traffic. The name is empty.
+    Total,
+    Interface,
+    Process,
+    Asn,
+    Country,
+    /// User-defined network label. Only labeled hosts are counted.
+    Label,
+}
+
+impl Dimension {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Dimension::Total => 0,
+            Dimension::Interface => 1,
+            Dimension::Process => 2,
+            Dimension::Asn => 3,
+            Dimension::Country => 4,
+            Dimension::Label => 5,
+        }
+    }
+    pub fn from_u8(n: u8) -> Option<Dimension> {
+        match n {
+            0 => Some(Dimension::Total),
+            1 => Some(Dimension::Interface),
+            2 => Some(Dimension::Process),
+            3 => Some(Dimension::Asn),
+            4 => Some(Dimension::Country),
+            5 => Some(Dimension::Label),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            Dimension::Total => "Total",
+            Dimension::Interface => "Interface",
+            Dimension::Process => "Process",
+            Dimension::Asn => "ASN",
+            Dimension::Country => "Country",
+            Dimension::Label => "Label",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrafficTotal {
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub packet_received: u64,
+    pub packet_sent: u64,
+}
+
+impl TrafficTotal {
+    pub fn add_traffic(&mut self, traffic: &TrafficInfo) {
+        self.bytes_received += traffic.bytes_received as u64;
+        self.bytes_sent += traffic.bytes_sent as u64;
+        self.packet_received += traffic.packet_received as u64;
+        self.packet_sent += traffic.packet_sent as u64;
+    }
+    pub fn add_total(&mut self, other: &TrafficTotal) {
+        self.bytes_received += other.bytes_received;
+        self.bytes_sent += other.bytes_sent;
+        self.packet_received += other.packet_received;
+        self.packet_sent += other.packet_sent;
+    }
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes_received + self.bytes_sent
+    }
+    fn to_tuple(&self) -> (u64, u64, u64, u64) {
+        (
+            self.bytes_received,
+            self.bytes_sent,
+            self.packet_received,
+            self.packet_sent,
+        )
+    }
+    fn from_tuple(value: (u64, u64, u64, u64)) -> TrafficTotal {
+        TrafficTotal {
+            bytes_received: value.0,
+            bytes_sent: value.1,
+            packet_received: value.2,
+            packet_sent: value.3,
+        }
+    }
+}
+
+/// A stored total.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrafficRecord {
+    pub bucket_start: i64,
+    pub dimension: Dimension,
+    pub name: String,
+    pub display_name: String,
+    pub total: TrafficTotal,
+}
+
+/// Totals of the current hour not yet written to the database.
+#[derive(Debug, Clone)]
+pub struct TrafficAccounting {
+    pub enabled: bool,
+    pub totals: TrafficTotals,
+    pub names: DisplayNames,
+    /// Accumulated local sockets for process attribution
+    pub local_socket_map: HashMap<LocalSocket, SocketProcess>,
+}
+
+impl TrafficAccounting {
+    pub fn new() -> Self {
+        TrafficAccounting {
+            enabled: false,
+            totals: HashMap::new(),
+            names: HashMap::new(),
+            local_socket_map: HashMap::new(),
+        }
+    }
+    /// Add the traffic of one tick (the data returned by clone_data_and_reset).
+    pub fn add(&mut self, netstat_data: &NetStatData) {
+        if !self.enabled {
+            return;
+        }
+        let hour_start = Granularity::Hour.bucket_start(Local::now().timestamp());
+        if self.local_socket_map.len() > LOCAL_SOCKET_MAP_CAPACITY {
+            self.local_socket_map.clear();
+        }
+        for (local_socket, socket_process) in &netstat_data.local_socket_map {
+            self.local_socket_map
+                .insert(local_socket.clone(), socket_process.clone());
+        }
+        self.add_traffic(
+            hour_start,
+            Dimension::Total,
+            String::new(),
+            &netstat_data.traffic,
+        );
+        for host in netstat_data.remote_hosts.values() {
+            let asn = if host.asn == 0 {
+                UNKNOWN_NAME.to_string()
+            } else {
+                host.asn.to_string()
+            };
+            if host.asn != 0 && !host.as_name.is_empty() {
+                self.names
+                    .insert((Dimension::Asn, asn.clone()), host.as_name.clone());
+            }
+            self.add_traffic(hour_start, Dimension::Asn, asn, &host.traffic_info);
+            let country_code = if host.country_code.is_empty() {
+                UNKNOWN_NAME.to_string()
+            } else {
+                host.country_code.clone()
+            };
+            if !host.country_code.is_empty() && !host.country_name.is_empty() {
+                self.names.insert(
+                    (Dimension::Country, country_code.clone()),
+                    host.country_name.clone(),
+                );
+            }
+            self.add_traffic(
+                hour_start,
+                Dimension::Country,
+                country_code,
+                &host.traffic_info,
+            );
+            if !host.label.is_empty() {
+                self.add_traffic(
+                    hour_start,
+                    Dimension::Label,
+                    host.label.clone(),
+                    &host.traffic_info,
+                );
+            }
+        }
+        for (interface_name, interface_traffic) in &netstat_data.interface_traffic {
+            self.add_traffic(
+                hour_start,
+                Dimension::Interface,
+                interface_name.clone(),
+                &interface_traffic.traffic,
+            );
+        }
+        for (conn, traffic) in &netstat_data.connection_map {
+            let process_name = match self.local_socket_map.get(&LocalSocket {
+                interface_name: conn.interface_name.clone(),
+                port: conn.local_port,
+                protocol: conn.protocol,
+            }) {
+                Some(socket_process) => match &socket_process.process {
+                    Some(process) => process.name.clone(),
+                    None => UNKNOWN_NAME.to_string(),
+                },
+                None => UNKNOWN_NAME.to_string(),
+            };
+            self.add_traffic(hour_start, Dimension::Process, process_name, traffic);
+        }
+    }
+    fn add_traffic(
+        &mut self,
+        hour_start: i64,
+        dimension: Dimension,
+        name: String,
+        traffic: &TrafficInfo,
+    ) {
+        if traffic.packet_sent == 0 && traffic.packet_received == 0 {
+            return;
+        }
+        self.totals
+            .entry((hour_start, dimension, name))
+            .or_default()
+            .add_traffic(traffic);
+    }
+    /// Take the pending totals and names, leaving them empty.
+    pub fn take(&mut self) -> (TrafficTotals, DisplayNames) {
+        (
+            std::mem::take(&mut self.totals),
+            std::mem::take(&mut self.names),
+        )
+    }
+    /// Put back totals that could not be written.
+    pub fn restore(&mut self, totals: TrafficTotals, names: DisplayNames) {
+        for (key, total) in totals {
+            self.totals.entry(key).or_default().add_total(&total);
+        }
+        for (key, name) in names {
+            self.names.entry(key).or_insert(name);
+        }
+    }
+}
+
+/// Persistent hourly/daily/monthly traffic totals.
+pub struct TrafficDatabase {
+    db: Database,
+}
+
+impl TrafficDatabase {
+    pub fn file_path() -> Option<PathBuf> {
+        sys::get_user_file_path(TRAFFIC_DB_FILE_NAME)
+    }
+    /// Open (or create) the database in the config directory.
+    pub fn open() -> Result<TrafficDatabase, Box<dyn Error>> {
+        match Self::file_path() {
+            Some(path) => Self::open_path(&path),
+            None => Err("Could not get config directory path".into()),
+        }
+    }
+    /// Open (or create) the database. Another ntap process may hold the file briefly, so retry.
+    pub fn open_path(path: &Path) -> Result<TrafficDatabase, Box<dyn Error>> {
+        let mut retry: usize = 0;
+        loop {
+            match Database::create(path) {
+                Ok(db) => return Ok(TrafficDatabase { db }),
+                Err(DatabaseError::DatabaseAlreadyOpen) if retry < 20 => {
+                    retry += 1;
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    /// Add hourly totals to the hourly, daily and monthly tables in one transaction.
+    pub fn add_totals(
+        &self,
+        totals: &TrafficTotals,
+        names: &DisplayNames,
+    ) -> Result<(), Box<dyn Error>> {
+        let write_txn = self.db.begin_write()?;
+        for granularity in [Granularity::Hour, Granularity::Day, Granularity::Month] {
+            let mut table = write_txn.open_table(granularity.table())?;
+            // Sum up the hours that fall into the same bucket first
+            let mut bucket_totals: HashMap<(i64, u8, &str), TrafficTotal> = HashMap::new();
+            for ((hour_start, dimension, name), total) in totals {
+                let bucket_start = match granularity {
+                    Granularity::Hour => *hour_start,
+                    _ => granularity.bucket_start(*hour_start),
+                };
+                bucket_totals
+                    .entry((bucket_start, dimension.to_u8(), name.as_str()))
+                    .or_default()
+                    .add_total(total);
+            }
+            for (key, total) in bucket_totals {
+                let mut stored = match table.get(key)? {
+                    Some(value) => TrafficTotal::from_tuple(value.value()),
+                    None => TrafficTotal::default(),
+                };
+                stored.add_total(&total);
+                table.insert(key, stored.to_tuple())?;
+            }
+        }
+        {
+            let mut table = write_txn.open_table(NAMES_TABLE)?;
+            for ((dimension, name), display_name) in names {
+                table.insert((dimension.to_u8(), name.as_str()), display_name.as_str())?;
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+    /// Remove buckets older than the retention. A retention of 0 keeps everything.
+    pub fn remove_old_buckets(
+        &self,
+        granularity: Granularity,
+        retention: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        if retention.is_zero() {
+            return Ok(());
+        }
+        let cutoff = Local::now().timestamp() - retention.as_secs() as i64;
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(granularity.table())?;
+            table.retain_in(..(cutoff, 0, ""), |_, _| false)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+    /// Get the stored totals of the buckets starting at or after since (unix time).
+    pub fn get_records(
+        &self,
+        granularity: Granularity,
+        since: i64,
+    ) -> Result<Vec<TrafficRecord>, Box<dyn Error>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(granularity.table()) {
+            Ok(table) => table,
+            // Nothing written yet
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let names = match read_txn.open_table(NAMES_TABLE) {
+            Ok(table) => Some(table),
+            Err(redb::TableError::TableDoesNotExist(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let mut records: Vec<TrafficRecord> = Vec::new();
+        for entry in table.range((since, 0, "")..)? {
+            let (key, value) = entry?;
+            let (bucket_start, dimension, name) = key.value();
+            let dimension = match Dimension::from_u8(dimension) {
+                Some(dimension) => dimension,
+                None => continue,
+            };
+            let display_name = match &names {
+                Some(names) => match names.get((dimension.to_u8(), name))? {
+                    Some(display_name) => display_name.value().to_string(),
+                    None => String::new(),
+                },
+                None => String::new(),
+            };
+            records.push(TrafficRecord {
+                bucket_start,
+                dimension,
+                name: name.to_string(),
+                display_name,
+                total: TrafficTotal::from_tuple(value.value()),
+            });
+        }
+        Ok(records)
+    }
+}
+
+/// Write the pending totals of the NetStatStrage to the database.
+pub fn flush_traffic_accounting(netstat_strage: &NetStatStrage) {
+    let (totals, names) = netstat_strage.take_accounting();
+    if totals.is_empty() {
+        return;
+    }
+    let _guard = match TRAFFIC_DB_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(e) => {
+            thread_log!(error, "flush_traffic_accounting lock error: {:?}", e);
+            netstat_strage.restore_accounting(totals, names);
+            return;
+        }
+    };
+    let result = TrafficDatabase::open().and_then(|db| db.add_totals(&totals, &names));
+    match result {
+        Ok(_) => {}
+        Err(e) => {
+            thread_log!(error, "flush_traffic_accounting error: {:?}", e);
+            netstat_strage.restore_accounting(totals, names);
+        }
+    }
+}
+
+/// Remove buckets beyond the configured retention.
+pub fn remove_old_traffic_records(hourly_retention: Duration, daily_retention: Duration) {
+    let _guard = match TRAFFIC_DB_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(e) => {
+            thread_log!(error, "remove_old_traffic_records lock error: {:?}", e);
+            return;
+        }
+    };
+    let result = TrafficDatabase::open().and_then(|db| {
+        db.remove_old_buckets(Granularity::Hour, hourly_retention)?;
+        db.remove_old_buckets(Granularity::Day, daily_retention)
+    });
+    if let Err(e) = result {
+        thread_log!(error, "remove_old_traffic_records error: {:?}", e);
+    }
+}
+
+/// Periodically write the accumulated totals to the traffic database.
+pub fn start_traffic_accounting(
+    netstat_strage: &mut Arc<NetStatStrage>,
+    hourly_retention: Duration,
+    daily_retention: Duration,
+) {
+    netstat_strage.enable_accounting();
+    remove_old_traffic_records(hourly_retention, daily_retention);
+    let mut flush_count: usize = 0;
+    loop {
+        thread::sleep(TRAFFIC_DB_FLUSH_INTERVAL);
+        flush_traffic_accounting(netstat_strage);
+        flush_count += 1;
+        // Once an hour
+        if flush_count.is_multiple_of(60) {
+            remove_old_traffic_records(hourly_retention, daily_retention);
+        }
+    }
+}
+
+/// Parse a period such as 24h, 7d, 2w, 3mo or 1y.
+pub fn parse_period(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in '{}'. Example: 24h, 7d, 2w, 3mo, 1y", s))?;
+    let (value, unit) = s.split_at(unit_start);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid number in '{}'", s))?;
+    let unit_secs: u64 = match unit {
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        "mo" => 30 * 24 * 3600,
+        "y" => 365 * 24 * 3600,
+        _ => {
+            return Err(format!(
+                "unknown unit '{}'. Use h (hours), d (days), w (weeks), mo (months) or y (years)",
+                unit
+            ))
+        }
+    };
+    match value.checked_mul(unit_secs) {
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Err(format!("period '{}' is too long", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::socket::{SocketConnection, TransportProtocol};
+    use crate::net::traffic::InterfaceTrafficInfo;
+    use chrono::Utc;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    // The time zone is process-wide. Tests that depend on it hold this lock.
+    static TIME_ZONE_LOCK: Mutex<()> = Mutex::new(());
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> i64 {
+        Utc.with_ymd_and_hms(year, month, day, hour, min, sec)
+            .unwrap()
+            .timestamp()
+    }
+
+    fn test_db(name: &str) -> (TrafficDatabase, PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("ntap-traffic-{}-{}.redb", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        (TrafficDatabase::open_path(&path).unwrap(), path)
+    }
+
+    fn traffic(bytes_received: usize, packet_received: usize) -> TrafficInfo {
+        TrafficInfo {
+            bytes_received,
+            packet_received,
+            ..TrafficInfo::new()
+        }
+    }
+
+    fn total(bytes_received: u64, packet_received: u64) -> TrafficTotal {
+        TrafficTotal {
+            bytes_received,
+            packet_received,
+            ..TrafficTotal::default()
+        }
+    }
+
+    fn get_total(
+        db: &TrafficDatabase,
+        granularity: Granularity,
+        bucket_start: i64,
+    ) -> Option<TrafficTotal> {
+        db.get_records(granularity, 0)
+            .unwrap()
+            .into_iter()
+            .find(|record| {
+                record.bucket_start == bucket_start && record.dimension == Dimension::Total
+            })
+            .map(|record| record.total)
+    }
+
+    #[test]
+    fn parse_periods() {
+        assert_eq!(parse_period("24h"), Ok(Duration::from_secs(24 * 3600)));
+        assert_eq!(parse_period(" 7d "), Ok(Duration::from_secs(7 * 24 * 3600)));
+        assert_eq!(parse_period("2w"), Ok(Duration::from_secs(14 * 24 * 3600)));
+        assert_eq!(parse_period("3mo"), Ok(Duration::from_secs(90 * 24 * 3600)));
+        assert_eq!(parse_period("1y"), Ok(Duration::from_secs(365 * 24 * 3600)));
+        assert_eq!(parse_period("0h"), Ok(Duration::ZERO));
+        assert!(parse_period("").unwrap_err().contains("missing unit"));
+        assert!(parse_period("7").unwrap_err().contains("missing unit"));
+        assert!(parse_period("d").unwrap_err().contains("invalid number"));
+        assert!(parse_period("-1d").unwrap_err().contains("invalid number"));
+        assert!(parse_period("7m").unwrap_err().contains("unknown unit"));
+        assert!(parse_period("99999999999999999999h")
+            .unwrap_err()
+            .contains("invalid number"));
+        assert!(parse_period("18446744073709551615y")
+            .unwrap_err()
+            .contains("too long"));
+    }
+
+    #[test]
+    fn bucket_start_in_local_time() {
+        let _guard = TIME_ZONE_LOCK.lock().unwrap();
+        let previous = std::env::var("TZ").ok();
+        // Central European Time. The clock goes forward on 2024-03-31 and back on 2024-10-27.
+        std::env::set_var("TZ", "CET-1CEST,M3.5.0,M10.5.0/3");
+        // A new thread, so the time zone is not taken from a cache
+        let result = thread::spawn(|| {
+            // 2024-03-31 23:59:59 CEST, the last second of March
+            let timestamp = utc(2024, 3, 31, 21, 59, 59);
+            assert_eq!(
+                Granularity::Hour.bucket_start(timestamp),
+                utc(2024, 3, 31, 21, 0, 0)
+            );
+            // The day is 23 hours long
+            assert_eq!(
+                Granularity::Day.bucket_start(timestamp),
+                utc(2024, 3, 30, 23, 0, 0)
+            );
+            assert_eq!(
+                Granularity::Month.bucket_start(timestamp),
+                utc(2024, 2, 29, 23, 0, 0)
+            );
+            // 2024-04-01 00:00:00 CEST starts a day and a month
+            let timestamp = utc(2024, 3, 31, 22, 0, 0);
+            assert_eq!(Granularity::Day.bucket_start(timestamp), timestamp);
+            assert_eq!(Granularity::Month.bucket_start(timestamp), timestamp);
+            // 03:30 CEST, right after the skipped hour
+            assert_eq!(
+                Granularity::Hour.bucket_start(utc(2024, 3, 31, 1, 30, 0)),
+                utc(2024, 3, 31, 1, 0, 0)
+            );
+            // 02:30 CEST and 02:30 CET are different hours of the same day
+            let first = utc(2024, 10, 27, 0, 30, 0);
+            let second = utc(2024, 10, 27, 1, 30, 0);
+            assert_eq!(
+                Granularity::Hour.bucket_start(first),
+                utc(2024, 10, 27, 0, 0, 0)
+            );
+            assert_eq!(
+                Granularity::Hour.bucket_start(second),
+                utc(2024, 10, 27, 1, 0, 0)
+            );
+            assert_eq!(Granularity::Hour.format_bucket(first), "2024-10-27 02:00");
+            assert_eq!(Granularity::Hour.format_bucket(second), "2024-10-27 02:00");
+            assert_eq!(
+                Granularity::Day.bucket_start(first),
+                utc(2024, 10, 26, 22, 0, 0)
+            );
+            assert_eq!(
+                Granularity::Day.bucket_start(second),
+                utc(2024, 10, 26, 22, 0, 0)
+            );
+        })
+        .join();
+        match previous {
+            Some(tz) => std::env::set_var("TZ", tz),
+            None => std::env::remove_var("TZ"),
+        }
+        if let Err(e) = result {
+            std::panic::resume_unwind(e);
+        }
+    }
+
+    #[test]
+    fn add_totals_accumulates() {
+        let _guard = TIME_ZONE_LOCK.lock().unwrap();
+        let (db, path) = test_db("accumulate");
+        let hour_a = Granularity::Hour.bucket_start(utc(2024, 6, 15, 12, 10, 0));
+        let hour_b = Granularity::Hour.bucket_start(utc(2024, 6, 15, 13, 10, 0));
+        let day_start = Granularity::Day.bucket_start(hour_a);
+        let month_start = Granularity::Month.bucket_start(hour_a);
+        assert_eq!(Granularity::Day.bucket_start(hour_b), day_start);
+
+        let mut totals = TrafficTotals::new();
+        totals.insert((hour_a, Dimension::Total, String::new()), total(100, 1));
+        totals.insert(
+            (hour_a, Dimension::Asn, String::from("13335")),
+            total(40, 1),
+        );
+        let mut names = DisplayNames::new();
+        names.insert(
+            (Dimension::Asn, String::from("13335")),
+            String::from("CLOUDFLARENET"),
+        );
+        db.add_totals(&totals, &names).unwrap();
+        // The next flush in the same hour, and the hour after
+        totals.insert((hour_b, Dimension::Total, String::new()), total(50, 2));
+        db.add_totals(&totals, &DisplayNames::new()).unwrap();
+
+        assert_eq!(
+            get_total(&db, Granularity::Hour, hour_a),
+            Some(total(200, 2))
+        );
+        assert_eq!(
+            get_total(&db, Granularity::Hour, hour_b),
+            Some(total(50, 2))
+        );
+        assert_eq!(
+            get_total(&db, Granularity::Day, day_start),
+            Some(total(250, 4))
+        );
+        assert_eq!(
+            get_total(&db, Granularity::Month, month_start),
+            Some(total(250, 4))
+        );
+        let records = db.get_records(Granularity::Day, day_start).unwrap();
+        let asn = records
+            .iter()
+            .find(|record| record.dimension == Dimension::Asn)
+            .unwrap();
+        assert_eq!(asn.name, "13335");
+        assert_eq!(asn.display_name, "CLOUDFLARENET");
+        assert_eq!(asn.total, total(80, 2));
+        // Only the buckets starting at or after since
+        assert!(db
+            .get_records(Granularity::Hour, hour_b)
+            .unwrap()
+            .iter()
+            .all(|record| record.bucket_start == hour_b));
+        drop(db);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn remove_old_buckets_keeps_recent() {
+        let _guard = TIME_ZONE_LOCK.lock().unwrap();
+        let (db, path) = test_db("retention");
+        let now = Local::now().timestamp();
+        let old_hour = Granularity::Hour.bucket_start(now - 10 * 24 * 3600);
+        let hour = Granularity::Hour.bucket_start(now);
+        let mut totals = TrafficTotals::new();
+        totals.insert((old_hour, Dimension::Total, String::new()), total(10, 1));
+        totals.insert((hour, Dimension::Total, String::new()), total(20, 1));
+        db.add_totals(&totals, &DisplayNames::new()).unwrap();
+
+        // A retention of 0 keeps everything
+        db.remove_old_buckets(Granularity::Hour, Duration::ZERO)
+            .unwrap();
+        assert_eq!(db.get_records(Granularity::Hour, 0).unwrap().len(), 2);
+
+        db.remove_old_buckets(Granularity::Hour, Duration::from_secs(2 * 24 * 3600))
+            .unwrap();
+        let records = db.get_records(Granularity::Hour, 0).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].bucket_start, hour);
+        // The other tables are kept
+        assert_eq!(db.get_records(Granularity::Day, 0).unwrap().len(), 2);
+        drop(db);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn accounting_add_take_and_restore() {
+        let mut accounting = TrafficAccounting::new();
+        let mut delta = NetStatData::new();
+        delta.traffic = traffic(300, 3);
+        let mut eth0 = InterfaceTrafficInfo::new();
+        eth0.traffic = traffic(300, 3);
+        delta.interface_traffic.insert(String::from("eth0"), eth0);
+        // No packets, no row
+        delta
+            .interface_traffic
+            .insert(String::from("lo"), InterfaceTrafficInfo::new());
+        // Only part of the traffic belongs to a TCP/UDP connection
+        delta.connection_map.insert(
+            SocketConnection {
+                interface_name: String::from("eth0"),
+                local_ip_addr: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)),
+                local_port: 50000,
+                remote_ip_addr: IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+                remote_port: 443,
+                protocol: TransportProtocol::TCP,
+            },
+            traffic(100, 1),
+        );
+        let sum = |accounting: &TrafficAccounting, dimension: Dimension, name: &str| {
+            let mut sum = TrafficTotal::default();
+            for ((_, d, n), total) in &accounting.totals {
+                if *d == dimension && n == name {
+                    sum.add_total(total);
+                }
+            }
+            sum
+        };
+
+        // Disabled until the accounting thread starts
+        accounting.add(&delta);
+        assert!(accounting.totals.is_empty());
+
+        accounting.enabled = true;
+        accounting.add(&delta);
+        assert_eq!(sum(&accounting, Dimension::Total, ""), total(300, 3));
+        assert_eq!(
+            sum(&accounting, Dimension::Interface, "eth0"),
+            total(300, 3)
+        );
+        assert_eq!(
+            sum(&accounting, Dimension::Process, UNKNOWN_NAME),
+            total(100, 1)
+        );
+        assert!(!accounting
+            .totals
+            .keys()
+            .any(|(_, _, name)| name.as_str() == "lo"));
+
+        let (totals, names) = accounting.take();
+        assert!(accounting.totals.is_empty());
+        accounting.add(&delta);
+        // A failed write puts the totals back on top of the newer ones
+        accounting.restore(totals, names);
+        assert_eq!(sum(&accounting, Dimension::Total, ""), total(600, 6));
+        assert_eq!(
+            sum(&accounting, Dimension::Interface, "eth0"),
+            total(600, 6)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use crate::db::build::{ImportedData, SourceFormat};
+use crate::db::manifest::DbManifest;
+use clap::ArgMatches;
+use comfy_table::presets::NOTHING;
+use comfy_table::*;
+use std::error::Error;
+use std::path::PathBuf;
+
+pub fn db(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let sub_args = match app.subcommand_matches("db") {
+        Some(matches) => matches,
+        None => {
+            eprintln!("Error: Could not get subcommand matches");
+            return Ok(());
+        }
+    };
+    match sub_args.subcommand() {
+        Some(("build", build_args)) => build_db(build_args),
+        Some(("manifest", manifest_args)) => write_manifest(manifest_args),
+        _ => {
+            eprintln!("Error: Specify a db subcommand. ntap db --help for more information");
+            Ok(())
+        }
+    }
+}
+
+fn build_db(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let format: SourceFormat = match args.get_one::<String>("format") {
+        Some(format) => match SourceFormat::from_str(format) {
+            Some(format) => format,
+            None => {
+                return Err(format!("Invalid format: {}", format).into());
+            }
+        },
+        None => {
+            return Err("Specify the source format with --format".into());
+        }
+    };
+    let files: Vec<PathBuf> = match args.get_many::<PathBuf>("files") {
+        Some(files) => files.cloned().collect(),
+        None => {
+            return Err("Specify the source files".into());
+        }
+    };
+    let output_dir: PathBuf = match args.get_one::<PathBuf>("output") {
+        Some(output_dir) => output_dir.clone(),
+        None => match crate::sys::get_database_dir_path() {
+            Some(output_dir) => output_dir,
+            None => {
+                return Err("Could not get database directory path".into());
+            }
+        },
+    };
+
+    let mut data = ImportedData::new();
+    for file in &files {
+        println!("Reading {}", file.display());
+    }
+    data.import(format, &files)?;
+    if data.is_empty() {
+        return Err("No IP ranges found in the source files".into());
+    }
+    if let Err(e) = data.validate() {
+        eprintln!("{}", e);
+        return Err(
+            "Overlapping ranges in the source files. No database files were written".into(),
+        );
+    }
+    let written_files = data.write(&output_dir)?;
+
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["File", "Imported", "Records"]);
+    for written_file in &written_files {
+        table.add_row(vec![
+            Cell::new(written_file.path.display()),
+            Cell::new(written_file.imported),
+            Cell::new(written_file.records),
+        ]);
+    }
+    println!();
+    println!("{}", table);
+    if data.skipped > 0 {
+        println!();
+        println!("Skipped {} invalid or incomplete entries", data.skipped);
+    }
+    Ok(())
+}
+
+fn write_manifest(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let dir: PathBuf = match args.get_one::<PathBuf>("dir") {
+        Some(dir) => dir.clone(),
+        None => match crate::sys::get_database_dir_path() {
+            Some(dir) => dir,
+            None => {
+                return Err("Could not get database directory path".into());
+            }
+        },
+    };
+    let version: String = match args.get_one::<String>("version") {
+        Some(version) => version.clone(),
+        None => chrono::Local::now().format("%Y-%m-%d").to_string(),
+    };
+    let manifest = DbManifest::from_dir(&dir, &version)?;
+    if manifest.files.is_empty() {
+        return Err(format!("No database files found in {}", dir.display()).into());
+    }
+    let path = manifest.save(&dir)?;
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["File", "Version", "Size", "SHA-256"]);
+    for entry in &manifest.files {
+        table.add_row(vec![
+            Cell::new(&entry.name),
+            Cell::new(&entry.version),
+            Cell::new(entry.size),
+            Cell::new(&entry.sha256),
+        ]);
+    }
+    println!("{}", table);
+    println!();
+    println!("Wrote {}", path.display());
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::db::oui::OuiDatabase;
 use crate::util::tree::node_label;
 use netdev::mac::MacAddr;
 use netdev::Interface;
@@ -6,6 +7,7 @@ use termtree::Tree;
 
 pub fn show_interfaces() -> Result<(), Box<dyn Error>> {
     let interfaces: Vec<Interface> = netdev::get_interfaces();
+    let oui_db: OuiDatabase = OuiDatabase::load().unwrap_or_else(|_| OuiDatabase::new());
 
     let mut tree = Tree::new(node_label("Interfaces", None, None));
     for iface in interfaces {
@@ -39,6 +41,9 @@ pub fn show_interfaces() -> Result<(), Box<dyn Error>> {
         if let Some(gateway) = &iface.gateway {
             let mut gateway_tree = Tree::new(node_label("Gateway", None, None));
             gateway_tree.push(node_label("MAC", Some(&gateway.mac_addr.to_string()), None));
+            if let Some(vendor_name) = oui_db.get_vendor_name(&gateway.mac_addr) {
+                gateway_tree.push(node_label("Vendor", Some(&vendor_name), None));
+            }
             let mut ipv4_tree = Tree::new(node_label("IPv4 Addresses", None, None));
             for ipv4 in &gateway.ipv4 {
                 ipv4_tree.push(node_label(&ipv4.to_string(), None, None));
@@ -67,6 +72,7 @@ pub fn show_interfaces() -> Result<(), Box<dyn Error>> {
 
 pub fn show_default_interface() -> Result<(), Box<dyn Error>> {
     let iface: Interface = netdev::get_default_interface()?;
+    let oui_db: OuiDatabase = OuiDatabase::load().unwrap_or_else(|_| OuiDatabase::new());
     let mut tree = Tree::new(node_label("Interface", None, None));
     tree.push(node_label("Index", Some(&iface.index.to_string()), None));
     tree.push(node_label("Name", Some(&iface.name), None));
@@ -97,6 +103,9 @@ pub fn show_default_interface() -> Result<(), Box<dyn Error>> {
     if let Some(gateway) = &iface.gateway {
         let mut gateway_tree = Tree::new(node_label("Gateway", None, None));
         gateway_tree.push(node_label("MAC", Some(&gateway.mac_addr.to_string()), None));
+        if let Some(vendor_name) = oui_db.get_vendor_name(&gateway.mac_addr) {
+            gateway_tree.push(node_label("Vendor", Some(&vendor_name), None));
+        }
         let mut ipv4_tree = Tree::new(node_label("IPv4 Addresses", None, None));
         for ipv4 in &gateway.ipv4 {
             ipv4_tree.push(node_label(&ipv4.to_string(), None, None));
@@ -1,14 +1,17 @@
 use crate::config::AppConfig;
+use crate::net::capfile::{self, CaptureFileWriter, LinkType, RotationOptions};
+use crate::net::filter::FilterExpr;
 use crate::net::packet::{PacketFrame, PacketStorage};
 use crate::thread_log;
 use std::collections::HashSet;
 use std::error::Error;
 use std::fs::File;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use clap::ArgMatches;
 use nex::packet::ethernet::EtherType;
@@ -109,6 +112,38 @@ pub fn live_capture(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
         storage_capacity = u8::MAX;
     }
 
+    // Filter expression
+    let filter: Option<FilterExpr> = sub_args.get_one::<FilterExpr>("filter").cloned();
+
+    // Capture file
+    let capture_file: Option<PathBuf> = sub_args.get_one::<PathBuf>("file").cloned();
+    let realtime: bool = sub_args.get_flag("realtime");
+
+    // Write captured packets to pcapng
+    let write_file: Option<PathBuf> = sub_args.get_one::<PathBuf>("write").cloned();
+    let mut rotation = RotationOptions::new();
+    if let Some(megabytes) = sub_args.get_one::<u64>("rotate-size") {
+        rotation.max_file_size = Some(megabytes.saturating_mul(1024 * 1024));
+    }
+    if let Some(seconds) = sub_args.get_one::<u64>("rotate-interval") {
+        rotation.interval = Some(Duration::from_secs(*seconds));
+    }
+    if let Some(max_files) = sub_args.get_one::<usize>("max-files") {
+        rotation.max_files = Some(*max_files);
+    }
+
+    // Raw bytes kept for the packet detail view
+    if let Some(snaplen) = sub_args.get_one::<usize>("snaplen") {
+        config.capture.snaplen = *snaplen;
+    }
+    let snaplen = config.capture.snaplen;
+    // The capture file needs the full packets even if the detail view keeps less
+    let capture_snaplen = if write_file.is_some() {
+        std::cmp::max(snaplen, capfile::DEFAULT_SNAPLEN)
+    } else {
+        snaplen
+    };
+
     // Init logger
     let log_file_path = if let Some(file_path) = &config.logging.file_path {
         // Convert to PathBuf
@@ -158,15 +193,35 @@ pub fn live_capture(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
         Arc::new(PacketStorage::with_capacity(storage_capacity as usize));
     let packet_strage_ui: Arc<PacketStorage> = Arc::clone(&packet_strage);
     let target_interfaces: Vec<netdev::Interface>;
-    if config.network.interfaces.is_empty() {
+    if capture_file.is_some() {
+        // Packets are read from the capture file instead of the interfaces
+        target_interfaces = Vec::new();
+    } else if config.network.interfaces.is_empty() {
         target_interfaces = crate::net::interface::get_usable_interfaces();
     } else {
         target_interfaces =
             crate::net::interface::get_interfaces_by_name(&config.network.interfaces);
     }
+    let capture_writer: Option<Arc<Mutex<CaptureFileWriter>>> = match &write_file {
+        Some(file_path) => {
+            let mut writer =
+                CaptureFileWriter::new(file_path.clone(), rotation, capfile::DEFAULT_SNAPLEN);
+            // One interface description block per capture thread
+            for iface in &target_interfaces {
+                writer.add_interface(
+                    iface.index,
+                    iface.name.clone(),
+                    LinkType::from_interface(iface),
+                );
+            }
+            Some(Arc::new(Mutex::new(writer)))
+        }
+        None => None,
+    };
+    let capture_writer_ui = capture_writer.clone();
     let mut pcap_thread_index = 0;
     let (tx, rx): (Sender<PacketFrame>, Receiver<PacketFrame>) = channel();
-    let pcap_handlers = target_interfaces
+    let mut pcap_handlers = target_interfaces
         .iter()
         .map(|iface| {
             let iface = iface.clone();
@@ -177,6 +232,8 @@ pub fn live_capture(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
             pcap_option.src_ports = ports.clone();
             pcap_option.dst_ips = ips.clone();
             pcap_option.dst_ports = ports.clone();
+            pcap_option.filter = filter.clone();
+            pcap_option.snaplen = capture_snaplen;
             let thread_name = format!("pcap-thread-{}", iface.name.clone());
             let pcap_thread = thread::Builder::new().name(thread_name.clone());
             let tx_clone = tx.clone();
@@ -189,9 +246,56 @@ pub fn live_capture(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
         })
         .collect::<Vec<_>>();
 
+    if let Some(file_path) = capture_file.clone() {
+        let mut pcap_option = crate::net::pcap::PacketCaptureOptions::from_capture_file(&file_path);
+        pcap_option.ether_types = ethertypes.clone();
+        pcap_option.ip_protocols = ip_next_protocols.clone();
+        pcap_option.src_ips = ips.clone();
+        pcap_option.src_ports = ports.clone();
+        pcap_option.dst_ips = ips.clone();
+        pcap_option.dst_ports = ports.clone();
+        pcap_option.filter = filter.clone();
+        pcap_option.snaplen = snaplen;
+        let thread_name = String::from("pcap-thread-file");
+        let pcap_thread = thread::Builder::new().name(thread_name.clone());
+        let tx_clone = tx.clone();
+        let pcap_handler = pcap_thread.spawn(move || {
+            crate::net::pcap::start_live_file_capture(pcap_option, file_path, realtime, tx_clone);
+        });
+        thread_log!(info, "start thread {}", thread_name);
+        pcap_handlers.push(pcap_handler);
+    }
+
     let receiver_handler = thread::spawn(move || {
         thread_log!(info, "start mpsc reveiver thread");
+        let mut last_flush = Instant::now();
         while let Ok(mut frame) = rx.recv() {
+            if let Some(writer) = &capture_writer {
+                match writer.lock() {
+                    Ok(mut writer) => {
+                        match writer.write_packet(
+                            frame.if_index,
+                            frame.capture_time,
+                            frame.packet_len,
+                            &frame.raw_packet,
+                        ) {
+                            Ok(_) => {}
+                            Err(e) => {
+                                thread_log!(error, "write_packet error: {:?}", e);
+                            }
+                        }
+                        if last_flush.elapsed() >= Duration::from_secs(1) {
+                            let _ = writer.flush();
+                            last_flush = Instant::now();
+                        }
+                    }
+                    Err(e) => {
+                        thread_log!(error, "capture_writer lock error: {:?}", e);
+                    }
+                }
+                // Keep only the bytes needed for the packet detail view
+                frame.raw_packet.truncate(snaplen);
+            }
             frame.capture_no = packet_strage.generate_capture_no();
             packet_strage.add_packet(frame);
         }
@@ -226,5 +330,17 @@ pub fn live_capture(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
         app.contains_id("enhanced-graphics"),
         &packet_strage_ui,
     )?;
+
+    // Flush the rest of the captured packets
+    if let Some(writer) = capture_writer_ui {
+        match writer.lock() {
+            Ok(mut writer) => {
+                writer.flush()?;
+            }
+            Err(e) => {
+                thread_log!(error, "capture_writer lock error: {:?}", e);
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,269 @@
+use crate::db::compact::CompactIpDatabase;
+use crate::db::ip::IpBlock;
+use clap::ArgMatches;
+use comfy_table::presets::NOTHING;
+use comfy_table::*;
+use ipnet::IpNet;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::BufRead;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Input of a lookup.
+#[derive(Debug, Clone)]
+enum LookupTarget {
+    Ip(IpAddr),
+    Cidr(IpNet),
+}
+
+impl LookupTarget {
+    fn from_str(s: &str) -> Option<LookupTarget> {
+        if let Ok(ip_addr) = s.parse::<IpAddr>() {
+            return Some(LookupTarget::Ip(ip_addr));
+        }
+        match s.parse::<IpNet>() {
+            Ok(ip_net) => Some(LookupTarget::Cidr(ip_net.trunc())),
+            Err(_) => None,
+        }
+    }
+    fn query(&self) -> String {
+        match self {
+            LookupTarget::Ip(ip_addr) => ip_addr.to_string(),
+            LookupTarget::Cidr(ip_net) => ip_net.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct LookupRow {
+    query: String,
+    ip_from: IpAddr,
+    ip_to: IpAddr,
+    host_name: String,
+    asn: u32,
+    as_name: String,
+    country_code: String,
+    country_name: String,
+}
+
+impl LookupRow {
+    fn from_block(query: String, block: IpBlock) -> LookupRow {
+        LookupRow {
+            query,
+            ip_from: block.ip_from,
+            ip_to: block.ip_to,
+            host_name: String::new(),
+            asn: block.asn,
+            as_name: block.as_name,
+            country_code: block.country_code,
+            country_name: block.country_name,
+        }
+    }
+}
+
+pub fn lookup(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let sub_args = match app.subcommand_matches("lookup") {
+        Some(matches) => matches,
+        None => {
+            eprintln!("Error: Could not get subcommand matches");
+            return Ok(());
+        }
+    };
+    let format: String = match sub_args.get_one::<String>("format") {
+        Some(format) => format.clone(),
+        None => String::from("table"),
+    };
+    let resolve_dns: bool = !sub_args.get_flag("no-dns");
+    let inputs: Vec<String> = match sub_args.get_many::<String>("targets") {
+        Some(inputs) => inputs.cloned().collect(),
+        None => vec![String::from("-")],
+    };
+
+    let mut targets: Vec<LookupTarget> = Vec::new();
+    for input in &inputs {
+        if input == "-" {
+            read_targets(std::io::stdin().lock(), "stdin", &mut targets)?;
+            continue;
+        }
+        match LookupTarget::from_str(input) {
+            Some(target) => targets.push(target),
+            None => {
+                let path = Path::new(input);
+                if path.is_file() {
+                    let file = std::fs::File::open(path)?;
+                    read_targets(std::io::BufReader::new(file), input, &mut targets)?;
+                } else {
+                    eprintln!("Invalid IP address, CIDR or file: {}", input);
+                }
+            }
+        }
+    }
+    if targets.is_empty() {
+        return Err("No IP addresses to look up".into());
+    }
+
+    #[cfg(not(feature = "bundle"))]
+    for file_name in [
+        ntap_db_as::AS_BIN_NAME,
+        ntap_db_country::COUNTRY_BIN_NAME,
+        ntap_db_ipv4_asn::IPV4_ASN_BIN_NAME,
+        ntap_db_ipv4_country::IPV4_COUNTRY_BIN_NAME,
+        ntap_db_ipv6_asn::IPV6_ASN_BIN_NAME,
+        ntap_db_ipv6_country::IPV6_COUNTRY_BIN_NAME,
+    ] {
+        match crate::sys::get_db_file_path(file_name) {
+            Some(path) => {
+                if !path.exists() {
+                    return Err(format!(
+                        "{} is missing. Run ntap update to download the databases",
+                        file_name
+                    )
+                    .into());
+                }
+            }
+            None => {
+                return Err("Could not get database directory path".into());
+            }
+        }
+    }
+    let ip_db = CompactIpDatabase::load()?;
+
+    // Reverse DNS for the single addresses
+    let host_names: HashMap<IpAddr, String> = if resolve_dns {
+        let mut ips: Vec<IpAddr> = targets
+            .iter()
+            .filter_map(|target| match target {
+                LookupTarget::Ip(ip_addr) => Some(*ip_addr),
+                LookupTarget::Cidr(_) => None,
+            })
+            .collect();
+        ips.sort();
+        ips.dedup();
+        if ips.is_empty() {
+            HashMap::new()
+        } else {
+            crate::net::dns::lookup_ips(ips)
+        }
+    } else {
+        HashMap::new()
+    };
+
+    let mut rows: Vec<LookupRow> = Vec::new();
+    for target in &targets {
+        match target {
+            LookupTarget::Ip(ip_addr) => {
+                let blocks = match ip_addr {
+                    IpAddr::V4(ipv4_addr) => ip_db.get_ipv4_blocks(*ipv4_addr, *ipv4_addr),
+                    IpAddr::V6(ipv6_addr) => ip_db.get_ipv6_blocks(*ipv6_addr, *ipv6_addr),
+                };
+                for block in blocks {
+                    let mut row = LookupRow::from_block(target.query(), block);
+                    row.host_name = host_names.get(ip_addr).cloned().unwrap_or_default();
+                    rows.push(row);
+                }
+            }
+            LookupTarget::Cidr(ip_net) => {
+                let blocks = match ip_net {
+                    IpNet::V4(ipv4_net) => {
+                        ip_db.get_ipv4_blocks(ipv4_net.network(), ipv4_net.broadcast())
+                    }
+                    IpNet::V6(ipv6_net) => {
+                        ip_db.get_ipv6_blocks(ipv6_net.network(), ipv6_net.broadcast())
+                    }
+                };
+                for block in blocks {
+                    rows.push(LookupRow::from_block(target.query(), block));
+                }
+            }
+        }
+    }
+
+    match format.as_str() {
+        "json" => {
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+        }
+        "csv" => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for row in &rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+        _ => {
+            show_lookup_table(&rows);
+        }
+    }
+    Ok(())
+}
+
+/// One IP address or CIDR per line. Empty lines and # comments are skipped,
+/// and only the first field of a whitespace or comma separated line is used.
+fn read_targets<R: BufRead>(
+    reader: R,
+    source: &str,
+    targets: &mut Vec<LookupTarget>,
+) -> Result<(), Box<dyn Error>> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let field = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .next()
+            .unwrap_or("");
+        match LookupTarget::from_str(field) {
+            Some(target) => targets.push(target),
+            None => {
+                eprintln!(
+                    "{}:{}: invalid IP address or CIDR: {}",
+                    source,
+                    i + 1,
+                    field
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn show_lookup_table(rows: &[LookupRow]) {
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            "Query",
+            "Range",
+            "Host Name",
+            "ASN",
+            "AS Name",
+            "Country",
+            "Country Name",
+        ]);
+    for row in rows {
+        let range = if row.ip_from == row.ip_to {
+            row.ip_from.to_string()
+        } else {
+            format!("{} - {}", row.ip_from, row.ip_to)
+        };
+        let asn = if row.asn == 0 {
+            String::new()
+        } else {
+            row.asn.to_string()
+        };
+        table.add_row(vec![
+            Cell::new(&row.query),
+            Cell::new(range),
+            Cell::new(&row.host_name),
+            Cell::new(asn),
+            Cell::new(&row.as_name),
+            Cell::new(&row.country_code),
+            Cell::new(&row.country_name),
+        ]);
+    }
+    println!("{}", table);
+}
@@ -1,8 +1,13 @@
+pub mod db;
+pub mod export;
 pub mod interface;
 pub mod ip_info;
 pub mod live;
+pub mod lookup;
 pub mod monitor;
+pub mod report;
 pub mod route;
+pub mod serve;
 pub mod socket;
 pub mod stat;
 pub mod update;
@@ -16,7 +21,12 @@ pub enum AppCommands {
     Route,
     Socket,
     IpInfo,
+    Lookup,
+    Serve,
+    Export,
+    Report,
     Update,
+    Db,
     Default,
 }
 
@@ -31,7 +41,12 @@ impl AppCommands {
             "route" => AppCommands::Route,
             "socket" => AppCommands::Socket,
             "ipinfo" => AppCommands::IpInfo,
+            "lookup" => AppCommands::Lookup,
+            "serve" => AppCommands::Serve,
+            "export" => AppCommands::Export,
+            "report" => AppCommands::Report,
             "update" => AppCommands::Update,
+            "db" => AppCommands::Db,
             _ => AppCommands::Default,
         }
     }
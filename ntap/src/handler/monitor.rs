@@ -1,11 +1,14 @@
 use crate::config::AppConfig;
+use crate::headless::{HeadlessOptions, OutputFormat};
+use crate::net::filter::FilterExpr;
 use crate::net::stat::NetStatStrage;
 use crate::thread_log;
 use std::collections::HashSet;
 use std::error::Error;
 use std::fs::File;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::thread;
 
@@ -125,6 +128,26 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
         }
     }
 
+    // Filter expression
+    let filter: Option<FilterExpr> = sub_args.get_one::<FilterExpr>("filter").cloned();
+
+    // Capture file
+    let capture_file: Option<PathBuf> = sub_args.get_one::<PathBuf>("file").cloned();
+    let realtime: bool = sub_args.get_flag("realtime");
+
+    // Output format
+    let output_format: OutputFormat = if sub_args.get_flag("headless") {
+        OutputFormat::Ndjson
+    } else {
+        match sub_args.get_one::<String>("format") {
+            Some(format) => OutputFormat::from_str(format),
+            None => OutputFormat::Tui,
+        }
+    };
+    let mut headless_options = HeadlessOptions::new();
+    headless_options.output = sub_args.get_one::<PathBuf>("output").cloned();
+    headless_options.count = sub_args.get_one::<usize>("count").cloned();
+
     // Init logger
     let log_file_path = if let Some(file_path) = &config.logging.file_path {
         // Convert to PathBuf
@@ -147,12 +170,17 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
     // Init logger with file and terminal output
     // debug build: log to terminal and file
     // release build: log to file only
+    // Headless output may be written to stdout, so terminal logs go to stderr
+    let terminal_mode = match output_format {
+        OutputFormat::Ndjson => simplelog::TerminalMode::Stderr,
+        OutputFormat::Tui => simplelog::TerminalMode::Mixed,
+    };
     if cfg!(debug_assertions) {
         simplelog::CombinedLogger::init(vec![
             simplelog::TermLogger::new(
                 simplelog::LevelFilter::Info,
                 default_log_config.clone(),
-                simplelog::TerminalMode::Mixed,
+                terminal_mode,
                 simplelog::ColorChoice::Auto,
             ),
             simplelog::WriteLogger::new(
@@ -172,18 +200,28 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let mut threads: Vec<thread::JoinHandle<()>> = vec![];
 
     let netstat_strage: Arc<NetStatStrage> = Arc::new(NetStatStrage::new());
+    netstat_strage.load_labels(&config.labels);
     let mut netstat_strage_socket = Arc::clone(&netstat_strage);
     let mut netstat_strage_ui = Arc::clone(&netstat_strage);
 
     let target_interfaces: Vec<netdev::Interface>;
-    if config.network.interfaces.is_empty() {
+    if capture_file.is_some() {
+        // Packets are read from the capture file instead of the interfaces
+        target_interfaces = Vec::new();
+    } else if config.network.interfaces.is_empty() {
         target_interfaces = crate::net::interface::get_usable_interfaces();
     } else {
         target_interfaces =
             crate::net::interface::get_interfaces_by_name(&config.network.interfaces);
     }
+    netstat_strage.add_interfaces(
+        target_interfaces
+            .iter()
+            .map(|iface| iface.name.clone())
+            .collect(),
+    );
     let mut pcap_thread_index = 0;
-    let pcap_handlers = target_interfaces
+    let mut pcap_handlers = target_interfaces
         .iter()
         .map(|iface| {
             let mut netstat_strage_pcap = Arc::clone(&netstat_strage);
@@ -195,11 +233,13 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
             pcap_option.src_ports = ports.clone();
             pcap_option.dst_ips = ips.clone();
             pcap_option.dst_ports = ports.clone();
+            pcap_option.filter = filter.clone();
             let thread_name = format!("pcap-thread-{}", iface.name.clone());
             let pcap_thread = thread::Builder::new().name(thread_name.clone());
             let pcap_handler = pcap_thread.spawn(move || {
                 if pcap_thread_index == 0 {
                     netstat_strage_pcap.load_ipdb();
+                    netstat_strage_pcap.load_oui_db();
                 }
                 crate::net::pcap::start_background_capture(
                     pcap_option,
@@ -213,10 +253,25 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
         })
         .collect::<Vec<_>>();
 
-    let socket_handler = thread::spawn(move || {
-        thread_log!(info, "start thread socket_info_update");
-        crate::net::socket::start_socket_info_update(&mut netstat_strage_socket);
-    });
+    if let Some(file_path) = capture_file.clone() {
+        let mut pcap_option = crate::net::pcap::PacketCaptureOptions::from_capture_file(&file_path);
+        pcap_option.ether_types = ethertypes.clone();
+        pcap_option.ip_protocols = ip_next_protocols.clone();
+        pcap_option.src_ips = ips.clone();
+        pcap_option.src_ports = ports.clone();
+        pcap_option.dst_ips = ips.clone();
+        pcap_option.dst_ports = ports.clone();
+        pcap_option.filter = filter.clone();
+        let capture_finished = Arc::new(AtomicBool::new(false));
+        headless_options.capture_finished = Some(Arc::clone(&capture_finished));
+        pcap_handlers.push(crate::net::pcap::spawn_file_capture_thread(
+            pcap_option,
+            file_path,
+            realtime,
+            &netstat_strage,
+            capture_finished,
+        ));
+    }
 
     for pcap_handler in pcap_handlers {
         match pcap_handler {
@@ -228,7 +283,14 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
             }
         }
     }
-    threads.push(socket_handler);
+    // Local sockets are not related to the packets in the capture file
+    if capture_file.is_none() {
+        let socket_handler = thread::spawn(move || {
+            thread_log!(info, "start thread socket_info_update");
+            crate::net::socket::start_socket_info_update(&mut netstat_strage_socket);
+        });
+        threads.push(socket_handler);
+    }
 
     if config.network.reverse_dns {
         let mut netstat_strage_dns = Arc::clone(&netstat_strage);
@@ -239,6 +301,33 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
         threads.push(dns_handler);
     }
 
+    // Record traffic totals of live captures only
+    let accounting_enabled = config.accounting.enabled && capture_file.is_none();
+    if accounting_enabled {
+        let mut netstat_strage_accounting = Arc::clone(&netstat_strage);
+        let hourly_retention = config.accounting.hourly_retention();
+        let daily_retention = config.accounting.daily_retention();
+        let accounting_handler = thread::spawn(move || {
+            thread_log!(info, "start thread traffic_accounting");
+            crate::db::traffic::start_traffic_accounting(
+                &mut netstat_strage_accounting,
+                hourly_retention,
+                daily_retention,
+            );
+        });
+        threads.push(accounting_handler);
+    }
+
+    if output_format == OutputFormat::Ndjson {
+        thread_log!(info, "start headless output, netstat_data_update");
+        let result = crate::headless::run(config, headless_options, &mut netstat_strage_ui);
+        if accounting_enabled {
+            crate::db::traffic::flush_traffic_accounting(&netstat_strage);
+        }
+        result?;
+        return Ok(());
+    }
+
     thread_log!(info, "start TUI, netstat_data_update");
 
     // Clear screen before starting TUI
@@ -250,10 +339,14 @@ pub fn monitor(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
     // Move cursor to top left corner
     crossterm::execute!(stdout, crossterm::cursor::MoveTo(0, 0))?;
 
-    crate::tui::monitor::terminal::run(
+    let result = crate::tui::monitor::terminal::run(
         config,
         app.contains_id("enhanced-graphics"),
         &mut netstat_strage_ui,
-    )?;
+    );
+    if accounting_enabled {
+        crate::db::traffic::flush_traffic_accounting(&netstat_strage);
+    }
+    result?;
     Ok(())
 }
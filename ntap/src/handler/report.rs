@@ -0,0 +1,191 @@
+use crate::db::traffic::{Dimension, Granularity, TrafficDatabase, TrafficRecord, TrafficTotal};
+use crate::net::traffic::TrafficInfo;
+use chrono::Local;
+use clap::ArgMatches;
+use comfy_table::presets::NOTHING;
+use comfy_table::*;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::time::Duration;
+
+const DEFAULT_REPORT_LIMIT: usize = 10;
+
+pub fn show_report(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let sub_args = match app.subcommand_matches("report") {
+        Some(matches) => matches,
+        None => {
+            eprintln!("Error: Could not get subcommand matches");
+            return Ok(());
+        }
+    };
+    let period: Duration = match sub_args.get_one::<Duration>("since") {
+        Some(period) => *period,
+        None => Duration::from_secs(7 * 24 * 3600),
+    };
+    let granularity: Granularity = match sub_args.get_one::<String>("granularity") {
+        Some(granularity) => match Granularity::from_str(granularity) {
+            Some(granularity) => granularity,
+            None => {
+                return Err(format!(
+                    "Invalid granularity: {}. Use hourly, daily or monthly",
+                    granularity
+                )
+                .into());
+            }
+        },
+        None => Granularity::for_period(period),
+    };
+    let limit: usize = *sub_args
+        .get_one::<usize>("limit")
+        .unwrap_or(&DEFAULT_REPORT_LIMIT);
+
+    match TrafficDatabase::file_path() {
+        Some(path) => {
+            if !path.exists() {
+                println!("No traffic has been recorded yet. Run ntap stat, monitor or serve to start recording.");
+                return Ok(());
+            }
+        }
+        None => {
+            return Err("Could not get config directory path".into());
+        }
+    }
+    let db = TrafficDatabase::open()?;
+    // Nothing is recorded before the UNIX epoch, so longer periods start there
+    let since_time = (Local::now().timestamp() as u64).saturating_sub(period.as_secs()) as i64;
+    let since = granularity.bucket_start(since_time);
+    let records = db.get_records(granularity, since)?;
+    drop(db);
+    if records.is_empty() {
+        println!(
+            "No traffic recorded since {}",
+            granularity.format_bucket(since)
+        );
+        return Ok(());
+    }
+
+    println!(
+        "Traffic since {} ({})",
+        granularity.format_bucket(since),
+        granularity.name()
+    );
+    println!();
+    show_period_table(&records, granularity);
+    for dimension in [
+        Dimension::Interface,
+        Dimension::Process,
+        Dimension::Asn,
+        Dimension::Country,
+        Dimension::Label,
+    ] {
+        // Labels are optional. Skip the table if none are configured
+        if dimension == Dimension::Label
+            && !records.iter().any(|record| record.dimension == dimension)
+        {
+            continue;
+        }
+        println!();
+        show_top_table(&records, dimension, limit);
+    }
+    Ok(())
+}
+
+fn show_period_table(records: &[TrafficRecord], granularity: Granularity) {
+    let mut period_totals: BTreeMap<i64, TrafficTotal> = BTreeMap::new();
+    let mut sum = TrafficTotal::default();
+    for record in records {
+        if record.dimension != Dimension::Total {
+            continue;
+        }
+        period_totals
+            .entry(record.bucket_start)
+            .or_default()
+            .add_total(&record.total);
+        sum.add_total(&record.total);
+    }
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Period", "↓ Received", "↑ Sent", "Total"]);
+    for (bucket_start, total) in &period_totals {
+        table.add_row(vec![
+            Cell::new(granularity.format_bucket(*bucket_start)),
+            Cell::new(TrafficInfo::format_bytes(total.bytes_received as usize)),
+            Cell::new(TrafficInfo::format_bytes(total.bytes_sent as usize)),
+            Cell::new(TrafficInfo::format_bytes(total.total_bytes() as usize)),
+        ]);
+    }
+    table.add_row(vec![
+        Cell::new("Total"),
+        Cell::new(TrafficInfo::format_bytes(sum.bytes_received as usize)),
+        Cell::new(TrafficInfo::format_bytes(sum.bytes_sent as usize)),
+        Cell::new(TrafficInfo::format_bytes(sum.total_bytes() as usize)),
+    ]);
+    println!("{}", table);
+}
+
+fn show_top_table(records: &[TrafficRecord], dimension: Dimension, limit: usize) {
+    let mut name_totals: HashMap<&str, TrafficTotal> = HashMap::new();
+    let mut display_names: HashMap<&str, &str> = HashMap::new();
+    let mut sum: u64 = 0;
+    for record in records {
+        if record.dimension != dimension {
+            continue;
+        }
+        name_totals
+            .entry(record.name.as_str())
+            .or_default()
+            .add_total(&record.total);
+        if !record.display_name.is_empty() {
+            display_names.insert(record.name.as_str(), record.display_name.as_str());
+        }
+        sum += record.total.total_bytes();
+    }
+    let mut name_totals: Vec<(&str, TrafficTotal)> = name_totals.into_iter().collect();
+    name_totals.sort_by(|a, b| {
+        b.1.total_bytes()
+            .cmp(&a.1.total_bytes())
+            .then_with(|| a.0.cmp(b.0))
+    });
+    // AS names and country names are stored separately from the key
+    let has_display_name = matches!(dimension, Dimension::Asn | Dimension::Country);
+    let mut header = vec![dimension.name()];
+    if has_display_name {
+        header.push("Name");
+    }
+    header.extend(["↓ Received", "↑ Sent", "Total", "%"]);
+    let title = match dimension {
+        Dimension::Total => "Total",
+        Dimension::Interface => "Top Interfaces",
+        Dimension::Process => "Top Processes",
+        Dimension::Asn => "Top ASNs",
+        Dimension::Country => "Top Countries",
+        Dimension::Label => "Top Labels",
+    };
+    println!("{}", title);
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(header);
+    for (name, total) in name_totals.iter().take(limit) {
+        let share = if sum == 0 {
+            0.0
+        } else {
+            total.total_bytes() as f64 * 100.0 / sum as f64
+        };
+        let mut row = vec![Cell::new(name)];
+        if has_display_name {
+            row.push(Cell::new(display_names.get(name).unwrap_or(&"")));
+        }
+        row.extend([
+            Cell::new(TrafficInfo::format_bytes(total.bytes_received as usize)),
+            Cell::new(TrafficInfo::format_bytes(total.bytes_sent as usize)),
+            Cell::new(TrafficInfo::format_bytes(total.total_bytes() as usize)),
+            Cell::new(format!("{:.1}", share)),
+        ]);
+        table.add_row(row);
+    }
+    println!("{}", table);
+}
@@ -0,0 +1,229 @@
+use crate::config::AppConfig;
+use crate::metrics::MetricsRegistry;
+use crate::net::filter::FilterExpr;
+use crate::net::stat::NetStatStrage;
+use crate::thread_log;
+use std::error::Error;
+use std::fs::File;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use clap::ArgMatches;
+
+pub fn serve(app: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let sub_args = match app.subcommand_matches("serve") {
+        Some(matches) => matches,
+        None => {
+            eprintln!("Error: Could not get subcommand matches");
+            return Ok(());
+        }
+    };
+    // Check .ntap directory
+    match crate::sys::get_config_dir_path() {
+        Some(_config_dir) => {}
+        None => {
+            let err_msg = "Could not get config directory path";
+            log::error!("{err_msg}");
+            return Err(err_msg.into());
+        }
+    }
+
+    // Check dependencies (Currently only for Windows)
+    match crate::sys::check_deps() {
+        Ok(_) => {}
+        Err(e) => {
+            log::error!("Error: {:?}", e);
+            return Err(e);
+        }
+    }
+
+    // The exporter runs unattended, so missing databases only leave the ASN/country labels empty
+    #[cfg(not(feature = "bundle"))]
+    match crate::deps::check_db_files() {
+        Ok(_) => {}
+        Err(e) => {
+            log::error!("{}", e);
+            eprintln!("Warning: ntap databases are missing. ASN and country labels will be unknown. Run `ntap update` to download them.");
+        }
+    }
+
+    // Load AppConfig
+    let mut config = AppConfig::load();
+
+    if app.contains_id("tickrate") {
+        config.display.tick_rate = *app.get_one("tickrate").unwrap_or(&1000);
+    }
+
+    // Interface filter
+    if sub_args.contains_id("interfaces") {
+        match sub_args.get_many::<String>("interfaces") {
+            Some(interfaces) => {
+                config.network.interfaces = interfaces.cloned().collect();
+            }
+            None => {
+                config.network.interfaces = Vec::new();
+            }
+        }
+    }
+
+    // Filter expression
+    let filter: Option<FilterExpr> = sub_args.get_one::<FilterExpr>("filter").cloned();
+
+    // Metrics options
+    let metrics_addr: SocketAddr = match sub_args.get_one::<SocketAddr>("metrics") {
+        Some(addr) => *addr,
+        None => crate::metrics::DEFAULT_METRICS_ADDR.parse()?,
+    };
+    if let Some(max_label_values) = sub_args.get_one::<usize>("max-label-values") {
+        config.metrics.max_label_values = *max_label_values;
+    }
+
+    // Init logger
+    let log_file_path = if let Some(file_path) = &config.logging.file_path {
+        // Convert to PathBuf
+        Path::new(&file_path).to_path_buf()
+    } else {
+        crate::sys::get_user_file_path(crate::thread_log::DEFAULT_LOG_FILE_PATH).unwrap()
+    };
+    let log_file: File = if log_file_path.exists() {
+        File::options().write(true).open(&log_file_path)?
+    } else {
+        File::create(&log_file_path)?
+    };
+    let mut log_config_builder = simplelog::ConfigBuilder::default();
+    log_config_builder.set_time_format_rfc3339();
+    if let Some(offset) = crate::time::get_local_offset() {
+        log_config_builder.set_time_offset(offset);
+    }
+    let default_log_config = log_config_builder.build();
+
+    // Init logger with file and terminal output
+    // debug build: log to terminal and file
+    // release build: log to file only
+    if cfg!(debug_assertions) {
+        simplelog::CombinedLogger::init(vec![
+            simplelog::TermLogger::new(
+                simplelog::LevelFilter::Info,
+                default_log_config.clone(),
+                simplelog::TerminalMode::Stderr,
+                simplelog::ColorChoice::Auto,
+            ),
+            simplelog::WriteLogger::new(
+                config.logging.level.to_level_filter(),
+                default_log_config,
+                log_file,
+            ),
+        ])?;
+    } else {
+        simplelog::CombinedLogger::init(vec![simplelog::WriteLogger::new(
+            config.logging.level.to_level_filter(),
+            default_log_config,
+            log_file,
+        )])?;
+    }
+    // Start threads
+    let mut threads: Vec<thread::JoinHandle<()>> = vec![];
+
+    let netstat_strage: Arc<NetStatStrage> = Arc::new(NetStatStrage::new());
+    netstat_strage.load_labels(&config.labels);
+    let mut netstat_strage_socket = Arc::clone(&netstat_strage);
+    let mut netstat_strage_metrics = Arc::clone(&netstat_strage);
+
+    let target_interfaces: Vec<netdev::Interface> = if config.network.interfaces.is_empty() {
+        crate::net::interface::get_usable_interfaces()
+    } else {
+        crate::net::interface::get_interfaces_by_name(&config.network.interfaces)
+    };
+    netstat_strage.add_interfaces(
+        target_interfaces
+            .iter()
+            .map(|iface| iface.name.clone())
+            .collect(),
+    );
+    let mut pcap_thread_index = 0;
+    let pcap_handlers = target_interfaces
+        .iter()
+        .map(|iface| {
+            let mut netstat_strage_pcap = Arc::clone(&netstat_strage);
+            let iface = iface.clone();
+            let mut pcap_option = crate::net::pcap::PacketCaptureOptions::from_interface(&iface);
+            pcap_option.filter = filter.clone();
+            let thread_name = format!("pcap-thread-{}", iface.name.clone());
+            let pcap_thread = thread::Builder::new().name(thread_name.clone());
+            let pcap_handler = pcap_thread.spawn(move || {
+                if pcap_thread_index == 0 {
+                    netstat_strage_pcap.load_ipdb();
+                    netstat_strage_pcap.load_oui_db();
+                }
+                crate::net::pcap::start_background_capture(
+                    pcap_option,
+                    &mut netstat_strage_pcap,
+                    iface,
+                );
+            });
+            thread_log!(info, "start thread {}", thread_name);
+            pcap_thread_index += 1;
+            pcap_handler
+        })
+        .collect::<Vec<_>>();
+
+    for pcap_handler in pcap_handlers {
+        match pcap_handler {
+            Ok(handle) => {
+                threads.push(handle);
+            }
+            Err(e) => {
+                thread_log!(error, "Error: {:?}", e);
+            }
+        }
+    }
+
+    let socket_handler = thread::spawn(move || {
+        thread_log!(info, "start thread socket_info_update");
+        crate::net::socket::start_socket_info_update(&mut netstat_strage_socket);
+    });
+    threads.push(socket_handler);
+
+    let registry: Arc<Mutex<MetricsRegistry>> = Arc::new(Mutex::new(MetricsRegistry::new(
+        config.metrics.max_label_values,
+    )));
+    let registry_update = Arc::clone(&registry);
+    let interval = Duration::from_millis(config.display.tick_rate);
+    let entry_ttl = Duration::from_millis(config.network.entry_ttl);
+    let metrics_handler = thread::spawn(move || {
+        thread_log!(info, "start thread metrics_update");
+        crate::metrics::start_metrics_update(
+            &mut netstat_strage_metrics,
+            registry_update,
+            interval,
+            entry_ttl,
+        );
+    });
+    threads.push(metrics_handler);
+
+    if config.accounting.enabled {
+        let mut netstat_strage_accounting = Arc::clone(&netstat_strage);
+        let hourly_retention = config.accounting.hourly_retention();
+        let daily_retention = config.accounting.daily_retention();
+        let accounting_handler = thread::spawn(move || {
+            thread_log!(info, "start thread traffic_accounting");
+            crate::db::traffic::start_traffic_accounting(
+                &mut netstat_strage_accounting,
+                hourly_retention,
+                daily_retention,
+            );
+        });
+        threads.push(accounting_handler);
+    }
+
+    println!(
+        "Serving metrics on http://{}{}",
+        metrics_addr,
+        crate::metrics::METRICS_PATH
+    );
+    crate::metrics::start_metrics_server(metrics_addr, registry)?;
+    Ok(())
+}
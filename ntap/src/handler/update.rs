@@ -1,58 +1,366 @@
+use crate::db::manifest::{sha256_file, DbFileEntry, DbManifest, DbSource, DB_MANIFEST_FILE_NAME};
 use crate::{config::AppConfig, thread_log};
+use clap::ArgMatches;
+use comfy_table::presets::NOTHING;
+use comfy_table::*;
 use std::{
+    error::Error,
     fs::File,
     path::{Path, PathBuf},
 };
 
-fn download_file(
-    url: &str,
-    save_dir_path: PathBuf,
-    file_name: &str,
-) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    // Check and create download dir
-    if !save_dir_path.exists() {
-        std::fs::create_dir_all(&save_dir_path)?;
+/// Staging directory for downloads, inside the database directory so that the swap is a rename.
+const UPDATE_STAGING_DIR_NAME: &str = ".update";
+const UPDATE_BACKUP_DIR_NAME: &str = "backup";
+
+/// Per-file URLs of the default source, used while it does not serve a manifest.
+const DEFAULT_DB_FILE_URLS: [(&str, &str); 9] = [
+    (ntap_db_as::AS_BIN_NAME, ntap_db_as::AS_R2_URL),
+    (
+        ntap_db_country::COUNTRY_BIN_NAME,
+        ntap_db_country::COUNTRY_R2_URL,
+    ),
+    (
+        ntap_db_ipv4_asn::IPV4_ASN_BIN_NAME,
+        ntap_db_ipv4_asn::IPV4_ASN_R2_URL,
+    ),
+    (
+        ntap_db_ipv4_country::IPV4_COUNTRY_BIN_NAME,
+        ntap_db_ipv4_country::IPV4_COUNTRY_R2_URL,
+    ),
+    (
+        ntap_db_ipv6_asn::IPV6_ASN_BIN_NAME,
+        ntap_db_ipv6_asn::IPV6_ASN_R2_URL,
+    ),
+    (
+        ntap_db_ipv6_country::IPV6_COUNTRY_BIN_NAME,
+        ntap_db_ipv6_country::IPV6_COUNTRY_R2_URL,
+    ),
+    (ntap_db_oui::OUI_BIN_NAME, ntap_db_oui::OUI_R2_URL),
+    (
+        ntap_db_tcp_service::TCP_SERVICE_BIN_NAME,
+        ntap_db_tcp_service::TCP_SERVICE_R2_URL,
+    ),
+    (
+        ntap_db_udp_service::UDP_SERVICE_BIN_NAME,
+        ntap_db_udp_service::UDP_SERVICE_R2_URL,
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DbFileStatus {
+    UpToDate,
+    Outdated,
+    Missing,
+    /// The local file does not match any known version (e.g. built with ntap db build)
+    Modified,
+}
+
+impl DbFileStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            DbFileStatus::UpToDate => "up to date",
+            DbFileStatus::Outdated => "outdated",
+            DbFileStatus::Missing => "missing",
+            DbFileStatus::Modified => "modified",
+        }
+    }
+}
+
+struct DbFileState {
+    entry: DbFileEntry,
+    installed_version: Option<String>,
+    status: DbFileStatus,
+}
+
+fn get_file_state(
+    database_dir: &Path,
+    entry: &DbFileEntry,
+    installed_manifest: &Option<DbManifest>,
+) -> DbFileState {
+    let path = database_dir.join(&entry.name);
+    let installed_entry = installed_manifest
+        .as_ref()
+        .and_then(|manifest| manifest.get(&entry.name));
+    let local_sha256 = if path.exists() {
+        match sha256_file(&path) {
+            Ok(sha256) => Some(sha256),
+            Err(e) => {
+                thread_log!(error, "{}: {:?}", path.display(), e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // The installed version is only trusted while the file still matches its hash
+    let installed_version = match (&local_sha256, installed_entry) {
+        (Some(local_sha256), Some(installed_entry))
+            if installed_entry.sha256.eq_ignore_ascii_case(local_sha256) =>
+        {
+            Some(installed_entry.version.clone())
+        }
+        (Some(local_sha256), _) if entry.sha256.eq_ignore_ascii_case(local_sha256) => {
+            Some(entry.version.clone())
+        }
+        _ => None,
+    };
+    let status = match &local_sha256 {
+        None => DbFileStatus::Missing,
+        Some(local_sha256) => {
+            if entry.sha256.eq_ignore_ascii_case(local_sha256) {
+                DbFileStatus::UpToDate
+            } else if installed_version.is_some() {
+                DbFileStatus::Outdated
+            } else {
+                DbFileStatus::Modified
+            }
+        }
+    };
+    DbFileState {
+        entry: entry.clone(),
+        installed_version,
+        status,
     }
-    let rt = match tokio::runtime::Runtime::new() {
-        Ok(rt) => rt,
-        Err(e) => {
-            return Err(Box::new(e));
+}
+
+/// Fetch the manifest of the source. Returns None if the source has no manifest.
+fn fetch_manifest(source: &DbSource) -> Result<Option<DbManifest>, Box<dyn Error>> {
+    let location = source.location(DB_MANIFEST_FILE_NAME);
+    thread_log!(info, "Fetching manifest from {}", location);
+    let json: Option<String> = match source {
+        DbSource::Http(_) => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(async {
+                let response = reqwest::get(&location).await?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok::<Option<String>, reqwest::Error>(None);
+                }
+                let response = response.error_for_status()?;
+                Ok(Some(response.text().await?))
+            })?
         }
+        DbSource::Dir(_) => match std::fs::read_to_string(&location) {
+            Ok(json) => Some(json),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(Box::new(e)),
+        },
     };
-    let save_file_path: PathBuf = save_dir_path.join(file_name);
+    match json {
+        Some(json) => match DbManifest::from_json(&json) {
+            Ok(manifest) => Ok(Some(manifest)),
+            Err(e) => Err(format!("Invalid manifest {}: {}", location, e).into()),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Download the file over HTTP(S) with a progress bar.
+fn download_file(url: &str, name: &str, dest_path: &Path) -> Result<(), Box<dyn Error>> {
+    let rt = tokio::runtime::Runtime::new()?;
     rt.block_on(async {
-        thread_log!(info, "Downloading {} from {}", file_name, url);
+        thread_log!(info, "Downloading {} from {}", name, url);
         // create a channel for progress
         let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(100);
-        let file_url: String = url.to_string();
-        let file_path: PathBuf = save_file_path.clone();
-        // spawn a task to handle the progress
-        tokio::spawn(async move {
-            let _ = crate::net::http::download_file_with_progress(file_url, file_path, progress_tx).await;
-        });
+        let download = crate::net::http::download_file_with_progress(
+            url.to_string(),
+            dest_path.to_path_buf(),
+            progress_tx,
+        );
         // Display progress with indicatif
         let bar = indicatif::ProgressBar::new(1000);
         bar.set_style(indicatif::ProgressStyle::default_bar().template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})").progress_chars("#>-"));
-        while let Some(progress) = progress_rx.recv().await {
-            match progress {
-                crate::net::http::DownloadProgress::ContentLength(content_length) => {
-                    thread_log!(info, "File URL: {}, Content-Length: {}", url, content_length);
-                    bar.set_length(content_length);
+        let progress = async {
+            while let Some(progress) = progress_rx.recv().await {
+                match progress {
+                    crate::net::http::DownloadProgress::ContentLength(content_length) => {
+                        thread_log!(info, "File URL: {}, Content-Length: {}", url, content_length);
+                        bar.set_length(content_length);
+                    }
+                    crate::net::http::DownloadProgress::Downloaded(downloaded) => {
+                        bar.set_position(downloaded);
+                    }
                 }
-                crate::net::http::DownloadProgress::Downloaded(downloaded) => {
-                    bar.set_position(downloaded);
+            }
+        };
+        let (result, _) = futures::future::join(download, progress).await;
+        bar.finish();
+        result
+    })
+}
+
+/// Fetch the file from the source and verify the size and SHA-256.
+fn fetch_file(
+    source: &DbSource,
+    entry: &DbFileEntry,
+    dest_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let location = source.location(&entry.name);
+    match source {
+        DbSource::Http(_) => download_file(&location, &entry.name, dest_path)?,
+        DbSource::Dir(_) => {
+            thread_log!(info, "Copying {} from {}", entry.name, location);
+            std::fs::copy(&location, dest_path)?;
+        }
+    }
+    let size = std::fs::metadata(dest_path)?.len();
+    if size != entry.size {
+        return Err(format!(
+            "{}: size mismatch (expected {}, got {})",
+            entry.name, entry.size, size
+        )
+        .into());
+    }
+    let sha256 = sha256_file(dest_path)?;
+    if !sha256.eq_ignore_ascii_case(&entry.sha256) {
+        return Err(format!(
+            "{}: SHA-256 mismatch (expected {}, got {})",
+            entry.name, entry.sha256, sha256
+        )
+        .into());
+    }
+    thread_log!(info, "Verified {} ({})", entry.name, entry.version);
+    Ok(())
+}
+
+/// Move the verified files into the database directory.
+/// The replaced files are moved to the backup directory first and restored if a rename fails.
+fn swap_files(
+    database_dir: &Path,
+    staging_dir: &Path,
+    names: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let backup_dir = staging_dir.join(UPDATE_BACKUP_DIR_NAME);
+    std::fs::create_dir_all(&backup_dir)?;
+    // (name, has_backup)
+    let mut swapped: Vec<(&String, bool)> = Vec::new();
+    let mut result: Result<(), std::io::Error> = Ok(());
+    for name in names {
+        let path = database_dir.join(name);
+        let has_backup = path.exists();
+        if has_backup {
+            if let Err(e) = std::fs::rename(&path, backup_dir.join(name)) {
+                result = Err(e);
+                break;
+            }
+        }
+        if let Err(e) = std::fs::rename(staging_dir.join(name), &path) {
+            if has_backup {
+                let _ = std::fs::rename(backup_dir.join(name), &path);
+            }
+            result = Err(e);
+            break;
+        }
+        swapped.push((name, has_backup));
+    }
+    if let Err(e) = result {
+        thread_log!(error, "Swap failed, rolling back: {:?}", e);
+        for (name, has_backup) in swapped.iter().rev() {
+            let path = database_dir.join(name);
+            if *has_backup {
+                if let Err(e) = std::fs::rename(backup_dir.join(name), &path) {
+                    thread_log!(error, "Rollback of {} failed: {:?}", name, e);
                 }
+            } else {
+                let _ = std::fs::remove_file(&path);
             }
         }
-        bar.finish();
-        thread_log!(info, "Downloaded {} to {}", file_name, save_file_path.display());
-    });
-    Ok(save_file_path)
+        return Err(Box::new(e));
+    }
+    Ok(())
+}
+
+/// Output of ntap update --check
+fn check_report(states: &[DbFileState]) -> String {
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["File", "Installed", "Available", "Status"]);
+    for state in states {
+        table.add_row(vec![
+            Cell::new(&state.entry.name),
+            Cell::new(state.installed_version.as_deref().unwrap_or("-")),
+            Cell::new(&state.entry.version),
+            Cell::new(state.status.name()),
+        ]);
+    }
+    let stale_count = states
+        .iter()
+        .filter(|state| state.status != DbFileStatus::UpToDate)
+        .count();
+    let summary = if stale_count == 0 {
+        String::from("All databases are up to date.")
+    } else {
+        format!(
+            "{} database file(s) can be updated. Run ntap update to update them.",
+            stale_count
+        )
+    };
+    format!("{}\n\n{}", table, summary)
+}
+
+/// Fetch the files into the staging directory, then swap them into the database directory.
+/// Nothing is replaced unless every file was fetched.
+fn stage_and_swap<F>(
+    database_dir: &Path,
+    names: &[String],
+    mut fetch: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(&str, &Path) -> Result<(), Box<dyn Error>>,
+{
+    let staging_dir = database_dir.join(UPDATE_STAGING_DIR_NAME);
+    if staging_dir.exists() {
+        std::fs::remove_dir_all(&staging_dir)?;
+    }
+    std::fs::create_dir_all(&staging_dir)?;
+    for name in names {
+        if let Err(e) = fetch(name, &staging_dir.join(name)) {
+            let _ = std::fs::remove_dir_all(&staging_dir);
+            return Err(format!("Update aborted, no files were changed: {}", e).into());
+        }
+    }
+    let result = swap_files(database_dir, &staging_dir, names);
+    let _ = std::fs::remove_dir_all(&staging_dir);
+    if let Err(e) = result {
+        return Err(format!("Update failed, previous files were restored: {}", e).into());
+    }
+    Ok(())
 }
 
+/// ntap update [--check] [--mirror <url|dir>]
+pub fn update(app: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let sub_args = match app.subcommand_matches("update") {
+        Some(matches) => matches,
+        None => {
+            eprintln!("Error: Could not get subcommand matches");
+            return Ok(());
+        }
+    };
+    let check_only: bool = sub_args.get_flag("check");
+    let mirror: Option<String> = sub_args.get_one::<String>("mirror").cloned();
+    update_db_files(mirror, check_only)
+}
+
+/// Download the missing or outdated database files from the configured source.
 pub fn download_db_files() -> Result<(), Box<dyn std::error::Error>> {
+    update_db_files(None, false)
+}
+
+fn update_db_files(
+    mirror: Option<String>,
+    check_only: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Load AppConfig
     let config = AppConfig::load();
+    let source: DbSource = match &mirror {
+        Some(mirror) => DbSource::from_str(mirror),
+        None => match &config.update.mirror {
+            Some(mirror) => DbSource::from_str(mirror),
+            None => DbSource::default(),
+        },
+    };
     // Init logger
     let log_file_path = if let Some(file_path) = &config.logging.file_path {
         // Convert to PathBuf
@@ -89,106 +397,286 @@ pub fn download_db_files() -> Result<(), Box<dyn std::error::Error>> {
         ),
     ])?;
 
-    let database_dir = crate::sys::get_database_dir_path().unwrap();
-    // AS
-    match download_file(
-        ntap_db_as::AS_R2_URL,
-        database_dir.clone(),
-        ntap_db_as::AS_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
+    let database_dir: PathBuf = match crate::sys::get_database_dir_path() {
+        Some(database_dir) => database_dir,
+        None => {
+            return Err("Could not get database directory path".into());
         }
-    }
-    // Country
-    match download_file(
-        ntap_db_country::COUNTRY_R2_URL,
-        database_dir.clone(),
-        ntap_db_country::COUNTRY_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
+    };
+    // Only the default source may still lack a manifest
+    let legacy_fallback = mirror.is_none() && config.update.mirror.is_none();
+    let report = update_from_source(&source, &database_dir, check_only, legacy_fallback)?;
+    println!("{}", report);
+    Ok(())
+}
+
+/// Check or update the database files in database_dir. Returns the message to show.
+/// With legacy_fallback, a source without a manifest is read through the per-file URLs.
+fn update_from_source(
+    source: &DbSource,
+    database_dir: &Path,
+    check_only: bool,
+    legacy_fallback: bool,
+) -> Result<String, Box<dyn Error>> {
+    let manifest = match fetch_manifest(source)? {
+        Some(manifest) => manifest,
+        None if legacy_fallback => {
+            thread_log!(
+                warn,
+                "{} was not found. Downloading the database files without version and SHA-256 checks.",
+                source.location(DB_MANIFEST_FILE_NAME)
+            );
+            return update_legacy_files(database_dir, check_only);
         }
-    }
-    // IPv4 ASN
-    match download_file(
-        ntap_db_ipv4_asn::IPV4_ASN_R2_URL,
-        database_dir.clone(),
-        ntap_db_ipv4_asn::IPV4_ASN_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
+        None => {
+            return Err(format!(
+                "No manifest found at {}",
+                source.location(DB_MANIFEST_FILE_NAME)
+            )
+            .into());
         }
+    };
+    let installed_manifest = DbManifest::load_installed(database_dir);
+    let states: Vec<DbFileState> = manifest
+        .files
+        .iter()
+        .map(|entry| get_file_state(database_dir, entry, &installed_manifest))
+        .collect();
+    if check_only {
+        return Ok(check_report(&states));
     }
-    // IPv4 Country
-    match download_file(
-        ntap_db_ipv4_country::IPV4_COUNTRY_R2_URL,
-        database_dir.clone(),
-        ntap_db_ipv4_country::IPV4_COUNTRY_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
-        }
+    let stale_entries: Vec<&DbFileEntry> = states
+        .iter()
+        .filter(|state| state.status != DbFileStatus::UpToDate)
+        .map(|state| &state.entry)
+        .collect();
+    if stale_entries.is_empty() {
+        manifest.save(database_dir)?;
+        return Ok(String::from("All databases are up to date."));
     }
-    // IPv6 ASN
-    match download_file(
-        ntap_db_ipv6_asn::IPV6_ASN_R2_URL,
-        database_dir.clone(),
-        ntap_db_ipv6_asn::IPV6_ASN_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
+    // Download everything to the staging directory first. Nothing is replaced unless all files verify.
+    let names: Vec<String> = stale_entries
+        .iter()
+        .map(|entry| entry.name.clone())
+        .collect();
+    stage_and_swap(database_dir, &names, |name, dest_path| {
+        match manifest.get(name) {
+            Some(entry) => fetch_file(source, entry, dest_path),
+            None => Err(format!("{} is not in the manifest", name).into()),
         }
-    }
-    // IPv6 Country
-    match download_file(
-        ntap_db_ipv6_country::IPV6_COUNTRY_R2_URL,
-        database_dir.clone(),
-        ntap_db_ipv6_country::IPV6_COUNTRY_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
+    })?;
+    manifest.save(database_dir)?;
+    Ok(format!(
+        "Successfully updated {} ntap database file(s).",
+        names.len()
+    ))
+}
+
+/// Download every database file from its per-file URL. The files cannot be verified.
+fn update_legacy_files(database_dir: &Path, check_only: bool) -> Result<String, Box<dyn Error>> {
+    if check_only {
+        let missing: Vec<&str> = DEFAULT_DB_FILE_URLS
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| !database_dir.join(name).exists())
+            .collect();
+        let mut report = String::from(
+            "The update source has no manifest, so installed versions cannot be checked.",
+        );
+        if missing.is_empty() {
+            report.push_str("\nAll database files are present.");
+        } else {
+            report.push_str(&format!("\nMissing: {}", missing.join(", ")));
         }
+        return Ok(report);
     }
-    // OUI
-    match download_file(
-        ntap_db_oui::OUI_R2_URL,
-        database_dir.clone(),
-        ntap_db_oui::OUI_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
-        }
+    let names: Vec<String> = DEFAULT_DB_FILE_URLS
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    stage_and_swap(
+        database_dir,
+        &names,
+        |name, dest_path| match DEFAULT_DB_FILE_URLS
+            .iter()
+            .find(|(file_name, _)| *file_name == name)
+        {
+            Some((_, url)) => download_file(url, name, dest_path),
+            None => Err(format!("No URL for {}", name).into()),
+        },
+    )?;
+    Ok(format!(
+        "Successfully downloaded {} ntap database file(s).",
+        names.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::manifest::DB_FILE_NAMES;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    const FILE_A: &str = DB_FILE_NAMES[0];
+    const FILE_B: &str = DB_FILE_NAMES[2];
+    const FILE_C: &str = DB_FILE_NAMES[6];
+
+    // Serve the files in dir over HTTP until the test process ends
+    fn serve_dir(dir: PathBuf) -> DbSource {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+                        break;
+                    }
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let (status, body) = match std::fs::read(dir.join(path.trim_start_matches('/'))) {
+                    Ok(body) => ("200 OK", body),
+                    Err(_) => ("404 Not Found", Vec::new()),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+        DbSource::Http(format!("http://{}", addr))
     }
-    // TCP Service
-    match download_file(
-        ntap_db_tcp_service::TCP_SERVICE_R2_URL,
-        database_dir.clone(),
-        ntap_db_tcp_service::TCP_SERVICE_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
-        }
+
+    // (mirror directory, database directory)
+    fn test_dirs(name: &str) -> (PathBuf, PathBuf) {
+        let base =
+            std::env::temp_dir().join(format!("ntap-update-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&base);
+        let mirror_dir = base.join("mirror");
+        let database_dir = base.join("db");
+        std::fs::create_dir_all(&mirror_dir).unwrap();
+        std::fs::create_dir_all(&database_dir).unwrap();
+        (mirror_dir, database_dir)
     }
-    // UDP Service
-    match download_file(
-        ntap_db_udp_service::UDP_SERVICE_R2_URL,
-        database_dir.clone(),
-        ntap_db_udp_service::UDP_SERVICE_BIN_NAME,
-    ) {
-        Ok(_) => {}
-        Err(e) => {
-            thread_log!(error, "{:?}", e);
+
+    fn publish(mirror_dir: &Path, files: &[(&str, &str)]) {
+        for (name, content) in files {
+            std::fs::write(mirror_dir.join(name), content).unwrap();
         }
+        DbManifest::from_dir(mirror_dir, "2024-06-01")
+            .unwrap()
+            .save(mirror_dir)
+            .unwrap();
+    }
+
+    fn read(dir: &Path, name: &str) -> String {
+        std::fs::read_to_string(dir.join(name)).unwrap()
+    }
+
+    #[test]
+    fn update_over_http() {
+        let (mirror_dir, database_dir) = test_dirs("update");
+        publish(&mirror_dir, &[(FILE_A, "new a"), (FILE_B, "new b")]);
+        std::fs::write(database_dir.join(FILE_A), "old a").unwrap();
+        let source = serve_dir(mirror_dir.clone());
+        let message = update_from_source(&source, &database_dir, false, false).unwrap();
+        assert_eq!(message, "Successfully updated 2 ntap database file(s).");
+        assert_eq!(read(&database_dir, FILE_A), "new a");
+        assert_eq!(read(&database_dir, FILE_B), "new b");
+        assert!(database_dir.join(DB_MANIFEST_FILE_NAME).exists());
+        assert!(!database_dir.join(UPDATE_STAGING_DIR_NAME).exists());
+        let report = update_from_source(&source, &database_dir, true, false).unwrap();
+        assert!(report.ends_with("All databases are up to date."));
+        let _ = std::fs::remove_dir_all(mirror_dir.parent().unwrap());
+    }
+
+    #[test]
+    fn check_output() {
+        let (mirror_dir, database_dir) = test_dirs("check");
+        publish(&mirror_dir, &[(FILE_A, "new a"), (FILE_B, "new b")]);
+        std::fs::write(database_dir.join(FILE_A), "old a").unwrap();
+        let source = serve_dir(mirror_dir.clone());
+        let report = update_from_source(&source, &database_dir, true, false).unwrap();
+        let lines: Vec<&str> = report.lines().map(|line| line.trim_end()).collect();
+        assert!(lines[0].contains("File") && lines[0].contains("Installed"));
+        assert!(lines[0].contains("Available") && lines[0].contains("Status"));
+        let line_a = lines.iter().find(|line| line.contains(FILE_A)).unwrap();
+        assert!(line_a.contains("2024-06-01") && line_a.ends_with("modified"));
+        let line_b = lines.iter().find(|line| line.contains(FILE_B)).unwrap();
+        assert!(line_b.ends_with("missing"));
+        assert_eq!(
+            *lines.last().unwrap(),
+            "2 database file(s) can be updated. Run ntap update to update them."
+        );
+        // --check changes nothing
+        assert_eq!(read(&database_dir, FILE_A), "old a");
+        assert!(!database_dir.join(FILE_B).exists());
+        assert!(!database_dir.join(DB_MANIFEST_FILE_NAME).exists());
+        let _ = std::fs::remove_dir_all(mirror_dir.parent().unwrap());
+    }
+
+    #[test]
+    fn hash_mismatch_aborts() {
+        let (mirror_dir, database_dir) = test_dirs("mismatch");
+        publish(&mirror_dir, &[(FILE_A, "new a"), (FILE_B, "new b")]);
+        // Same size, different content
+        std::fs::write(mirror_dir.join(FILE_B), "bad b").unwrap();
+        std::fs::write(database_dir.join(FILE_A), "old a").unwrap();
+        std::fs::write(database_dir.join(FILE_B), "old b").unwrap();
+        let source = serve_dir(mirror_dir.clone());
+        let err = update_from_source(&source, &database_dir, false, false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no files were changed"), "{}", err);
+        assert!(err.contains("SHA-256 mismatch"), "{}", err);
+        assert_eq!(read(&database_dir, FILE_A), "old a");
+        assert_eq!(read(&database_dir, FILE_B), "old b");
+        assert!(!database_dir.join(DB_MANIFEST_FILE_NAME).exists());
+        assert!(!database_dir.join(UPDATE_STAGING_DIR_NAME).exists());
+        let _ = std::fs::remove_dir_all(mirror_dir.parent().unwrap());
+    }
+
+    #[test]
+    fn missing_manifest() {
+        let (mirror_dir, database_dir) = test_dirs("no-manifest");
+        std::fs::write(mirror_dir.join(FILE_A), "new a").unwrap();
+        let source = serve_dir(mirror_dir.clone());
+        let err = update_from_source(&source, &database_dir, false, false)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("No manifest found at http://127.0.0.1:"),
+            "{}",
+            err
+        );
+        assert!(!database_dir.join(FILE_A).exists());
+        let _ = std::fs::remove_dir_all(mirror_dir.parent().unwrap());
+    }
+
+    #[test]
+    fn swap_failure_rolls_back() {
+        let (staging_dir, database_dir) = test_dirs("swap");
+        std::fs::write(database_dir.join(FILE_A), "old a").unwrap();
+        std::fs::write(database_dir.join(FILE_B), "old b").unwrap();
+        std::fs::write(staging_dir.join(FILE_A), "new a").unwrap();
+        std::fs::write(staging_dir.join(FILE_C), "new c").unwrap();
+        // FILE_B is missing from the staging directory, so its rename fails after A and C were swapped
+        let names = vec![FILE_A.to_string(), FILE_C.to_string(), FILE_B.to_string()];
+        assert!(swap_files(&database_dir, &staging_dir, &names).is_err());
+        assert_eq!(read(&database_dir, FILE_A), "old a");
+        assert_eq!(read(&database_dir, FILE_B), "old b");
+        assert!(!database_dir.join(FILE_C).exists());
+        let _ = std::fs::remove_dir_all(staging_dir.parent().unwrap());
     }
-    log::info!("Successfully downloaded ntap databases.");
-    Ok(())
 }
@@ -0,0 +1,143 @@
+use crate::alert::{AlertEngine, TickTraffic};
+use crate::config::AppConfig;
+use crate::net::host::HostDisplayInfo;
+use crate::net::socket::SocketDisplayInfo;
+use crate::net::stat::{NetStatData, NetStatStrage, Overview};
+use crate::process::ProcessDisplayInfo;
+use crate::sys;
+use crate::thread_log;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Interactive terminal UI.
+    Tui,
+    /// One JSON object per tick (newline delimited JSON).
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub fn from_str(s: &str) -> OutputFormat {
+        match s {
+            "ndjson" => OutputFormat::Ndjson,
+            _ => OutputFormat::Tui,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    /// Output file path. If None, write to stdout.
+    pub output: Option<PathBuf>,
+    /// Number of ticks to emit before exiting. If None, run until interrupted.
+    pub count: Option<usize>,
+    /// Set when the capture file has been read. The output stops after the next tick.
+    pub capture_finished: Option<Arc<AtomicBool>>,
+}
+
+impl HeadlessOptions {
+    pub fn new() -> Self {
+        HeadlessOptions {
+            output: None,
+            count: None,
+            capture_finished: None,
+        }
+    }
+}
+
+/// Snapshot of the network statistics emitted on each tick.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HeadlessRecord {
+    /// Time of the tick. RFC3339 format.
+    pub timestamp: String,
+    pub interface: String,
+    pub overview: Overview,
+    pub remote_hosts: Vec<HostDisplayInfo>,
+    pub connections: Vec<SocketDisplayInfo>,
+    pub processes: Vec<ProcessDisplayInfo>,
+}
+
+impl HeadlessRecord {
+    pub fn from_netstat_data(netstat_data: &NetStatData, config: &AppConfig) -> Self {
+        HeadlessRecord {
+            timestamp: sys::get_sysdate(),
+            interface: netstat_data.default_interface.name.clone(),
+            overview: netstat_data.get_overview(),
+            remote_hosts: netstat_data.get_remote_hosts(Some(config.display.top_remote_hosts)),
+            connections: netstat_data.get_connections(Some(config.display.connection_count)),
+            processes: netstat_data.get_processes(None),
+        }
+    }
+}
+
+/// Run without a terminal UI and write one JSON object per tick.
+pub fn run(
+    app_config: AppConfig,
+    options: HeadlessOptions,
+    netstat_strage: &mut Arc<NetStatStrage>,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(BufWriter::new(
+            File::options().create(true).append(true).open(path)?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let tick_rate = Duration::from_millis(app_config.display.tick_rate);
+    let entry_ttl = Duration::from_millis(app_config.network.entry_ttl);
+    let mut netstat_data = NetStatData::new();
+    let mut alert_engine = AlertEngine::new(&app_config.alerts);
+    let mut last_tick = Instant::now();
+    let mut last_clear = Instant::now();
+    let mut tick_count: usize = 0;
+    loop {
+        thread::sleep(tick_rate.saturating_sub(last_tick.elapsed()));
+        last_tick = Instant::now();
+        if last_clear.elapsed() >= entry_ttl {
+            netstat_data.remove_old_entries(entry_ttl);
+            last_clear = Instant::now();
+        }
+        // Checked before taking the data, so the last tick has every packet of the file
+        let capture_finished = match &options.capture_finished {
+            Some(capture_finished) => capture_finished.load(Ordering::Acquire),
+            None => false,
+        };
+        let tick_data = netstat_strage.clone_data_and_reset();
+        let tick_traffic = TickTraffic::from_netstat_data(&tick_data);
+        netstat_data.merge(tick_data, tick_rate);
+        let notifications = alert_engine.evaluate(&tick_traffic, &netstat_data, tick_rate);
+        netstat_data.add_notifications(notifications, app_config.alerts.max_notifications);
+        let record = HeadlessRecord::from_netstat_data(&netstat_data, &app_config);
+        let result = serde_json::to_writer(&mut writer, &record)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"))
+            .and_then(|_| writer.flush());
+        match result {
+            Ok(_) => {}
+            Err(e) => {
+                // The reader went away (e.g. piped to head)
+                if e.kind() == io::ErrorKind::BrokenPipe {
+                    return Ok(());
+                }
+                thread_log!(error, "headless output error: {:?}", e);
+                return Err(e.into());
+            }
+        }
+        tick_count += 1;
+        if let Some(count) = options.count {
+            if tick_count >= count {
+                return Ok(());
+            }
+        }
+        if capture_finished {
+            return Ok(());
+        }
+    }
+}
@@ -1,8 +1,12 @@
+mod alert;
 mod config;
 mod db;
 mod deps;
 mod handler;
+mod headless;
+mod metrics;
 mod net;
+mod netflow;
 mod notification;
 mod process;
 mod sys;
@@ -15,7 +19,8 @@ use clap::{crate_description, crate_name, crate_version, value_parser};
 use clap::{Arg, ArgMatches, Command};
 use handler::AppCommands;
 use std::error::Error;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Parse command line arguments
@@ -31,7 +36,12 @@ fn main() -> Result<(), Box<dyn Error>> {
         AppCommands::Route => handler::route::show_routes(),
         AppCommands::Socket => handler::socket::show_socket_info(&args),
         AppCommands::IpInfo => handler::ip_info::show_public_ip_info(),
-        AppCommands::Update => handler::update::download_db_files(),
+        AppCommands::Lookup => handler::lookup::lookup(&args),
+        AppCommands::Serve => handler::serve::serve(&args),
+        AppCommands::Export => handler::export::export(&args),
+        AppCommands::Report => handler::report::show_report(&args),
+        AppCommands::Update => handler::update::update(&args),
+        AppCommands::Db => handler::db::db(&args),
         AppCommands::Default => {
             // If no subcommand is specified, enter stat mode by default
             handler::stat::show_stat_default(&args)
@@ -80,6 +90,56 @@ fn parse_args() -> ArgMatches {
                 .value_delimiter(',')
                 .value_parser(value_parser!(u16))
         )
+        .arg(
+            Arg::new("filter")
+                .help("Specify a filter expression. Example: ntap -f \"tcp and (dst port 443 or src net 10.0.0.0/8) and not host 1.1.1.1\"")
+                .short('f')
+                .long("filter")
+                .value_name("expression")
+                .value_parser(net::filter::parse_filter_expr)
+        )
+        .arg(
+            Arg::new("file")
+                .help("Read packets from a pcap or pcapng file instead of capturing. Example: ntap --file capture.pcapng")
+                .long("file")
+                .value_name("file_path")
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("realtime")
+                .help("Replay the capture file with the original packet timing")
+                .long("realtime")
+                .requires("file")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("format")
+                .help("Output format. ndjson writes one JSON object per tick without the terminal UI")
+                .long("format")
+                .value_name("format")
+                .value_parser(["tui", "ndjson"])
+        )
+        .arg(
+            Arg::new("headless")
+                .help("Run without the terminal UI. Same as --format ndjson")
+                .long("headless")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("output")
+                .help("Write the headless output to a file instead of stdout. Example: ntap --headless -o stats.ndjson")
+                .short('o')
+                .long("output")
+                .value_name("file_path")
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("count")
+                .help("Exit after writing the number of ticks in headless mode")
+                .long("count")
+                .value_name("count")
+                .value_parser(value_parser!(usize))
+        )
         .arg(
             Arg::new("tickrate")
                 .help("Time in milliseconds between refreshes")
@@ -133,6 +193,56 @@ fn parse_args() -> ArgMatches {
                     .value_delimiter(',')
                     .value_parser(value_parser!(u16))
             )
+            .arg(
+                Arg::new("filter")
+                    .help("Specify a filter expression. Example: ntap stat -f \"tcp and dst port 443\"")
+                    .short('f')
+                    .long("filter")
+                    .value_name("expression")
+                    .value_parser(net::filter::parse_filter_expr)
+            )
+            .arg(
+                Arg::new("file")
+                    .help("Read packets from a pcap or pcapng file instead of capturing. Example: ntap stat --file capture.pcapng")
+                    .long("file")
+                    .value_name("file_path")
+                    .value_parser(value_parser!(PathBuf))
+            )
+            .arg(
+                Arg::new("realtime")
+                    .help("Replay the capture file with the original packet timing")
+                    .long("realtime")
+                    .requires("file")
+                    .num_args(0),
+            )
+            .arg(
+                Arg::new("format")
+                    .help("Output format. ndjson writes one JSON object per tick without the terminal UI")
+                    .long("format")
+                    .value_name("format")
+                    .value_parser(["tui", "ndjson"])
+            )
+            .arg(
+                Arg::new("headless")
+                    .help("Run without the terminal UI. Same as --format ndjson")
+                    .long("headless")
+                    .num_args(0),
+            )
+            .arg(
+                Arg::new("output")
+                    .help("Write the headless output to a file instead of stdout. Example: ntap stat --headless -o stats.ndjson")
+                    .short('o')
+                    .long("output")
+                    .value_name("file_path")
+                    .value_parser(value_parser!(PathBuf))
+            )
+            .arg(
+                Arg::new("count")
+                    .help("Exit after writing the number of ticks in headless mode")
+                    .long("count")
+                    .value_name("count")
+                    .value_parser(value_parser!(usize))
+            )
         )
         // Sub-command for live mode.
         .subcommand(Command::new("live")
@@ -181,6 +291,69 @@ fn parse_args() -> ArgMatches {
                     .value_delimiter(',')
                     .value_parser(value_parser!(u16))
             )
+            .arg(
+                Arg::new("filter")
+                    .help("Specify a filter expression. Example: ntap live -f \"tcp and dst port 443\"")
+                    .short('f')
+                    .long("filter")
+                    .value_name("expression")
+                    .value_parser(net::filter::parse_filter_expr)
+            )
+            .arg(
+                Arg::new("file")
+                    .help("Read packets from a pcap or pcapng file instead of capturing. Example: ntap live --file capture.pcapng")
+                    .long("file")
+                    .value_name("file_path")
+                    .value_parser(value_parser!(PathBuf))
+            )
+            .arg(
+                Arg::new("realtime")
+                    .help("Replay the capture file with the original packet timing")
+                    .long("realtime")
+                    .requires("file")
+                    .num_args(0),
+            )
+            .arg(
+                Arg::new("write")
+                    .help("Write captured packets to a pcapng file. Example: ntap live --write capture.pcapng")
+                    .short('w')
+                    .long("write")
+                    .value_name("file_path")
+                    .conflicts_with("file")
+                    .value_parser(value_parser!(PathBuf))
+            )
+            .arg(
+                Arg::new("rotate-size")
+                    .help("Start a new capture file when the current file exceeds the size in megabytes")
+                    .long("rotate-size")
+                    .value_name("megabytes")
+                    .requires("write")
+                    .value_parser(value_parser!(u64).range(1..))
+            )
+            .arg(
+                Arg::new("rotate-interval")
+                    .help("Start a new capture file after the interval in seconds")
+                    .long("rotate-interval")
+                    .value_name("seconds")
+                    .requires("write")
+                    .value_parser(value_parser!(u64).range(1..))
+            )
+            .arg(
+                Arg::new("max-files")
+                    .help("Maximum number of capture files to keep. The oldest file is removed first")
+                    .long("max-files")
+                    .value_name("count")
+                    .requires("write")
+                    .value_parser(value_parser!(usize))
+            )
+            .arg(
+                Arg::new("snaplen")
+                    .help("Maximum number of bytes kept per packet for the packet detail view. 0 keeps none. Example: ntap live --snaplen 128")
+                    .short('s')
+                    .long("snaplen")
+                    .value_name("bytes")
+                    .value_parser(value_parser!(usize))
+            )
         )
         // Sub-command for monitor mode.
         .subcommand(Command::new("monitor")
@@ -221,6 +394,167 @@ fn parse_args() -> ArgMatches {
                     .value_delimiter(',')
                     .value_parser(value_parser!(u16))
             )
+            .arg(
+                Arg::new("filter")
+                    .help("Specify a filter expression. Example: ntap monitor -f \"tcp and dst port 443\"")
+                    .short('f')
+                    .long("filter")
+                    .value_name("expression")
+                    .value_parser(net::filter::parse_filter_expr)
+            )
+            .arg(
+                Arg::new("file")
+                    .help("Read packets from a pcap or pcapng file instead of capturing. Example: ntap monitor --file capture.pcapng")
+                    .long("file")
+                    .value_name("file_path")
+                    .value_parser(value_parser!(PathBuf))
+            )
+            .arg(
+                Arg::new("realtime")
+                    .help("Replay the capture file with the original packet timing")
+                    .long("realtime")
+                    .requires("file")
+                    .num_args(0),
+            )
+            .arg(
+                Arg::new("format")
+                    .help("Output format. ndjson writes one JSON object per tick without the terminal UI")
+                    .long("format")
+                    .value_name("format")
+                    .value_parser(["tui", "ndjson"])
+            )
+            .arg(
+                Arg::new("headless")
+                    .help("Run without the terminal UI. Same as --format ndjson")
+                    .long("headless")
+                    .num_args(0),
+            )
+            .arg(
+                Arg::new("output")
+                    .help("Write the headless output to a file instead of stdout. Example: ntap monitor --headless -o stats.ndjson")
+                    .short('o')
+                    .long("output")
+                    .value_name("file_path")
+                    .value_parser(value_parser!(PathBuf))
+            )
+            .arg(
+                Arg::new("count")
+                    .help("Exit after writing the number of ticks in headless mode")
+                    .long("count")
+                    .value_name("count")
+                    .value_parser(value_parser!(usize))
+            )
+        )
+        // Sub-command for metrics exporter
+        .subcommand(Command::new("serve")
+            .about("Serve network statistics as Prometheus metrics. Example: ntap serve --metrics 127.0.0.1:9100")
+            .arg(
+                Arg::new("metrics")
+                    .help("Listen address for the Prometheus /metrics endpoint")
+                    .long("metrics")
+                    .value_name("address")
+                    .default_value(metrics::DEFAULT_METRICS_ADDR)
+                    .value_parser(value_parser!(SocketAddr))
+            )
+            .arg(
+                Arg::new("max-label-values")
+                    .help("Maximum number of label values per metric. Further values are counted as \"other\"")
+                    .long("max-label-values")
+                    .value_name("count")
+                    .value_parser(value_parser!(usize))
+            )
+            .arg(
+                Arg::new("interfaces")
+                    .help("Specify the interfaces by name. Example: ntap serve -i eth0,eth1")
+                    .short('i')
+                    .long("interfaces")
+                    .value_name("interfaces")
+                    .value_delimiter(',')
+                    .value_parser(value_parser!(String))
+            )
+            .arg(
+                Arg::new("filter")
+                    .help("Specify a filter expression. Example: ntap serve -f \"not port 22\"")
+                    .short('f')
+                    .long("filter")
+                    .value_name("expression")
+                    .value_parser(net::filter::parse_filter_expr)
+            )
+        )
+        // Sub-command for flow exporter
+        .subcommand(Command::new("export")
+            .about("Export flows as NetFlow v9 or IPFIX to a collector. Example: ntap export --collector 192.0.2.10:4739")
+            .arg(
+                Arg::new("collector")
+                    .help("Collector address. Example: 192.0.2.10:4739")
+                    .long("collector")
+                    .value_name("address")
+                    .value_parser(value_parser!(String))
+            )
+            .arg(
+                Arg::new("protocol")
+                    .help("Export protocol")
+                    .long("protocol")
+                    .value_name("protocol")
+                    .value_parser(["netflow-v9", "ipfix"])
+            )
+            .arg(
+                Arg::new("active-timeout")
+                    .help("Export long-lived flows after this many seconds")
+                    .long("active-timeout")
+                    .value_name("seconds")
+                    .value_parser(value_parser!(u64))
+            )
+            .arg(
+                Arg::new("inactive-timeout")
+                    .help("Export flows after this many seconds without traffic")
+                    .long("inactive-timeout")
+                    .value_name("seconds")
+                    .value_parser(value_parser!(u64))
+            )
+            .arg(
+                Arg::new("interfaces")
+                    .help("Specify the interfaces by name. Example: ntap export -i eth0,eth1")
+                    .short('i')
+                    .long("interfaces")
+                    .value_name("interfaces")
+                    .value_delimiter(',')
+                    .value_parser(value_parser!(String))
+            )
+            .arg(
+                Arg::new("filter")
+                    .help("Specify a filter expression. Example: ntap export -f \"not port 22\"")
+                    .short('f')
+                    .long("filter")
+                    .value_name("expression")
+                    .value_parser(net::filter::parse_filter_expr)
+            )
+        )
+        // Sub-command for traffic accounting report
+        .subcommand(Command::new("report")
+            .about("Show recorded traffic totals per period, interface, process, ASN and country. Example: ntap report --since 7d")
+            .arg(
+                Arg::new("since")
+                    .help("Report period. Units: h (hours), d (days), w (weeks), mo (months), y (years)")
+                    .long("since")
+                    .value_name("period")
+                    .default_value("7d")
+                    .value_parser(db::traffic::parse_period)
+            )
+            .arg(
+                Arg::new("granularity")
+                    .help("Granularity of the period table. Default: chosen from the period")
+                    .long("granularity")
+                    .value_name("granularity")
+                    .value_parser(["hourly", "daily", "monthly"])
+            )
+            .arg(
+                Arg::new("limit")
+                    .help("Number of rows in the top interfaces, processes, ASNs and countries tables")
+                    .long("limit")
+                    .value_name("count")
+                    .value_parser(value_parser!(usize))
+            )
         )
         // Sub-command for show active TCP connections and the TCP and UDP ports on which is listening
         .subcommand(
@@ -252,9 +586,94 @@ fn parse_args() -> ArgMatches {
         .subcommand(Command::new("ipinfo")
             .about("Show public IP info")
         )
+        // Sub-command for offline IP lookup
+        .subcommand(Command::new("lookup")
+            .about("Look up ASN, AS name, country and reverse DNS from the local databases. Example: ntap lookup 1.1.1.1 8.8.8.0/24 ips.txt")
+            .arg(
+                Arg::new("targets")
+                    .help("IP addresses, CIDRs or files with one entry per line. Reads stdin if omitted or -")
+                    .value_name("ip|cidr|file")
+                    .num_args(1..)
+                    .value_parser(value_parser!(String))
+            )
+            .arg(
+                Arg::new("format")
+                    .help("Output format")
+                    .long("format")
+                    .value_name("format")
+                    .default_value("table")
+                    .value_parser(["table", "json", "csv"])
+            )
+            .arg(
+                Arg::new("no-dns")
+                    .help("Skip reverse DNS lookups")
+                    .long("no-dns")
+                    .num_args(0),
+            )
+        )
         // Sub-command for update ntap database
         .subcommand(Command::new("update")
             .about("Update ntap database")
+            .arg(
+                Arg::new("check")
+                    .help("Only check for updates and show the status of each database file")
+                    .long("check")
+                    .num_args(0),
+            )
+            .arg(
+                Arg::new("mirror")
+                    .help("Fetch the manifest and files from a mirror URL or a local directory. Example: ntap update --mirror https://mirror.example.com/ntap")
+                    .long("mirror")
+                    .value_name("url|dir")
+                    .value_parser(value_parser!(String))
+            )
+        )
+        // Sub-command for building databases from public data sources
+        .subcommand(Command::new("db")
+            .about("Manage ntap databases. ntap db --help for more information")
+            .subcommand(Command::new("build")
+                .about("Build the IP-to-ASN/country databases from CSV, TSV or MMDB files. Example: ntap db build --format iptoasn ip2asn-combined.tsv")
+                .arg(
+                    Arg::new("format")
+                        .help("Source format")
+                        .long("format")
+                        .value_name("format")
+                        .required(true)
+                        .value_parser(db::build::SourceFormat::names())
+                )
+                .arg(
+                    Arg::new("files")
+                        .help("Source files. GeoLite2 country CSV needs the Blocks and Locations files")
+                        .value_name("files")
+                        .num_args(1..)
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("Output directory. Default: the ntap database directory")
+                        .short('o')
+                        .long("output")
+                        .value_name("dir_path")
+                        .value_parser(value_parser!(PathBuf))
+                )
+            )
+            .subcommand(Command::new("manifest")
+                .about("Write manifest.json with the versions and SHA-256 hashes of the database files, for serving them as an update mirror. Example: ntap db manifest ./mirror --version 2024-06-01")
+                .arg(
+                    Arg::new("dir")
+                        .help("Directory with the database files. Default: the ntap database directory")
+                        .value_name("dir_path")
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("version")
+                        .help("Dataset version. Default: today's date")
+                        .long("version")
+                        .value_name("version")
+                        .value_parser(value_parser!(String))
+                )
+            )
         )
         ;
     app.get_matches()
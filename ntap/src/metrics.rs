@@ -0,0 +1,648 @@
+use crate::db::service::ServiceDatabase;
+use crate::net::socket::{LocalSocket, TransportProtocol};
+use crate::net::stat::{NetStatData, NetStatStrage};
+use crate::net::traffic::TrafficInfo;
+use crate::thread_log;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_METRICS_ADDR: &str = "127.0.0.1:9100";
+pub const METRICS_PATH: &str = "/metrics";
+/// Label value used once a metric reached its label value limit.
+pub const OTHER_LABEL_VALUE: &str = "other";
+const UNKNOWN_LABEL_VALUE: &str = "unknown";
+const CONTENT_TYPE_METRICS: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Maximum size of the request line and headers
+const MAX_REQUEST_HEADER_LEN: u64 = 8192;
+/// Time allowed to receive the whole request. Requests are served one at a time.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Monotonic traffic counters.
+#[derive(Debug, Clone, Default)]
+pub struct TrafficCounter {
+    pub packet_sent: u64,
+    pub packet_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl TrafficCounter {
+    pub fn add_traffic(&mut self, traffic: &TrafficInfo) {
+        self.packet_sent += traffic.packet_sent as u64;
+        self.packet_received += traffic.packet_received as u64;
+        self.bytes_sent += traffic.bytes_sent as u64;
+        self.bytes_received += traffic.bytes_received as u64;
+    }
+}
+
+/// Traffic counters partitioned by a set of labels.
+#[derive(Debug, Clone)]
+pub struct CounterFamily {
+    /// Metric name part. e.g. "asn" for ntap_asn_bytes_total
+    pub name: &'static str,
+    pub help: &'static str,
+    pub label_names: Vec<&'static str>,
+    pub series: BTreeMap<Vec<String>, TrafficCounter>,
+    pub max_label_values: usize,
+}
+
+impl CounterFamily {
+    pub fn new(
+        name: &'static str,
+        help: &'static str,
+        label_names: Vec<&'static str>,
+        max_label_values: usize,
+    ) -> Self {
+        CounterFamily {
+            name,
+            help,
+            label_names,
+            series: BTreeMap::new(),
+            max_label_values,
+        }
+    }
+    /// Add traffic to the series. New label values beyond the limit are counted under "other".
+    pub fn add_traffic(&mut self, label_values: Vec<String>, traffic: &TrafficInfo) {
+        let label_values = if self.series.contains_key(&label_values)
+            || self.series.len() < self.max_label_values
+        {
+            label_values
+        } else {
+            vec![OTHER_LABEL_VALUE.to_string(); self.label_names.len()]
+        };
+        self.series
+            .entry(label_values)
+            .or_default()
+            .add_traffic(traffic);
+    }
+    fn write_to(&self, out: &mut String) {
+        let bytes_name = format!("ntap_{}_bytes_total", self.name);
+        let packets_name = format!("ntap_{}_packets_total", self.name);
+        write_header(
+            out,
+            &bytes_name,
+            &format!("{} (bytes)", self.help),
+            "counter",
+        );
+        for (label_values, counter) in &self.series {
+            let labels = format_labels(&self.label_names, label_values);
+            write_direction_samples(
+                out,
+                &bytes_name,
+                &labels,
+                counter.bytes_received,
+                counter.bytes_sent,
+            );
+        }
+        write_header(
+            out,
+            &packets_name,
+            &format!("{} (packets)", self.help),
+            "counter",
+        );
+        for (label_values, counter) in &self.series {
+            let labels = format_labels(&self.label_names, label_values);
+            write_direction_samples(
+                out,
+                &packets_name,
+                &labels,
+                counter.packet_received,
+                counter.packet_sent,
+            );
+        }
+    }
+}
+
+/// Cumulative metrics built from the per-tick deltas of NetStatStrage.
+#[derive(Debug, Clone)]
+pub struct MetricsRegistry {
+    pub total: TrafficCounter,
+    pub interfaces: CounterFamily,
+    pub asns: CounterFamily,
+    pub countries: CounterFamily,
+    pub labels: CounterFamily,
+    pub processes: CounterFamily,
+    pub services: CounterFamily,
+    pub remote_hosts: usize,
+    pub connections: usize,
+    pub service_db: ServiceDatabase,
+}
+
+impl MetricsRegistry {
+    pub fn new(max_label_values: usize) -> Self {
+        let service_db = match ServiceDatabase::load() {
+            Ok(db) => db,
+            Err(e) => {
+                thread_log!(error, "MetricsRegistry load service db error: {:?}", e);
+                ServiceDatabase::new()
+            }
+        };
+        MetricsRegistry {
+            total: TrafficCounter::default(),
+            interfaces: CounterFamily::new(
+                "interface",
+                "Traffic by interface",
+                vec!["interface"],
+                max_label_values,
+            ),
+            asns: CounterFamily::new(
+                "asn",
+                "Traffic by remote autonomous system",
+                vec!["asn", "as_name"],
+                max_label_values,
+            ),
+            countries: CounterFamily::new(
+                "country",
+                "Traffic by remote country",
+                vec!["country_code"],
+                max_label_values,
+            ),
+            labels: CounterFamily::new(
+                "label",
+                "Traffic by user-defined network label",
+                vec!["label"],
+                max_label_values,
+            ),
+            processes: CounterFamily::new(
+                "process",
+                "TCP/UDP traffic by process name",
+                vec!["process"],
+                max_label_values,
+            ),
+            services: CounterFamily::new(
+                "service",
+                "TCP/UDP traffic by service port",
+                vec!["protocol", "port", "service"],
+                max_label_values,
+            ),
+            remote_hosts: 0,
+            connections: 0,
+            service_db,
+        }
+    }
+    /// Add the traffic of one tick.
+    /// netstat_data is the accumulated data, used for process lookup and the gauges.
+    pub fn update(&mut self, delta: &NetStatData, netstat_data: &NetStatData) {
+        self.total.add_traffic(&delta.traffic);
+        for host in delta.remote_hosts.values() {
+            let asn = if host.asn == 0 {
+                UNKNOWN_LABEL_VALUE.to_string()
+            } else {
+                host.asn.to_string()
+            };
+            self.asns
+                .add_traffic(vec![asn, host.as_name.clone()], &host.traffic_info);
+            let country_code = if host.country_code.is_empty() {
+                UNKNOWN_LABEL_VALUE.to_string()
+            } else {
+                host.country_code.clone()
+            };
+            self.countries
+                .add_traffic(vec![country_code], &host.traffic_info);
+            if !host.label.is_empty() {
+                self.labels
+                    .add_traffic(vec![host.label.clone()], &host.traffic_info);
+            }
+        }
+        // Every packet is counted per interface, so the interfaces add up to the total
+        for (interface_name, interface_traffic) in &delta.interface_traffic {
+            self.interfaces
+                .add_traffic(vec![interface_name.clone()], &interface_traffic.traffic);
+        }
+        for (conn, traffic) in &delta.connection_map {
+            let process_name = match netstat_data.local_socket_map.get(&LocalSocket {
+                interface_name: conn.interface_name.clone(),
+                port: conn.local_port,
+                protocol: conn.protocol,
+            }) {
+                Some(socket_process) => match &socket_process.process {
+                    Some(process) => process.name.clone(),
+                    None => UNKNOWN_LABEL_VALUE.to_string(),
+                },
+                None => UNKNOWN_LABEL_VALUE.to_string(),
+            };
+            self.processes.add_traffic(vec![process_name], traffic);
+            // Use the remote port as in get_app_protocols, unless the local port is a known service (server side)
+            let port = if conn.local_port < conn.remote_port
+                && self.service_name(conn.protocol, conn.local_port).is_some()
+            {
+                conn.local_port
+            } else {
+                conn.remote_port
+            };
+            let service_name = self
+                .service_name(conn.protocol, port)
+                .unwrap_or_else(|| UNKNOWN_LABEL_VALUE.to_string());
+            self.services.add_traffic(
+                vec![
+                    conn.protocol.as_str().to_lowercase(),
+                    port.to_string(),
+                    service_name,
+                ],
+                traffic,
+            );
+        }
+        self.remote_hosts = netstat_data.remote_hosts.len();
+        self.connections = netstat_data.connection_map.len();
+    }
+    fn service_name(&self, protocol: TransportProtocol, port: u16) -> Option<String> {
+        match protocol {
+            TransportProtocol::TCP => self.service_db.tcp_map.get(&port).cloned(),
+            TransportProtocol::UDP => self.service_db.udp_map.get(&port).cloned(),
+        }
+    }
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_header(
+            &mut out,
+            "ntap_bytes_total",
+            "Total captured traffic (bytes)",
+            "counter",
+        );
+        write_direction_samples(
+            &mut out,
+            "ntap_bytes_total",
+            "",
+            self.total.bytes_received,
+            self.total.bytes_sent,
+        );
+        write_header(
+            &mut out,
+            "ntap_packets_total",
+            "Total captured traffic (packets)",
+            "counter",
+        );
+        write_direction_samples(
+            &mut out,
+            "ntap_packets_total",
+            "",
+            self.total.packet_received,
+            self.total.packet_sent,
+        );
+        self.interfaces.write_to(&mut out);
+        self.asns.write_to(&mut out);
+        self.countries.write_to(&mut out);
+        self.labels.write_to(&mut out);
+        self.processes.write_to(&mut out);
+        self.services.write_to(&mut out);
+        write_header(
+            &mut out,
+            "ntap_remote_hosts",
+            "Number of active remote hosts",
+            "gauge",
+        );
+        let _ = writeln!(out, "ntap_remote_hosts {}", self.remote_hosts);
+        write_header(
+            &mut out,
+            "ntap_connections",
+            "Number of active TCP/UDP connections",
+            "gauge",
+        );
+        let _ = writeln!(out, "ntap_connections {}", self.connections);
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, metric_type: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+}
+
+// labels: formatted labels without braces. e.g. asn="13335",as_name="CLOUDFLARENET"
+fn write_direction_samples(out: &mut String, name: &str, labels: &str, ingress: u64, egress: u64) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    let _ = writeln!(
+        out,
+        "{}{{{}{}direction=\"ingress\"}} {}",
+        name, labels, separator, ingress
+    );
+    let _ = writeln!(
+        out,
+        "{}{{{}{}direction=\"egress\"}} {}",
+        name, labels, separator, egress
+    );
+}
+
+fn format_labels(label_names: &[&str], label_values: &[String]) -> String {
+    label_names
+        .iter()
+        .zip(label_values.iter())
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Move the captured traffic into the registry at each interval.
+pub fn start_metrics_update(
+    netstat_strage: &mut Arc<NetStatStrage>,
+    registry: Arc<Mutex<MetricsRegistry>>,
+    interval: Duration,
+    entry_ttl: Duration,
+) {
+    let mut netstat_data = NetStatData::new();
+    let mut last_clear = Instant::now();
+    loop {
+        thread::sleep(interval);
+        if last_clear.elapsed() >= entry_ttl {
+            netstat_data.remove_old_entries(entry_ttl);
+            last_clear = Instant::now();
+        }
+        let delta = netstat_strage.clone_data_and_reset();
+        netstat_data.merge(delta.clone(), interval);
+        match registry.lock() {
+            Ok(mut registry) => {
+                registry.update(&delta, &netstat_data);
+            }
+            Err(e) => {
+                thread_log!(error, "start_metrics_update error: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Serve the registry over HTTP. Blocks the current thread.
+pub fn start_metrics_server(
+    addr: SocketAddr,
+    registry: Arc<Mutex<MetricsRegistry>>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    thread_log!(
+        info,
+        "metrics server listening on http://{}{}",
+        addr,
+        METRICS_PATH
+    );
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(stream, &registry) {
+                    thread_log!(error, "metrics request error: {:?}", e);
+                }
+            }
+            Err(e) => {
+                thread_log!(error, "metrics accept error: {:?}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reader that fails once the deadline has passed, however slowly the client sends.
+struct DeadlineReader {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request not received in time",
+            ));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        match self.stream.read(buf) {
+            // Read timeouts are reported as WouldBlock on Unix
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request not received in time",
+            )),
+            result => result,
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, registry: &Arc<Mutex<MetricsRegistry>>) -> io::Result<()> {
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let deadline_reader = DeadlineReader {
+        stream: stream.try_clone()?,
+        deadline: Instant::now() + REQUEST_TIMEOUT,
+    };
+    let mut reader = BufReader::new(deadline_reader.take(MAX_REQUEST_HEADER_LEN));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the request headers. Running out of the size limit ends the loop without the empty line.
+    let mut complete = request_line.ends_with('\n');
+    while complete {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        if !header.ends_with('\n') {
+            complete = false;
+        } else if header.trim().is_empty() {
+            break;
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = if complete {
+        parts.next().unwrap_or("")
+    } else {
+        ""
+    };
+    let path = parts.next().unwrap_or("");
+    let path = path.split('?').next().unwrap_or("");
+    let (status, content_type, body) = match (method, path) {
+        ("", _) => (
+            "400 Bad Request",
+            "text/plain",
+            String::from("Bad Request\n"),
+        ),
+        ("GET", METRICS_PATH) | ("HEAD", METRICS_PATH) => match registry.lock() {
+            Ok(registry) => ("200 OK", CONTENT_TYPE_METRICS, registry.render()),
+            Err(e) => {
+                thread_log!(error, "metrics registry lock error: {:?}", e);
+                (
+                    "500 Internal Server Error",
+                    "text/plain",
+                    String::from("Internal Server Error\n"),
+                )
+            }
+        },
+        ("GET", "/") | ("HEAD", "/") => (
+            "200 OK",
+            "text/plain",
+            format!(
+                "ntap metrics exporter. Metrics are served at {}\n",
+                METRICS_PATH
+            ),
+        ),
+        ("GET", _) | ("HEAD", _) => ("404 Not Found", "text/plain", String::from("Not Found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            String::from("Method Not Allowed\n"),
+        ),
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::traffic::InterfaceTrafficInfo;
+
+    // Serve one connection on a local port
+    fn serve_one(
+        registry: Arc<Mutex<MetricsRegistry>>,
+    ) -> (SocketAddr, thread::JoinHandle<io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            handle_connection(stream, &registry)
+        });
+        (addr, handle)
+    }
+
+    fn request(registry: Arc<Mutex<MetricsRegistry>>, request: &str) -> String {
+        let (addr, handle) = serve_one(registry);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        handle.join().unwrap().unwrap();
+        response
+    }
+
+    fn test_registry() -> Arc<Mutex<MetricsRegistry>> {
+        let mut registry = MetricsRegistry::new(2);
+        registry.total.bytes_received = 1234;
+        registry.total.packet_sent = 5;
+        let traffic = TrafficInfo {
+            bytes_received: 100,
+            ..TrafficInfo::new()
+        };
+        for asn in ["13335", "15169", "16509"] {
+            registry
+                .asns
+                .add_traffic(vec![asn.to_string(), String::from("AS")], &traffic);
+        }
+        Arc::new(Mutex::new(registry))
+    }
+
+    #[test]
+    fn get_metrics() {
+        let response = request(
+            test_registry(),
+            "GET /metrics?x=1 HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n",
+        );
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Type: {}", CONTENT_TYPE_METRICS)));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert!(body.contains("# TYPE ntap_bytes_total counter\n"));
+        assert!(body.contains("ntap_bytes_total{direction=\"ingress\"} 1234\n"));
+        assert!(body.contains("ntap_packets_total{direction=\"egress\"} 5\n"));
+        assert!(body.contains(
+            "ntap_asn_bytes_total{asn=\"13335\",as_name=\"AS\",direction=\"ingress\"} 100\n"
+        ));
+        // The third ASN is over the label value limit
+        assert!(body.contains(
+            "ntap_asn_bytes_total{asn=\"other\",as_name=\"other\",direction=\"ingress\"} 100\n"
+        ));
+        assert!(!body.contains("16509"));
+    }
+
+    #[test]
+    fn interface_traffic_without_connections() {
+        // ICMP and other packets without a TCP/UDP connection still count per interface
+        let mut delta = NetStatData::new();
+        delta.traffic.packet_received = 3;
+        delta.traffic.bytes_received = 300;
+        let mut interface_traffic = InterfaceTrafficInfo::new();
+        interface_traffic.traffic = delta.traffic.clone();
+        delta
+            .interface_traffic
+            .insert(String::from("eth0"), interface_traffic);
+        let mut registry = MetricsRegistry::new(10);
+        registry.update(&delta, &NetStatData::new());
+        let body = registry.render();
+        assert!(body.contains(
+            "ntap_interface_bytes_total{interface=\"eth0\",direction=\"ingress\"} 300\n"
+        ));
+        assert!(body.contains(
+            "ntap_interface_packets_total{interface=\"eth0\",direction=\"ingress\"} 3\n"
+        ));
+    }
+
+    #[test]
+    fn head_metrics() {
+        let response = request(test_registry(), "HEAD /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn not_found_and_method_not_allowed() {
+        let response = request(test_registry(), "GET /other HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = request(test_registry(), "POST /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+
+    #[test]
+    fn oversized_header() {
+        let (addr, handle) = serve_one(test_registry());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut request = String::from("GET /metrics HTTP/1.1\r\nX-Long: ");
+        request.push_str(&"a".repeat(MAX_REQUEST_HEADER_LEN as usize));
+        stream.write_all(request.as_bytes()).unwrap();
+        // The server stops reading at the limit, without waiting for the end of the line
+        handle.join().unwrap().unwrap();
+        let mut response = String::new();
+        if stream.read_to_string(&mut response).is_ok() {
+            assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        }
+    }
+
+    #[test]
+    fn slow_request_deadline() {
+        let (addr, handle) = serve_one(test_registry());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let started = Instant::now();
+        // Each byte arrives within the read timeout, but the request never ends
+        let writer = thread::spawn(move || {
+            while stream.write_all(b"a").is_ok() {
+                thread::sleep(Duration::from_millis(200));
+                if started.elapsed() > REQUEST_TIMEOUT * 3 {
+                    break;
+                }
+            }
+        });
+        let err = handle.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < REQUEST_TIMEOUT * 2);
+        writer.join().unwrap();
+    }
+}
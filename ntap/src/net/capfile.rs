@@ -0,0 +1,1009 @@
+use ipnet::IpNet;
+use netdev::mac::MacAddr;
+use nex::packet::frame::{Frame, ParseOption};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// pcap magic number (microsecond timestamps)
+pub const PCAP_MAGIC_MICROS: u32 = 0xa1b2c3d4;
+/// pcap magic number (nanosecond timestamps)
+pub const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+/// pcapng Section Header Block type
+pub const PCAPNG_SHB_TYPE: u32 = 0x0A0D0D0A;
+/// pcapng byte-order magic
+pub const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+/// pcapng Interface Description Block type
+pub const PCAPNG_IDB_TYPE: u32 = 0x00000001;
+/// pcapng (obsolete) Packet Block type
+pub const PCAPNG_PB_TYPE: u32 = 0x00000002;
+/// pcapng Simple Packet Block type
+pub const PCAPNG_SPB_TYPE: u32 = 0x00000003;
+/// pcapng Enhanced Packet Block type
+pub const PCAPNG_EPB_TYPE: u32 = 0x00000006;
+/// Largest packet accepted from a capture file (the libpcap maximum snaplen)
+pub const MAX_PACKET_LEN: usize = 262144;
+/// Largest pcapng block accepted from a capture file
+pub const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+/// Link-layer header types supported by ntap.
+/// See https://www.tcpdump.org/linktypes.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkType {
+    Null,
+    Ethernet,
+    Raw,
+    Loop,
+    LinuxSll,
+    Ipv4,
+    Ipv6,
+    Unknown(u32),
+}
+
+impl LinkType {
+    pub fn from_u32(value: u32) -> LinkType {
+        match value {
+            0 => LinkType::Null,
+            1 => LinkType::Ethernet,
+            // LINKTYPE_RAW and the platform specific DLT_RAW values
+            12 | 14 | 101 => LinkType::Raw,
+            108 => LinkType::Loop,
+            113 => LinkType::LinuxSll,
+            228 => LinkType::Ipv4,
+            229 => LinkType::Ipv6,
+            _ => LinkType::Unknown(value),
+        }
+    }
+    pub fn to_u32(self) -> u32 {
+        match self {
+            LinkType::Null => 0,
+            LinkType::Ethernet => 1,
+            LinkType::Raw => 101,
+            LinkType::Loop => 108,
+            LinkType::LinuxSll => 113,
+            LinkType::Ipv4 => 228,
+            LinkType::Ipv6 => 229,
+            LinkType::Unknown(value) => value,
+        }
+    }
+    /// Get the link type of the frames captured from the interface.
+    pub fn from_interface(interface: &netdev::Interface) -> LinkType {
+        // TUN interfaces deliver IP packets without link-layer header
+        if interface.is_tun() {
+            LinkType::Raw
+        } else {
+            LinkType::Ethernet
+        }
+    }
+    /// Get the parse option for nex Frame.
+    /// Returns None if the link type is not supported.
+    pub fn parse_option(&self) -> Option<ParseOption> {
+        match self {
+            LinkType::Ethernet => Some(ParseOption::default()),
+            LinkType::Raw | LinkType::Ipv4 | LinkType::Ipv6 => Some(ParseOption::new(true, 0)),
+            LinkType::Null | LinkType::Loop => Some(ParseOption::new(true, 4)),
+            LinkType::LinuxSll => Some(ParseOption::new(true, 16)),
+            LinkType::Unknown(_) => None,
+        }
+    }
+}
+
+/// Interface recorded in the capture file
+#[derive(Debug, Clone)]
+pub struct FileInterface {
+    /// Interface name. (if_name option, or the file name if not present)
+    pub name: String,
+    pub link_type: LinkType,
+    pub snaplen: u32,
+    /// Timestamp units per second
+    pub ts_units: u64,
+}
+
+/// Packet read from the capture file
+#[derive(Debug, Clone)]
+pub struct FilePacket {
+    /// Interface ID (index of the interface in the file)
+    pub if_id: u32,
+    /// Time since UNIX epoch
+    pub timestamp: Duration,
+    /// Original length of the packet on the wire
+    pub orig_len: usize,
+    /// Captured bytes
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    Pcap,
+    Pcapng,
+}
+
+/// Sequential reader for pcap and pcapng files
+pub struct CaptureFileReader {
+    reader: BufReader<File>,
+    format: FileFormat,
+    big_endian: bool,
+    file_name: String,
+    interfaces: Vec<FileInterface>,
+    last_timestamp: Duration,
+}
+
+impl CaptureFileReader {
+    pub fn open(file_path: &Path) -> io::Result<CaptureFileReader> {
+        let file = File::open(file_path)?;
+        let mut reader = BufReader::new(file);
+        let file_name = match file_path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => file_path.to_string_lossy().to_string(),
+        };
+        let mut magic_buf = [0u8; 4];
+        reader.read_exact(&mut magic_buf)?;
+        let mut capfile = CaptureFileReader {
+            reader,
+            format: FileFormat::Pcap,
+            big_endian: false,
+            file_name,
+            interfaces: Vec::new(),
+            last_timestamp: Duration::ZERO,
+        };
+        if u32::from_le_bytes(magic_buf) == PCAPNG_SHB_TYPE {
+            capfile.format = FileFormat::Pcapng;
+            capfile.read_section_header()?;
+            return Ok(capfile);
+        }
+        let nanos = if u32::from_le_bytes(magic_buf) == PCAP_MAGIC_MICROS {
+            false
+        } else if u32::from_le_bytes(magic_buf) == PCAP_MAGIC_NANOS {
+            true
+        } else if u32::from_be_bytes(magic_buf) == PCAP_MAGIC_MICROS {
+            capfile.big_endian = true;
+            false
+        } else if u32::from_be_bytes(magic_buf) == PCAP_MAGIC_NANOS {
+            capfile.big_endian = true;
+            true
+        } else {
+            return Err(invalid_data("not a pcap or pcapng file"));
+        };
+        // version_major(2), version_minor(2), thiszone(4), sigfigs(4), snaplen(4), network(4)
+        let mut header = [0u8; 20];
+        capfile.reader.read_exact(&mut header)?;
+        let snaplen = capfile.u32_at(&header, 12);
+        // The upper bits of the network field may contain FCS information
+        let link_type = LinkType::from_u32(capfile.u32_at(&header, 16) & 0xFFFF);
+        capfile.interfaces.push(FileInterface {
+            name: capfile.file_name.clone(),
+            link_type,
+            snaplen,
+            ts_units: if nanos { 1_000_000_000 } else { 1_000_000 },
+        });
+        Ok(capfile)
+    }
+    /// Get the interface by interface ID
+    pub fn interface(&self, if_id: u32) -> Option<&FileInterface> {
+        self.interfaces.get(if_id as usize)
+    }
+    /// Get the interfaces read so far
+    pub fn interfaces(&self) -> &Vec<FileInterface> {
+        &self.interfaces
+    }
+    pub fn file_name(&self) -> String {
+        self.file_name.clone()
+    }
+    /// Read the next packet. Returns None at the end of the file.
+    pub fn next_packet(&mut self) -> io::Result<Option<FilePacket>> {
+        match self.format {
+            FileFormat::Pcap => self.next_pcap_packet(),
+            FileFormat::Pcapng => self.next_pcapng_packet(),
+        }
+    }
+    fn next_pcap_packet(&mut self) -> io::Result<Option<FilePacket>> {
+        let mut header = [0u8; 16];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let ts_sec = self.u32_at(&header, 0) as u64;
+        let ts_frac = self.u32_at(&header, 4) as u64;
+        let incl_len = self.u32_at(&header, 8) as usize;
+        let orig_len = self.u32_at(&header, 12) as usize;
+        // Check the length before allocating, a corrupt file may claim up to 4 GiB
+        if incl_len > MAX_PACKET_LEN {
+            return Err(invalid_data("invalid pcap packet length"));
+        }
+        let mut data = vec![0u8; incl_len];
+        self.reader.read_exact(&mut data)?;
+        let ts_units = self.interfaces[0].ts_units;
+        let timestamp = Duration::from_secs(ts_sec) + units_to_duration(ts_frac, ts_units);
+        self.last_timestamp = timestamp;
+        Ok(Some(FilePacket {
+            if_id: 0,
+            timestamp,
+            orig_len,
+            data,
+        }))
+    }
+    fn next_pcapng_packet(&mut self) -> io::Result<Option<FilePacket>> {
+        loop {
+            let mut type_buf = [0u8; 4];
+            if !read_exact_or_eof(&mut self.reader, &mut type_buf)? {
+                return Ok(None);
+            }
+            // The SHB type is a palindrome, so the byte order does not matter here.
+            if u32::from_le_bytes(type_buf) == PCAPNG_SHB_TYPE {
+                self.read_section_header()?;
+                continue;
+            }
+            let block_type = self.u32_at(&type_buf, 0);
+            let mut len_buf = [0u8; 4];
+            self.reader.read_exact(&mut len_buf)?;
+            let block_len = self.u32_at(&len_buf, 0) as usize;
+            if block_len < 12 || !block_len.is_multiple_of(4) || block_len > MAX_BLOCK_LEN {
+                return Err(invalid_data("invalid pcapng block length"));
+            }
+            // body + trailing block length
+            let mut body = vec![0u8; block_len - 8];
+            self.reader.read_exact(&mut body)?;
+            body.truncate(block_len - 12);
+            match block_type {
+                PCAPNG_IDB_TYPE => {
+                    let interface = self.parse_interface_description(&body)?;
+                    self.interfaces.push(interface);
+                }
+                PCAPNG_EPB_TYPE => {
+                    if body.len() < 20 {
+                        return Err(invalid_data("truncated enhanced packet block"));
+                    }
+                    let if_id = self.u32_at(&body, 0);
+                    let ts = ((self.u32_at(&body, 4) as u64) << 32) | self.u32_at(&body, 8) as u64;
+                    let cap_len = self.u32_at(&body, 12) as usize;
+                    let orig_len = self.u32_at(&body, 16) as usize;
+                    if body.len() < 20 + cap_len {
+                        return Err(invalid_data("truncated enhanced packet block"));
+                    }
+                    let timestamp = self.pcapng_timestamp(if_id, ts);
+                    return Ok(Some(FilePacket {
+                        if_id,
+                        timestamp,
+                        orig_len,
+                        data: body[20..20 + cap_len].to_vec(),
+                    }));
+                }
+                PCAPNG_PB_TYPE => {
+                    if body.len() < 20 {
+                        return Err(invalid_data("truncated packet block"));
+                    }
+                    let if_id = self.u16_at(&body, 0) as u32;
+                    let ts = ((self.u32_at(&body, 4) as u64) << 32) | self.u32_at(&body, 8) as u64;
+                    let cap_len = self.u32_at(&body, 12) as usize;
+                    let orig_len = self.u32_at(&body, 16) as usize;
+                    if body.len() < 20 + cap_len {
+                        return Err(invalid_data("truncated packet block"));
+                    }
+                    let timestamp = self.pcapng_timestamp(if_id, ts);
+                    return Ok(Some(FilePacket {
+                        if_id,
+                        timestamp,
+                        orig_len,
+                        data: body[20..20 + cap_len].to_vec(),
+                    }));
+                }
+                PCAPNG_SPB_TYPE => {
+                    if body.len() < 4 {
+                        return Err(invalid_data("truncated simple packet block"));
+                    }
+                    let orig_len = self.u32_at(&body, 0) as usize;
+                    let cap_len = std::cmp::min(orig_len, body.len() - 4);
+                    // Simple Packet Blocks have no timestamp
+                    return Ok(Some(FilePacket {
+                        if_id: 0,
+                        timestamp: self.last_timestamp,
+                        orig_len,
+                        data: body[4..4 + cap_len].to_vec(),
+                    }));
+                }
+                _ => {
+                    // Skip unsupported blocks (NRB, ISB, custom blocks, etc.)
+                }
+            }
+        }
+    }
+    /// Read the rest of a Section Header Block. The block type has already been read.
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 8];
+        self.reader.read_exact(&mut buf)?;
+        let magic_buf: [u8; 4] = [buf[4], buf[5], buf[6], buf[7]];
+        if u32::from_le_bytes(magic_buf) == PCAPNG_BYTE_ORDER_MAGIC {
+            self.big_endian = false;
+        } else if u32::from_be_bytes(magic_buf) == PCAPNG_BYTE_ORDER_MAGIC {
+            self.big_endian = true;
+        } else {
+            return Err(invalid_data("invalid pcapng byte-order magic"));
+        }
+        let block_len = self.u32_at(&buf, 0) as usize;
+        if block_len < 28 || !block_len.is_multiple_of(4) || block_len > MAX_BLOCK_LEN {
+            return Err(invalid_data("invalid pcapng section header length"));
+        }
+        // Skip the rest of the block (version, section length, options, trailing length)
+        let mut rest = vec![0u8; block_len - 12];
+        self.reader.read_exact(&mut rest)?;
+        // Interface IDs are scoped to the section
+        self.interfaces.clear();
+        Ok(())
+    }
+    fn parse_interface_description(&self, body: &[u8]) -> io::Result<FileInterface> {
+        if body.len() < 8 {
+            return Err(invalid_data("truncated interface description block"));
+        }
+        let mut interface = FileInterface {
+            name: self.file_name.clone(),
+            link_type: LinkType::from_u32(self.u16_at(body, 0) as u32),
+            snaplen: self.u32_at(body, 4),
+            ts_units: 1_000_000,
+        };
+        let mut offset = 8;
+        while offset + 4 <= body.len() {
+            let code = self.u16_at(body, offset);
+            let len = self.u16_at(body, offset + 2) as usize;
+            offset += 4;
+            if code == 0 || offset + len > body.len() {
+                break;
+            }
+            let value = &body[offset..offset + len];
+            match code {
+                // if_name
+                2 => {
+                    interface.name = String::from_utf8_lossy(value).to_string();
+                }
+                // if_tsresol
+                9 => {
+                    if let Some(resol) = value.first() {
+                        let exp = (resol & 0x7F) as u32;
+                        interface.ts_units = if resol & 0x80 == 0 {
+                            10u64.checked_pow(exp).unwrap_or(u64::MAX)
+                        } else {
+                            2u64.checked_pow(exp).unwrap_or(u64::MAX)
+                        };
+                    }
+                }
+                _ => {}
+            }
+            // Options are padded to 32 bits
+            offset += (len + 3) & !3;
+        }
+        Ok(interface)
+    }
+    fn pcapng_timestamp(&mut self, if_id: u32, ts: u64) -> Duration {
+        let ts_units = match self.interface(if_id) {
+            Some(interface) => interface.ts_units,
+            None => 1_000_000,
+        };
+        let timestamp = units_to_duration(ts, ts_units);
+        self.last_timestamp = timestamp;
+        timestamp
+    }
+    fn u16_at(&self, buf: &[u8], offset: usize) -> u16 {
+        let bytes: [u8; 2] = [buf[offset], buf[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+    fn u32_at(&self, buf: &[u8], offset: usize) -> u32 {
+        let bytes: [u8; 4] = [
+            buf[offset],
+            buf[offset + 1],
+            buf[offset + 2],
+            buf[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Fill the buffer. Returns false if the reader is at EOF before reading any byte.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => {
+                if read == 0 {
+                    return Ok(false);
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "unexpected end of capture file",
+                ));
+            }
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn units_to_duration(value: u64, units_per_sec: u64) -> Duration {
+    if units_per_sec == 0 {
+        return Duration::ZERO;
+    }
+    let secs = value / units_per_sec;
+    let nanos = ((value % units_per_sec) as u128 * 1_000_000_000 / units_per_sec as u128) as u32;
+    Duration::new(secs, nanos)
+}
+
+/// Convert a capture timestamp (time since UNIX epoch) to RFC3339 local time.
+pub fn format_timestamp(timestamp: Duration) -> String {
+    match chrono::DateTime::from_timestamp(timestamp.as_secs() as i64, timestamp.subsec_nanos()) {
+        Some(datetime) => datetime.with_timezone(&chrono::Local).to_rfc3339(),
+        None => String::new(),
+    }
+}
+
+/// Guess the IP addresses of the host that recorded the capture file.
+///
+/// The capturing host's MAC address appears in (almost) every frame, so the
+/// most frequent unicast MAC address is taken as the local one, and the source
+/// IP addresses sent from it are treated as local. For captures without an
+/// Ethernet header, the most frequent IP address is used instead.
+/// Returns IpAddr -> Interface Name, the same form as the live local_ip_map.
+pub fn guess_local_ip_map(file_path: &Path) -> io::Result<HashMap<IpAddr, String>> {
+    let mut reader = CaptureFileReader::open(file_path)?;
+    let mut mac_count: HashMap<MacAddr, usize> = HashMap::new();
+    let mut mac_ips: HashMap<MacAddr, HashMap<IpAddr, String>> = HashMap::new();
+    let mut ip_count: HashMap<IpAddr, usize> = HashMap::new();
+    while let Some(packet) = reader.next_packet()? {
+        let interface = match reader.interface(packet.if_id) {
+            Some(interface) => interface.clone(),
+            None => continue,
+        };
+        let parse_option = match interface.link_type.parse_option() {
+            Some(parse_option) => parse_option,
+            None => continue,
+        };
+        let ethernet_header = interface.link_type == LinkType::Ethernet;
+        let frame = Frame::from_bytes(&packet.data, parse_option);
+        let (src_ip, dst_ip) = match &frame.ip {
+            Some(ip) => {
+                if let Some(ipv4) = &ip.ipv4 {
+                    (IpAddr::V4(ipv4.source), IpAddr::V4(ipv4.destination))
+                } else if let Some(ipv6) = &ip.ipv6 {
+                    (IpAddr::V6(ipv6.source), IpAddr::V6(ipv6.destination))
+                } else {
+                    continue;
+                }
+            }
+            None => continue,
+        };
+        *ip_count.entry(src_ip).or_insert(0) += 1;
+        *ip_count.entry(dst_ip).or_insert(0) += 1;
+        if !ethernet_header {
+            continue;
+        }
+        if let Some(datalink) = &frame.datalink {
+            if let Some(ethernet) = &datalink.ethernet {
+                for mac in [ethernet.source, ethernet.destination] {
+                    // Skip multicast/broadcast and zero addresses
+                    if mac.0 & 0x01 == 0 && mac != MacAddr::zero() {
+                        *mac_count.entry(mac).or_insert(0) += 1;
+                    }
+                }
+                if !src_ip.is_unspecified() {
+                    mac_ips
+                        .entry(ethernet.source)
+                        .or_default()
+                        .insert(src_ip, interface.name.clone());
+                }
+            }
+        }
+    }
+    if let Some((mac, _)) = mac_count.iter().max_by_key(|(_, count)| **count) {
+        if let Some(ips) = mac_ips.get(mac) {
+            return Ok(ips.clone());
+        }
+    }
+    let mut local_ip_map: HashMap<IpAddr, String> = HashMap::new();
+    if let Some((ip, _)) = ip_count.iter().max_by_key(|(_, count)| **count) {
+        let if_name = match reader.interfaces().first() {
+            Some(interface) => interface.name.clone(),
+            None => reader.file_name(),
+        };
+        local_ip_map.insert(*ip, if_name);
+    }
+    Ok(local_ip_map)
+}
+
+/// Guess the networks of the capturing host from its addresses.
+/// The prefix lengths are not in the file, so /24 and /64 are assumed.
+pub fn guess_local_networks(local_ip_map: &HashMap<IpAddr, String>) -> Vec<IpNet> {
+    let mut networks: Vec<IpNet> = Vec::new();
+    for ip_addr in local_ip_map.keys() {
+        let prefix_len = if ip_addr.is_ipv4() { 24 } else { 64 };
+        if let Ok(network) = IpNet::new(*ip_addr, prefix_len) {
+            let network = network.trunc();
+            if !networks.contains(&network) {
+                networks.push(network);
+            }
+        }
+    }
+    networks
+}
+
+/// Default snap length for the written capture files
+pub const DEFAULT_SNAPLEN: usize = 65535;
+
+/// Rotation options for the capture file writer
+#[derive(Debug, Clone)]
+pub struct RotationOptions {
+    /// Start a new file when the current file exceeds this size (in bytes)
+    pub max_file_size: Option<u64>,
+    /// Start a new file after this interval
+    pub interval: Option<Duration>,
+    /// Maximum number of files to keep. The oldest file is removed first
+    pub max_files: Option<usize>,
+}
+
+impl RotationOptions {
+    pub fn new() -> RotationOptions {
+        RotationOptions {
+            max_file_size: None,
+            interval: None,
+            max_files: None,
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        self.max_file_size.is_some() || self.interval.is_some()
+    }
+}
+
+/// pcapng writer with rotation.
+///
+/// Every file starts with a Section Header Block followed by an
+/// Interface Description Block for each registered interface,
+/// so each rotated file can be opened on its own.
+pub struct CaptureFileWriter {
+    file_path: PathBuf,
+    rotation: RotationOptions,
+    snaplen: usize,
+    /// (Interface index, Interface). The position is the interface ID in the file
+    interfaces: Vec<(u32, FileInterface)>,
+    writer: Option<BufWriter<File>>,
+    file_size: u64,
+    file_opened_at: Instant,
+    file_count: usize,
+    written_files: VecDeque<PathBuf>,
+}
+
+impl CaptureFileWriter {
+    pub fn new(file_path: PathBuf, rotation: RotationOptions, snaplen: usize) -> CaptureFileWriter {
+        CaptureFileWriter {
+            file_path,
+            rotation,
+            snaplen,
+            interfaces: Vec::new(),
+            writer: None,
+            file_size: 0,
+            file_opened_at: Instant::now(),
+            file_count: 0,
+            written_files: VecDeque::new(),
+        }
+    }
+    /// Register the interface of a capture thread.
+    /// Must be called before the first packet is written.
+    pub fn add_interface(&mut self, if_index: u32, if_name: String, link_type: LinkType) {
+        if self.interfaces.iter().any(|(index, _)| *index == if_index) {
+            return;
+        }
+        self.interfaces.push((
+            if_index,
+            FileInterface {
+                name: if_name,
+                link_type,
+                snaplen: self.snaplen as u32,
+                ts_units: 1_000_000_000,
+            },
+        ));
+    }
+    /// Write the packet to the current file. Rotate the file if needed.
+    pub fn write_packet(
+        &mut self,
+        if_index: u32,
+        timestamp: Duration,
+        orig_len: usize,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let if_id = match self
+            .interfaces
+            .iter()
+            .position(|(index, _)| *index == if_index)
+        {
+            Some(if_id) => if_id as u32,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("interface {} is not registered", if_index),
+                ));
+            }
+        };
+        if self.writer.is_none() || self.should_rotate() {
+            self.open_next_file()?;
+        }
+        let cap_len = std::cmp::min(data.len(), self.snaplen);
+        let ts = timestamp.as_nanos() as u64;
+        let mut body: Vec<u8> = Vec::with_capacity(20 + cap_len + 3);
+        body.extend_from_slice(&if_id.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(cap_len as u32).to_le_bytes());
+        body.extend_from_slice(&(orig_len as u32).to_le_bytes());
+        body.extend_from_slice(&data[..cap_len]);
+        self.write_block(PCAPNG_EPB_TYPE, body)
+    }
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+    fn should_rotate(&self) -> bool {
+        if let Some(max_file_size) = self.rotation.max_file_size {
+            if self.file_size >= max_file_size {
+                return true;
+            }
+        }
+        if let Some(interval) = self.rotation.interval {
+            if self.file_opened_at.elapsed() >= interval {
+                return true;
+            }
+        }
+        false
+    }
+    /// Get the path of the next file.
+    /// With rotation, files are named {stem}_{number}_{datetime}.{extension}
+    fn next_file_path(&self) -> PathBuf {
+        if !self.rotation.enabled() {
+            return self.file_path.clone();
+        }
+        let stem = match self.file_path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => String::from("ntap"),
+        };
+        let extension = match self.file_path.extension() {
+            Some(extension) => extension.to_string_lossy().to_string(),
+            None => String::from("pcapng"),
+        };
+        let datetime = chrono::Local::now().format("%Y%m%d%H%M%S");
+        let file_name = format!(
+            "{}_{:05}_{}.{}",
+            stem,
+            self.file_count + 1,
+            datetime,
+            extension
+        );
+        self.file_path.with_file_name(file_name)
+    }
+    fn open_next_file(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        let file_path = self.next_file_path();
+        let file = File::create(&file_path)?;
+        self.writer = Some(BufWriter::new(file));
+        self.file_size = 0;
+        self.file_opened_at = Instant::now();
+        self.file_count += 1;
+        self.written_files.push_back(file_path);
+        self.remove_old_files();
+        self.write_section_header()?;
+        for i in 0..self.interfaces.len() {
+            let body = interface_description_body(&self.interfaces[i].1);
+            self.write_block(PCAPNG_IDB_TYPE, body)?;
+        }
+        Ok(())
+    }
+    fn remove_old_files(&mut self) {
+        let max_files = match self.rotation.max_files {
+            Some(max_files) => std::cmp::max(max_files, 1),
+            None => return,
+        };
+        while self.written_files.len() > max_files {
+            if let Some(old_file) = self.written_files.pop_front() {
+                if let Err(e) = std::fs::remove_file(&old_file) {
+                    log::error!("Failed to remove {}: {}", old_file.display(), e);
+                }
+            }
+        }
+    }
+    fn write_section_header(&mut self) -> io::Result<()> {
+        let mut body: Vec<u8> = Vec::new();
+        body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+        // Version 1.0
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // Section length is not specified
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        // shb_userappl
+        push_option(&mut body, 4, crate::sys::get_app_title().as_bytes());
+        push_option(&mut body, 0, &[]);
+        self.write_block(PCAPNG_SHB_TYPE, body)
+    }
+    fn write_block(&mut self, block_type: u32, mut body: Vec<u8>) -> io::Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "no file opened",
+                ))
+            }
+        };
+        // Pad the body to 32 bits
+        body.resize((body.len() + 3) & !3, 0);
+        let block_len = (body.len() + 12) as u32;
+        writer.write_all(&block_type.to_le_bytes())?;
+        writer.write_all(&block_len.to_le_bytes())?;
+        writer.write_all(&body)?;
+        writer.write_all(&block_len.to_le_bytes())?;
+        self.file_size += block_len as u64;
+        Ok(())
+    }
+}
+
+impl Drop for CaptureFileWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn interface_description_body(interface: &FileInterface) -> Vec<u8> {
+    let mut body: Vec<u8> = Vec::new();
+    body.extend_from_slice(&(interface.link_type.to_u32() as u16).to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    body.extend_from_slice(&interface.snaplen.to_le_bytes());
+    // if_name
+    push_option(&mut body, 2, interface.name.as_bytes());
+    // if_tsresol: nanoseconds
+    push_option(&mut body, 9, &[9]);
+    push_option(&mut body, 0, &[]);
+    body
+}
+
+/// Append a pcapng option (little endian) padded to 32 bits
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize((body.len() + 3) & !3, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ntap-capfile-{}-{}", std::process::id(), name))
+    }
+
+    fn pcap_file(magic: u32, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&magic.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bytes.extend_from_slice(&0i32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(DEFAULT_SNAPLEN as u32).to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        for (ts_sec, ts_frac, data) in records {
+            bytes.extend_from_slice(&ts_sec.to_le_bytes());
+            bytes.extend_from_slice(&ts_frac.to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    fn pcapng_block(block_type: u32, mut body: Vec<u8>) -> Vec<u8> {
+        body.resize((body.len() + 3) & !3, 0);
+        let block_len = (body.len() + 12) as u32;
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&block_type.to_le_bytes());
+        bytes.extend_from_slice(&block_len.to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes.extend_from_slice(&block_len.to_le_bytes());
+        bytes
+    }
+
+    fn pcapng_section_header() -> Vec<u8> {
+        let mut body: Vec<u8> = Vec::new();
+        body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        pcapng_block(PCAPNG_SHB_TYPE, body)
+    }
+
+    fn read_file(name: &str, bytes: &[u8]) -> io::Result<(Vec<FilePacket>, Vec<FileInterface>)> {
+        let file_path = temp_path(name);
+        std::fs::write(&file_path, bytes)?;
+        let result = (|| {
+            let mut reader = CaptureFileReader::open(&file_path)?;
+            let mut packets: Vec<FilePacket> = Vec::new();
+            while let Some(packet) = reader.next_packet()? {
+                packets.push(packet);
+            }
+            Ok((packets, reader.interfaces().clone()))
+        })();
+        let _ = std::fs::remove_file(&file_path);
+        result
+    }
+
+    #[test]
+    fn read_pcap_microseconds() {
+        let bytes = pcap_file(
+            PCAP_MAGIC_MICROS,
+            &[(1700000000, 250000, &[1, 2, 3]), (1700000001, 999999, &[4])],
+        );
+        let (packets, interfaces) = read_file("pcap-micros.pcap", &bytes).unwrap();
+        assert_eq!(interfaces[0].link_type, LinkType::Ethernet);
+        assert_eq!(interfaces[0].ts_units, 1_000_000);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].timestamp, Duration::new(1700000000, 250_000_000));
+        assert_eq!(packets[0].data, vec![1, 2, 3]);
+        assert_eq!(packets[1].timestamp, Duration::new(1700000001, 999_999_000));
+        assert_eq!(packets[1].orig_len, 1);
+    }
+
+    #[test]
+    fn read_pcap_nanoseconds() {
+        let bytes = pcap_file(PCAP_MAGIC_NANOS, &[(1700000000, 123456789, &[0xAA; 60])]);
+        let (packets, interfaces) = read_file("pcap-nanos.pcap", &bytes).unwrap();
+        assert_eq!(interfaces[0].ts_units, 1_000_000_000);
+        assert_eq!(packets[0].timestamp, Duration::new(1700000000, 123_456_789));
+        assert_eq!(packets[0].data.len(), 60);
+    }
+
+    #[test]
+    fn read_pcap_truncated() {
+        let mut bytes = pcap_file(
+            PCAP_MAGIC_MICROS,
+            &[(1, 0, &[1, 2, 3, 4]), (2, 0, &[5, 6, 7, 8])],
+        );
+        bytes.truncate(bytes.len() - 2);
+        let err = read_file("pcap-truncated.pcap", &bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_pcap_oversized_packet() {
+        let mut bytes = pcap_file(PCAP_MAGIC_MICROS, &[(1, 0, &[1, 2, 3, 4])]);
+        // incl_len of the first record
+        bytes[24 + 8..24 + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = read_file("pcap-oversized.pcap", &bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn pcapng_round_trip_nanoseconds() {
+        let file_path = temp_path("round-trip.pcapng");
+        let mut writer = CaptureFileWriter::new(file_path.clone(), RotationOptions::new(), 8);
+        writer.add_interface(7, String::from("eth7"), LinkType::Ethernet);
+        writer.add_interface(9, String::from("tun0"), LinkType::Raw);
+        writer
+            .write_packet(
+                7,
+                Duration::new(1700000000, 123_456_789),
+                5,
+                &[1, 2, 3, 4, 5],
+            )
+            .unwrap();
+        // Longer than the snaplen
+        writer
+            .write_packet(9, Duration::new(1700000002, 1), 10, &[9; 10])
+            .unwrap();
+        drop(writer);
+        let bytes = std::fs::read(&file_path).unwrap();
+        let _ = std::fs::remove_file(&file_path);
+        let (packets, interfaces) = read_file("round-trip-read.pcapng", &bytes).unwrap();
+        assert_eq!(interfaces.len(), 2);
+        assert_eq!(interfaces[0].name, "eth7");
+        assert_eq!(interfaces[0].ts_units, 1_000_000_000);
+        assert_eq!(interfaces[1].name, "tun0");
+        assert_eq!(interfaces[1].link_type, LinkType::Raw);
+        assert_eq!(interfaces[1].snaplen, 8);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].if_id, 0);
+        assert_eq!(packets[0].timestamp, Duration::new(1700000000, 123_456_789));
+        assert_eq!(packets[0].data, vec![1, 2, 3, 4, 5]);
+        assert_eq!(packets[1].if_id, 1);
+        assert_eq!(packets[1].timestamp, Duration::new(1700000002, 1));
+        assert_eq!(packets[1].orig_len, 10);
+        assert_eq!(packets[1].data, vec![9; 8]);
+    }
+
+    #[test]
+    fn read_pcapng_microseconds() {
+        // Without if_tsresol the timestamps are in microseconds
+        let mut idb: Vec<u8> = Vec::new();
+        idb.extend_from_slice(&1u16.to_le_bytes());
+        idb.extend_from_slice(&0u16.to_le_bytes());
+        idb.extend_from_slice(&65535u32.to_le_bytes());
+        let ts: u64 = 1_700_000_000_500_000;
+        let mut epb: Vec<u8> = Vec::new();
+        epb.extend_from_slice(&0u32.to_le_bytes());
+        epb.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(ts as u32).to_le_bytes());
+        epb.extend_from_slice(&3u32.to_le_bytes());
+        epb.extend_from_slice(&3u32.to_le_bytes());
+        epb.extend_from_slice(&[7, 8, 9]);
+        let mut bytes = pcapng_section_header();
+        bytes.extend(pcapng_block(PCAPNG_IDB_TYPE, idb));
+        bytes.extend(pcapng_block(PCAPNG_EPB_TYPE, epb));
+        let (packets, interfaces) = read_file("micros.pcapng", &bytes).unwrap();
+        assert_eq!(interfaces[0].ts_units, 1_000_000);
+        assert_eq!(packets[0].timestamp, Duration::new(1700000000, 500_000_000));
+        assert_eq!(packets[0].data, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn read_pcapng_truncated() {
+        let file_path = temp_path("truncated-write.pcapng");
+        let mut writer =
+            CaptureFileWriter::new(file_path.clone(), RotationOptions::new(), DEFAULT_SNAPLEN);
+        writer.add_interface(1, String::from("eth0"), LinkType::Ethernet);
+        writer
+            .write_packet(1, Duration::new(1, 0), 4, &[1, 2, 3, 4])
+            .unwrap();
+        writer
+            .write_packet(1, Duration::new(2, 0), 4, &[5, 6, 7, 8])
+            .unwrap();
+        drop(writer);
+        let mut bytes = std::fs::read(&file_path).unwrap();
+        let _ = std::fs::remove_file(&file_path);
+        bytes.truncate(bytes.len() - 6);
+        let err = read_file("truncated.pcapng", &bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_pcapng_bad_block_length() {
+        for block_len in [13u32, 8, 0xFFFF_FFF0] {
+            let mut bytes = pcapng_section_header();
+            bytes.extend_from_slice(&PCAPNG_EPB_TYPE.to_le_bytes());
+            bytes.extend_from_slice(&block_len.to_le_bytes());
+            bytes.extend_from_slice(&[0u8; 32]);
+            let err = read_file("bad-block-length.pcapng", &bytes).unwrap_err();
+            assert_eq!(
+                err.kind(),
+                io::ErrorKind::InvalidData,
+                "block length {}",
+                block_len
+            );
+        }
+    }
+
+    #[test]
+    fn guess_local_networks_from_addresses() {
+        let mut local_ip_map: HashMap<IpAddr, String> = HashMap::new();
+        for ip in ["192.168.1.10", "192.168.1.11", "2001:db8::10"] {
+            local_ip_map.insert(ip.parse().unwrap(), String::from("eth0"));
+        }
+        let mut networks = guess_local_networks(&local_ip_map);
+        networks.sort();
+        let expected: Vec<IpNet> = vec![
+            "192.168.1.0/24".parse().unwrap(),
+            "2001:db8::/64".parse().unwrap(),
+        ];
+        assert_eq!(networks, expected);
+        assert!(guess_local_networks(&HashMap::new()).is_empty());
+    }
+}
@@ -0,0 +1,550 @@
+use crate::net::packet::PacketFrame;
+use nex::packet::arp::ArpHeader;
+use nex::packet::ethernet::{EtherType, EthernetHeader};
+use nex::packet::icmp::IcmpType;
+use nex::packet::icmpv6::Icmpv6Type;
+use nex::packet::ip::IpNextLevelProtocol;
+use nex::packet::ipv4::Ipv4Header;
+use nex::packet::ipv6::Ipv6Header;
+use nex::packet::tcp::{TcpFlags, TcpHeader, TcpOptionKind};
+use nex::packet::udp::UdpHeader;
+use nex::packet::vlan::VlanPacket;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const VLAN_TAG_LEN: usize = 4;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
+const ICMP_HEADER_LEN: usize = 8;
+const ARP_HEADER_LEN: usize = 28;
+
+const TCP_FLAGS: [(u8, &str); 8] = [
+    (TcpFlags::CWR, "CWR"),
+    (TcpFlags::ECE, "ECE"),
+    (TcpFlags::URG, "URG"),
+    (TcpFlags::ACK, "ACK"),
+    (TcpFlags::PSH, "PSH"),
+    (TcpFlags::RST, "RST"),
+    (TcpFlags::SYN, "SYN"),
+    (TcpFlags::FIN, "FIN"),
+];
+
+/// One field of a decoded layer. Bit fields and options are children.
+#[derive(Clone, Debug)]
+pub struct DecodedField {
+    pub name: String,
+    pub value: String,
+    pub children: Vec<DecodedField>,
+}
+
+impl DecodedField {
+    pub fn new(name: &str, value: String) -> DecodedField {
+        DecodedField {
+            name: name.to_string(),
+            value,
+            children: Vec::new(),
+        }
+    }
+    pub fn with_children(name: &str, value: String, children: Vec<DecodedField>) -> DecodedField {
+        DecodedField {
+            name: name.to_string(),
+            value,
+            children,
+        }
+    }
+}
+
+/// One protocol layer of a packet and the bytes it covers in the raw packet.
+#[derive(Clone, Debug)]
+pub struct DecodedLayer {
+    pub name: String,
+    pub summary: String,
+    /// Offset of the layer in the raw packet
+    pub offset: usize,
+    /// Length of the layer in bytes
+    pub len: usize,
+    pub fields: Vec<DecodedField>,
+}
+
+impl DecodedLayer {
+    fn new(name: &str, summary: String, offset: usize, len: usize) -> DecodedLayer {
+        DecodedLayer {
+            name: name.to_string(),
+            summary,
+            offset,
+            len,
+            fields: Vec::new(),
+        }
+    }
+    fn field(&mut self, name: &str, value: String) {
+        self.fields.push(DecodedField::new(name, value));
+    }
+}
+
+/// Decode the raw bytes of a packet layer by layer.
+/// Returns an empty list if the raw bytes were not kept. (snaplen 0)
+pub fn decode_packet(frame: &PacketFrame) -> Vec<DecodedLayer> {
+    let bytes = &frame.raw_packet;
+    let mut layers: Vec<DecodedLayer> = Vec::new();
+    if bytes.is_empty() {
+        return layers;
+    }
+    let end = if frame.raw_parse_option.from_ip_packet {
+        // TUN and BSD loopback packets start with the IP header, after an optional link header
+        let offset = std::cmp::min(frame.raw_parse_option.offset, bytes.len());
+        if offset > 0 {
+            layers.push(DecodedLayer::new(
+                "Link-layer header",
+                format!("{} bytes", offset),
+                0,
+                offset,
+            ));
+        }
+        decode_ip(bytes, offset, &mut layers)
+    } else {
+        decode_ethernet(bytes, &mut layers)
+    };
+    let decoded_len = layers
+        .last()
+        .map(|layer| layer.offset + layer.len)
+        .unwrap_or(0);
+    if decoded_len < end {
+        layers.push(DecodedLayer::new(
+            "Data",
+            format!("{} bytes", end - decoded_len),
+            decoded_len,
+            end - decoded_len,
+        ));
+    }
+    if end < bytes.len() {
+        layers.push(DecodedLayer::new(
+            "Trailer",
+            format!("{} bytes", bytes.len() - end),
+            end,
+            bytes.len() - end,
+        ));
+    }
+    layers
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode Ethernet and VLAN tags, then the payload by EtherType.
+/// Returns the end of the decoded packet. (Bytes after it are padding)
+fn decode_ethernet(bytes: &[u8], layers: &mut Vec<DecodedLayer>) -> usize {
+    let ethernet = match EthernetHeader::from_bytes(bytes) {
+        Ok(ethernet) => ethernet,
+        Err(_) => return bytes.len(),
+    };
+    let mut ethertype = u16_at(bytes, 12);
+    let mut layer = DecodedLayer::new(
+        "Ethernet II",
+        format!("Src: {}, Dst: {}", ethernet.source, ethernet.destination),
+        0,
+        ETHERNET_HEADER_LEN,
+    );
+    layer.field("Destination", ethernet.destination.to_string());
+    layer.field("Source", ethernet.source.to_string());
+    layer.field(
+        "Type",
+        format!("{} (0x{:04x})", ethernet.ethertype.name(), ethertype),
+    );
+    layers.push(layer);
+    let mut offset = ETHERNET_HEADER_LEN;
+    // 802.1Q and QinQ tags
+    while matches!(
+        EtherType::new(ethertype),
+        EtherType::Vlan | EtherType::PBridge | EtherType::QinQ
+    ) {
+        let vlan = match VlanPacket::new(&bytes[offset..]) {
+            Some(vlan) => vlan,
+            None => return bytes.len(),
+        };
+        ethertype = u16_at(bytes, offset + 2);
+        let mut layer = DecodedLayer::new(
+            "802.1Q VLAN",
+            format!("ID: {}", vlan.get_vlan_identifier()),
+            offset,
+            VLAN_TAG_LEN,
+        );
+        layer.field("Priority", format!("{:?}", vlan.get_priority_code_point()));
+        layer.field("DEI", vlan.get_drop_eligible_indicator().to_string());
+        layer.field("ID", vlan.get_vlan_identifier().to_string());
+        layer.field(
+            "Type",
+            format!("{} (0x{:04x})", EtherType::new(ethertype).name(), ethertype),
+        );
+        layers.push(layer);
+        offset += VLAN_TAG_LEN;
+    }
+    match EtherType::new(ethertype) {
+        EtherType::Ipv4 | EtherType::Ipv6 => decode_ip(bytes, offset, layers),
+        EtherType::Arp => {
+            decode_arp(bytes, offset, layers);
+            std::cmp::min(offset + ARP_HEADER_LEN, bytes.len())
+        }
+        _ => bytes.len(),
+    }
+}
+
+/// Decode an IPv4 or IPv6 packet by its version. Returns the end of the IP packet.
+fn decode_ip(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) -> usize {
+    match bytes.get(offset).map(|b| b >> 4) {
+        Some(4) => decode_ipv4(bytes, offset, layers),
+        Some(6) => decode_ipv6(bytes, offset, layers),
+        _ => bytes.len(),
+    }
+}
+
+fn decode_ipv4(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) -> usize {
+    let ipv4 = match Ipv4Header::from_bytes(&bytes[offset..]) {
+        Ok(ipv4) => ipv4,
+        Err(_) => return bytes.len(),
+    };
+    let header_len = ipv4.header_length as usize * 4;
+    let protocol = bytes[offset + 9];
+    let mut layer = DecodedLayer::new(
+        "Internet Protocol Version 4",
+        format!("Src: {}, Dst: {}", ipv4.source, ipv4.destination),
+        offset,
+        std::cmp::min(header_len, bytes.len() - offset),
+    );
+    layer.field("Version", ipv4.version.to_string());
+    layer.field("Header Length", format!("{} bytes", header_len));
+    layer.field("DSCP", ipv4.dscp.to_string());
+    layer.field("ECN", ipv4.ecn.to_string());
+    layer.field("Total Length", ipv4.total_length.to_string());
+    layer.field(
+        "Identification",
+        format!("0x{:04x} ({})", ipv4.identification, ipv4.identification),
+    );
+    layer.fields.push(DecodedField::with_children(
+        "Flags",
+        format!("0x{:x}", ipv4.flags),
+        vec![
+            DecodedField::new("Don't fragment", flag_value(ipv4.flags & 0b010 != 0)),
+            DecodedField::new("More fragments", flag_value(ipv4.flags & 0b001 != 0)),
+        ],
+    ));
+    layer.field("Fragment Offset", ipv4.fragment_offset.to_string());
+    layer.field("Time to Live", ipv4.ttl.to_string());
+    layer.field(
+        "Protocol",
+        format!("{} ({})", ipv4.next_level_protocol.as_str(), protocol),
+    );
+    layer.field("Header Checksum", format!("0x{:04x}", ipv4.checksum));
+    layer.field("Source", ipv4.source.to_string());
+    layer.field("Destination", ipv4.destination.to_string());
+    if !ipv4.options.is_empty() {
+        let options = ipv4
+            .options
+            .iter()
+            .map(|option| {
+                let length = match option.length {
+                    Some(length) => format!("{} bytes", length),
+                    None => "1 byte".to_string(),
+                };
+                DecodedField::new(&format!("{:?}", option.number), length)
+            })
+            .collect();
+        layer.fields.push(DecodedField::with_children(
+            "Options",
+            format!("{} bytes", header_len.saturating_sub(20)),
+            options,
+        ));
+    }
+    layers.push(layer);
+    // Ethernet padding after the IP packet is not part of it
+    let end = std::cmp::min(offset + ipv4.total_length as usize, bytes.len());
+    let payload_offset = offset + header_len;
+    // Only the first fragment has the transport header
+    if ipv4.fragment_offset == 0 && payload_offset < end {
+        decode_transport(&bytes[..end], payload_offset, protocol, layers);
+    }
+    std::cmp::max(end, payload_offset.min(bytes.len()))
+}
+
+fn decode_ipv6(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) -> usize {
+    let ipv6 = match Ipv6Header::from_bytes(&bytes[offset..]) {
+        Ok(ipv6) => ipv6,
+        Err(_) => return bytes.len(),
+    };
+    let next_header = bytes[offset + 6];
+    let mut layer = DecodedLayer::new(
+        "Internet Protocol Version 6",
+        format!("Src: {}, Dst: {}", ipv6.source, ipv6.destination),
+        offset,
+        IPV6_HEADER_LEN,
+    );
+    layer.field("Version", ipv6.version.to_string());
+    layer.field("Traffic Class", format!("0x{:02x}", ipv6.traffic_class));
+    layer.field("Flow Label", format!("0x{:05x}", ipv6.flow_label));
+    layer.field("Payload Length", ipv6.payload_length.to_string());
+    layer.field(
+        "Next Header",
+        format!("{} ({})", ipv6.next_header.as_str(), next_header),
+    );
+    layer.field("Hop Limit", ipv6.hop_limit.to_string());
+    layer.field("Source", ipv6.source.to_string());
+    layer.field("Destination", ipv6.destination.to_string());
+    layers.push(layer);
+    let payload_offset = offset + IPV6_HEADER_LEN;
+    let end = std::cmp::min(payload_offset + ipv6.payload_length as usize, bytes.len());
+    // Extension headers are shown as data
+    if payload_offset < end {
+        decode_transport(&bytes[..end], payload_offset, next_header, layers);
+    }
+    std::cmp::max(end, payload_offset.min(bytes.len()))
+}
+
+fn decode_transport(bytes: &[u8], offset: usize, protocol: u8, layers: &mut Vec<DecodedLayer>) {
+    match IpNextLevelProtocol::new(protocol) {
+        IpNextLevelProtocol::Tcp => decode_tcp(bytes, offset, layers),
+        IpNextLevelProtocol::Udp => decode_udp(bytes, offset, layers),
+        IpNextLevelProtocol::Icmp => decode_icmp(bytes, offset, layers),
+        IpNextLevelProtocol::Icmpv6 => decode_icmpv6(bytes, offset, layers),
+        _ => {}
+    }
+}
+
+fn flag_value(set: bool) -> String {
+    if set {
+        "Set".to_string()
+    } else {
+        "Not set".to_string()
+    }
+}
+
+/// Names of the TCP flags that are set. Example: SYN, ACK
+fn tcp_flag_names(flags: u8) -> Vec<&'static str> {
+    TCP_FLAGS
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+fn decode_tcp(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) {
+    let tcp = match TcpHeader::from_bytes(&bytes[offset..]) {
+        Ok(tcp) => tcp,
+        Err(_) => return,
+    };
+    let header_len = tcp.data_offset as usize * 4;
+    let header_len = std::cmp::min(header_len, bytes.len() - offset);
+    let payload_len = bytes.len() - offset - header_len;
+    let flag_names = tcp_flag_names(tcp.flags);
+    let mut layer = DecodedLayer::new(
+        "Transmission Control Protocol",
+        format!(
+            "Src Port: {}, Dst Port: {}, [{}], Seq: {}, Ack: {}, Len: {}",
+            tcp.source,
+            tcp.destination,
+            flag_names.join(", "),
+            tcp.sequence,
+            tcp.acknowledgement,
+            payload_len
+        ),
+        offset,
+        header_len,
+    );
+    layer.field("Source Port", tcp.source.to_string());
+    layer.field("Destination Port", tcp.destination.to_string());
+    layer.field("Sequence Number", tcp.sequence.to_string());
+    layer.field("Acknowledgment Number", tcp.acknowledgement.to_string());
+    layer.field(
+        "Header Length",
+        format!("{} bytes", tcp.data_offset as usize * 4),
+    );
+    let flags = TCP_FLAGS
+        .iter()
+        .map(|(flag, name)| DecodedField::new(name, flag_value(tcp.flags & flag != 0)))
+        .collect();
+    layer.fields.push(DecodedField::with_children(
+        "Flags",
+        format!("0x{:03x} ({})", tcp.flags, flag_names.join(", ")),
+        flags,
+    ));
+    layer.field("Window", tcp.window.to_string());
+    layer.field("Checksum", format!("0x{:04x}", tcp.checksum));
+    layer.field("Urgent Pointer", tcp.urgent_ptr.to_string());
+    if !tcp.options.is_empty() {
+        let options = tcp
+            .options
+            .iter()
+            .map(|option| {
+                let value = match option.kind {
+                    TcpOptionKind::MSS => option.get_mss().to_string(),
+                    TcpOptionKind::WSCALE => {
+                        let shift = option.get_wscale();
+                        format!("{} (multiply by {})", shift, 1u32 << shift.min(14))
+                    }
+                    TcpOptionKind::TIMESTAMPS => {
+                        let (tsval, tsecr) = option.get_timestamp();
+                        format!("TSval {}, TSecr {}", tsval, tsecr)
+                    }
+                    TcpOptionKind::SACK => option
+                        .data
+                        .chunks_exact(8)
+                        .map(|block| {
+                            let left = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+                            let right =
+                                u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+                            format!("{}-{}", left, right)
+                        })
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    _ => hex_string(&option.data),
+                };
+                DecodedField::new(&option.kind.name(), value)
+            })
+            .collect();
+        layer.fields.push(DecodedField::with_children(
+            "Options",
+            format!(
+                "{} bytes",
+                (tcp.data_offset as usize * 4).saturating_sub(20)
+            ),
+            options,
+        ));
+    }
+    layers.push(layer);
+}
+
+fn decode_udp(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) {
+    let udp = match UdpHeader::from_bytes(&bytes[offset..]) {
+        Ok(udp) => udp,
+        Err(_) => return,
+    };
+    let mut layer = DecodedLayer::new(
+        "User Datagram Protocol",
+        format!(
+            "Src Port: {}, Dst Port: {}, Len: {}",
+            udp.source,
+            udp.destination,
+            (udp.length as usize).saturating_sub(UDP_HEADER_LEN)
+        ),
+        offset,
+        UDP_HEADER_LEN,
+    );
+    layer.field("Source Port", udp.source.to_string());
+    layer.field("Destination Port", udp.destination.to_string());
+    layer.field("Length", udp.length.to_string());
+    layer.field("Checksum", format!("0x{:04x}", udp.checksum));
+    layers.push(layer);
+}
+
+fn decode_icmp(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) {
+    if bytes.len() < offset + 4 {
+        return;
+    }
+    let icmp_type = IcmpType::new(bytes[offset]);
+    let icmp_code = bytes[offset + 1];
+    let len = std::cmp::min(ICMP_HEADER_LEN, bytes.len() - offset);
+    let mut layer = DecodedLayer::new(
+        "Internet Control Message Protocol",
+        format!(
+            "{} (type {}, code {})",
+            icmp_type.name(),
+            bytes[offset],
+            icmp_code
+        ),
+        offset,
+        len,
+    );
+    layer.field("Type", format!("{} ({})", icmp_type.name(), bytes[offset]));
+    layer.field("Code", icmp_code.to_string());
+    layer.field("Checksum", format!("0x{:04x}", u16_at(bytes, offset + 2)));
+    if len == ICMP_HEADER_LEN {
+        match icmp_type {
+            IcmpType::EchoRequest | IcmpType::EchoReply => {
+                layer.field("Identifier", u16_at(bytes, offset + 4).to_string());
+                layer.field("Sequence Number", u16_at(bytes, offset + 6).to_string());
+            }
+            _ => {
+                layer.field("Rest of Header", hex_string(&bytes[offset + 4..offset + 8]));
+            }
+        }
+    }
+    layers.push(layer);
+}
+
+fn decode_icmpv6(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) {
+    if bytes.len() < offset + 4 {
+        return;
+    }
+    let icmpv6_type = Icmpv6Type::new(bytes[offset]);
+    let icmpv6_code = bytes[offset + 1];
+    let len = std::cmp::min(ICMP_HEADER_LEN, bytes.len() - offset);
+    let mut layer = DecodedLayer::new(
+        "Internet Control Message Protocol v6",
+        format!(
+            "{:?} (type {}, code {})",
+            icmpv6_type, bytes[offset], icmpv6_code
+        ),
+        offset,
+        len,
+    );
+    layer.field("Type", format!("{:?} ({})", icmpv6_type, bytes[offset]));
+    layer.field("Code", icmpv6_code.to_string());
+    layer.field("Checksum", format!("0x{:04x}", u16_at(bytes, offset + 2)));
+    if len == ICMP_HEADER_LEN {
+        match icmpv6_type {
+            Icmpv6Type::EchoRequest | Icmpv6Type::EchoReply => {
+                layer.field("Identifier", u16_at(bytes, offset + 4).to_string());
+                layer.field("Sequence Number", u16_at(bytes, offset + 6).to_string());
+            }
+            _ => {
+                layer.field("Rest of Header", hex_string(&bytes[offset + 4..offset + 8]));
+            }
+        }
+    }
+    layers.push(layer);
+}
+
+fn decode_arp(bytes: &[u8], offset: usize, layers: &mut Vec<DecodedLayer>) {
+    let arp = match ArpHeader::from_bytes(&bytes[offset..]) {
+        Ok(arp) => arp,
+        Err(_) => return,
+    };
+    let mut layer = DecodedLayer::new(
+        "Address Resolution Protocol",
+        format!(
+            "{:?}, {} ({}) -> {} ({})",
+            arp.operation,
+            arp.sender_proto_addr,
+            arp.sender_hw_addr,
+            arp.target_proto_addr,
+            arp.target_hw_addr
+        ),
+        offset,
+        ARP_HEADER_LEN,
+    );
+    layer.field(
+        "Hardware Type",
+        format!("{:?} ({})", arp.hardware_type, u16_at(bytes, offset)),
+    );
+    layer.field(
+        "Protocol Type",
+        format!(
+            "{} (0x{:04x})",
+            arp.protocol_type.name(),
+            u16_at(bytes, offset + 2)
+        ),
+    );
+    layer.field("Hardware Size", arp.hw_addr_len.to_string());
+    layer.field("Protocol Size", arp.proto_addr_len.to_string());
+    layer.field(
+        "Opcode",
+        format!("{:?} ({})", arp.operation, u16_at(bytes, offset + 6)),
+    );
+    layer.field("Sender MAC Address", arp.sender_hw_addr.to_string());
+    layer.field("Sender IP Address", arp.sender_proto_addr.to_string());
+    layer.field("Target MAC Address", arp.target_hw_addr.to_string());
+    layer.field("Target IP Address", arp.target_proto_addr.to_string());
+    layers.push(layer);
+}
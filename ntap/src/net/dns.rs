@@ -1,10 +1,13 @@
 use crate::thread_log;
+use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 #[cfg(not(any(unix, target_os = "windows")))]
 use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::proto::op::{Message, MessageType, ResponseCode};
+use hickory_resolver::proto::rr::RData;
 use hickory_resolver::Resolver;
 
 use futures::stream::{self, StreamExt};
@@ -310,14 +313,26 @@ pub fn start_dns_map_update(netstat_strage: &mut Arc<NetStatStrage>) {
                 continue;
             }
         };
+        // Lock the passive_dns_map
+        let passive_dns_map_inner = match netstat_strage.passive_dns_map.try_lock() {
+            Ok(passive_dns_map) => passive_dns_map,
+            Err(e) => {
+                thread_log!(error, "[dns_map_update] lock error: {}", e);
+                continue;
+            }
+        };
         for (ip_addr, _remote_host) in remote_hosts_inner.iter() {
-            if !reverse_dns_map_inner.contains_key(ip_addr) {
+            // Names seen in DNS responses are preferred, and no need to ask the resolver.
+            if !reverse_dns_map_inner.contains_key(ip_addr)
+                && !passive_dns_map_inner.contains_key(ip_addr)
+            {
                 lookup_target_ips.push(*ip_addr);
             }
         }
         // Drop the lock before calling lookup_ips
         drop(remote_hosts_inner);
         drop(reverse_dns_map_inner);
+        drop(passive_dns_map_inner);
         let mut resolver = DnsResolver::new();
         let dns_map = resolver.lookup_ips(lookup_target_ips);
         // Lock the remote_hosts
@@ -338,7 +353,9 @@ pub fn start_dns_map_update(netstat_strage: &mut Arc<NetStatStrage>) {
         };
         for (ip_addr, hostname) in dns_map {
             if let Some(remote_host) = remote_hosts_inner.get_mut(&ip_addr) {
-                remote_host.hostname = hostname.clone();
+                if remote_host.hostname.is_empty() {
+                    remote_host.hostname = hostname.clone();
+                }
             }
             reverse_dns_map_inner.insert(ip_addr, hostname);
         }
@@ -365,3 +382,221 @@ impl DnsResolver {
         handle.join().unwrap()
     }
 }
+
+/// Maximum number of entries in the passive DNS map.
+/// When the map exceeds this size, expired entries are removed first, then the oldest ones.
+pub const PASSIVE_DNS_MAP_CAPACITY: usize = 65536;
+/// Number of entries removed at once when the passive DNS map is full,
+/// so the map is not scanned again on every response.
+pub const PASSIVE_DNS_MAP_EVICTION: usize = PASSIVE_DNS_MAP_CAPACITY / 10;
+
+/// Remove expired records. If the map is still over capacity, remove the oldest records
+/// until PASSIVE_DNS_MAP_EVICTION entries are free.
+pub fn prune_passive_dns_map(passive_dns_map: &mut HashMap<IpAddr, DnsRecord>) {
+    if passive_dns_map.len() <= PASSIVE_DNS_MAP_CAPACITY {
+        return;
+    }
+    passive_dns_map.retain(|_, record| !record.is_expired());
+    let target_len = PASSIVE_DNS_MAP_CAPACITY - PASSIVE_DNS_MAP_EVICTION;
+    if passive_dns_map.len() <= target_len {
+        return;
+    }
+    let mut timestamps: Vec<SystemTime> = passive_dns_map
+        .values()
+        .map(|record| record.timestamp)
+        .collect();
+    let remove_count = passive_dns_map.len() - target_len;
+    timestamps.select_nth_unstable(remove_count - 1);
+    let cutoff = timestamps[remove_count - 1];
+    passive_dns_map.retain(|_, record| record.timestamp > cutoff);
+}
+
+/// Name observed in a captured DNS response
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DnsRecord {
+    /// The name the client queried (before following CNAMEs)
+    pub name: String,
+    /// TTL of the A/AAAA record in seconds
+    pub ttl: u32,
+    /// The time the response was captured
+    pub timestamp: SystemTime,
+}
+
+impl DnsRecord {
+    pub fn new(name: String, ttl: u32) -> DnsRecord {
+        DnsRecord {
+            name,
+            ttl,
+            timestamp: SystemTime::now(),
+        }
+    }
+    pub fn is_expired(&self) -> bool {
+        match self.timestamp.elapsed() {
+            Ok(elapsed) => elapsed > Duration::from_secs(self.ttl as u64),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Parse a DNS response message and get the addresses with the queried name.
+/// For TCP, the payload starts with the 2-byte message length.
+pub fn parse_dns_response(payload: &[u8], tcp: bool) -> Vec<(IpAddr, DnsRecord)> {
+    let mut records: Vec<(IpAddr, DnsRecord)> = Vec::new();
+    let message_bytes: &[u8] = if tcp {
+        if payload.len() < 2 {
+            return records;
+        }
+        let len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+        // The message is split into multiple segments
+        if payload.len() < 2 + len {
+            return records;
+        }
+        &payload[2..2 + len]
+    } else {
+        payload
+    };
+    let message = match Message::from_vec(message_bytes) {
+        Ok(message) => message,
+        Err(_) => return records,
+    };
+    if message.message_type() != MessageType::Response
+        || message.response_code() != ResponseCode::NoError
+    {
+        return records;
+    }
+    let query_name: String = match message.queries().first() {
+        Some(query) => query.name().to_utf8().trim_end_matches('.').to_string(),
+        None => return records,
+    };
+    if query_name.is_empty() {
+        return records;
+    }
+    for answer in message.answers() {
+        let ip_addr: IpAddr = match answer.data() {
+            Some(RData::A(a)) => IpAddr::V4(a.0),
+            Some(RData::AAAA(aaaa)) => IpAddr::V6(aaaa.0),
+            _ => continue,
+        };
+        records.push((ip_addr, DnsRecord::new(query_name.clone(), answer.ttl())));
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    // Response to "www.example.com A" with a CNAME to edge.example.net,
+    // which has one A and one AAAA record. Names are compressed.
+    const RESPONSE: [u8; 107] = [
+        0x1a, 0x2b, 0x81, 0x80, 0x00, 0x01, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77,
+        0x77, 0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00,
+        0x01, 0x00, 0x01, 0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x12,
+        0x04, 0x65, 0x64, 0x67, 0x65, 0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x6e,
+        0x65, 0x74, 0x00, 0xc0, 0x2d, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c, 0x00, 0x04,
+        0x5d, 0xb8, 0xd8, 0x22, 0xc0, 0x2d, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00,
+        0x10, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x01,
+    ];
+
+    fn check_records(records: &[(IpAddr, DnsRecord)]) {
+        let v4 = IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34));
+        let v6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let map: HashMap<IpAddr, &DnsRecord> =
+            records.iter().map(|(ip, record)| (*ip, record)).collect();
+        assert_eq!(records.len(), 2);
+        // The queried name, not the CNAME target
+        assert_eq!(map[&v4].name, "www.example.com");
+        assert_eq!(map[&v4].ttl, 300);
+        assert_eq!(map[&v6].name, "www.example.com");
+        assert_eq!(map[&v6].ttl, 60);
+        assert!(!map[&v4].is_expired());
+    }
+
+    #[test]
+    fn parse_response_with_cname_chain() {
+        check_records(&parse_dns_response(&RESPONSE, false));
+    }
+
+    #[test]
+    fn parse_tcp_response() {
+        let mut payload = (RESPONSE.len() as u16).to_be_bytes().to_vec();
+        payload.extend_from_slice(&RESPONSE);
+        check_records(&parse_dns_response(&payload, true));
+        // The rest of the message is in the next segment
+        assert!(parse_dns_response(&payload[..60], true).is_empty());
+        assert!(parse_dns_response(&payload[..1], true).is_empty());
+    }
+
+    #[test]
+    fn parse_truncated_or_garbage() {
+        for len in 0..RESPONSE.len() {
+            let records = parse_dns_response(&RESPONSE[..len], false);
+            assert!(records.is_empty(), "truncated at {} bytes", len);
+        }
+        assert!(parse_dns_response(&[0xff; 64], false).is_empty());
+        assert!(parse_dns_response(b"GET / HTTP/1.1\r\n\r\n", false).is_empty());
+        // A query, not a response
+        let mut query = RESPONSE;
+        query[2] &= 0x7f;
+        assert!(parse_dns_response(&query, false).is_empty());
+        // NXDOMAIN
+        let mut nxdomain = RESPONSE;
+        nxdomain[3] = 0x83;
+        assert!(parse_dns_response(&nxdomain, false).is_empty());
+    }
+
+    fn record(age_secs: u64, ttl: u32) -> DnsRecord {
+        DnsRecord {
+            name: format!("host-{}.example.com", age_secs),
+            ttl,
+            timestamp: SystemTime::now() - Duration::from_secs(age_secs),
+        }
+    }
+
+    fn ip(i: usize) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i as u32))
+    }
+
+    #[test]
+    fn expired_record() {
+        assert!(record(61, 60).is_expired());
+        assert!(!record(59, 60).is_expired());
+    }
+
+    #[test]
+    fn prune_evicts_oldest_at_capacity() {
+        let mut map: HashMap<IpAddr, DnsRecord> = HashMap::new();
+        for i in 0..PASSIVE_DNS_MAP_CAPACITY {
+            map.insert(ip(i), record(i as u64, 86400 * 30));
+        }
+        // Nothing is removed up to the capacity
+        prune_passive_dns_map(&mut map);
+        assert_eq!(map.len(), PASSIVE_DNS_MAP_CAPACITY);
+        map.insert(ip(PASSIVE_DNS_MAP_CAPACITY), record(0, 86400 * 30));
+        prune_passive_dns_map(&mut map);
+        let target_len = PASSIVE_DNS_MAP_CAPACITY - PASSIVE_DNS_MAP_EVICTION;
+        assert_eq!(map.len(), target_len);
+        // The records of age 0..target_len are kept, and the new one
+        assert!(map.contains_key(&ip(PASSIVE_DNS_MAP_CAPACITY)));
+        assert!(map.contains_key(&ip(target_len - 2)));
+        assert!(!map.contains_key(&ip(target_len - 1)));
+        assert!(!map.contains_key(&ip(PASSIVE_DNS_MAP_CAPACITY - 1)));
+    }
+
+    #[test]
+    fn prune_removes_expired_first() {
+        let mut map: HashMap<IpAddr, DnsRecord> = HashMap::new();
+        for i in 0..=PASSIVE_DNS_MAP_CAPACITY {
+            // Every other record is expired
+            let ttl = if i % 2 == 0 { 1 } else { 86400 * 30 };
+            map.insert(ip(i), record(10 + i as u64, ttl));
+        }
+        prune_passive_dns_map(&mut map);
+        // The expired half frees enough room, no live record is evicted
+        assert_eq!(map.len(), PASSIVE_DNS_MAP_CAPACITY / 2);
+        assert!(map.values().all(|record| !record.is_expired()));
+        assert!(map.contains_key(&ip(PASSIVE_DNS_MAP_CAPACITY - 1)));
+    }
+}
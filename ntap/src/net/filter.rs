@@ -0,0 +1,886 @@
+use ipnet::IpNet;
+use netdev::mac::MacAddr;
+use nex::packet::ethernet::EtherType;
+use nex::packet::frame::Frame;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Maximum nesting of "not" and parentheses. Deeper expressions are rejected
+/// instead of overflowing the stack of the recursive parser.
+pub const MAX_FILTER_DEPTH: usize = 64;
+
+/// Direction qualifier of a filter primitive
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDirection {
+    Src,
+    Dst,
+    SrcOrDst,
+    SrcAndDst,
+}
+
+impl FilterDirection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterDirection::Src => "src",
+            FilterDirection::Dst => "dst",
+            FilterDirection::SrcOrDst => "src or dst",
+            FilterDirection::SrcAndDst => "src and dst",
+        }
+    }
+    /// Qualifier prefix for display. "src or dst" is the default and omitted.
+    fn qualifier(&self) -> String {
+        match self {
+            FilterDirection::SrcOrDst => String::new(),
+            _ => format!("{} ", self.name()),
+        }
+    }
+    fn matches<T, F: Fn(&T) -> bool>(&self, src: &T, dst: &T, f: F) -> bool {
+        match self {
+            FilterDirection::Src => f(src),
+            FilterDirection::Dst => f(dst),
+            FilterDirection::SrcOrDst => f(src) || f(dst),
+            FilterDirection::SrcAndDst => f(src) && f(dst),
+        }
+    }
+}
+
+/// Protocol primitive
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterProtocol {
+    Ether,
+    Arp,
+    Vlan,
+    Ip,
+    Ip6,
+    Tcp,
+    Udp,
+    Icmp,
+    Icmp6,
+}
+
+impl FilterProtocol {
+    pub fn from_name(s: &str) -> Option<FilterProtocol> {
+        match s {
+            "ether" => Some(FilterProtocol::Ether),
+            "arp" => Some(FilterProtocol::Arp),
+            "vlan" => Some(FilterProtocol::Vlan),
+            "ip" | "ipv4" => Some(FilterProtocol::Ip),
+            "ip6" | "ipv6" => Some(FilterProtocol::Ip6),
+            "tcp" => Some(FilterProtocol::Tcp),
+            "udp" => Some(FilterProtocol::Udp),
+            "icmp" => Some(FilterProtocol::Icmp),
+            "icmp6" | "icmpv6" => Some(FilterProtocol::Icmp6),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterProtocol::Ether => "ether",
+            FilterProtocol::Arp => "arp",
+            FilterProtocol::Vlan => "vlan",
+            FilterProtocol::Ip => "ip",
+            FilterProtocol::Ip6 => "ip6",
+            FilterProtocol::Tcp => "tcp",
+            FilterProtocol::Udp => "udp",
+            FilterProtocol::Icmp => "icmp",
+            FilterProtocol::Icmp6 => "icmp6",
+        }
+    }
+    fn matches(&self, frame: &Frame) -> bool {
+        match self {
+            FilterProtocol::Ether => match &frame.datalink {
+                Some(datalink) => datalink.ethernet.is_some(),
+                None => false,
+            },
+            FilterProtocol::Arp => match &frame.datalink {
+                Some(datalink) => datalink.arp.is_some(),
+                None => false,
+            },
+            FilterProtocol::Vlan => match &frame.datalink {
+                Some(datalink) => match &datalink.ethernet {
+                    Some(ethernet) => ethernet.ethertype == EtherType::Vlan,
+                    None => false,
+                },
+                None => false,
+            },
+            FilterProtocol::Ip => match &frame.ip {
+                Some(ip) => ip.ipv4.is_some(),
+                None => false,
+            },
+            FilterProtocol::Ip6 => match &frame.ip {
+                Some(ip) => ip.ipv6.is_some(),
+                None => false,
+            },
+            FilterProtocol::Tcp => match &frame.transport {
+                Some(transport) => transport.tcp.is_some(),
+                None => false,
+            },
+            FilterProtocol::Udp => match &frame.transport {
+                Some(transport) => transport.udp.is_some(),
+                None => false,
+            },
+            FilterProtocol::Icmp => match &frame.ip {
+                Some(ip) => ip.icmp.is_some(),
+                None => false,
+            },
+            FilterProtocol::Icmp6 => match &frame.ip {
+                Some(ip) => ip.icmpv6.is_some(),
+                None => false,
+            },
+        }
+    }
+}
+
+/// Filter primitive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum FilterPrimitive {
+    Protocol(FilterProtocol),
+    Host(FilterDirection, IpAddr),
+    Net(FilterDirection, IpNet),
+    Port(FilterDirection, u16),
+    PortRange(FilterDirection, u16, u16),
+    EtherHost(FilterDirection, MacAddr),
+    /// Packet length is less than or equal to
+    Less(usize),
+    /// Packet length is greater than or equal to
+    Greater(usize),
+}
+
+impl FilterPrimitive {
+    fn matches(&self, frame: &Frame) -> bool {
+        match self {
+            FilterPrimitive::Protocol(protocol) => protocol.matches(frame),
+            FilterPrimitive::Host(dir, addr) => match get_ip_addrs(frame) {
+                Some((src, dst)) => dir.matches(&src, &dst, |ip| ip == addr),
+                None => false,
+            },
+            FilterPrimitive::Net(dir, net) => match get_ip_addrs(frame) {
+                Some((src, dst)) => dir.matches(&src, &dst, |ip| net.contains(ip)),
+                None => false,
+            },
+            FilterPrimitive::Port(dir, port) => match get_ports(frame) {
+                Some((src, dst)) => dir.matches(&src, &dst, |p| p == port),
+                None => false,
+            },
+            FilterPrimitive::PortRange(dir, start, end) => match get_ports(frame) {
+                Some((src, dst)) => dir.matches(&src, &dst, |p| p >= start && p <= end),
+                None => false,
+            },
+            FilterPrimitive::EtherHost(dir, mac) => match &frame.datalink {
+                Some(datalink) => match &datalink.ethernet {
+                    Some(ethernet) => {
+                        dir.matches(&ethernet.source, &ethernet.destination, |m| m == mac)
+                    }
+                    None => false,
+                },
+                None => false,
+            },
+            FilterPrimitive::Less(len) => frame.packet_len <= *len,
+            FilterPrimitive::Greater(len) => frame.packet_len >= *len,
+        }
+    }
+}
+
+impl fmt::Display for FilterPrimitive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterPrimitive::Protocol(protocol) => write!(f, "{}", protocol.name()),
+            FilterPrimitive::Host(dir, addr) => write!(f, "{}host {}", dir.qualifier(), addr),
+            FilterPrimitive::Net(dir, net) => write!(f, "{}net {}", dir.qualifier(), net),
+            FilterPrimitive::Port(dir, port) => write!(f, "{}port {}", dir.qualifier(), port),
+            FilterPrimitive::PortRange(dir, start, end) => {
+                write!(f, "{}portrange {}-{}", dir.qualifier(), start, end)
+            }
+            FilterPrimitive::EtherHost(dir, mac) => {
+                write!(f, "ether {}host {}", dir.qualifier(), mac)
+            }
+            FilterPrimitive::Less(len) => write!(f, "less {}", len),
+            FilterPrimitive::Greater(len) => write!(f, "greater {}", len),
+        }
+    }
+}
+
+/// Parsed filter expression (AST)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Primitive(FilterPrimitive),
+}
+
+impl FilterExpr {
+    /// Evaluate the filter against the frame
+    pub fn matches(&self, frame: &Frame) -> bool {
+        match self {
+            FilterExpr::And(lhs, rhs) => lhs.matches(frame) && rhs.matches(frame),
+            FilterExpr::Or(lhs, rhs) => lhs.matches(frame) || rhs.matches(frame),
+            FilterExpr::Not(expr) => !expr.matches(frame),
+            FilterExpr::Primitive(primitive) => primitive.matches(frame),
+        }
+    }
+}
+
+impl fmt::Display for FilterExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterExpr::And(lhs, rhs) => write!(f, "({} and {})", lhs, rhs),
+            FilterExpr::Or(lhs, rhs) => write!(f, "({} or {})", lhs, rhs),
+            FilterExpr::Not(expr) => write!(f, "not {}", expr),
+            FilterExpr::Primitive(primitive) => write!(f, "{}", primitive),
+        }
+    }
+}
+
+impl FromStr for FilterExpr {
+    type Err = FilterParseError;
+    fn from_str(s: &str) -> Result<FilterExpr, FilterParseError> {
+        let tokens = tokenize(s)?;
+        let mut parser = FilterParser {
+            tokens,
+            pos: 0,
+            input_len: s.len(),
+            depth: 0,
+        };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(FilterParseError::new(
+                format!("unexpected '{}'", token.text),
+                token.offset,
+            )),
+        }
+    }
+}
+
+/// Parse the filter expression. Used as the clap value parser.
+pub fn parse_filter_expr(s: &str) -> Result<FilterExpr, String> {
+    FilterExpr::from_str(s).map_err(|e| e.describe(s))
+}
+
+/// Filter expression parse error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterParseError {
+    pub message: String,
+    /// Byte offset in the expression
+    pub offset: usize,
+}
+
+impl FilterParseError {
+    fn new(message: String, offset: usize) -> FilterParseError {
+        FilterParseError { message, offset }
+    }
+    /// Describe the error with the expression and a caret under the error position
+    pub fn describe(&self, expr: &str) -> String {
+        let column = expr[..self.offset.min(expr.len())].chars().count();
+        format!(
+            "{} at column {}\n  {}\n  {}^",
+            self.message,
+            column + 1,
+            expr,
+            " ".repeat(column)
+        )
+    }
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for FilterParseError {}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    offset: usize,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, FilterParseError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' | ')' | '!' => tokens.push(Token {
+                text: c.to_string(),
+                offset,
+            }),
+            '&' | '|' => match chars.next() {
+                Some((_, next)) if next == c => tokens.push(Token {
+                    text: format!("{}{}", c, c),
+                    offset,
+                }),
+                _ => {
+                    return Err(FilterParseError::new(
+                        format!("expected '{}{}'", c, c),
+                        offset,
+                    ));
+                }
+            },
+            _ => {
+                let mut end = offset + c.len_utf8();
+                while let Some((next_offset, next)) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '(' | ')' | '!' | '&' | '|') {
+                        break;
+                    }
+                    end = next_offset + next.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token {
+                    text: s[offset..end].to_lowercase(),
+                    offset,
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser.
+///
+/// expr     := and_expr (("or" | "||") and_expr)*
+/// and_expr := not_expr (("and" | "&&") not_expr)*
+/// not_expr := ("not" | "!") not_expr | "(" expr ")" | primitive
+struct FilterParser {
+    tokens: Vec<Token>,
+    pos: usize,
+    input_len: usize,
+    /// Current nesting of "not" and parentheses
+    depth: usize,
+}
+
+impl FilterParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn peek_text(&self) -> Option<&str> {
+        self.peek().map(|token| token.text.as_str())
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+    /// Take the next token, or return an error describing what was expected
+    fn expect(&mut self, expected: &str) -> Result<Token, FilterParseError> {
+        match self.next() {
+            Some(token) => Ok(token),
+            None => Err(FilterParseError::new(
+                format!("expected {}, found end of expression", expected),
+                self.input_len,
+            )),
+        }
+    }
+    fn parse_or(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let mut expr = self.parse_and()?;
+        while let Some("or" | "||") = self.peek_text() {
+            self.next();
+            let rhs = self.parse_and()?;
+            expr = FilterExpr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+    fn parse_and(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let mut expr = self.parse_not()?;
+        while let Some("and" | "&&") = self.peek_text() {
+            self.next();
+            let rhs = self.parse_not()?;
+            expr = FilterExpr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+    fn parse_not(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let token = self.expect("a filter primitive")?;
+        match token.text.as_str() {
+            "not" | "!" | "(" => {
+                if self.depth >= MAX_FILTER_DEPTH {
+                    return Err(FilterParseError::new(
+                        format!(
+                            "expression is nested too deeply, at most {} levels are allowed",
+                            MAX_FILTER_DEPTH
+                        ),
+                        token.offset,
+                    ));
+                }
+                self.depth += 1;
+                let expr = self.parse_nested(token);
+                self.depth -= 1;
+                expr
+            }
+            _ => self.parse_primitive(token),
+        }
+    }
+    /// Parse the operand of "not" or the group after "("
+    fn parse_nested(&mut self, token: Token) -> Result<FilterExpr, FilterParseError> {
+        match token.text.as_str() {
+            "(" => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(close) if close.text == ")" => Ok(expr),
+                    Some(other) => Err(FilterParseError::new(
+                        format!("expected ')', found '{}'", other.text),
+                        other.offset,
+                    )),
+                    None => Err(FilterParseError::new(
+                        String::from("unclosed '('"),
+                        token.offset,
+                    )),
+                }
+            }
+            _ => {
+                let expr = self.parse_not()?;
+                Ok(FilterExpr::Not(Box::new(expr)))
+            }
+        }
+    }
+    fn parse_primitive(&mut self, token: Token) -> Result<FilterExpr, FilterParseError> {
+        // Protocol, optionally followed by qualifiers. e.g. "tcp", "tcp port 80", "ip6 net ::/0"
+        if let Some(protocol) = FilterProtocol::from_name(&token.text) {
+            if protocol == FilterProtocol::Ether {
+                return self.parse_ether(token);
+            }
+            let proto_expr = FilterExpr::Primitive(FilterPrimitive::Protocol(protocol));
+            match self.peek_text() {
+                Some("src" | "dst" | "host" | "net" | "port" | "portrange") => {
+                    let token = self.expect("a filter primitive")?;
+                    let expr = self.parse_qualified(token)?;
+                    Ok(FilterExpr::And(Box::new(proto_expr), Box::new(expr)))
+                }
+                _ => Ok(proto_expr),
+            }
+        } else {
+            self.parse_qualified(token)
+        }
+    }
+    /// Parse "[src|dst|src or dst|src and dst] (host|net|port|portrange) <value>"
+    /// or "less <len>", "greater <len>"
+    fn parse_qualified(&mut self, token: Token) -> Result<FilterExpr, FilterParseError> {
+        let (dir, keyword) = self.parse_direction(token)?;
+        let primitive = match keyword.text.as_str() {
+            "host" => {
+                let value = self.expect("an IP address after 'host'")?;
+                match IpAddr::from_str(&value.text) {
+                    Ok(addr) => FilterPrimitive::Host(dir, addr),
+                    Err(_) => {
+                        return Err(FilterParseError::new(
+                            format!("invalid IP address '{}'", value.text),
+                            value.offset,
+                        ));
+                    }
+                }
+            }
+            "net" => {
+                let value = self.expect("a network (CIDR) after 'net'")?;
+                match parse_net(&value.text) {
+                    Some(net) => FilterPrimitive::Net(dir, net),
+                    None => {
+                        return Err(FilterParseError::new(
+                            format!(
+                                "invalid network '{}', expected CIDR such as 10.0.0.0/8",
+                                value.text
+                            ),
+                            value.offset,
+                        ));
+                    }
+                }
+            }
+            "port" => {
+                let value = self.expect("a port number after 'port'")?;
+                FilterPrimitive::Port(dir, parse_port(&value)?)
+            }
+            "portrange" => {
+                let value = self.expect("a port range after 'portrange'")?;
+                let (start, end) = match value.text.split_once('-') {
+                    Some((start, end)) => (start, end),
+                    None => {
+                        return Err(FilterParseError::new(
+                            format!(
+                                "invalid port range '{}', expected <start>-<end>",
+                                value.text
+                            ),
+                            value.offset,
+                        ));
+                    }
+                };
+                let start_port = parse_port(&Token {
+                    text: start.to_string(),
+                    offset: value.offset,
+                })?;
+                let end_port = parse_port(&Token {
+                    text: end.to_string(),
+                    offset: value.offset + start.len() + 1,
+                })?;
+                if start_port > end_port {
+                    return Err(FilterParseError::new(
+                        format!(
+                            "invalid port range '{}', start is greater than end",
+                            value.text
+                        ),
+                        value.offset,
+                    ));
+                }
+                FilterPrimitive::PortRange(dir, start_port, end_port)
+            }
+            "less" | "greater" if dir == FilterDirection::SrcOrDst => {
+                let value = self.expect(&format!("a length after '{}'", keyword.text))?;
+                let len = match value.text.parse::<usize>() {
+                    Ok(len) => len,
+                    Err(_) => {
+                        return Err(FilterParseError::new(
+                            format!("invalid length '{}'", value.text),
+                            value.offset,
+                        ));
+                    }
+                };
+                if keyword.text == "less" {
+                    FilterPrimitive::Less(len)
+                } else {
+                    FilterPrimitive::Greater(len)
+                }
+            }
+            _ => {
+                // A bare IP address or network is treated as "host" or "net"
+                if dir == FilterDirection::SrcOrDst {
+                    if let Ok(addr) = IpAddr::from_str(&keyword.text) {
+                        return Ok(FilterExpr::Primitive(FilterPrimitive::Host(dir, addr)));
+                    }
+                    if let Ok(net) = IpNet::from_str(&keyword.text) {
+                        return Ok(FilterExpr::Primitive(FilterPrimitive::Net(
+                            dir,
+                            net.trunc(),
+                        )));
+                    }
+                }
+                return Err(FilterParseError::new(
+                    format!(
+                        "unknown primitive '{}', expected a protocol, host, net, port, portrange, less or greater",
+                        keyword.text
+                    ),
+                    keyword.offset,
+                ));
+            }
+        };
+        Ok(FilterExpr::Primitive(primitive))
+    }
+    /// Parse the direction qualifier. Returns the direction and the keyword that follows it.
+    fn parse_direction(
+        &mut self,
+        token: Token,
+    ) -> Result<(FilterDirection, Token), FilterParseError> {
+        let dir = match token.text.as_str() {
+            "src" => FilterDirection::Src,
+            "dst" => FilterDirection::Dst,
+            _ => return Ok((FilterDirection::SrcOrDst, token)),
+        };
+        // "src or dst" / "src and dst"
+        if let Some("or" | "and") = self.peek_text() {
+            let other = match self.tokens.get(self.pos + 1) {
+                Some(next) => next.text.clone(),
+                None => String::new(),
+            };
+            if (dir == FilterDirection::Src && other == "dst")
+                || (dir == FilterDirection::Dst && other == "src")
+            {
+                let op = self.expect("'or' or 'and'")?;
+                self.next();
+                let keyword = self.expect("host, net, port or portrange")?;
+                if op.text == "or" {
+                    return Ok((FilterDirection::SrcOrDst, keyword));
+                } else {
+                    return Ok((FilterDirection::SrcAndDst, keyword));
+                }
+            }
+        }
+        let keyword = self.expect(&format!(
+            "host, net, port or portrange after '{}'",
+            token.text
+        ))?;
+        match keyword.text.as_str() {
+            "host" | "net" | "port" | "portrange" => Ok((dir, keyword)),
+            _ => {
+                // "src 10.0.0.1" is the same as "src host 10.0.0.1",
+                // "src 10.0.0.0/8" the same as "src net 10.0.0.0/8"
+                let implied = if IpAddr::from_str(&keyword.text).is_ok() {
+                    Some("host")
+                } else if IpNet::from_str(&keyword.text).is_ok() {
+                    Some("net")
+                } else {
+                    None
+                };
+                if let Some(implied) = implied {
+                    self.pos -= 1;
+                    Ok((
+                        dir,
+                        Token {
+                            text: String::from(implied),
+                            offset: keyword.offset,
+                        },
+                    ))
+                } else {
+                    Err(FilterParseError::new(
+                        format!(
+                            "expected host, net, port or portrange after '{}', found '{}'",
+                            token.text, keyword.text
+                        ),
+                        keyword.offset,
+                    ))
+                }
+            }
+        }
+    }
+    /// Parse "ether [src|dst] host <mac>" or "ether [src|dst] <mac>"
+    fn parse_ether(&mut self, token: Token) -> Result<FilterExpr, FilterParseError> {
+        let proto_expr = FilterExpr::Primitive(FilterPrimitive::Protocol(FilterProtocol::Ether));
+        let dir = match self.peek_text() {
+            Some("src") => FilterDirection::Src,
+            Some("dst") => FilterDirection::Dst,
+            Some("host") => FilterDirection::SrcOrDst,
+            _ => return Ok(proto_expr),
+        };
+        if dir != FilterDirection::SrcOrDst {
+            self.next();
+        }
+        if let Some("host") = self.peek_text() {
+            self.next();
+        }
+        let value = self.expect(&format!("a MAC address after '{}'", token.text))?;
+        match MacAddr::from_str(&value.text) {
+            Ok(mac) => Ok(FilterExpr::Primitive(FilterPrimitive::EtherHost(dir, mac))),
+            Err(_) => Err(FilterParseError::new(
+                format!("invalid MAC address '{}'", value.text),
+                value.offset,
+            )),
+        }
+    }
+}
+
+fn parse_port(token: &Token) -> Result<u16, FilterParseError> {
+    match token.text.parse::<u16>() {
+        Ok(port) => Ok(port),
+        Err(_) => Err(FilterParseError::new(
+            format!(
+                "invalid port '{}', expected a number from 0 to 65535",
+                token.text
+            ),
+            token.offset,
+        )),
+    }
+}
+
+/// Parse a network. A bare address is treated as a host network (/32 or /128)
+fn parse_net(s: &str) -> Option<IpNet> {
+    match IpNet::from_str(s) {
+        Ok(net) => Some(net.trunc()),
+        Err(_) => match IpAddr::from_str(s) {
+            Ok(addr) => Some(IpNet::from(addr)),
+            Err(_) => None,
+        },
+    }
+}
+
+fn get_ip_addrs(frame: &Frame) -> Option<(IpAddr, IpAddr)> {
+    if let Some(ip) = &frame.ip {
+        if let Some(ipv4) = &ip.ipv4 {
+            return Some((IpAddr::V4(ipv4.source), IpAddr::V4(ipv4.destination)));
+        }
+        if let Some(ipv6) = &ip.ipv6 {
+            return Some((IpAddr::V6(ipv6.source), IpAddr::V6(ipv6.destination)));
+        }
+    }
+    if let Some(datalink) = &frame.datalink {
+        if let Some(arp) = &datalink.arp {
+            return Some((
+                IpAddr::V4(arp.sender_proto_addr),
+                IpAddr::V4(arp.target_proto_addr),
+            ));
+        }
+    }
+    None
+}
+
+fn get_ports(frame: &Frame) -> Option<(u16, u16)> {
+    if let Some(transport) = &frame.transport {
+        if let Some(tcp) = &transport.tcp {
+            return Some((tcp.source, tcp.destination));
+        }
+        if let Some(udp) = &transport.udp {
+            return Some((udp.source, udp.destination));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nex::packet::frame::ParseOption;
+    use std::net::Ipv4Addr;
+
+    const TCP: u8 = 6;
+    const UDP: u8 = 17;
+
+    // Ethernet + IPv4 + TCP or UDP header without payload
+    fn frame(protocol: u8, src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16)) -> Frame {
+        let transport_len: usize = if protocol == TCP { 20 } else { 8 };
+        let mut bytes: Vec<u8> = vec![0x02, 0, 0, 0, 0, 0x01, 0x02, 0, 0, 0, 0, 0x02, 0x08, 0x00];
+        bytes.extend_from_slice(&[0x45, 0]);
+        bytes.extend_from_slice(&((20 + transport_len) as u16).to_be_bytes());
+        bytes.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+        bytes.extend_from_slice(&src.0.octets());
+        bytes.extend_from_slice(&dst.0.octets());
+        bytes.extend_from_slice(&src.1.to_be_bytes());
+        bytes.extend_from_slice(&dst.1.to_be_bytes());
+        if protocol == TCP {
+            bytes.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0]);
+        } else {
+            bytes.extend_from_slice(&(transport_len as u16).to_be_bytes());
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        Frame::from_bytes(&bytes, ParseOption::default())
+    }
+
+    fn https() -> Frame {
+        frame(
+            TCP,
+            (Ipv4Addr::new(192, 168, 1, 10), 50000),
+            (Ipv4Addr::new(1, 1, 1, 1), 443),
+        )
+    }
+
+    fn dns() -> Frame {
+        frame(
+            UDP,
+            (Ipv4Addr::new(192, 168, 1, 10), 50001),
+            (Ipv4Addr::new(10, 0, 0, 53), 53),
+        )
+    }
+
+    fn parse(s: &str) -> FilterExpr {
+        FilterExpr::from_str(s).unwrap()
+    }
+
+    fn parse_err(s: &str) -> FilterParseError {
+        FilterExpr::from_str(s).unwrap_err()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = parse("tcp or udp and port 53");
+        assert_eq!(expr.to_string(), "(tcp or (udp and port 53))");
+        assert!(expr.matches(&https()));
+        assert!(expr.matches(&dns()));
+        let expr = parse("(tcp or udp) and port 53");
+        assert_eq!(expr.to_string(), "((tcp or udp) and port 53)");
+        assert!(!expr.matches(&https()));
+        assert!(expr.matches(&dns()));
+        assert_eq!(
+            parse("tcp || udp && port 53"),
+            parse("tcp or udp and port 53")
+        );
+    }
+
+    #[test]
+    fn not_and_parentheses() {
+        let expr = parse("not tcp");
+        assert!(!expr.matches(&https()));
+        assert!(expr.matches(&dns()));
+        assert_eq!(parse("! tcp"), expr);
+        assert_eq!(parse("not not tcp"), parse("not (not tcp)"));
+        assert!(parse("not not tcp").matches(&https()));
+        // not applies to the primitive only
+        let expr = parse("not tcp and port 53");
+        assert_eq!(expr.to_string(), "(not tcp and port 53)");
+        assert!(expr.matches(&dns()));
+        assert!(!parse("not (tcp or port 53)").matches(&dns()));
+        assert!(parse("((udp))").matches(&dns()));
+    }
+
+    #[test]
+    fn ports_and_directions() {
+        assert!(parse("port 443").matches(&https()));
+        assert!(parse("dst port 443").matches(&https()));
+        assert!(!parse("src port 443").matches(&https()));
+        assert!(parse("portrange 400-500").matches(&https()));
+        assert!(parse("src portrange 50000-50010").matches(&https()));
+        assert!(!parse("dst portrange 1-100").matches(&https()));
+        assert!(parse("tcp dst port 443").matches(&https()));
+        assert!(!parse("udp dst port 443").matches(&https()));
+        assert!(parse("src host 192.168.1.10").matches(&https()));
+        assert!(!parse("dst host 192.168.1.10").matches(&https()));
+        assert!(parse("src or dst host 1.1.1.1").matches(&https()));
+        assert!(!parse("src and dst host 1.1.1.1").matches(&https()));
+        assert!(parse("dst net 1.1.0.0/16").matches(&https()));
+        assert_eq!(
+            parse("src portrange 1-1024").to_string(),
+            "src portrange 1-1024"
+        );
+    }
+
+    #[test]
+    fn bare_address_and_network() {
+        assert_eq!(
+            parse("1.1.1.1"),
+            FilterExpr::Primitive(FilterPrimitive::Host(
+                FilterDirection::SrcOrDst,
+                IpAddr::from_str("1.1.1.1").unwrap()
+            ))
+        );
+        assert_eq!(parse("10.1.2.3/8").to_string(), "net 10.0.0.0/8");
+        assert!(parse("10.0.0.0/8").matches(&dns()));
+        assert!(!parse("10.0.0.0/8").matches(&https()));
+        assert_eq!(parse("src 192.168.1.10"), parse("src host 192.168.1.10"));
+        assert_eq!(parse("dst 1.1.0.0/16"), parse("dst net 1.1.0.0/16"));
+        assert!(parse("2001:db8::/32 or 1.1.1.1").matches(&https()));
+    }
+
+    #[test]
+    fn parse_error_offsets() {
+        let err = parse_err("tcp and bogus");
+        assert_eq!(err.offset, 8);
+        assert!(err.message.contains("unknown primitive 'bogus'"));
+        assert_eq!(parse_err("port 70000").offset, 5);
+        // The end port of the range
+        assert_eq!(parse_err("portrange 10-x").offset, 13);
+        assert!(parse_err("portrange 20-10")
+            .message
+            .contains("start is greater"));
+        assert_eq!(parse_err("host 1.1.1").offset, 5);
+        assert_eq!(parse_err("tcp & udp").offset, 4);
+        assert_eq!(parse_err("tcp )").offset, 4);
+        let err = parse_err("(tcp or udp");
+        assert_eq!(err.message, "unclosed '('");
+        assert_eq!(err.offset, 0);
+        let err = parse_err("tcp and");
+        assert_eq!(err.offset, 7);
+        assert!(err.message.contains("found end of expression"));
+        assert_eq!(parse_err("").offset, 0);
+        assert_eq!(
+            parse_err("udp port x").describe("udp port x"),
+            "invalid port 'x', expected a number from 0 to 65535 at column 10\n  udp port x\n           ^"
+        );
+    }
+
+    #[test]
+    fn nesting_depth_is_limited() {
+        let nested = format!(
+            "{}tcp{}",
+            "(".repeat(MAX_FILTER_DEPTH),
+            ")".repeat(MAX_FILTER_DEPTH)
+        );
+        assert!(parse(&nested).matches(&https()));
+        let too_deep = format!("{}tcp", "(".repeat(100_000));
+        let err = parse_err(&too_deep);
+        assert_eq!(err.offset, MAX_FILTER_DEPTH);
+        assert!(err.message.contains("nested too deeply"));
+        let err = parse_err(&"not ".repeat(100_000));
+        assert_eq!(err.offset, MAX_FILTER_DEPTH * 4);
+        let err = parse_err(&"!".repeat(MAX_FILTER_DEPTH + 1));
+        assert_eq!(err.offset, MAX_FILTER_DEPTH);
+    }
+}
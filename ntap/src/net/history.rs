@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of one-second samples kept. (5 minutes)
+pub const SECOND_HISTORY_LEN: usize = 300;
+/// Number of one-minute samples kept. (1 hour)
+pub const MINUTE_HISTORY_LEN: usize = 60;
+
+/// Fixed-size buffer of samples. The oldest sample is dropped when full.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RingBuffer {
+    capacity: usize,
+    samples: VecDeque<u64>,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            capacity,
+            samples: VecDeque::new(),
+        }
+    }
+    pub fn push(&mut self, value: u64) {
+        if self.samples.len() >= self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+    /// Samples, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &u64> {
+        self.samples.iter()
+    }
+    /// The latest n samples, oldest first.
+    pub fn latest(&self, n: usize) -> Vec<u64> {
+        self.samples
+            .iter()
+            .skip(self.samples.len().saturating_sub(n))
+            .copied()
+            .collect()
+    }
+    pub fn stats(&self) -> Option<HistoryStats> {
+        HistoryStats::from_samples(self.samples.iter().copied().collect())
+    }
+}
+
+/// Summary of the samples in a buffer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryStats {
+    pub min: u64,
+    pub avg: u64,
+    pub max: u64,
+    /// 95th percentile (nearest rank)
+    pub p95: u64,
+}
+
+impl HistoryStats {
+    pub fn from_samples(mut samples: Vec<u64>) -> Option<HistoryStats> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let sum: u64 = samples.iter().sum();
+        let rank = (samples.len() * 95).div_ceil(100);
+        Some(HistoryStats {
+            min: samples[0],
+            avg: sum / samples.len() as u64,
+            max: samples[samples.len() - 1],
+            p95: samples[rank.saturating_sub(1)],
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryResolution {
+    Second,
+    Minute,
+}
+
+impl HistoryResolution {
+    pub fn interval(&self) -> Duration {
+        match self {
+            HistoryResolution::Second => Duration::from_secs(1),
+            HistoryResolution::Minute => Duration::from_secs(60),
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            HistoryResolution::Second => "1s",
+            HistoryResolution::Minute => "1m",
+        }
+    }
+    pub fn next(&self) -> HistoryResolution {
+        match self {
+            HistoryResolution::Second => HistoryResolution::Minute,
+            HistoryResolution::Minute => HistoryResolution::Second,
+        }
+    }
+}
+
+/// Ingress and egress bytes per second at one resolution.
+/// Ticks are collected until a full interval has passed, so the samples do not depend on the tick rate.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BandwidthSeries {
+    pub resolution: HistoryResolution,
+    pub ingress: RingBuffer,
+    pub egress: RingBuffer,
+    pending_elapsed: Duration,
+    pending_ingress_bytes: f64,
+    pending_egress_bytes: f64,
+}
+
+impl BandwidthSeries {
+    pub fn new(resolution: HistoryResolution, capacity: usize) -> Self {
+        BandwidthSeries {
+            resolution,
+            ingress: RingBuffer::new(capacity),
+            egress: RingBuffer::new(capacity),
+            pending_elapsed: Duration::ZERO,
+            pending_ingress_bytes: 0.0,
+            pending_egress_bytes: 0.0,
+        }
+    }
+    pub fn add(&mut self, ingress_bytes: usize, egress_bytes: usize, duration: Duration) {
+        self.pending_elapsed += duration;
+        self.pending_ingress_bytes += ingress_bytes as f64;
+        self.pending_egress_bytes += egress_bytes as f64;
+        let interval = self.resolution.interval();
+        if self.pending_elapsed < interval {
+            return;
+        }
+        let elapsed_secs = self.pending_elapsed.as_secs_f64();
+        let ingress_rate = self.pending_ingress_bytes / elapsed_secs;
+        let egress_rate = self.pending_egress_bytes / elapsed_secs;
+        // A tick longer than the interval fills several samples with the same rate
+        let count = (self.pending_elapsed.as_nanos() / interval.as_nanos()) as u32;
+        for _ in 0..count {
+            self.ingress.push(ingress_rate as u64);
+            self.egress.push(egress_rate as u64);
+        }
+        // Carry the rest of the tick over to the next sample
+        self.pending_elapsed -= interval * count;
+        let rest_secs = self.pending_elapsed.as_secs_f64();
+        self.pending_ingress_bytes = ingress_rate * rest_secs;
+        self.pending_egress_bytes = egress_rate * rest_secs;
+    }
+    /// Ingress + egress of the latest n samples, oldest first.
+    pub fn latest_total(&self, n: usize) -> Vec<u64> {
+        self.ingress
+            .latest(n)
+            .iter()
+            .zip(self.egress.latest(n).iter())
+            .map(|(ingress, egress)| ingress + egress)
+            .collect()
+    }
+    pub fn total_stats(&self) -> Option<HistoryStats> {
+        HistoryStats::from_samples(self.latest_total(self.ingress.len()))
+    }
+}
+
+/// Bandwidth history at one-second and one-minute resolution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BandwidthHistory {
+    pub seconds: BandwidthSeries,
+    pub minutes: BandwidthSeries,
+}
+
+impl BandwidthHistory {
+    pub fn new() -> Self {
+        BandwidthHistory {
+            seconds: BandwidthSeries::new(HistoryResolution::Second, SECOND_HISTORY_LEN),
+            minutes: BandwidthSeries::new(HistoryResolution::Minute, MINUTE_HISTORY_LEN),
+        }
+    }
+    /// Add the bytes of one tick.
+    pub fn add(&mut self, ingress_bytes: usize, egress_bytes: usize, duration: Duration) {
+        self.seconds.add(ingress_bytes, egress_bytes, duration);
+        self.minutes.add(ingress_bytes, egress_bytes, duration);
+    }
+    pub fn series(&self, resolution: HistoryResolution) -> &BandwidthSeries {
+        match resolution {
+            HistoryResolution::Second => &self.seconds,
+            HistoryResolution::Minute => &self.minutes,
+        }
+    }
+    /// Small text graph of the latest n one-second samples. Example: ▁▁▃▇▂
+    pub fn trend(&self, n: usize) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let samples = self.seconds.latest_total(n);
+        let max = samples.iter().copied().max().unwrap_or(0);
+        samples
+            .iter()
+            .map(|sample| {
+                let level = (*sample * (BARS.len() as u64 - 1))
+                    .checked_div(max)
+                    .unwrap_or(0);
+                BARS[level as usize]
+            })
+            .collect()
+    }
+}
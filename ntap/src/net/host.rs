@@ -11,6 +11,10 @@ pub struct RemoteHostInfo {
     pub country_name: String,
     pub asn: u32,
     pub as_name: String,
+    /// Vendor name from the OUI database. Only for LAN peers and gateways.
+    pub vendor_name: String,
+    /// User-defined label of the longest matching network in the label config.
+    pub label: String,
     pub traffic_info: TrafficInfo,
 }
 
@@ -24,6 +28,8 @@ impl RemoteHostInfo {
             country_name: String::new(),
             asn: 0,
             as_name: String::new(),
+            vendor_name: String::new(),
+            label: String::new(),
             traffic_info: TrafficInfo::new(),
         }
     }
@@ -33,7 +39,8 @@ impl RemoteHostInfo {
             .update_bytes_per_sec(&other.traffic_info, duration);
         self.traffic_info.add_traffic(&other.traffic_info);
         // Update other fields
-        if self.hostname.is_empty() {
+        // The latest hostname wins, so the passive DNS name replaces the reverse DNS name
+        if !other.hostname.is_empty() {
             self.hostname = other.hostname.clone();
         }
         if self.country_code.is_empty() {
@@ -48,6 +55,12 @@ impl RemoteHostInfo {
         if self.as_name.is_empty() {
             self.as_name = other.as_name.clone();
         }
+        if self.vendor_name.is_empty() {
+            self.vendor_name = other.vendor_name.clone();
+        }
+        if self.label.is_empty() {
+            self.label = other.label.clone();
+        }
     }
 }
 
@@ -59,5 +72,37 @@ pub struct HostDisplayInfo {
     pub country_name: String,
     pub asn: u32,
     pub as_name: String,
+    pub vendor_name: String,
+    pub label: String,
+    pub traffic: TrafficDisplayInfo,
+}
+
+/// Remote host traffic aggregated by label.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LabelDisplayInfo {
+    pub label: String,
+    pub host_count: usize,
+    pub traffic: TrafficDisplayInfo,
+}
+
+/// Remote host traffic aggregated by country.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CountryDisplayInfo {
+    /// Empty if the country is unknown
+    pub country_code: String,
+    pub country_name: String,
+    pub host_count: usize,
+    pub connection_count: usize,
+    pub traffic: TrafficDisplayInfo,
+}
+
+/// Remote host traffic aggregated by autonomous system.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AsDisplayInfo {
+    /// 0 if the AS is unknown
+    pub asn: u32,
+    pub as_name: String,
+    pub host_count: usize,
+    pub connection_count: usize,
     pub traffic: TrafficDisplayInfo,
 }
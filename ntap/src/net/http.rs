@@ -46,7 +46,7 @@ pub async fn download_file_with_progress(
         return Err("Invalid save file path".into());
     }
     // Download file with progress
-    let response = reqwest::get(&url).await?;
+    let response = reqwest::get(&url).await?.error_for_status()?;
     let content_length = response.content_length().unwrap_or(0);
     progress_tx
         .send(DownloadProgress::ContentLength(content_length))
@@ -1,3 +1,4 @@
+use ipnet::IpNet;
 use netdev::mac::MacAddr;
 use nex::net::interface::Interface;
 use std::{
@@ -167,3 +168,26 @@ pub fn get_gateway_macaddr(iface: &Interface) -> MacAddr {
         None => MacAddr::zero(),
     }
 }
+
+/// Networks directly attached to the interfaces, including the gateway addresses.
+/// Hosts in these networks are reached without routing, so their MAC addresses are their own.
+pub fn get_local_networks() -> Vec<IpNet> {
+    let mut networks: Vec<IpNet> = Vec::new();
+    for iface in netdev::get_interfaces() {
+        for ipv4 in &iface.ipv4 {
+            networks.push(IpNet::V4(ipv4.trunc()));
+        }
+        for ipv6 in &iface.ipv6 {
+            networks.push(IpNet::V6(ipv6.trunc()));
+        }
+        if let Some(gateway) = &iface.gateway {
+            for ipv4 in &gateway.ipv4 {
+                networks.push(IpNet::from(IpAddr::V4(*ipv4)));
+            }
+            for ipv6 in &gateway.ipv6 {
+                networks.push(IpNet::from(IpAddr::V6(*ipv6)));
+            }
+        }
+    }
+    networks
+}
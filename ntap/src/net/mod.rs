@@ -1,6 +1,10 @@
 #![allow(unused)]
 
+pub mod capfile;
+pub mod decode;
 pub mod dns;
+pub mod filter;
+pub mod history;
 pub mod host;
 pub mod http;
 pub mod interface;
@@ -11,4 +15,6 @@ pub mod protocol;
 pub mod service;
 pub mod socket;
 pub mod stat;
+pub mod tcp;
+pub mod tls;
 pub mod traffic;
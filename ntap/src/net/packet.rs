@@ -1,12 +1,17 @@
 use crate::sys;
 use nex::packet::ethernet::EtherType;
-use nex::packet::frame::{DatalinkLayer, IpLayer, TransportLayer};
+use nex::packet::frame::{DatalinkLayer, IpLayer, ParseOption, TransportLayer};
 use nex::packet::ip::IpNextLevelProtocol;
 use nex::packet::{arp, icmp};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Maximum payload kept on a PacketFrame.
+/// A full TLS record (ClientHello) or a DNS response fits in this size.
+pub const MAX_PAYLOAD_LEN: usize = 16384 + 5;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PacketFrame {
@@ -23,11 +28,22 @@ pub struct PacketFrame {
     /// The transport layer.
     pub transport: Option<TransportLayer>,
     /// Rest of the packet that could not be parsed as a header. (Usually payload)
-    //pub payload: Vec<u8>,
+    /// Truncated to MAX_PAYLOAD_LEN, only the DNS and TLS/QUIC parsers use it.
+    #[serde(skip)]
+    pub payload: Vec<u8>,
     /// Packet length.
     pub packet_len: usize,
     /// Packet arrival time. RFC3339 format.
     pub timestamp: String,
+    /// Packet arrival time since UNIX epoch. Capture time when reading a capture file.
+    #[serde(skip)]
+    pub capture_time: Duration,
+    /// Raw packet bytes, up to the snap length of the capture.
+    #[serde(skip)]
+    pub raw_packet: Vec<u8>,
+    /// Where the IP header starts in the raw packet. Used to decode raw_packet.
+    #[serde(skip)]
+    pub raw_parse_option: ParseOption,
 }
 
 impl PacketFrame {
@@ -39,9 +55,12 @@ impl PacketFrame {
             datalink: None,
             ip: None,
             transport: None,
-            //payload: Vec::new(),
+            payload: Vec::new(),
             packet_len: 0,
             timestamp: String::new(),
+            capture_time: Duration::ZERO,
+            raw_packet: Vec::new(),
+            raw_parse_option: ParseOption::default(),
         }
     }
     pub fn from_nex_frame(
@@ -50,6 +69,11 @@ impl PacketFrame {
         if_name: String,
         frame: nex::packet::frame::Frame,
     ) -> PacketFrame {
+        let mut payload = frame.payload;
+        if payload.len() > MAX_PAYLOAD_LEN {
+            payload.truncate(MAX_PAYLOAD_LEN);
+            payload.shrink_to_fit();
+        }
         PacketFrame {
             capture_no: capture_no,
             if_index: if_index,
@@ -57,10 +81,25 @@ impl PacketFrame {
             datalink: frame.datalink,
             ip: frame.ip,
             transport: frame.transport,
-            //payload: frame.payload,
+            payload,
             packet_len: frame.packet_len,
             timestamp: sys::get_sysdate(),
+            capture_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO),
+            raw_packet: Vec::new(),
+            raw_parse_option: ParseOption::default(),
+        }
+    }
+    /// Keep the raw packet bytes, truncated to snaplen. Nothing is kept if snaplen is 0.
+    /// parse_option is the option the packet was parsed with.
+    pub fn set_raw_packet(&mut self, packet: &[u8], snaplen: usize, parse_option: ParseOption) {
+        if snaplen == 0 {
+            return;
         }
+        let len = std::cmp::min(packet.len(), snaplen);
+        self.raw_packet = packet[..len].to_vec();
+        self.raw_parse_option = parse_option;
     }
     pub fn get_time(&self) -> String {
         let datetime_vec: Vec<&str> = self.timestamp.split('T').collect::<Vec<&str>>();
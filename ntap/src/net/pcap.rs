@@ -1,3 +1,5 @@
+use crate::net::capfile::{self, CaptureFileReader};
+use crate::net::filter::FilterExpr;
 use crate::net::interface;
 use crate::net::packet::PacketFrame;
 use crate::net::stat::NetStatStrage;
@@ -9,9 +11,13 @@ use nex::packet::frame::ParseOption;
 use nex::packet::{ethernet::EtherType, ip::IpNextLevelProtocol};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::io;
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
@@ -69,6 +75,10 @@ pub struct PacketCaptureOptions {
     pub tunnel: bool,
     /// Loopback interface
     pub loopback: bool,
+    /// Maximum number of raw bytes kept in PacketFrame. 0 means raw bytes are not kept
+    pub snaplen: usize,
+    /// Filter expression. If None, all packets will be captured
+    pub filter: Option<FilterExpr>,
 }
 
 impl PacketCaptureOptions {
@@ -89,6 +99,8 @@ impl PacketCaptureOptions {
             receive_undefined: true,
             tunnel: iface.is_tun(),
             loopback: iface.is_loopback(),
+            snaplen: 0,
+            filter: None,
         };
         Ok(options)
     }
@@ -109,6 +121,8 @@ impl PacketCaptureOptions {
             receive_undefined: true,
             tunnel: iface.is_tun(),
             loopback: iface.is_loopback(),
+            snaplen: 0,
+            filter: None,
         };
         Some(options)
     }
@@ -129,6 +143,8 @@ impl PacketCaptureOptions {
             receive_undefined: true,
             tunnel: iface.is_tun(),
             loopback: iface.is_loopback(),
+            snaplen: 0,
+            filter: None,
         };
         options
     }
@@ -148,9 +164,35 @@ impl PacketCaptureOptions {
             receive_undefined: true,
             tunnel: iface.is_tun(),
             loopback: iface.is_loopback(),
+            snaplen: 0,
+            filter: None,
         };
         options
     }
+    pub fn from_capture_file(file_path: &Path) -> PacketCaptureOptions {
+        let file_name = match file_path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => file_path.to_string_lossy().to_string(),
+        };
+        PacketCaptureOptions {
+            interface_index: 0,
+            interface_name: file_name,
+            src_ips: HashSet::new(),
+            dst_ips: HashSet::new(),
+            src_ports: HashSet::new(),
+            dst_ports: HashSet::new(),
+            ether_types: HashSet::new(),
+            ip_protocols: HashSet::new(),
+            capture_timeout: Duration::MAX,
+            read_timeout: Duration::from_millis(200),
+            promiscuous: false,
+            receive_undefined: true,
+            tunnel: false,
+            loopback: false,
+            snaplen: 0,
+            filter: None,
+        }
+    }
     pub fn add_ethertype_filter(&mut self, ethertype_name: &str) {
         // Currently, EtherType not support from_str, so we need to match it manually
         let name = ethertype_name.to_lowercase();
@@ -259,14 +301,15 @@ pub fn start_capture(
                 }
                 report.bytes = report.bytes.saturating_add(packet.len());
                 report.packets = report.packets.saturating_add(1);
-                let frame: Frame = Frame::from_bytes(&packet, parse_option);
+                let frame: Frame = Frame::from_bytes(&packet, parse_option.clone());
                 if filter_packet(&frame, &capture_options) {
-                    let packet_frame = PacketFrame::from_nex_frame(
+                    let mut packet_frame = PacketFrame::from_nex_frame(
                         report.packets,
                         interface.index,
                         interface.name.clone(),
                         frame,
                     );
+                    packet_frame.set_raw_packet(packet, capture_options.snaplen, parse_option);
                     match msg_tx.send(packet_frame) {
                         Ok(_) => {}
                         Err(_) => {}
@@ -336,14 +379,15 @@ pub fn start_live_capture(
                     parse_option.from_ip_packet = true;
                     parse_option.offset = payload_offset;
                 }
-                let frame: Frame = Frame::from_bytes(&packet, parse_option);
+                let frame: Frame = Frame::from_bytes(&packet, parse_option.clone());
                 if filter_packet(&frame, &capture_options) {
-                    let packet_frame = PacketFrame::from_nex_frame(
+                    let mut packet_frame = PacketFrame::from_nex_frame(
                         0,
                         interface.index,
                         interface.name.clone(),
                         frame,
                     );
+                    packet_frame.set_raw_packet(packet, capture_options.snaplen, parse_option);
                     match msg_tx.send(packet_frame) {
                         Ok(_) => {}
                         Err(_) => {}
@@ -421,6 +465,150 @@ pub fn start_background_capture(
     }
 }
 
+/// Read packets from a pcap/pcapng file and pass them to the handler.
+/// If `realtime` is true, packets are replayed with the original timing,
+/// otherwise they are read as fast as possible.
+fn read_capture_file<F: FnMut(PacketFrame)>(
+    capture_options: &PacketCaptureOptions,
+    file_path: &Path,
+    realtime: bool,
+    mut handler: F,
+) -> CaptureReport {
+    let mut report = CaptureReport::new();
+    let mut reader = match CaptureFileReader::open(file_path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            thread_log!(error, "Failed to open {}: {}", file_path.display(), e);
+            return report;
+        }
+    };
+    let start_time = Instant::now();
+    report.start_time = sys::get_sysdate();
+    let mut first_timestamp: Option<Duration> = None;
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break,
+            Err(e) => {
+                thread_log!(error, "Failed to read {}: {}", file_path.display(), e);
+                break;
+            }
+        };
+        let (if_name, parse_option) = match reader.interface(packet.if_id) {
+            Some(file_iface) => match file_iface.link_type.parse_option() {
+                Some(parse_option) => (file_iface.name.clone(), parse_option),
+                None => {
+                    thread_log!(
+                        warn,
+                        "Unsupported link type: {}",
+                        file_iface.link_type.to_u32()
+                    );
+                    continue;
+                }
+            },
+            None => continue,
+        };
+        if realtime {
+            match first_timestamp {
+                Some(first) => {
+                    let offset = packet.timestamp.saturating_sub(first);
+                    let elapsed = Instant::now().duration_since(start_time);
+                    if offset > elapsed {
+                        std::thread::sleep(offset - elapsed);
+                    }
+                }
+                None => {
+                    first_timestamp = Some(packet.timestamp);
+                }
+            }
+        }
+        report.bytes = report.bytes.saturating_add(packet.orig_len);
+        report.packets = report.packets.saturating_add(1);
+        let frame: Frame = Frame::from_bytes(&packet.data, parse_option.clone());
+        if filter_packet(&frame, capture_options) {
+            let mut packet_frame =
+                PacketFrame::from_nex_frame(report.packets, packet.if_id, if_name, frame);
+            packet_frame.packet_len = packet.orig_len;
+            packet_frame.timestamp = capfile::format_timestamp(packet.timestamp);
+            packet_frame.capture_time = packet.timestamp;
+            packet_frame.set_raw_packet(&packet.data, capture_options.snaplen, parse_option);
+            handler(packet_frame);
+        }
+    }
+    report.end_time = sys::get_sysdate();
+    report.duration = Instant::now().duration_since(start_time);
+    thread_log!(
+        info,
+        "Finished reading {}: {} packets, {} bytes",
+        file_path.display(),
+        report.packets,
+        report.bytes
+    );
+    report
+}
+
+pub fn start_live_file_capture(
+    capture_options: PacketCaptureOptions,
+    file_path: PathBuf,
+    realtime: bool,
+    msg_tx: Sender<PacketFrame>,
+) -> CaptureReport {
+    read_capture_file(&capture_options, &file_path, realtime, |packet_frame| {
+        let _ = msg_tx.send(packet_frame);
+    })
+}
+
+pub fn start_background_file_capture(
+    capture_options: PacketCaptureOptions,
+    file_path: PathBuf,
+    realtime: bool,
+    netstat_strage: &mut Arc<NetStatStrage>,
+) -> CaptureReport {
+    read_capture_file(&capture_options, &file_path, realtime, |packet_frame| {
+        netstat_strage.update(packet_frame);
+    })
+}
+
+/// Start a thread that reads the capture file into the netstat storage.
+/// capture_finished is set once the whole file has been read.
+pub fn spawn_file_capture_thread(
+    capture_options: PacketCaptureOptions,
+    file_path: PathBuf,
+    realtime: bool,
+    netstat_strage: &Arc<NetStatStrage>,
+    capture_finished: Arc<AtomicBool>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let mut netstat_strage = Arc::clone(netstat_strage);
+    let thread_name = String::from("pcap-thread-file");
+    let handle = thread::Builder::new()
+        .name(thread_name.clone())
+        .spawn(move || {
+            netstat_strage.load_ipdb();
+            netstat_strage.load_oui_db();
+            // The capturing host is unknown, so guess the local addresses and networks
+            // from the file instead of using the networks of this host
+            match capfile::guess_local_ip_map(&file_path) {
+                Ok(local_ip_map) => {
+                    netstat_strage.set_local_networks(capfile::guess_local_networks(&local_ip_map));
+                    netstat_strage.set_local_ip_map(local_ip_map);
+                }
+                Err(e) => {
+                    netstat_strage.set_local_networks(Vec::new());
+                    thread_log!(error, "guess_local_ip_map error: {:?}", e);
+                }
+            }
+            start_background_file_capture(
+                capture_options,
+                file_path,
+                realtime,
+                &mut netstat_strage,
+            );
+            capture_finished.store(true, Ordering::Release);
+        })?;
+    thread_log!(info, "start thread {}", thread_name);
+    Ok(handle)
+}
+
 fn filter_packet(frame: &Frame, capture_options: &PacketCaptureOptions) -> bool {
     if let Some(datalink) = &frame.datalink {
         if let Some(ethernet_header) = &datalink.ethernet {
@@ -476,6 +664,11 @@ fn filter_packet(frame: &Frame, capture_options: &PacketCaptureOptions) -> bool
             }
         }
     }
+    if let Some(filter) = &capture_options.filter {
+        if !filter.matches(frame) {
+            return false;
+        }
+    }
     true
 }
 
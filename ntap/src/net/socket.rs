@@ -1,4 +1,6 @@
 use crate::net::stat::NetStatStrage;
+use crate::net::tcp::TcpFlowInfo;
+use crate::net::tls::ServerNameInfo;
 use crate::net::traffic::{TrafficDisplayInfo, TrafficInfo};
 use crate::process::ProcessInfo;
 use crate::thread_log;
@@ -160,6 +162,10 @@ pub struct SocketDisplayInfo {
     pub ip_version: AddressFamily,
     pub process: Option<ProcessInfo>,
     pub traffic: TrafficDisplayInfo,
+    /// Server name and ALPN from the TLS/QUIC ClientHello.
+    pub server_name: Option<ServerNameInfo>,
+    /// TCP state from the captured flags.
+    pub tcp_flow: Option<TcpFlowInfo>,
 }
 
 impl SocketDisplayInfo {
@@ -174,8 +180,28 @@ impl SocketDisplayInfo {
             ip_version: socket_traffic_info.ip_version.clone(),
             process: socket_traffic_info.process.clone(),
             traffic: socket_traffic_info.traffic.to_display_info(),
+            server_name: None,
+            tcp_flow: None,
         }
     }
+    /// TCP state from the captured flags. Empty if the flow was not tracked.
+    pub fn tcp_state_string(&self) -> String {
+        match &self.tcp_flow {
+            Some(tcp_flow) => tcp_flow.state.to_string(),
+            None => String::new(),
+        }
+    }
+    /// Key of the connection in NetStatData. None if the remote socket is unknown.
+    pub fn to_socket_connection(&self) -> Option<SocketConnection> {
+        Some(SocketConnection {
+            interface_name: self.interface_name.clone(),
+            local_ip_addr: self.local_ip_addr,
+            local_port: self.local_port,
+            remote_ip_addr: self.remote_ip_addr?,
+            remote_port: self.remote_port?,
+            protocol: self.protocol,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -1,12 +1,23 @@
 use super::interface;
 use super::{
-    host::{HostDisplayInfo, RemoteHostInfo},
+    dns::{self, DnsRecord},
+    history::BandwidthHistory,
+    host::{AsDisplayInfo, CountryDisplayInfo, HostDisplayInfo, LabelDisplayInfo, RemoteHostInfo},
     packet::PacketFrame,
     service::ServiceDisplayInfo,
-    traffic::{Direction, TrafficDisplayInfo, TrafficInfo},
+    tcp::{self, TcpFlowInfo, TcpMetrics, TcpSegment},
+    tls::{self, HandshakeBuffer, ServerNameInfo},
+    traffic::{
+        Direction, InterfaceTrafficDisplayInfo, InterfaceTrafficInfo, TrafficDisplayInfo,
+        TrafficInfo,
+    },
 };
-use crate::db::ip::IpDatabase;
+use crate::config::LabelConfig;
+use crate::db::compact::CompactIpDatabase;
+use crate::db::label::LabelDatabase;
+use crate::db::oui::OuiDatabase;
 use crate::db::service::ServiceDatabase;
+use crate::db::traffic::{DisplayNames, TrafficAccounting, TrafficTotals};
 use crate::net::socket::{
     AddressFamily, LocalSocket, ProtocolPort, SocketConnection, SocketDisplayInfo,
     SocketInfoOption, SocketProcess, TransportProtocol,
@@ -14,12 +25,13 @@ use crate::net::socket::{
 use crate::notification::Notification;
 use crate::process::{ProcessDisplayInfo, ProcessInfo};
 use crate::thread_log;
+use ipnet::IpNet;
 use netdev::{mac::MacAddr, Interface};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     net::IpAddr,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, OnceLock},
     thread,
     time::Duration,
 };
@@ -28,6 +40,8 @@ use std::{
 pub struct NetStatStrage {
     pub interface: Arc<Mutex<Interface>>,
     pub traffic: Arc<Mutex<TrafficInfo>>,
+    /// Interface Traffic Map (Interface Name -> TrafficInfo)
+    pub interface_traffic: Arc<Mutex<HashMap<String, TrafficInfo>>>,
     /// Remote Host Traffic Info Map (IpAddr -> RemoteHostInfo)
     pub remote_hosts: Arc<Mutex<HashMap<IpAddr, RemoteHostInfo>>>,
     /// Socket Connection Traffic Map (SocketConnection -> TrafficInfo)
@@ -36,10 +50,26 @@ pub struct NetStatStrage {
     pub local_socket_map: Arc<Mutex<HashMap<LocalSocket, SocketProcess>>>,
     /// Reverse DNS Map (IpAddr -> Hostname)
     pub reverse_dns_map: Arc<Mutex<HashMap<IpAddr, String>>>,
+    /// Passive DNS Map built from captured DNS responses (IpAddr -> DnsRecord)
+    pub passive_dns_map: Arc<Mutex<HashMap<IpAddr, DnsRecord>>>,
+    /// Pending TLS/QUIC handshakes (SocketConnection -> HandshakeBuffer)
+    pub handshake_map: Arc<Mutex<HashMap<SocketConnection, HandshakeBuffer>>>,
+    /// Server Name Map from TLS ClientHello (SocketConnection -> ServerNameInfo)
+    pub server_name_map: Arc<Mutex<HashMap<SocketConnection, ServerNameInfo>>>,
+    /// TCP flow states from the captured flags (SocketConnection -> TcpFlowInfo)
+    pub tcp_flow_map: Arc<Mutex<HashMap<SocketConnection, TcpFlowInfo>>>,
     /// Local IP Map (IpAddr -> Interface Name)
     pub local_ip_map: Arc<Mutex<HashMap<IpAddr, String>>>,
-    /// IP Database for IP, ASN, Country, etc.
-    pub ipdb: Arc<Mutex<IpDatabase>>,
+    /// IP Database for IP, ASN, Country, etc. Set once by load_ipdb and read without a lock.
+    pub ipdb: Arc<OnceLock<CompactIpDatabase>>,
+    /// OUI Database for MAC vendor names
+    pub oui_db: Arc<Mutex<OuiDatabase>>,
+    /// User-defined network labels
+    pub label_db: Arc<Mutex<LabelDatabase>>,
+    /// Networks attached to the local interfaces (LAN peers and gateways)
+    pub local_networks: Arc<Mutex<Vec<IpNet>>>,
+    /// Traffic totals not yet written to the traffic database
+    pub accounting: Arc<Mutex<TrafficAccounting>>,
 }
 
 impl NetStatStrage {
@@ -52,15 +82,25 @@ impl NetStatStrage {
             }
         };
         let local_ip_map = interface::get_local_ip_map();
+        let local_networks = interface::get_local_networks();
         NetStatStrage {
             interface: Arc::new(Mutex::new(default_interface)),
             traffic: Arc::new(Mutex::new(TrafficInfo::new())),
+            interface_traffic: Arc::new(Mutex::new(HashMap::new())),
             remote_hosts: Arc::new(Mutex::new(HashMap::new())),
             connection_map: Arc::new(Mutex::new(HashMap::new())),
             local_socket_map: Arc::new(Mutex::new(HashMap::new())),
             reverse_dns_map: Arc::new(Mutex::new(HashMap::new())),
+            passive_dns_map: Arc::new(Mutex::new(HashMap::new())),
+            handshake_map: Arc::new(Mutex::new(HashMap::new())),
+            server_name_map: Arc::new(Mutex::new(HashMap::new())),
+            tcp_flow_map: Arc::new(Mutex::new(HashMap::new())),
             local_ip_map: Arc::new(Mutex::new(local_ip_map)),
-            ipdb: Arc::new(Mutex::new(IpDatabase::new())),
+            ipdb: Arc::new(OnceLock::new()),
+            oui_db: Arc::new(Mutex::new(OuiDatabase::new())),
+            label_db: Arc::new(Mutex::new(LabelDatabase::new())),
+            local_networks: Arc::new(Mutex::new(local_networks)),
+            accounting: Arc::new(Mutex::new(TrafficAccounting::new())),
         }
     }
     // Set interface
@@ -114,6 +154,38 @@ impl NetStatStrage {
             }
         }
     }
+    // The traffic of the tick without history
+    fn get_interface_traffic(&self) -> BTreeMap<String, InterfaceTrafficInfo> {
+        match self.interface_traffic.lock() {
+            Ok(interface_traffic) => interface_traffic
+                .iter()
+                .map(|(interface_name, traffic)| {
+                    let mut interface_traffic = InterfaceTrafficInfo::new();
+                    interface_traffic.traffic = traffic.clone();
+                    (interface_name.clone(), interface_traffic)
+                })
+                .collect(),
+            Err(e) => {
+                thread_log!(error, "get_interface_traffic error: {:?}", e);
+                BTreeMap::new()
+            }
+        }
+    }
+    /// Register the captured interfaces so that they are shown before the first packet.
+    pub fn add_interfaces(&self, interface_names: Vec<String>) {
+        match self.interface_traffic.lock() {
+            Ok(mut interface_traffic) => {
+                for interface_name in interface_names {
+                    interface_traffic
+                        .entry(interface_name)
+                        .or_insert_with(TrafficInfo::new);
+                }
+            }
+            Err(e) => {
+                thread_log!(error, "add_interfaces error: {:?}", e);
+            }
+        }
+    }
     /// Get the remote hosts. (thread safe clone)
     pub fn get_remote_hosts(&self) -> HashMap<IpAddr, RemoteHostInfo> {
         match self.remote_hosts.lock() {
@@ -153,6 +225,155 @@ impl NetStatStrage {
             }
         }
     }
+    pub fn set_local_ip_map(&self, new_local_ip_map: HashMap<IpAddr, String>) {
+        match self.local_ip_map.lock() {
+            Ok(mut local_ip_map) => {
+                *local_ip_map = new_local_ip_map;
+            }
+            Err(e) => {
+                thread_log!(error, "set_local_ip_map error: {:?}", e);
+            }
+        }
+    }
+    pub fn get_passive_dns_map(&self) -> HashMap<IpAddr, DnsRecord> {
+        match self.passive_dns_map.lock() {
+            Ok(passive_dns_map) => passive_dns_map.clone(),
+            Err(e) => {
+                thread_log!(error, "get_passive_dns_map error: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
+    /// Update the passive DNS map with the DNS response in the frame, if any.
+    fn update_passive_dns_map(&self, frame: &PacketFrame) {
+        let tcp: bool = match &frame.transport {
+            Some(transport) => {
+                if let Some(tcp) = &transport.tcp {
+                    if tcp.source != 53 {
+                        return;
+                    }
+                    true
+                } else if let Some(udp) = &transport.udp {
+                    if udp.source != 53 {
+                        return;
+                    }
+                    false
+                } else {
+                    return;
+                }
+            }
+            None => return,
+        };
+        let records = dns::parse_dns_response(&frame.payload, tcp);
+        if records.is_empty() {
+            return;
+        }
+        match self.passive_dns_map.lock() {
+            Ok(mut passive_dns_map) => {
+                for (ip_addr, record) in records {
+                    passive_dns_map.insert(ip_addr, record);
+                }
+                dns::prune_passive_dns_map(&mut passive_dns_map);
+            }
+            Err(e) => {
+                thread_log!(error, "update_passive_dns_map error: {:?}", e);
+            }
+        }
+    }
+    /// Get the server_name_map (thread safe clone)
+    pub fn get_server_name_map(&self) -> HashMap<SocketConnection, ServerNameInfo> {
+        match self.server_name_map.lock() {
+            Ok(server_name_map) => server_name_map.clone(),
+            Err(e) => {
+                thread_log!(error, "get_server_name_map error: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
+    fn update_server_name_map(
+        &self,
+        conn: &SocketConnection,
+        direction: Direction,
+        tcp_seq: Option<u32>,
+        payload: &[u8],
+    ) {
+        let server_name_info = match self.handshake_map.lock() {
+            Ok(mut handshake_map) => {
+                tls::inspect_payload(&mut handshake_map, conn, direction, tcp_seq, payload)
+            }
+            Err(e) => {
+                thread_log!(error, "update_server_name_map error: {:?}", e);
+                return;
+            }
+        };
+        if let Some(server_name_info) = server_name_info {
+            match self.server_name_map.lock() {
+                Ok(mut server_name_map) => {
+                    server_name_map.insert(conn.clone(), server_name_info);
+                }
+                Err(e) => {
+                    thread_log!(error, "update_server_name_map error: {:?}", e);
+                }
+            }
+        }
+    }
+    /// Get the tcp_flow_map (thread safe clone)
+    pub fn get_tcp_flow_map(&self) -> HashMap<SocketConnection, TcpFlowInfo> {
+        match self.tcp_flow_map.lock() {
+            Ok(tcp_flow_map) => tcp_flow_map.clone(),
+            Err(e) => {
+                thread_log!(error, "get_tcp_flow_map error: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
+    fn update_tcp_flow_map(
+        &self,
+        conn: &SocketConnection,
+        direction: Direction,
+        segment: &TcpSegment,
+        timestamp: Duration,
+    ) -> TcpMetrics {
+        match self.tcp_flow_map.lock() {
+            Ok(mut tcp_flow_map) => {
+                let metrics = tcp_flow_map
+                    .entry(conn.clone())
+                    .or_insert_with(TcpFlowInfo::new)
+                    .update(direction, segment, timestamp);
+                if tcp_flow_map.len() > tcp::TCP_FLOW_MAP_CAPACITY {
+                    tcp_flow_map.retain(|_, flow| !flow.is_closed() && !flow.is_idle());
+                }
+                metrics
+            }
+            Err(e) => {
+                thread_log!(error, "update_tcp_flow_map error: {:?}", e);
+                TcpMetrics::default()
+            }
+        }
+    }
+    // Closed flows are kept a little longer than CLOSED_FLOW_TTL so NetStatData sees them expire
+    fn remove_old_tcp_flows(&self) {
+        match self.tcp_flow_map.lock() {
+            Ok(mut tcp_flow_map) => {
+                tcp_flow_map.retain(|_, flow| {
+                    !flow.is_expired(tcp::CLOSED_FLOW_TTL * 2) && !flow.is_idle()
+                });
+            }
+            Err(e) => {
+                thread_log!(error, "remove_old_tcp_flows error: {:?}", e);
+            }
+        }
+    }
+    fn clear_tcp_flow_map(&self) {
+        match self.tcp_flow_map.lock() {
+            Ok(mut tcp_flow_map) => {
+                tcp_flow_map.clear();
+            }
+            Err(e) => {
+                thread_log!(error, "clear_tcp_flow_map error: {:?}", e);
+            }
+        }
+    }
     fn clear_trraffic(&self) {
         match self.traffic.lock() {
             Ok(mut traffic) => {
@@ -163,6 +384,19 @@ impl NetStatStrage {
             }
         }
     }
+    // Keep the interface names and reset the counters
+    fn clear_interface_traffic(&self) {
+        match self.interface_traffic.lock() {
+            Ok(mut interface_traffic) => {
+                interface_traffic
+                    .values_mut()
+                    .for_each(|traffic| *traffic = TrafficInfo::new());
+            }
+            Err(e) => {
+                thread_log!(error, "clear_interface_traffic error: {:?}", e);
+            }
+        }
+    }
     fn clear_remote_hosts(&self) {
         match self.remote_hosts.lock() {
             Ok(mut remote_hosts) => {
@@ -193,6 +427,58 @@ impl NetStatStrage {
             }
         }
     }
+    // Start accumulating traffic totals for the traffic database
+    pub fn enable_accounting(&self) {
+        match self.accounting.lock() {
+            Ok(mut accounting) => {
+                accounting.enabled = true;
+            }
+            Err(e) => {
+                thread_log!(error, "enable_accounting error: {:?}", e);
+            }
+        }
+    }
+    fn update_accounting(&self, netstat_data: &NetStatData) {
+        match self.accounting.lock() {
+            Ok(mut accounting) => {
+                accounting.add(netstat_data);
+            }
+            Err(e) => {
+                thread_log!(error, "update_accounting error: {:?}", e);
+            }
+        }
+    }
+    // Take the pending traffic totals and names
+    pub fn take_accounting(&self) -> (TrafficTotals, DisplayNames) {
+        match self.accounting.lock() {
+            Ok(mut accounting) => accounting.take(),
+            Err(e) => {
+                thread_log!(error, "take_accounting error: {:?}", e);
+                (HashMap::new(), HashMap::new())
+            }
+        }
+    }
+    // Put back traffic totals that could not be written
+    pub fn restore_accounting(&self, totals: TrafficTotals, names: DisplayNames) {
+        match self.accounting.lock() {
+            Ok(mut accounting) => {
+                accounting.restore(totals, names);
+            }
+            Err(e) => {
+                thread_log!(error, "restore_accounting error: {:?}", e);
+            }
+        }
+    }
+    fn clear_server_name_map(&self) {
+        match self.server_name_map.lock() {
+            Ok(mut server_name_map) => {
+                server_name_map.clear();
+            }
+            Err(e) => {
+                thread_log!(error, "clear_server_name_map error: {:?}", e);
+            }
+        }
+    }
     fn clear_reverse_dns_map(&self) {
         match self.reverse_dns_map.lock() {
             Ok(mut reverse_dns_map) => {
@@ -205,16 +491,21 @@ impl NetStatStrage {
     }
     pub fn reset(&self) {
         self.clear_trraffic();
+        self.clear_interface_traffic();
         self.clear_remote_hosts();
         self.clear_connection_map();
         self.clear_local_socket_map();
+        self.clear_server_name_map();
         self.clear_reverse_dns_map();
+        self.clear_tcp_flow_map();
     }
     pub fn reset_data(&self) {
         self.clear_trraffic();
+        self.clear_interface_traffic();
         self.clear_remote_hosts();
         self.clear_connection_map();
         self.clear_local_socket_map();
+        self.clear_server_name_map();
     }
     pub fn clone_and_reset(&self) -> Self {
         let clone = self.clone();
@@ -225,20 +516,28 @@ impl NetStatStrage {
         let mut clone: NetStatData = NetStatData::new();
         clone.default_interface = self.get_interface();
         clone.traffic = self.get_trrafic();
+        clone.interface_traffic = self.get_interface_traffic();
         clone.remote_hosts = self.get_remote_hosts();
         clone.connection_map = self.get_connection_map();
         clone.local_socket_map = self.get_local_socket_map();
+        clone.server_name_map = self.get_server_name_map();
+        clone.tcp_flow_map = self.get_tcp_flow_map();
         clone.local_ip_map = self.get_local_ip_map();
         self.reset_data();
+        self.remove_old_tcp_flows();
+        self.update_accounting(&clone);
         clone
     }
     pub fn clone_data(&self) -> NetStatData {
         let mut clone: NetStatData = NetStatData::new();
         clone.default_interface = self.get_interface();
         clone.traffic = self.get_trrafic();
+        clone.interface_traffic = self.get_interface_traffic();
         clone.remote_hosts = self.get_remote_hosts();
         clone.connection_map = self.get_connection_map();
         clone.local_socket_map = self.get_local_socket_map();
+        clone.server_name_map = self.get_server_name_map();
+        clone.tcp_flow_map = self.get_tcp_flow_map();
         clone
     }
     pub fn change_interface(&self, interface: &Interface) {
@@ -253,17 +552,91 @@ impl NetStatStrage {
         false
     }
     pub fn load_ipdb(&self) {
-        match IpDatabase::load() {
+        match CompactIpDatabase::load() {
             Ok(ipdb) => {
-                let mut ipdb_mutex = self.ipdb.lock().unwrap();
-                *ipdb_mutex = ipdb;
+                if self.ipdb.set(ipdb).is_err() {
+                    thread_log!(warn, "load_ipdb: IP database is already loaded");
+                }
             }
             Err(e) => {
                 thread_log!(error, "load_ipdb error: {:?}", e);
             }
         }
     }
+    pub fn set_local_networks(&self, networks: Vec<IpNet>) {
+        match self.local_networks.lock() {
+            Ok(mut local_networks) => {
+                *local_networks = networks;
+            }
+            Err(e) => {
+                thread_log!(error, "set_local_networks error: {:?}", e);
+            }
+        }
+    }
+    pub fn load_oui_db(&self) {
+        match OuiDatabase::load() {
+            Ok(oui_db) => match self.oui_db.lock() {
+                Ok(mut oui_db_mutex) => {
+                    *oui_db_mutex = oui_db;
+                }
+                Err(e) => {
+                    thread_log!(error, "load_oui_db error: {:?}", e);
+                }
+            },
+            Err(e) => {
+                thread_log!(error, "load_oui_db error: {:?}", e);
+            }
+        }
+    }
+    pub fn load_labels(&self, config: &LabelConfig) {
+        let label_db = LabelDatabase::from_config(config);
+        match self.label_db.lock() {
+            Ok(mut label_db_mutex) => {
+                *label_db_mutex = label_db;
+            }
+            Err(e) => {
+                thread_log!(error, "load_labels error: {:?}", e);
+            }
+        }
+    }
+    fn get_label(&self, ip_addr: &IpAddr) -> String {
+        match self.label_db.lock() {
+            Ok(label_db) => label_db.get_label(ip_addr).unwrap_or_default(),
+            Err(e) => {
+                thread_log!(error, "get_label error: {:?}", e);
+                String::new()
+            }
+        }
+    }
+    // Get the vendor name of a LAN peer or gateway.
+    // Routed hosts carry the gateway's MAC address, so they get no vendor.
+    fn get_vendor_name(&self, mac_addr: &str, ip_addr: &IpAddr) -> String {
+        match self.local_networks.lock() {
+            Ok(local_networks) => {
+                if !local_networks
+                    .iter()
+                    .any(|network| network.contains(ip_addr))
+                {
+                    return String::new();
+                }
+            }
+            Err(e) => {
+                thread_log!(error, "get_vendor_name error: {:?}", e);
+                return String::new();
+            }
+        }
+        match self.oui_db.lock() {
+            Ok(oui_db) => oui_db
+                .get_vendor_name_from_str(mac_addr)
+                .unwrap_or_default(),
+            Err(e) => {
+                thread_log!(error, "get_vendor_name error: {:?}", e);
+                String::new()
+            }
+        }
+    }
     pub fn update(&self, frame: PacketFrame) {
+        self.update_passive_dns_map(&frame);
         let local_ip_map_inner = match self.local_ip_map.lock() {
             Ok(inner) => inner,
             Err(e) => {
@@ -295,14 +668,17 @@ impl NetStatStrage {
                 return;
             }
         };
-        // Lock ipdb field
-        let ipdb_inner = match self.ipdb.lock() {
+        // The IP database is read without a lock. None until load_ipdb is done.
+        let ipdb = self.ipdb.get();
+        // Lock passive_dns_map field
+        let passive_dns_map_inner = match self.passive_dns_map.lock() {
             Ok(inner) => inner,
             Err(e) => {
-                thread_log!(error, "Failed to lock ipdb: {:?}", e);
+                thread_log!(error, "Failed to lock passive_dns_map: {:?}", e);
                 return;
             }
         };
+        let tcp_payload_len = tcp::payload_len(&frame);
         let datalink_layer = match frame.datalink {
             Some(datalink) => datalink,
             None => return,
@@ -382,6 +758,27 @@ impl NetStatStrage {
             Some(name) => name.clone(),
             None => String::from("unknown"),
         };
+        // Update the TrafficInfo of the interface
+        match self.interface_traffic.lock() {
+            Ok(mut interface_traffic) => {
+                let traffic = interface_traffic
+                    .entry(interface_name.clone())
+                    .or_insert_with(TrafficInfo::new);
+                match direction {
+                    Direction::Egress => {
+                        traffic.packet_sent += 1;
+                        traffic.bytes_sent += frame.packet_len;
+                    }
+                    Direction::Ingress => {
+                        traffic.packet_received += 1;
+                        traffic.bytes_received += frame.packet_len;
+                    }
+                }
+            }
+            Err(e) => {
+                thread_log!(error, "Failed to lock interface_traffic: {:?}", e);
+            }
+        }
         let local_port: u16 = match direction {
             Direction::Egress => {
                 if let Some(transport) = &frame.transport {
@@ -459,9 +856,16 @@ impl NetStatStrage {
             }
         };
         // Update or Insert RemoteHostInfo
-        let remote_host: &mut RemoteHostInfo = remote_hosts_inner
-            .entry(remote_ip_addr)
-            .or_insert(RemoteHostInfo::new(mac_addr, remote_ip_addr));
+        let remote_host: &mut RemoteHostInfo = match remote_hosts_inner.entry(remote_ip_addr) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let mut remote_host = RemoteHostInfo::new(mac_addr, remote_ip_addr);
+                remote_host.vendor_name =
+                    self.get_vendor_name(&remote_host.mac_addr, &remote_ip_addr);
+                remote_host.label = self.get_label(&remote_ip_addr);
+                entry.insert(remote_host)
+            }
+        };
         match direction {
             Direction::Egress => {
                 remote_host.traffic_info.packet_sent += 1;
@@ -472,9 +876,15 @@ impl NetStatStrage {
                 remote_host.traffic_info.bytes_received += frame.packet_len;
             }
         }
+        // Prefer the name the client actually queried over the reverse DNS name
+        if let Some(dns_record) = passive_dns_map_inner.get(&remote_ip_addr) {
+            if !dns_record.is_expired() {
+                remote_host.hostname = dns_record.name.clone();
+            }
+        }
         match remote_host.ip_addr {
             IpAddr::V4(ipv4) => {
-                if let Some(ipv4_info) = ipdb_inner.get_ipv4_info(ipv4) {
+                if let Some(ipv4_info) = ipdb.and_then(|ipdb| ipdb.get_ipv4_info(ipv4)) {
                     remote_host.country_code = ipv4_info.country_code;
                     remote_host.country_name = ipv4_info.country_name;
                     remote_host.asn = ipv4_info.asn;
@@ -482,7 +892,7 @@ impl NetStatStrage {
                 }
             }
             IpAddr::V6(ipv6) => {
-                if let Some(ipv6_info) = ipdb_inner.get_ipv6_info(ipv6) {
+                if let Some(ipv6_info) = ipdb.and_then(|ipdb| ipdb.get_ipv6_info(ipv6)) {
                     remote_host.country_code = ipv6_info.country_code;
                     remote_host.country_name = ipv6_info.country_name;
                     remote_host.asn = ipv6_info.asn;
@@ -492,7 +902,7 @@ impl NetStatStrage {
         }
         // Update SocketConnection if the packet is TCP or UDP.
         if let Some(transport) = frame.transport {
-            if let Some(_tcp) = transport.tcp {
+            if let Some(tcp) = transport.tcp {
                 let socket_connection: SocketConnection = SocketConnection {
                     interface_name: interface_name.clone(),
                     local_ip_addr: local_ip_addr,
@@ -501,9 +911,23 @@ impl NetStatStrage {
                     remote_port: remote_port,
                     protocol: TransportProtocol::TCP,
                 };
+                self.update_server_name_map(
+                    &socket_connection,
+                    direction,
+                    Some(tcp.sequence),
+                    &frame.payload,
+                );
+                let tcp_metrics = self.update_tcp_flow_map(
+                    &socket_connection,
+                    direction,
+                    &TcpSegment::from_header(&tcp, tcp_payload_len),
+                    frame.capture_time,
+                );
+                remote_host.traffic_info.tcp.add(&tcp_metrics);
                 let socket_traffic: &mut TrafficInfo = connections_inner
                     .entry(socket_connection)
                     .or_insert(TrafficInfo::new());
+                socket_traffic.tcp.add(&tcp_metrics);
                 match direction {
                     Direction::Egress => {
                         socket_traffic.packet_sent += 1;
@@ -524,6 +948,7 @@ impl NetStatStrage {
                     remote_port: remote_port,
                     protocol: TransportProtocol::UDP,
                 };
+                self.update_server_name_map(&socket_connection, direction, None, &frame.payload);
                 let socket_traffic: &mut TrafficInfo = connections_inner
                     .entry(socket_connection)
                     .or_insert(TrafficInfo::new());
@@ -543,16 +968,20 @@ impl NetStatStrage {
         drop(traffic_inner);
         drop(remote_hosts_inner);
         drop(connections_inner);
-        drop(ipdb_inner);
+        drop(passive_dns_map_inner);
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Overview {
     pub traffic: TrafficDisplayInfo,
+    pub interfaces: Vec<InterfaceTrafficDisplayInfo>,
     pub top_processes: Vec<ProcessDisplayInfo>,
     pub top_remote_hosts: Vec<HostDisplayInfo>,
     pub top_app_protocols: Vec<ServiceDisplayInfo>,
+    pub top_labels: Vec<LabelDisplayInfo>,
+    pub top_countries: Vec<CountryDisplayInfo>,
+    pub top_autonomous_systems: Vec<AsDisplayInfo>,
     pub notificatons: Vec<Notification>,
 }
 
@@ -560,9 +989,13 @@ impl Overview {
     pub fn new() -> Self {
         Overview {
             traffic: TrafficDisplayInfo::new(),
+            interfaces: Vec::new(),
             top_processes: Vec::new(),
             top_remote_hosts: Vec::new(),
             top_app_protocols: Vec::new(),
+            top_labels: Vec::new(),
+            top_countries: Vec::new(),
+            top_autonomous_systems: Vec::new(),
             notificatons: Vec::new(),
         }
     }
@@ -572,10 +1005,25 @@ impl Overview {
 pub struct NetStatData {
     pub default_interface: Interface,
     pub traffic: TrafficInfo,
+    /// Traffic of each captured interface (Interface Name -> InterfaceTrafficInfo)
+    pub interface_traffic: BTreeMap<String, InterfaceTrafficInfo>,
+    /// Bandwidth history of all captured traffic
+    pub traffic_history: BandwidthHistory,
+    /// Bandwidth history of each remote host (IpAddr -> BandwidthHistory)
+    pub host_history: HashMap<IpAddr, BandwidthHistory>,
+    /// Bandwidth history of each process (PID -> BandwidthHistory)
+    pub process_history: HashMap<u32, BandwidthHistory>,
+    /// Bandwidth history of each connection
+    pub connection_history: HashMap<SocketConnection, BandwidthHistory>,
     pub remote_hosts: HashMap<IpAddr, RemoteHostInfo>,
     pub connection_map: HashMap<SocketConnection, TrafficInfo>,
     pub local_socket_map: HashMap<LocalSocket, SocketProcess>,
+    pub server_name_map: HashMap<SocketConnection, ServerNameInfo>,
+    /// TCP flow states from the captured flags
+    pub tcp_flow_map: HashMap<SocketConnection, TcpFlowInfo>,
     pub local_ip_map: HashMap<IpAddr, String>,
+    /// Recent notifications from the alert rules. Oldest first.
+    pub notifications: Vec<Notification>,
 }
 
 impl NetStatData {
@@ -590,10 +1038,18 @@ impl NetStatData {
         NetStatData {
             default_interface: default_interface,
             traffic: TrafficInfo::new(),
+            interface_traffic: BTreeMap::new(),
+            traffic_history: BandwidthHistory::new(),
+            host_history: HashMap::new(),
+            process_history: HashMap::new(),
+            connection_history: HashMap::new(),
             remote_hosts: HashMap::new(),
             connection_map: HashMap::new(),
             local_socket_map: HashMap::new(),
+            server_name_map: HashMap::new(),
+            tcp_flow_map: HashMap::new(),
             local_ip_map: HashMap::new(),
+            notifications: Vec::new(),
         }
     }
     // merge using entry method to merge traffic info.
@@ -603,6 +1059,22 @@ impl NetStatData {
         // Update Traffic Info
         self.traffic.update_bytes_per_sec(&other.traffic, duration);
         self.traffic.add_traffic(&other.traffic);
+        // Update Interface Traffic Info. Idle interfaces get a zero sample.
+        for interface_name in other.interface_traffic.keys() {
+            if !self.interface_traffic.contains_key(interface_name) {
+                self.interface_traffic
+                    .insert(interface_name.clone(), InterfaceTrafficInfo::new());
+            }
+        }
+        let no_traffic = TrafficInfo::new();
+        for (interface_name, interface_traffic) in self.interface_traffic.iter_mut() {
+            let traffic = other
+                .interface_traffic
+                .get(interface_name)
+                .map(|info| &info.traffic)
+                .unwrap_or(&no_traffic);
+            interface_traffic.add_tick(traffic, duration);
+        }
         // Update RemoteHostInfo
         other
             .remote_hosts
@@ -647,10 +1119,104 @@ impl NetStatData {
                     }
                 }
             });
+        // Update server_name_map
+        self.server_name_map.extend(other.server_name_map);
+        // Update tcp_flow_map
+        self.tcp_flow_map.extend(other.tcp_flow_map);
         // Update local_ip_map
         self.local_ip_map = other.local_ip_map;
+        // Update the bandwidth histories. Entries without traffic in this tick get a zero sample.
+        self.traffic_history.add(
+            other.traffic.bytes_received,
+            other.traffic.bytes_sent,
+            duration,
+        );
+        for ip in self.remote_hosts.keys() {
+            let history = self
+                .host_history
+                .entry(*ip)
+                .or_insert_with(BandwidthHistory::new);
+            match other.remote_hosts.get(ip) {
+                Some(host) => history.add(
+                    host.traffic_info.bytes_received,
+                    host.traffic_info.bytes_sent,
+                    duration,
+                ),
+                None => history.add(0, 0, duration),
+            }
+        }
+        // PID -> (received bytes, sent bytes) of this tick
+        let mut process_bytes: HashMap<u32, (usize, usize)> = HashMap::new();
+        for (conn, traffic_info) in &other.connection_map {
+            if let Some(pid) = self.get_process_id(conn) {
+                let bytes = process_bytes.entry(pid).or_insert((0, 0));
+                bytes.0 += traffic_info.bytes_received;
+                bytes.1 += traffic_info.bytes_sent;
+            }
+        }
+        for pid in process_bytes.keys() {
+            self.process_history
+                .entry(*pid)
+                .or_insert_with(BandwidthHistory::new);
+        }
+        for (pid, history) in self.process_history.iter_mut() {
+            let (received, sent) = process_bytes.get(pid).copied().unwrap_or((0, 0));
+            history.add(received, sent, duration);
+        }
+        for conn in self.connection_map.keys() {
+            let history = self
+                .connection_history
+                .entry(conn.clone())
+                .or_insert_with(BandwidthHistory::new);
+            match other.connection_map.get(conn) {
+                Some(traffic_info) => history.add(
+                    traffic_info.bytes_received,
+                    traffic_info.bytes_sent,
+                    duration,
+                ),
+                None => history.add(0, 0, duration),
+            }
+        }
+        self.remove_closed_connections();
+    }
+
+    // Closed TCP flows leave the connection list after CLOSED_FLOW_TTL instead of the entry TTL
+    fn remove_closed_connections(&mut self) {
+        let closed: Vec<SocketConnection> = self
+            .tcp_flow_map
+            .iter()
+            .filter(|(_, flow)| flow.is_expired(tcp::CLOSED_FLOW_TTL))
+            .map(|(conn, _)| conn.clone())
+            .collect();
+        for conn in closed {
+            self.connection_map.remove(&conn);
+            self.connection_history.remove(&conn);
+            self.server_name_map.remove(&conn);
+            self.tcp_flow_map.remove(&conn);
+        }
     }
 
+    // PID of the process that owns the local socket of the connection
+    fn get_process_id(&self, conn: &SocketConnection) -> Option<u32> {
+        let local_socket: LocalSocket = LocalSocket {
+            interface_name: conn.interface_name.clone(),
+            port: conn.local_port,
+            protocol: conn.protocol,
+        };
+        self.local_socket_map
+            .get(&local_socket)
+            .and_then(|socket_process| socket_process.process.as_ref())
+            .map(|process| process.pid)
+    }
+
+    // Keep the latest max notifications
+    pub fn add_notifications(&mut self, notifications: Vec<Notification>, max: usize) {
+        self.notifications.extend(notifications);
+        if self.notifications.len() > max {
+            let excess = self.notifications.len() - max;
+            self.notifications.drain(..excess);
+        }
+    }
     // Remove old entries from remote_hosts, connection_map, local_socket_map
     // TrafficInfo.last_seen is used to determine if the entry is old.
     // If the entry is older than ttl, it will be removed.
@@ -707,10 +1273,25 @@ impl NetStatData {
             .map(|(conn, traffic_info)| (conn.clone(), traffic_info.clone()))
             .collect();
         self.connection_map = connection_map;
+        self.server_name_map
+            .retain(|conn, _| self.connection_map.contains_key(conn));
+        self.tcp_flow_map
+            .retain(|conn, _| self.connection_map.contains_key(conn));
 
         for local_socket in remove_local_socket {
             self.local_socket_map.remove(&local_socket);
         }
+        // Drop the histories of removed hosts, processes and connections
+        self.host_history
+            .retain(|ip, _| self.remote_hosts.contains_key(ip));
+        self.connection_history
+            .retain(|conn, _| self.connection_map.contains_key(conn));
+        let pids: std::collections::HashSet<u32> = self
+            .connection_map
+            .keys()
+            .filter_map(|conn| self.get_process_id(conn))
+            .collect();
+        self.process_history.retain(|pid, _| pids.contains(pid));
     }
 
     pub fn get_remote_hosts(&self, limit: Option<usize>) -> Vec<HostDisplayInfo> {
@@ -747,6 +1328,8 @@ impl NetStatData {
                     country_name: host.country_name.clone(),
                     asn: host.asn.clone(),
                     as_name: host.as_name.clone(),
+                    vendor_name: host.vendor_name.clone(),
+                    label: host.label.clone(),
                     traffic: host.traffic_info.to_display_info(),
                 };
                 remote_hosts.push(host);
@@ -851,6 +1434,8 @@ impl NetStatData {
                     },
                     traffic: traffic.to_display_info(),
                     process: process,
+                    server_name: self.server_name_map.get(conn).cloned(),
+                    tcp_flow: self.tcp_flow_map.get(conn).cloned(),
                 };
                 top_connections.push(socket_traffic_info);
             }
@@ -900,6 +1485,8 @@ impl NetStatData {
                     },
                     traffic: traffic.to_display_info(),
                     process: process,
+                    server_name: self.server_name_map.get(conn).cloned(),
+                    tcp_flow: self.tcp_flow_map.get(conn).cloned(),
                 };
                 if opt.address_family.contains(&socket_traffic_info.ip_version)
                     && opt
@@ -975,19 +1562,152 @@ impl NetStatData {
         top_app_protocols
     }
 
+    pub fn get_labels(&self, limit: Option<usize>) -> Vec<LabelDisplayInfo> {
+        // label -> (host count, traffic)
+        let mut label_map: HashMap<&str, (usize, TrafficInfo)> = HashMap::new();
+        self.remote_hosts.values().for_each(|host| {
+            if host.label.is_empty() {
+                return;
+            }
+            let (host_count, traffic) = label_map
+                .entry(host.label.as_str())
+                .or_insert((0, TrafficInfo::new()));
+            *host_count += 1;
+            add_host_traffic(traffic, &host.traffic_info);
+        });
+        let mut label_vec: Vec<(&str, (usize, TrafficInfo))> = label_map.into_iter().collect();
+        label_vec.sort_by_key(|(_, (_, traffic))| std::cmp::Reverse(traffic.total_bytes()));
+        // limit : if limit is None, return all labels.
+        label_vec
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(label, (host_count, traffic))| LabelDisplayInfo {
+                label: label.to_string(),
+                host_count,
+                traffic: traffic.to_display_info(),
+            })
+            .collect()
+    }
+
+    pub fn get_interfaces(&self) -> Vec<InterfaceTrafficDisplayInfo> {
+        self.interface_traffic
+            .iter()
+            .map(
+                |(interface_name, interface_traffic)| InterfaceTrafficDisplayInfo {
+                    interface_name: interface_name.clone(),
+                    traffic: interface_traffic.traffic.to_display_info(),
+                },
+            )
+            .collect()
+    }
+
+    // Number of connections per remote IP address
+    fn get_host_connection_counts(&self) -> HashMap<IpAddr, usize> {
+        let mut connection_counts: HashMap<IpAddr, usize> = HashMap::new();
+        for conn in self.connection_map.keys() {
+            *connection_counts.entry(conn.remote_ip_addr).or_insert(0) += 1;
+        }
+        connection_counts
+    }
+
+    pub fn get_countries(&self, limit: Option<usize>) -> Vec<CountryDisplayInfo> {
+        let connection_counts = self.get_host_connection_counts();
+        // country_code -> (country_name, host count, connection count, traffic)
+        let mut country_map: HashMap<&str, (&str, usize, usize, TrafficInfo)> = HashMap::new();
+        self.remote_hosts.values().for_each(|host| {
+            let (country_name, host_count, connection_count, traffic) = country_map
+                .entry(host.country_code.as_str())
+                .or_insert(("", 0, 0, TrafficInfo::new()));
+            if country_name.is_empty() {
+                *country_name = host.country_name.as_str();
+            }
+            *host_count += 1;
+            *connection_count += connection_counts.get(&host.ip_addr).unwrap_or(&0);
+            add_host_traffic(traffic, &host.traffic_info);
+        });
+        let mut country_vec: Vec<_> = country_map.into_iter().collect();
+        country_vec.sort_by_key(|(_, (_, _, _, traffic))| std::cmp::Reverse(traffic.total_bytes()));
+        // limit : if limit is None, return all countries.
+        country_vec
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(
+                |(country_code, (country_name, host_count, connection_count, traffic))| {
+                    CountryDisplayInfo {
+                        country_code: country_code.to_string(),
+                        country_name: country_name.to_string(),
+                        host_count,
+                        connection_count,
+                        traffic: traffic.to_display_info(),
+                    }
+                },
+            )
+            .collect()
+    }
+
+    pub fn get_autonomous_systems(&self, limit: Option<usize>) -> Vec<AsDisplayInfo> {
+        let connection_counts = self.get_host_connection_counts();
+        // asn -> (as_name, host count, connection count, traffic)
+        let mut as_map: HashMap<u32, (&str, usize, usize, TrafficInfo)> = HashMap::new();
+        self.remote_hosts.values().for_each(|host| {
+            let (as_name, host_count, connection_count, traffic) = as_map
+                .entry(host.asn)
+                .or_insert(("", 0, 0, TrafficInfo::new()));
+            if as_name.is_empty() {
+                *as_name = host.as_name.as_str();
+            }
+            *host_count += 1;
+            *connection_count += connection_counts.get(&host.ip_addr).unwrap_or(&0);
+            add_host_traffic(traffic, &host.traffic_info);
+        });
+        let mut as_vec: Vec<_> = as_map.into_iter().collect();
+        as_vec.sort_by_key(|(_, (_, _, _, traffic))| std::cmp::Reverse(traffic.total_bytes()));
+        // limit : if limit is None, return all autonomous systems.
+        as_vec
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(
+                |(asn, (as_name, host_count, connection_count, traffic))| AsDisplayInfo {
+                    asn,
+                    as_name: as_name.to_string(),
+                    host_count,
+                    connection_count,
+                    traffic: traffic.to_display_info(),
+                },
+            )
+            .collect()
+    }
+
     pub fn get_overview(&self) -> Overview {
         let mut overview = Overview::new();
         overview.traffic = TrafficDisplayInfo::from_traffic(&self.traffic);
+        overview.interfaces = self.get_interfaces();
         // Get top remote hosts
         overview.top_remote_hosts = self.get_remote_hosts(Some(10));
         // Get top processes
         overview.top_processes = self.get_processes(Some(10));
         // Get top app protocols
         overview.top_app_protocols = self.get_app_protocols(Some(10));
+        // Get top labels
+        overview.top_labels = self.get_labels(Some(10));
+        // Get top countries and autonomous systems
+        overview.top_countries = self.get_countries(Some(10));
+        overview.top_autonomous_systems = self.get_autonomous_systems(Some(10));
+        // Newest first
+        overview.notificatons = self.notifications.iter().rev().cloned().collect();
         overview
     }
 }
 
+// Add the totals and the current rates of a host to an aggregate
+fn add_host_traffic(traffic: &mut TrafficInfo, host_traffic: &TrafficInfo) {
+    traffic.add_traffic(host_traffic);
+    traffic.egress_packets_per_sec += host_traffic.egress_packets_per_sec;
+    traffic.ingress_packets_per_sec += host_traffic.ingress_packets_per_sec;
+    traffic.egress_bytes_per_sec += host_traffic.egress_bytes_per_sec;
+    traffic.ingress_bytes_per_sec += host_traffic.ingress_bytes_per_sec;
+}
+
 pub fn update_netstat_data(
     netstat_strage: &mut Arc<NetStatStrage>,
     netstat_data: &mut Arc<Mutex<NetStatData>>,
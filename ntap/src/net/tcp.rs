@@ -0,0 +1,590 @@
+use crate::net::packet::PacketFrame;
+use crate::net::socket::SocketStatus;
+use crate::net::traffic::Direction;
+use nex::packet::tcp::{TcpFlags, TcpHeader};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// Closed flows are removed from the connection list after this time.
+pub const CLOSED_FLOW_TTL: Duration = Duration::from_secs(5);
+/// Flows without packets for this long are no longer tracked.
+pub const IDLE_FLOW_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// Maximum number of tracked flows. Closed and idle flows are dropped first when full.
+pub const TCP_FLOW_MAP_CAPACITY: usize = 65536;
+/// Data below the highest sequence number that arrives within this time of the highest segment
+/// is counted as out of order instead of retransmitted, until the flow has an RTT sample.
+pub const REORDER_WINDOW: Duration = Duration::from_millis(3);
+
+/// Header fields of one captured TCP segment.
+#[derive(Debug, Clone)]
+pub struct TcpSegment {
+    pub flags: u8,
+    pub sequence: u32,
+    pub acknowledgement: u32,
+    pub window: u16,
+    pub payload_len: usize,
+}
+
+impl TcpSegment {
+    pub fn from_header(tcp: &TcpHeader, payload_len: usize) -> Self {
+        TcpSegment {
+            flags: tcp.flags,
+            sequence: tcp.sequence,
+            acknowledgement: tcp.acknowledgement,
+            window: tcp.window,
+            payload_len,
+        }
+    }
+}
+
+/// TCP payload length from the IP header, so a short snap length does not look like lost data.
+/// 0 if the frame is not TCP.
+pub fn payload_len(frame: &PacketFrame) -> usize {
+    let tcp = match frame.transport.as_ref().and_then(|t| t.tcp.as_ref()) {
+        Some(tcp) => tcp,
+        None => return 0,
+    };
+    let tcp_header_len = tcp.data_offset as usize * 4;
+    let ip_payload_len = match &frame.ip {
+        Some(ip) => {
+            if let Some(ipv4) = &ip.ipv4 {
+                (ipv4.total_length as usize).saturating_sub(ipv4.header_length as usize * 4)
+            } else if let Some(ipv6) = &ip.ipv6 {
+                ipv6.payload_length as usize
+            } else {
+                return 0;
+            }
+        }
+        None => return 0,
+    };
+    ip_payload_len.saturating_sub(tcp_header_len)
+}
+
+/// RTT samples of a connection, host or AS.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RttStats {
+    pub samples: usize,
+    pub total: Duration,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    /// Most recent sample
+    pub last: Option<Duration>,
+}
+
+impl RttStats {
+    pub fn add_sample(&mut self, rtt: Duration) {
+        self.samples += 1;
+        self.total += rtt;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+        self.last = Some(rtt);
+    }
+    pub fn merge(&mut self, other: &RttStats) {
+        self.samples += other.samples;
+        self.total += other.total;
+        if let Some(other_min) = other.min {
+            self.min = Some(self.min.map_or(other_min, |min| min.min(other_min)));
+        }
+        if let Some(other_max) = other.max {
+            self.max = Some(self.max.map_or(other_max, |max| max.max(other_max)));
+        }
+        if other.last.is_some() {
+            self.last = other.last;
+        }
+    }
+    pub fn avg(&self) -> Option<Duration> {
+        if self.samples == 0 {
+            None
+        } else {
+            Some(self.total / self.samples as u32)
+        }
+    }
+    /// Example: `12.3 ms`. Empty if there is no sample.
+    pub fn formatted_avg(&self) -> String {
+        match self.avg() {
+            Some(avg) => format_rtt(avg),
+            None => String::new(),
+        }
+    }
+    /// Example: `avg 12.3 ms  min 10.1 ms  max 20.4 ms  (8 samples)`
+    pub fn summary(&self) -> String {
+        match (self.avg(), self.min, self.max) {
+            (Some(avg), Some(min), Some(max)) => format!(
+                "avg {}  min {}  max {}  ({} samples)",
+                format_rtt(avg),
+                format_rtt(min),
+                format_rtt(max),
+                self.samples
+            ),
+            _ => String::from("-"),
+        }
+    }
+}
+
+/// Passive TCP quality metrics from the captured headers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TcpMetrics {
+    /// SYN to the ACK of the SYN-ACK
+    pub handshake_rtt: RttStats,
+    /// Data segment to the ACK that covers it. Retransmitted segments are not sampled.
+    pub rtt: RttStats,
+    /// Segments that carry data
+    pub data_segments: usize,
+    pub retransmissions: usize,
+    pub out_of_order: usize,
+    pub dup_acks: usize,
+    /// Times a side advertised a zero window after a non-zero one
+    pub zero_windows: usize,
+}
+
+impl TcpMetrics {
+    pub fn add(&mut self, other: &TcpMetrics) {
+        self.handshake_rtt.merge(&other.handshake_rtt);
+        self.rtt.merge(&other.rtt);
+        self.data_segments += other.data_segments;
+        self.retransmissions += other.retransmissions;
+        self.out_of_order += other.out_of_order;
+        self.dup_acks += other.dup_acks;
+        self.zero_windows += other.zero_windows;
+    }
+    /// RTT of the data segments, or the handshake RTT if there is no data sample yet
+    pub fn avg_rtt(&self) -> Option<Duration> {
+        self.rtt.avg().or_else(|| self.handshake_rtt.avg())
+    }
+    pub fn formatted_avg_rtt(&self) -> String {
+        match self.avg_rtt() {
+            Some(rtt) => format_rtt(rtt),
+            None => String::new(),
+        }
+    }
+    /// Retransmitted share of the data segments in percent
+    pub fn retransmission_rate(&self) -> f64 {
+        if self.data_segments == 0 {
+            0.0
+        } else {
+            self.retransmissions as f64 * 100.0 / self.data_segments as f64
+        }
+    }
+    /// Example: `3 (1.2%)`. Empty if no data segment was seen.
+    pub fn formatted_retransmissions(&self) -> String {
+        if self.data_segments == 0 {
+            String::new()
+        } else {
+            format!(
+                "{} ({:.1}%)",
+                self.retransmissions,
+                self.retransmission_rate()
+            )
+        }
+    }
+}
+
+pub fn format_rtt(rtt: Duration) -> String {
+    let ms = rtt.as_secs_f64() * 1000.0;
+    if ms >= 1000.0 {
+        format!("{:.2} s", ms / 1000.0)
+    } else {
+        format!("{:.1} ms", ms)
+    }
+}
+
+/// Sequence tracking of one side of a flow.
+#[derive(Debug, Clone, Default)]
+struct TcpSender {
+    /// Sequence number after the highest segment sent
+    next_seq: Option<u32>,
+    /// Capture time of the highest segment
+    next_seq_time: Duration,
+    /// Segment waiting for the ACK that covers it (ACK number, capture time)
+    rtt_probe: Option<(u32, Duration)>,
+    /// Last ACK number and window sent by this side
+    last_ack: Option<u32>,
+    last_window: u16,
+    zero_window: bool,
+}
+
+// Sequence number comparison with wrap around (RFC 1982)
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// How the connection was closed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpCloseKind {
+    Fin,
+    Rst,
+}
+
+impl TcpCloseKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            TcpCloseKind::Fin => "FIN",
+            TcpCloseKind::Rst => "RST",
+        }
+    }
+}
+
+/// TCP state of one flow as seen on the wire.
+/// Egress means the local side and Ingress the remote side.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TcpFlowInfo {
+    /// SYN_SENT until the SYN-ACK is seen, then SYN_RCVD until the handshake ACK.
+    /// After a FIN from one side, FIN_WAIT_1 or CLOSE_WAIT from the local side's point of view.
+    pub state: SocketStatus,
+    /// Side that sent the first SYN. None if the handshake was not captured.
+    pub opened_by: Option<Direction>,
+    /// Capture time of the first SYN
+    pub syn_time: Option<Duration>,
+    /// Time from the SYN to the ACK of the SYN-ACK
+    pub handshake_time: Option<Duration>,
+    /// Side that sent the first FIN or the RST
+    pub closed_by: Option<Direction>,
+    pub close_kind: Option<TcpCloseKind>,
+    /// When the flow was closed. Local clock, also when reading a capture file.
+    pub closed_at: Option<SystemTime>,
+    /// When the last packet of the flow was seen. Local clock.
+    pub last_seen: SystemTime,
+    /// Smallest RTT sample, used to tell reordering from retransmission
+    pub min_rtt: Option<Duration>,
+    #[serde(skip)]
+    fin_sent: bool,
+    #[serde(skip)]
+    fin_received: bool,
+    #[serde(skip)]
+    local: TcpSender,
+    #[serde(skip)]
+    remote: TcpSender,
+}
+
+impl TcpFlowInfo {
+    pub fn new() -> Self {
+        TcpFlowInfo {
+            state: SocketStatus::Unknown,
+            opened_by: None,
+            syn_time: None,
+            handshake_time: None,
+            closed_by: None,
+            close_kind: None,
+            closed_at: None,
+            last_seen: SystemTime::now(),
+            min_rtt: None,
+            fin_sent: false,
+            fin_received: false,
+            local: TcpSender::default(),
+            remote: TcpSender::default(),
+        }
+    }
+    /// Advance the state with one captured segment and return the metrics it produced.
+    /// timestamp is the capture time of the segment.
+    pub fn update(
+        &mut self,
+        direction: Direction,
+        segment: &TcpSegment,
+        timestamp: Duration,
+    ) -> TcpMetrics {
+        let mut metrics = TcpMetrics::default();
+        let handshake_done = self.handshake_time.is_some();
+        self.update_state(direction, segment.flags, timestamp);
+        if !handshake_done {
+            if let Some(handshake_time) = self.handshake_time {
+                metrics.handshake_rtt.add_sample(handshake_time);
+                self.min_rtt = Some(handshake_time);
+            }
+        }
+        if segment.flags & TcpFlags::RST == 0 {
+            self.track_segment(direction, segment, timestamp, &mut metrics);
+        }
+        metrics
+    }
+    fn update_state(&mut self, direction: Direction, flags: u8, timestamp: Duration) {
+        let now = SystemTime::now();
+        self.last_seen = now;
+        let syn = flags & TcpFlags::SYN != 0;
+        let ack = flags & TcpFlags::ACK != 0;
+        if flags & TcpFlags::RST != 0 {
+            if self.closed_at.is_none() {
+                self.state = SocketStatus::Closed;
+                self.closed_by = Some(direction);
+                self.close_kind = Some(TcpCloseKind::Rst);
+                self.closed_at = Some(now);
+            }
+            return;
+        }
+        if syn && !ack {
+            // A new SYN on a closed flow is a new connection on the same ports.
+            // Retransmitted SYNs keep the time of the first one.
+            if self.closed_at.is_some() || self.opened_by != Some(direction) {
+                *self = TcpFlowInfo::new();
+                self.state = SocketStatus::SynSent;
+                self.opened_by = Some(direction);
+                self.syn_time = Some(timestamp);
+            }
+            return;
+        }
+        if syn && ack {
+            if self.state == SocketStatus::SynSent && self.opened_by != Some(direction) {
+                self.state = SocketStatus::SynReceived;
+            }
+            return;
+        }
+        match self.state {
+            SocketStatus::Unknown => {
+                // The flow was open before the capture started
+                self.state = SocketStatus::Established;
+            }
+            // The opener acknowledged the SYN-ACK
+            SocketStatus::SynReceived if ack && self.opened_by == Some(direction) => {
+                self.state = SocketStatus::Established;
+                self.handshake_time = self
+                    .syn_time
+                    .and_then(|syn_time| timestamp.checked_sub(syn_time));
+            }
+            _ => {}
+        }
+        if flags & TcpFlags::FIN != 0 && self.closed_at.is_none() {
+            match direction {
+                Direction::Egress => self.fin_sent = true,
+                Direction::Ingress => self.fin_received = true,
+            }
+            if self.closed_by.is_none() {
+                self.closed_by = Some(direction);
+                self.close_kind = Some(TcpCloseKind::Fin);
+            }
+            if self.fin_sent && self.fin_received {
+                self.state = SocketStatus::Closed;
+                self.closed_at = Some(now);
+            } else if direction == Direction::Egress {
+                self.state = SocketStatus::FinWait1;
+            } else {
+                self.state = SocketStatus::CloseWait;
+            }
+        }
+    }
+    // Sequence and ACK tracking for the metrics
+    fn track_segment(
+        &mut self,
+        direction: Direction,
+        segment: &TcpSegment,
+        timestamp: Duration,
+        metrics: &mut TcpMetrics,
+    ) {
+        let syn = segment.flags & TcpFlags::SYN != 0;
+        let fin = segment.flags & TcpFlags::FIN != 0;
+        let reorder_window = self.min_rtt.unwrap_or(REORDER_WINDOW);
+        let (sender, receiver) = match direction {
+            Direction::Egress => (&mut self.local, &mut self.remote),
+            Direction::Ingress => (&mut self.remote, &mut self.local),
+        };
+        // SYN and FIN take one sequence number
+        let seg_len = segment.payload_len as u32 + syn as u32 + fin as u32;
+        if segment.payload_len > 0 {
+            metrics.data_segments += 1;
+        }
+        if seg_len > 0 {
+            let seg_end = segment.sequence.wrapping_add(seg_len);
+            match sender.next_seq {
+                Some(next_seq) if seq_lt(segment.sequence, next_seq) => {
+                    // Data below the highest sequence number
+                    if timestamp.saturating_sub(sender.next_seq_time) < reorder_window {
+                        metrics.out_of_order += 1;
+                    } else {
+                        metrics.retransmissions += 1;
+                    }
+                    // No RTT sample from data that was sent twice (Karn's algorithm)
+                    if let Some((probe_ack, _)) = sender.rtt_probe {
+                        if seq_lt(segment.sequence, probe_ack) {
+                            sender.rtt_probe = None;
+                        }
+                    }
+                    if seq_lt(next_seq, seg_end) {
+                        sender.next_seq = Some(seg_end);
+                        sender.next_seq_time = timestamp;
+                    }
+                }
+                _ => {
+                    sender.next_seq = Some(seg_end);
+                    sender.next_seq_time = timestamp;
+                    // Only local data gives an RTT sample. Timing remote data
+                    // against our own ACK would measure the local ACK delay.
+                    if direction == Direction::Egress
+                        && sender.rtt_probe.is_none()
+                        && segment.payload_len > 0
+                    {
+                        sender.rtt_probe = Some((seg_end, timestamp));
+                    }
+                }
+            }
+        }
+        if segment.flags & TcpFlags::ACK != 0 {
+            let ack = segment.acknowledgement;
+            // The ACK covers the probe segment of the other side
+            if let Some((probe_ack, sent)) = receiver.rtt_probe {
+                if !seq_lt(ack, probe_ack) {
+                    if let Some(rtt) = timestamp.checked_sub(sent) {
+                        metrics.rtt.add_sample(rtt);
+                        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+                    }
+                    receiver.rtt_probe = None;
+                }
+            }
+            // Same ACK and window without data while the other side has data in flight
+            let in_flight = match receiver.next_seq {
+                Some(next_seq) => seq_lt(ack, next_seq),
+                None => false,
+            };
+            if seg_len == 0
+                && in_flight
+                && sender.last_ack == Some(ack)
+                && sender.last_window == segment.window
+            {
+                metrics.dup_acks += 1;
+            }
+            sender.last_ack = Some(ack);
+            sender.last_window = segment.window;
+        }
+        if segment.window == 0 && !syn {
+            if !sender.zero_window {
+                metrics.zero_windows += 1;
+                sender.zero_window = true;
+            }
+        } else {
+            sender.zero_window = false;
+        }
+    }
+    /// The handshake was started but not completed
+    pub fn is_half_open(&self) -> bool {
+        self.state == SocketStatus::SynSent || self.state == SocketStatus::SynReceived
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed_at.is_some()
+    }
+    /// The flow was closed more than ttl ago
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        match self.closed_at {
+            Some(closed_at) => match closed_at.elapsed() {
+                Ok(elapsed) => elapsed > ttl,
+                Err(_) => false,
+            },
+            None => false,
+        }
+    }
+    pub fn is_idle(&self) -> bool {
+        match self.last_seen.elapsed() {
+            Ok(elapsed) => elapsed > IDLE_FLOW_TIMEOUT,
+            Err(_) => false,
+        }
+    }
+    /// Example: `RST by remote`. Empty if the flow is not closing.
+    pub fn close_string(&self) -> String {
+        match (self.close_kind, self.closed_by) {
+            (Some(kind), Some(direction)) => {
+                format!("{} by {}", kind.as_str(), side_name(direction))
+            }
+            _ => String::new(),
+        }
+    }
+    /// Example: `12.3 ms, opened by local`
+    pub fn handshake_string(&self) -> String {
+        let opened_by = match self.opened_by {
+            Some(direction) => side_name(direction),
+            None => return "not captured (open before the capture)".to_string(),
+        };
+        match self.handshake_time {
+            Some(handshake_time) => format!(
+                "{:.1} ms, opened by {}",
+                handshake_time.as_secs_f64() * 1000.0,
+                opened_by
+            ),
+            None if self.is_half_open() => format!("half-open, opened by {}", opened_by),
+            None => format!("incomplete, opened by {}", opened_by),
+        }
+    }
+}
+
+fn side_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Egress => "local",
+        Direction::Ingress => "remote",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(flags: u8, sequence: u32, acknowledgement: u32, payload_len: usize) -> TcpSegment {
+        TcpSegment {
+            flags,
+            sequence,
+            acknowledgement,
+            window: 65535,
+            payload_len,
+        }
+    }
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn rtt_from_egress_data_only() {
+        let mut flow = TcpFlowInfo::new();
+        let ack = TcpFlags::ACK;
+        // Remote data acknowledged by the local side 1ms later is not an RTT sample
+        let metrics = flow.update(
+            Direction::Ingress,
+            &segment(ack, 1000, 5000, 100),
+            millis(0),
+        );
+        assert_eq!(metrics.rtt.samples, 0);
+        let metrics = flow.update(Direction::Egress, &segment(ack, 5000, 1100, 0), millis(1));
+        assert_eq!(metrics.rtt.samples, 0);
+        assert_eq!(flow.min_rtt, None);
+        // Local data acknowledged by the remote side is
+        flow.update(
+            Direction::Egress,
+            &segment(ack, 5000, 1100, 200),
+            millis(10),
+        );
+        let metrics = flow.update(Direction::Ingress, &segment(ack, 1100, 5200, 0), millis(40));
+        assert_eq!(metrics.rtt.samples, 1);
+        assert_eq!(metrics.rtt.last, Some(millis(30)));
+        assert_eq!(flow.min_rtt, Some(millis(30)));
+    }
+
+    #[test]
+    fn reorder_window_uses_egress_rtt() {
+        let mut flow = TcpFlowInfo::new();
+        let ack = TcpFlags::ACK;
+        flow.update(Direction::Egress, &segment(ack, 5000, 1000, 100), millis(0));
+        flow.update(Direction::Ingress, &segment(ack, 1000, 5100, 0), millis(50));
+        // Fast local ACKs of remote data must not shrink the window
+        flow.update(
+            Direction::Ingress,
+            &segment(ack, 1000, 5100, 100),
+            millis(60),
+        );
+        flow.update(Direction::Egress, &segment(ack, 5100, 1100, 0), millis(61));
+        assert_eq!(flow.min_rtt, Some(millis(50)));
+        // Remote segments arrive out of order within the RTT
+        flow.update(
+            Direction::Ingress,
+            &segment(ack, 1200, 5100, 100),
+            millis(70),
+        );
+        let metrics = flow.update(
+            Direction::Ingress,
+            &segment(ack, 1100, 5100, 100),
+            millis(75),
+        );
+        assert_eq!(metrics.out_of_order, 1);
+        assert_eq!(metrics.retransmissions, 0);
+        // The same data again after more than the RTT is a retransmission
+        let metrics = flow.update(
+            Direction::Ingress,
+            &segment(ack, 1100, 5100, 100),
+            millis(200),
+        );
+        assert_eq!(metrics.retransmissions, 1);
+    }
+}
@@ -0,0 +1,999 @@
+use crate::net::socket::SocketConnection;
+use crate::net::traffic::Direction;
+use ring::aead;
+use ring::hkdf;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Maximum number of packets inspected per connection while looking for a ClientHello.
+pub const MAX_HANDSHAKE_PACKETS: usize = 10;
+/// Maximum number of handshake bytes buffered per connection.
+pub const MAX_HANDSHAKE_BYTES: usize = 16384;
+/// Maximum number of connections with a pending handshake.
+pub const HANDSHAKE_MAP_CAPACITY: usize = 4096;
+/// Pending handshakes older than this are dropped when the map is full.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const TLS_CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const TLS_HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const TLS_EXTENSION_SERVER_NAME: u16 = 0x0000;
+const TLS_EXTENSION_ALPN: u16 = 0x0010;
+
+const QUIC_VERSION_1: u32 = 0x00000001;
+const QUIC_VERSION_2: u32 = 0x6b3343cf;
+const QUIC_V1_INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+const QUIC_V2_INITIAL_SALT: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+pub const QUIC_PORT: u16 = 443;
+
+/// Server name and application protocols announced by the client.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServerNameInfo {
+    /// SNI host name. Empty if the client did not send one.
+    pub server_name: String,
+    /// ALPN protocol names in the order offered by the client.
+    pub alpn: Vec<String>,
+    /// Whether the ClientHello was carried in QUIC Initial packets.
+    pub quic: bool,
+}
+
+impl ServerNameInfo {
+    pub fn new() -> Self {
+        ServerNameInfo {
+            server_name: String::new(),
+            alpn: Vec::new(),
+            quic: false,
+        }
+    }
+    pub fn protocol_name(&self) -> &str {
+        if self.quic {
+            "QUIC"
+        } else {
+            "TLS"
+        }
+    }
+    pub fn alpn_string(&self) -> String {
+        self.alpn.join(",")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeStatus {
+    /// More data is required.
+    Incomplete,
+    /// ClientHello parsed.
+    Complete(ServerNameInfo),
+    /// Not a TLS ClientHello.
+    Invalid,
+}
+
+/// Reassembly state for the first flight of a connection.
+#[derive(Debug, Clone)]
+pub enum HandshakeBuffer {
+    Tls(TlsStreamBuffer),
+    Quic(QuicInitialBuffer),
+}
+
+impl HandshakeBuffer {
+    fn packets(&self) -> usize {
+        match self {
+            HandshakeBuffer::Tls(buffer) => buffer.packets,
+            HandshakeBuffer::Quic(buffer) => buffer.packets,
+        }
+    }
+    fn direction(&self) -> Direction {
+        match self {
+            HandshakeBuffer::Tls(buffer) => buffer.direction,
+            HandshakeBuffer::Quic(buffer) => buffer.direction,
+        }
+    }
+    fn is_expired(&self) -> bool {
+        let started = match self {
+            HandshakeBuffer::Tls(buffer) => buffer.started,
+            HandshakeBuffer::Quic(buffer) => buffer.started,
+        };
+        started.elapsed() > HANDSHAKE_TIMEOUT
+    }
+}
+
+/// In-order TCP stream data for a TLS ClientHello.
+#[derive(Debug, Clone)]
+pub struct TlsStreamBuffer {
+    pub direction: Direction,
+    pub next_seq: u32,
+    pub data: Vec<u8>,
+    pub packets: usize,
+    pub started: Instant,
+}
+
+impl TlsStreamBuffer {
+    /// Start a buffer if the payload looks like the first record of a ClientHello.
+    pub fn start(direction: Direction, seq: u32, payload: &[u8]) -> Option<TlsStreamBuffer> {
+        if payload.len() < 6 {
+            return None;
+        }
+        if payload[0] != TLS_CONTENT_TYPE_HANDSHAKE
+            || payload[1] != 0x03
+            || payload[5] != TLS_HANDSHAKE_CLIENT_HELLO
+        {
+            return None;
+        }
+        Some(TlsStreamBuffer {
+            direction,
+            next_seq: seq.wrapping_add(payload.len() as u32),
+            data: payload.to_vec(),
+            packets: 1,
+            started: Instant::now(),
+        })
+    }
+    /// Append a segment. Retransmitted data is ignored, a gap aborts the reassembly.
+    pub fn push(&mut self, seq: u32, payload: &[u8]) -> HandshakeStatus {
+        self.packets += 1;
+        let offset = seq.wrapping_sub(self.next_seq) as i32;
+        if offset > 0 {
+            return HandshakeStatus::Invalid;
+        }
+        let overlap = offset.unsigned_abs() as usize;
+        if overlap < payload.len() {
+            self.data.extend_from_slice(&payload[overlap..]);
+            self.next_seq = seq.wrapping_add(payload.len() as u32);
+        }
+        if self.data.len() > MAX_HANDSHAKE_BYTES {
+            return HandshakeStatus::Invalid;
+        }
+        self.status()
+    }
+    pub fn status(&self) -> HandshakeStatus {
+        parse_tls_records(&self.data)
+    }
+}
+
+/// Key material for decrypting client Initial packets.
+#[derive(Debug, Clone)]
+pub struct QuicInitialKeys {
+    pub version: u32,
+    pub key: [u8; 16],
+    pub iv: [u8; 12],
+    pub hp: [u8; 16],
+}
+
+/// CRYPTO frame data collected from client Initial packets.
+#[derive(Debug, Clone)]
+pub struct QuicInitialBuffer {
+    pub direction: Direction,
+    pub keys: QuicInitialKeys,
+    pub crypto: BTreeMap<u64, Vec<u8>>,
+    pub packets: usize,
+    pub started: Instant,
+}
+
+impl QuicInitialBuffer {
+    /// Start a buffer if the datagram carries a client Initial packet.
+    /// Keys are derived from the Destination Connection ID of this first packet.
+    pub fn start(direction: Direction, datagram: &[u8]) -> Option<QuicInitialBuffer> {
+        let header = parse_initial_header(datagram)?;
+        let keys = derive_client_initial_keys(header.version, header.dcid)?;
+        let mut buffer = QuicInitialBuffer {
+            direction,
+            keys,
+            crypto: BTreeMap::new(),
+            packets: 0,
+            started: Instant::now(),
+        };
+        if !buffer.add_datagram(datagram) {
+            return None;
+        }
+        Some(buffer)
+    }
+    pub fn push(&mut self, datagram: &[u8]) -> HandshakeStatus {
+        self.add_datagram(datagram);
+        self.status()
+    }
+    pub fn status(&self) -> HandshakeStatus {
+        let mut data: Vec<u8> = Vec::new();
+        for (offset, chunk) in &self.crypto {
+            let offset = *offset as usize;
+            if offset > data.len() {
+                break;
+            }
+            if offset + chunk.len() > data.len() {
+                data.extend_from_slice(&chunk[data.len() - offset..]);
+            }
+        }
+        match parse_handshake(&data) {
+            HandshakeStatus::Complete(mut info) => {
+                info.quic = true;
+                HandshakeStatus::Complete(info)
+            }
+            status => status,
+        }
+    }
+    // A datagram may coalesce several long header packets.
+    fn add_datagram(&mut self, datagram: &[u8]) -> bool {
+        self.packets += 1;
+        let mut decrypted = false;
+        let mut pos: usize = 0;
+        while pos < datagram.len() {
+            let packet = &datagram[pos..];
+            let header = match parse_initial_header(packet) {
+                Some(header) => header,
+                None => break,
+            };
+            if header.version == self.keys.version {
+                if let Some(payload) = decrypt_initial(packet, &header, &self.keys) {
+                    decrypted = true;
+                    let mut total: usize = self.crypto.values().map(|c| c.len()).sum();
+                    for (offset, data) in parse_crypto_frames(&payload) {
+                        total += data.len();
+                        if total > MAX_HANDSHAKE_BYTES {
+                            break;
+                        }
+                        self.crypto.insert(offset, data);
+                    }
+                }
+            }
+            pos += header.packet_end;
+        }
+        decrypted
+    }
+}
+
+/// Inspect a payload of a connection and return the server name once the ClientHello is complete.
+/// Only the side that sent the first ClientHello record is followed.
+pub fn inspect_payload(
+    handshake_map: &mut HashMap<SocketConnection, HandshakeBuffer>,
+    conn: &SocketConnection,
+    direction: Direction,
+    tcp_seq: Option<u32>,
+    payload: &[u8],
+) -> Option<ServerNameInfo> {
+    if payload.is_empty() {
+        return None;
+    }
+    let status = match handshake_map.get_mut(conn) {
+        Some(buffer) => {
+            if buffer.direction() != direction {
+                return None;
+            }
+            match (buffer, tcp_seq) {
+                (HandshakeBuffer::Tls(tls), Some(seq)) => tls.push(seq, payload),
+                (HandshakeBuffer::Quic(quic), None) => quic.push(payload),
+                _ => HandshakeStatus::Invalid,
+            }
+        }
+        None => {
+            let buffer = match tcp_seq {
+                Some(seq) => {
+                    TlsStreamBuffer::start(direction, seq, payload).map(HandshakeBuffer::Tls)
+                }
+                None => {
+                    if conn.remote_port != QUIC_PORT && conn.local_port != QUIC_PORT {
+                        return None;
+                    }
+                    QuicInitialBuffer::start(direction, payload).map(HandshakeBuffer::Quic)
+                }
+            };
+            let buffer = buffer?;
+            let status = match &buffer {
+                HandshakeBuffer::Tls(tls) => tls.status(),
+                HandshakeBuffer::Quic(quic) => quic.status(),
+            };
+            if status == HandshakeStatus::Incomplete {
+                if handshake_map.len() >= HANDSHAKE_MAP_CAPACITY {
+                    handshake_map.retain(|_, buffer| !buffer.is_expired());
+                    if handshake_map.len() >= HANDSHAKE_MAP_CAPACITY {
+                        return None;
+                    }
+                }
+                handshake_map.insert(conn.clone(), buffer);
+                return None;
+            }
+            status
+        }
+    };
+    match status {
+        HandshakeStatus::Complete(info) => {
+            handshake_map.remove(conn);
+            Some(info)
+        }
+        HandshakeStatus::Invalid => {
+            handshake_map.remove(conn);
+            None
+        }
+        HandshakeStatus::Incomplete => {
+            if let Some(buffer) = handshake_map.get(conn) {
+                if buffer.packets() >= MAX_HANDSHAKE_PACKETS {
+                    handshake_map.remove(conn);
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Collect handshake messages from TLS records and parse the ClientHello.
+pub fn parse_tls_records(stream: &[u8]) -> HandshakeStatus {
+    let mut handshake: Vec<u8> = Vec::new();
+    let mut pos: usize = 0;
+    while pos + 5 <= stream.len() {
+        if stream[pos] != TLS_CONTENT_TYPE_HANDSHAKE || stream[pos + 1] != 0x03 {
+            if pos == 0 {
+                return HandshakeStatus::Invalid;
+            }
+            break;
+        }
+        let record_len = u16::from_be_bytes([stream[pos + 3], stream[pos + 4]]) as usize;
+        let record_end = pos + 5 + record_len;
+        let end = std::cmp::min(record_end, stream.len());
+        handshake.extend_from_slice(&stream[pos + 5..end]);
+        pos = record_end;
+    }
+    parse_handshake(&handshake)
+}
+
+/// Parse a ClientHello handshake message (without the record layer).
+pub fn parse_handshake(data: &[u8]) -> HandshakeStatus {
+    if data.is_empty() {
+        return HandshakeStatus::Incomplete;
+    }
+    if data[0] != TLS_HANDSHAKE_CLIENT_HELLO {
+        return HandshakeStatus::Invalid;
+    }
+    if data.len() < 4 {
+        return HandshakeStatus::Incomplete;
+    }
+    let len = u32::from_be_bytes([0, data[1], data[2], data[3]]) as usize;
+    if len > MAX_HANDSHAKE_BYTES {
+        return HandshakeStatus::Invalid;
+    }
+    if data.len() < 4 + len {
+        return HandshakeStatus::Incomplete;
+    }
+    match parse_client_hello(&data[4..4 + len]) {
+        Some(info) => HandshakeStatus::Complete(info),
+        None => HandshakeStatus::Invalid,
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+    fn read(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.remaining() < len {
+            return None;
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Some(bytes)
+    }
+    fn read_u8(&mut self) -> Option<u8> {
+        self.read(1).map(|b| b[0])
+    }
+    fn read_u16(&mut self) -> Option<u16> {
+        self.read(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn read_varint(&mut self) -> Option<u64> {
+        let first = self.read_u8()?;
+        let len = 1usize << (first >> 6);
+        let mut value = (first & 0x3f) as u64;
+        for b in self.read(len - 1)? {
+            value = (value << 8) | *b as u64;
+        }
+        Some(value)
+    }
+}
+
+fn parse_client_hello(body: &[u8]) -> Option<ServerNameInfo> {
+    let mut reader = ByteReader::new(body);
+    // legacy_version + random
+    reader.read(2 + 32)?;
+    let session_id_len = reader.read_u8()? as usize;
+    reader.read(session_id_len)?;
+    let cipher_suites_len = reader.read_u16()? as usize;
+    reader.read(cipher_suites_len)?;
+    let compression_len = reader.read_u8()? as usize;
+    reader.read(compression_len)?;
+    let mut info = ServerNameInfo::new();
+    if reader.remaining() == 0 {
+        return Some(info);
+    }
+    let extensions_len = reader.read_u16()? as usize;
+    let mut extensions = ByteReader::new(reader.read(extensions_len)?);
+    while extensions.remaining() >= 4 {
+        let ext_type = extensions.read_u16()?;
+        let ext_len = extensions.read_u16()? as usize;
+        let ext_data = extensions.read(ext_len)?;
+        match ext_type {
+            TLS_EXTENSION_SERVER_NAME => {
+                let mut ext = ByteReader::new(ext_data);
+                let list_len = ext.read_u16()? as usize;
+                let mut list = ByteReader::new(ext.read(list_len)?);
+                while list.remaining() >= 3 {
+                    let name_type = list.read_u8()?;
+                    let name_len = list.read_u16()? as usize;
+                    let name = list.read(name_len)?;
+                    // host_name(0)
+                    if name_type == 0 {
+                        info.server_name = String::from_utf8_lossy(name).to_string();
+                        break;
+                    }
+                }
+            }
+            TLS_EXTENSION_ALPN => {
+                let mut ext = ByteReader::new(ext_data);
+                let list_len = ext.read_u16()? as usize;
+                let mut list = ByteReader::new(ext.read(list_len)?);
+                while list.remaining() >= 1 {
+                    let proto_len = list.read_u8()? as usize;
+                    let proto = list.read(proto_len)?;
+                    info.alpn.push(String::from_utf8_lossy(proto).to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(info)
+}
+
+struct QuicInitialHeader<'a> {
+    version: u32,
+    dcid: &'a [u8],
+    /// Offset of the packet number field.
+    pn_offset: usize,
+    /// End of this packet within the datagram.
+    packet_end: usize,
+}
+
+fn parse_initial_header(packet: &[u8]) -> Option<QuicInitialHeader<'_>> {
+    let mut reader = ByteReader::new(packet);
+    let first = reader.read_u8()?;
+    // Long header form
+    if first & 0x80 == 0 {
+        return None;
+    }
+    let version = reader
+        .read(4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))?;
+    let packet_type = (first & 0x30) >> 4;
+    let initial = match version {
+        QUIC_VERSION_1 => packet_type == 0b00,
+        QUIC_VERSION_2 => packet_type == 0b01,
+        _ => false,
+    };
+    if !initial {
+        return None;
+    }
+    let dcid_len = reader.read_u8()? as usize;
+    if dcid_len > 20 {
+        return None;
+    }
+    let dcid = reader.read(dcid_len)?;
+    let scid_len = reader.read_u8()? as usize;
+    reader.read(scid_len)?;
+    let token_len = reader.read_varint()? as usize;
+    reader.read(token_len)?;
+    let length = reader.read_varint()? as usize;
+    let pn_offset = reader.pos;
+    let packet_end = pn_offset.checked_add(length)?;
+    if packet_end > packet.len() {
+        return None;
+    }
+    Some(QuicInitialHeader {
+        version,
+        dcid,
+        pn_offset,
+        packet_end,
+    })
+}
+
+struct HkdfLen(usize);
+
+impl hkdf::KeyType for HkdfLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn hkdf_expand_label(prk: &hkdf::Prk, label: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut info: Vec<u8> = Vec::new();
+    info.extend_from_slice(&(len as u16).to_be_bytes());
+    info.push((6 + label.len()) as u8);
+    info.extend_from_slice(b"tls13 ");
+    info.extend_from_slice(label);
+    // Empty context
+    info.push(0);
+    let info_parts = [info.as_slice()];
+    let okm = prk.expand(&info_parts, HkdfLen(len)).ok()?;
+    let mut out = vec![0u8; len];
+    okm.fill(&mut out).ok()?;
+    Some(out)
+}
+
+/// Derive the client Initial secrets (RFC 9001 Section 5.2, RFC 9369 Section 3.3).
+pub fn derive_client_initial_keys(version: u32, dcid: &[u8]) -> Option<QuicInitialKeys> {
+    let (salt, key_label, iv_label, hp_label): (&[u8], &[u8], &[u8], &[u8]) = match version {
+        QUIC_VERSION_1 => (&QUIC_V1_INITIAL_SALT, b"quic key", b"quic iv", b"quic hp"),
+        QUIC_VERSION_2 => (
+            &QUIC_V2_INITIAL_SALT,
+            b"quicv2 key",
+            b"quicv2 iv",
+            b"quicv2 hp",
+        ),
+        _ => return None,
+    };
+    let initial_secret = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(dcid);
+    let client_secret = hkdf_expand_label(&initial_secret, b"client in", 32)?;
+    let client_prk = hkdf::Prk::new_less_safe(hkdf::HKDF_SHA256, &client_secret);
+    let mut keys = QuicInitialKeys {
+        version,
+        key: [0u8; 16],
+        iv: [0u8; 12],
+        hp: [0u8; 16],
+    };
+    keys.key
+        .copy_from_slice(&hkdf_expand_label(&client_prk, key_label, 16)?);
+    keys.iv
+        .copy_from_slice(&hkdf_expand_label(&client_prk, iv_label, 12)?);
+    keys.hp
+        .copy_from_slice(&hkdf_expand_label(&client_prk, hp_label, 16)?);
+    Some(keys)
+}
+
+fn decrypt_initial(
+    packet: &[u8],
+    header: &QuicInitialHeader,
+    keys: &QuicInitialKeys,
+) -> Option<Vec<u8>> {
+    // The sample starts 4 bytes after the start of the packet number field
+    let sample_offset = header.pn_offset + 4;
+    if sample_offset + 16 > header.packet_end {
+        return None;
+    }
+    let hp_key = aead::quic::HeaderProtectionKey::new(&aead::quic::AES_128, &keys.hp).ok()?;
+    let mask = hp_key
+        .new_mask(&packet[sample_offset..sample_offset + 16])
+        .ok()?;
+    let mut header_bytes: Vec<u8> = packet[..sample_offset].to_vec();
+    header_bytes[0] ^= mask[0] & 0x0f;
+    let pn_len = (header_bytes[0] & 0x03) as usize + 1;
+    let mut packet_number: u64 = 0;
+    for i in 0..pn_len {
+        header_bytes[header.pn_offset + i] ^= mask[1 + i];
+        packet_number = (packet_number << 8) | header_bytes[header.pn_offset + i] as u64;
+    }
+    header_bytes.truncate(header.pn_offset + pn_len);
+    let mut nonce = keys.iv;
+    for (i, b) in packet_number.to_be_bytes().iter().enumerate() {
+        nonce[4 + i] ^= b;
+    }
+    let unbound_key = aead::UnboundKey::new(&aead::AES_128_GCM, &keys.key).ok()?;
+    let key = aead::LessSafeKey::new(unbound_key);
+    let mut payload: Vec<u8> = packet[header.pn_offset + pn_len..header.packet_end].to_vec();
+    let plaintext = key
+        .open_in_place(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::from(&header_bytes),
+            &mut payload,
+        )
+        .ok()?;
+    Some(plaintext.to_vec())
+}
+
+// Collect CRYPTO frames (offset, data). Parsing stops at the first unknown frame type.
+fn parse_crypto_frames(payload: &[u8]) -> Vec<(u64, Vec<u8>)> {
+    let mut frames: Vec<(u64, Vec<u8>)> = Vec::new();
+    let mut reader = ByteReader::new(payload);
+    while reader.remaining() > 0 {
+        let frame_type = match reader.read_varint() {
+            Some(frame_type) => frame_type,
+            None => break,
+        };
+        match frame_type {
+            // PADDING, PING
+            0x00 | 0x01 => {}
+            // ACK
+            0x02 | 0x03 => {
+                let mut skip_ack = || -> Option<()> {
+                    // Largest Acknowledged, ACK Delay
+                    reader.read_varint()?;
+                    reader.read_varint()?;
+                    let range_count = reader.read_varint()?;
+                    // First ACK Range
+                    reader.read_varint()?;
+                    for _ in 0..range_count {
+                        reader.read_varint()?;
+                        reader.read_varint()?;
+                    }
+                    if frame_type == 0x03 {
+                        // ECN counts
+                        reader.read_varint()?;
+                        reader.read_varint()?;
+                        reader.read_varint()?;
+                    }
+                    Some(())
+                };
+                if skip_ack().is_none() {
+                    break;
+                }
+            }
+            // CRYPTO
+            0x06 => {
+                let offset = match reader.read_varint() {
+                    Some(offset) => offset,
+                    None => break,
+                };
+                let data = match reader
+                    .read_varint()
+                    .and_then(|len| reader.read(len as usize))
+                {
+                    Some(data) => data,
+                    None => break,
+                };
+                frames.push((offset, data.to_vec()));
+            }
+            _ => break,
+        }
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::socket::TransportProtocol;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const DCID: [u8; 8] = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+    // ClientHello of RFC 9001 Appendix A.2 (SNI "example.com", ALPN "alpn")
+    const CLIENT_HELLO: &str = "
+        010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e86804fe3a47f06a2b69484c00000413011302010000
+        c000000010000e00000b6578616d706c652e636f6dff01000100000a00080006001d0017001800100007000504616c70
+        6e000500050100000000003300260024001d00209370b2c9caa47fbabaf4559fedba753de171fa71f50f1ce15d43e994
+        ec74d748002b0003020304000d0010000e0403050306030203080408050806002d00020101001c000240010039003204
+        08ffffffffffffffff05048000ffff07048000ffff0801100104800075300901100f088394c8f03e51570806048000ff
+        ff
+    ";
+
+    // Client Initial packet of RFC 9001 Appendix A.2
+    const QUIC_V1_CLIENT_INITIAL: &str = "
+        c000000001088394c8f03e5157080000449e7b9aec34d1b1c98dd7689fb8ec11d242b123dc9bd8bab936b47d92ec356c
+        0bab7df5976d27cd449f63300099f3991c260ec4c60d17b31f8429157bb35a1282a643a8d2262cad67500cadb8e7378c
+        8eb7539ec4d4905fed1bee1fc8aafba17c750e2c7ace01e6005f80fcb7df621230c83711b39343fa028cea7f7fb5ff89
+        eac2308249a02252155e2347b63d58c5457afd84d05dfffdb20392844ae812154682e9cf012f9021a6f0be17ddd0c208
+        4dce25ff9b06cde535d0f920a2db1bf362c23e596d11a4f5a6cf3948838a3aec4e15daf8500a6ef69ec4e3feb6b1d98e
+        610ac8b7ec3faf6ad760b7bad1db4ba3485e8a94dc250ae3fdb41ed15fb6a8e5eba0fc3dd60bc8e30c5c4287e53805db
+        059ae0648db2f64264ed5e39be2e20d82df566da8dd5998ccabdae053060ae6c7b4378e846d29f37ed7b4ea9ec5d82e7
+        961b7f25a9323851f681d582363aa5f89937f5a67258bf63ad6f1a0b1d96dbd4faddfcefc5266ba6611722395c906556
+        be52afe3f565636ad1b17d508b73d8743eeb524be22b3dcbc2c7468d54119c7468449a13d8e3b95811a198f3491de3e7
+        fe942b330407abf82a4ed7c1b311663ac69890f4157015853d91e923037c227a33cdd5ec281ca3f79c44546b9d90ca00
+        f064c99e3dd97911d39fe9c5d0b23a229a234cb36186c4819e8b9c5927726632291d6a418211cc2962e20fe47feb3edf
+        330f2c603a9d48c0fcb5699dbfe5896425c5bac4aee82e57a85aaf4e2513e4f05796b07ba2ee47d80506f8d2c25e50fd
+        14de71e6c418559302f939b0e1abd576f279c4b2e0feb85c1f28ff18f58891ffef132eef2fa09346aee33c28eb130ff2
+        8f5b766953334113211996d20011a198e3fc433f9f2541010ae17c1bf202580f6047472fb36857fe843b19f5984009dd
+        c324044e847a4f4a0ab34f719595de37252d6235365e9b84392b061085349d73203a4a13e96f5432ec0fd4a1ee65accd
+        d5e3904df54c1da510b0ff20dcc0c77fcb2c0e0eb605cb0504db87632cf3d8b4dae6e705769d1de354270123cb11450e
+        fc60ac47683d7b8d0f811365565fd98c4c8eb936bcab8d069fc33bd801b03adea2e1fbc5aa463d08ca19896d2bf59a07
+        1b851e6c239052172f296bfb5e72404790a2181014f3b94a4e97d117b438130368cc39dbb2d198065ae3986547926cd2
+        162f40a29f0c3c8745c0f50fba3852e566d44575c29d39a03f0cda721984b6f440591f355e12d439ff150aab7613499d
+        bd49adabc8676eef023b15b65bfc5ca06948109f23f350db82123535eb8a7433bdabcb909271a6ecbcb58b936a88cd4e
+        8f2e6ff5800175f113253d8fa9ca8885c2f552e657dc603f252e1a8e308f76f0be79e2fb8f5d5fbbe2e30ecadd220723
+        c8c0aea8078cdfcb3868263ff8f0940054da48781893a7e49ad5aff4af300cd804a6b6279ab3ff3afb64491c85194aab
+        760d58a606654f9f4400e8b38591356fbf6425aca26dc85244259ff2b19c41b9f96f3ca9ec1dde434da7d2d392b905dd
+        f3d1f9af93d1af5950bd493f5aa731b4056df31bd267b6b90a079831aaf579be0a39013137aac6d404f518cfd4684064
+        7e78bfe706ca4cf5e9c5453e9f7cfd2b8b4c8d169a44e55c88d4a9a7f9474241e221af44860018ab0856972e194cd934
+    ";
+
+    // Client Initial packet of RFC 9369 Appendix A.2
+    const QUIC_V2_CLIENT_INITIAL: &str = "
+        d76b3343cf088394c8f03e5157080000449ea0c95e82ffe67b6abcdb4298b485dd04de806071bf03dceebfa162e75d6c
+        96058bdbfb127cdfcbf903388e99ad049f9a3dd4425ae4d0992cfff18ecf0fdb5a842d09747052f17ac2053d21f57c5d
+        250f2c4f0e0202b70785b7946e992e58a59ac52dea6774d4f03b55545243cf1a12834e3f249a78d395e0d18f4d766004
+        f1a2674802a747eaa901c3f10cda5500cb9122faa9f1df66c392079a1b40f0de1c6054196a11cbea40afb6ef5253cd68
+        18f6625efce3b6def6ba7e4b37a40f7732e093daa7d52190935b8da58976ff3312ae50b187c1433c0f028edcc4c2838b
+        6a9bfc226ca4b4530e7a4ccee1bfa2a3d396ae5a3fb512384b2fdd851f784a65e03f2c4fbe11a53c7777c023462239dd
+        6f7521a3f6c7d5dd3ec9b3f233773d4b46d23cc375eb198c63301c21801f6520bcfb7966fc49b393f0061d974a2706df
+        8c4a9449f11d7f3d2dcbb90c6b877045636e7c0c0fe4eb0f697545460c806910d2c355f1d253bc9d2452aaa549e27a1f
+        ac7cf4ed77f322e8fa894b6a83810a34b361901751a6f5eb65a0326e07de7c1216ccce2d0193f958bb3850a833f7ae43
+        2b65bc5a53975c155aa4bcb4f7b2c4e54df16efaf6ddea94e2c50b4cd1dfe06017e0e9d02900cffe1935e0491d77ffb4
+        fdf85290fdd893d577b1131a610ef6a5c32b2ee0293617a37cbb08b847741c3b8017c25ca9052ca1079d8b78aebd4787
+        6d330a30f6a8c6d61dd1ab5589329de714d19d61370f8149748c72f132f0fc99f34d766c6938597040d8f9e2bb522ff9
+        9c63a344d6a2ae8aa8e51b7b90a4a806105fcbca31506c446151adfeceb51b91abfe43960977c87471cf9ad4074d30e1
+        0d6a7f03c63bd5d4317f68ff325ba3bd80bf4dc8b52a0ba031758022eb025cdd770b44d6d6cf0670f4e990b22347a7db
+        848265e3e5eb72dfe8299ad7481a408322cac55786e52f633b2fb6b614eaed18d703dd84045a274ae8bfa73379661388
+        d6991fe39b0d93debb41700b41f90a15c4d526250235ddcd6776fc77bc97e7a417ebcb31600d01e57f32162a8560cacc
+        7e27a096d37a1a86952ec71bd89a3e9a30a2a26162984d7740f81193e8238e61f6b5b984d4d3dfa033c1bb7e4f0037fe
+        bf406d91c0dccf32acf423cfa1e7071010d3f270121b493ce85054ef58bada42310138fe081adb04e2bd901f2f13458b
+        3d6758158197107c14ebb193230cd1157380aa79cae1374a7c1e5bbcb80ee23e06ebfde206bfb0fcbc0edc4ebec30966
+        1bdd908d532eb0c6adc38b7ca7331dce8dfce39ab71e7c32d318d136b6100671a1ae6a6600e3899f31f0eed19e3417d1
+        34b90c9058f8632c798d4490da4987307cba922d61c39805d072b589bd52fdf1e86215c2d54e6670e07383a27bbffb5a
+        ddf47d66aa85a0c6f9f32e59d85a44dd5d3b22dc2be80919b490437ae4f36a0ae55edf1d0b5cb4e9a3ecabee93dfc6e3
+        8d209d0fa6536d27a5d6fbb17641cde27525d61093f1b28072d111b2b4ae5f89d5974ee12e5cf7d5da4d6a31123041f3
+        3e61407e76cffcdcfd7e19ba58cf4b536f4c4938ae79324dc402894b44faf8afbab35282ab659d13c93f70412e85cb19
+        9a37ddec600545473cfb5a05e08d0b209973b2172b4d21fb69745a262ccde96ba18b2faa745b6fe189cf772a9f84cbfc
+    ";
+
+    fn hex(s: &str) -> Vec<u8> {
+        let digits: Vec<u8> = s.bytes().filter(|b| b.is_ascii_hexdigit()).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn example_info(quic: bool) -> ServerNameInfo {
+        ServerNameInfo {
+            server_name: String::from("example.com"),
+            alpn: vec![String::from("alpn")],
+            quic,
+        }
+    }
+
+    fn conn(remote_port: u16, protocol: TransportProtocol) -> SocketConnection {
+        SocketConnection {
+            interface_name: String::from("test0"),
+            local_ip_addr: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)),
+            local_port: 50000,
+            remote_ip_addr: IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34)),
+            remote_port,
+            protocol,
+        }
+    }
+
+    // Pseudo-random bytes for the garbage inputs
+    fn noise(seed: u32, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quic_v1_initial_keys() {
+        let keys = derive_client_initial_keys(QUIC_VERSION_1, &DCID).unwrap();
+        assert_eq!(keys.key.to_vec(), hex("1f369613dd76d5467730efcbe3b1a22d"));
+        assert_eq!(keys.iv.to_vec(), hex("fa044b2f42a3fd3b46fb255c"));
+        assert_eq!(keys.hp.to_vec(), hex("9f50449e04a0e810283a1e9933adedd2"));
+    }
+
+    #[test]
+    fn quic_v2_initial_keys() {
+        let keys = derive_client_initial_keys(QUIC_VERSION_2, &DCID).unwrap();
+        assert_eq!(keys.key.to_vec(), hex("8b1a0bc121284290a29e0971b5cd045d"));
+        assert_eq!(keys.iv.to_vec(), hex("91f73e2351d8fa91660e909f"));
+        assert_eq!(keys.hp.to_vec(), hex("45b95e15235d6f45a6b19cbcb0294ba9"));
+        assert!(derive_client_initial_keys(0xff00001d, &DCID).is_none());
+    }
+
+    fn check_client_initial(version: u32, packet_hex: &str) {
+        let packet = hex(packet_hex);
+        assert_eq!(packet.len(), 1200);
+        let header = parse_initial_header(&packet).unwrap();
+        assert_eq!(header.version, version);
+        assert_eq!(header.dcid, DCID);
+        assert_eq!(header.pn_offset, 18);
+        assert_eq!(header.packet_end, 1200);
+        let keys = derive_client_initial_keys(version, header.dcid).unwrap();
+        let payload = decrypt_initial(&packet, &header, &keys).unwrap();
+        // 4 byte packet number and 16 byte tag
+        assert_eq!(payload.len(), 1200 - 18 - 4 - 16);
+        // One CRYPTO frame, then PADDING
+        assert_eq!(payload[..4], [0x06, 0x00, 0x40, 0xf1]);
+        assert_eq!(payload[4..245], hex(CLIENT_HELLO)[..]);
+        assert!(payload[245..].iter().all(|b| *b == 0));
+        assert_eq!(parse_crypto_frames(&payload), vec![(0, hex(CLIENT_HELLO))]);
+        let buffer = QuicInitialBuffer::start(Direction::Egress, &packet).unwrap();
+        assert_eq!(
+            buffer.status(),
+            HandshakeStatus::Complete(example_info(true))
+        );
+    }
+
+    #[test]
+    fn quic_v1_client_initial() {
+        check_client_initial(QUIC_VERSION_1, QUIC_V1_CLIENT_INITIAL);
+    }
+
+    #[test]
+    fn quic_v2_client_initial() {
+        check_client_initial(QUIC_VERSION_2, QUIC_V2_CLIENT_INITIAL);
+    }
+
+    #[test]
+    fn quic_inspect_payload() {
+        let mut handshake_map = HashMap::new();
+        let packet = hex(QUIC_V1_CLIENT_INITIAL);
+        let info = inspect_payload(
+            &mut handshake_map,
+            &conn(443, TransportProtocol::UDP),
+            Direction::Egress,
+            None,
+            &packet,
+        );
+        assert_eq!(info, Some(example_info(true)));
+        assert!(handshake_map.is_empty());
+        // Only UDP port 443 is inspected
+        let info = inspect_payload(
+            &mut handshake_map,
+            &conn(8443, TransportProtocol::UDP),
+            Direction::Egress,
+            None,
+            &packet,
+        );
+        assert_eq!(info, None);
+    }
+
+    #[test]
+    fn quic_corrupt_or_truncated() {
+        let packet = hex(QUIC_V1_CLIENT_INITIAL);
+        // The AEAD tag does not verify
+        for pos in [22, 600, 1199] {
+            let mut corrupt = packet.clone();
+            corrupt[pos] ^= 0x01;
+            assert!(QuicInitialBuffer::start(Direction::Egress, &corrupt).is_none());
+        }
+        // A different DCID derives different keys
+        let mut corrupt = packet.clone();
+        corrupt[6] ^= 0x01;
+        assert!(QuicInitialBuffer::start(Direction::Egress, &corrupt).is_none());
+        for len in 0..packet.len() {
+            assert!(QuicInitialBuffer::start(Direction::Egress, &packet[..len]).is_none());
+        }
+        for seed in 0..1000 {
+            let mut datagram = noise(seed, 1 + seed as usize % 1300);
+            // Long header Initial of version 1 so the parser gets further
+            datagram[0] = 0xc0 | (datagram[0] & 0x0f);
+            if datagram.len() > 5 {
+                datagram[1..5].copy_from_slice(&QUIC_VERSION_1.to_be_bytes());
+            }
+            assert!(QuicInitialBuffer::start(Direction::Egress, &datagram).is_none());
+        }
+        assert!(parse_crypto_frames(&[0x06, 0x40]).is_empty());
+        assert!(parse_crypto_frames(&[0x06, 0x00, 0x05, 0x01]).is_empty());
+        // Length varint larger than the frame
+        assert!(
+            parse_crypto_frames(&[0x06, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])
+                .is_empty()
+        );
+    }
+
+    // The ClientHello in two TLS records
+    fn tls_records() -> Vec<u8> {
+        let client_hello = hex(CLIENT_HELLO);
+        let (first, second) = client_hello.split_at(100);
+        let mut stream = Vec::new();
+        for (version, fragment) in [(0x01, first), (0x03, second)] {
+            stream.extend_from_slice(&[TLS_CONTENT_TYPE_HANDSHAKE, 0x03, version]);
+            stream.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            stream.extend_from_slice(fragment);
+        }
+        stream
+    }
+
+    #[test]
+    fn tls_client_hello_across_segments_and_records() {
+        let stream = tls_records();
+        assert_eq!(
+            parse_tls_records(&stream),
+            HandshakeStatus::Complete(example_info(false))
+        );
+        let conn = conn(443, TransportProtocol::TCP);
+        let mut handshake_map = HashMap::new();
+        let seq: u32 = u32::MAX - 50;
+        // The first segment ends inside the second record
+        let (first, second) = stream.split_at(120);
+        let info = inspect_payload(
+            &mut handshake_map,
+            &conn,
+            Direction::Egress,
+            Some(seq),
+            first,
+        );
+        assert_eq!(info, None);
+        assert_eq!(handshake_map.len(), 1);
+        // Data from the other side is not part of the ClientHello
+        let info = inspect_payload(
+            &mut handshake_map,
+            &conn,
+            Direction::Ingress,
+            Some(1),
+            second,
+        );
+        assert_eq!(info, None);
+        // A retransmission of the first segment is ignored
+        let info = inspect_payload(
+            &mut handshake_map,
+            &conn,
+            Direction::Egress,
+            Some(seq),
+            first,
+        );
+        assert_eq!(info, None);
+        let next_seq = seq.wrapping_add(first.len() as u32);
+        let info = inspect_payload(
+            &mut handshake_map,
+            &conn,
+            Direction::Egress,
+            Some(next_seq),
+            second,
+        );
+        assert_eq!(info, Some(example_info(false)));
+        assert!(handshake_map.is_empty());
+    }
+
+    #[test]
+    fn tls_gap_aborts() {
+        let stream = tls_records();
+        let mut buffer = TlsStreamBuffer::start(Direction::Egress, 1000, &stream[..50]).unwrap();
+        assert_eq!(buffer.status(), HandshakeStatus::Incomplete);
+        assert_eq!(buffer.push(1060, &stream[60..]), HandshakeStatus::Invalid);
+    }
+
+    #[test]
+    fn tls_truncated_or_garbage() {
+        let stream = tls_records();
+        for len in 0..stream.len() {
+            assert_eq!(
+                parse_tls_records(&stream[..len]),
+                HandshakeStatus::Incomplete,
+                "truncated at {} bytes",
+                len
+            );
+        }
+        for len in 0..6 {
+            assert!(TlsStreamBuffer::start(Direction::Egress, 0, &stream[..len]).is_none());
+        }
+        assert!(TlsStreamBuffer::start(Direction::Egress, 0, b"GET / HTTP/1.1\r\n").is_none());
+        assert_eq!(
+            parse_tls_records(b"GET / HTTP/1.1\r\n"),
+            HandshakeStatus::Invalid
+        );
+        // ServerHello
+        assert_eq!(parse_handshake(&[0x02, 0, 0, 0]), HandshakeStatus::Invalid);
+        // Length over the limit
+        assert_eq!(
+            parse_handshake(&[0x01, 0xff, 0xff, 0xff]),
+            HandshakeStatus::Invalid
+        );
+        // Lengths inside the ClientHello that run past its end
+        let client_hello = hex(CLIENT_HELLO);
+        for pos in 4..client_hello.len() {
+            let mut corrupt = client_hello.clone();
+            corrupt[pos] = 0xff;
+            let _ = parse_handshake(&corrupt);
+        }
+        let mut corrupt = client_hello.clone();
+        // Session ID length
+        corrupt[4 + 34] = 0xff;
+        assert_eq!(parse_handshake(&corrupt), HandshakeStatus::Invalid);
+        for seed in 0..1000 {
+            let mut data = noise(seed, seed as usize % 600);
+            if !data.is_empty() {
+                data[0] = TLS_HANDSHAKE_CLIENT_HELLO;
+            }
+            // Any result, but no panic
+            let _ = parse_handshake(&data);
+            let _ = parse_tls_records(&data);
+        }
+    }
+}
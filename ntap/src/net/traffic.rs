@@ -2,6 +2,9 @@ use std::time::{Duration, SystemTime};
 
 use serde::{Deserialize, Serialize};
 
+use super::history::BandwidthHistory;
+use super::tcp::TcpMetrics;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Hash, Eq, Clone, PartialOrd, Ord, Copy)]
 pub enum Direction {
     Egress,
@@ -20,6 +23,9 @@ pub struct TrafficInfo {
     pub ingress_bytes_per_sec: usize,
     pub first_seen: SystemTime,
     pub last_seen: SystemTime,
+    /// Passive TCP metrics. Only set for connections and remote hosts.
+    #[serde(default)]
+    pub tcp: TcpMetrics,
 }
 
 impl TrafficInfo {
@@ -35,6 +41,7 @@ impl TrafficInfo {
             ingress_bytes_per_sec: 0,
             first_seen: SystemTime::now(),
             last_seen: SystemTime::now(),
+            tcp: TcpMetrics::default(),
         }
     }
     pub fn add_traffic(&mut self, traffic: &TrafficInfo) {
@@ -42,6 +49,7 @@ impl TrafficInfo {
         self.packet_received += traffic.packet_received;
         self.bytes_sent += traffic.bytes_sent;
         self.bytes_received += traffic.bytes_received;
+        self.tcp.add(&traffic.tcp);
         self.last_seen = SystemTime::now();
     }
     pub fn update_egress_packets_per_sec(&mut self, sent_packets: usize, duration: Duration) {
@@ -127,6 +135,35 @@ impl TrafficInfo {
     }
 }
 
+/// Traffic of one captured interface with its bandwidth history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InterfaceTrafficInfo {
+    pub traffic: TrafficInfo,
+    pub history: BandwidthHistory,
+}
+
+impl InterfaceTrafficInfo {
+    pub fn new() -> Self {
+        InterfaceTrafficInfo {
+            traffic: TrafficInfo::new(),
+            history: BandwidthHistory::new(),
+        }
+    }
+    /// Add the traffic of one tick and record the bandwidth.
+    pub fn add_tick(&mut self, traffic: &TrafficInfo, duration: Duration) {
+        self.traffic.update_bytes_per_sec(traffic, duration);
+        self.traffic.add_traffic(traffic);
+        self.history
+            .add(traffic.bytes_received, traffic.bytes_sent, duration);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InterfaceTrafficDisplayInfo {
+    pub interface_name: String,
+    pub traffic: TrafficDisplayInfo,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TrafficDisplayInfo {
     pub packet_sent: usize,
@@ -144,6 +181,9 @@ pub struct TrafficDisplayInfo {
     pub formatted_ingress_packets_per_sec: String,
     pub formatted_egress_bytes_per_sec: String,
     pub formatted_ingress_bytes_per_sec: String,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    pub tcp: TcpMetrics,
 }
 
 impl TrafficDisplayInfo {
@@ -164,6 +204,9 @@ impl TrafficDisplayInfo {
             formatted_ingress_packets_per_sec: String::new(),
             formatted_egress_bytes_per_sec: String::new(),
             formatted_ingress_bytes_per_sec: String::new(),
+            first_seen: SystemTime::now(),
+            last_seen: SystemTime::now(),
+            tcp: TcpMetrics::default(),
         }
     }
     pub fn from_traffic(traffic: &TrafficInfo) -> Self {
@@ -183,6 +226,9 @@ impl TrafficDisplayInfo {
             formatted_ingress_packets_per_sec: traffic.formatted_ingress_packets_per_sec(),
             formatted_egress_bytes_per_sec: traffic.formatted_egress_bytes_per_sec(),
             formatted_ingress_bytes_per_sec: traffic.formatted_ingress_bytes_per_sec(),
+            first_seen: traffic.first_seen,
+            last_seen: traffic.last_seen,
+            tcp: traffic.tcp.clone(),
         }
     }
 }
@@ -0,0 +1,923 @@
+use crate::config::{ExportConfig, FlowProtocol};
+use crate::net::socket::{SocketConnection, TransportProtocol};
+use crate::net::stat::{NetStatData, NetStatStrage};
+use crate::net::traffic::Direction;
+use crate::thread_log;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Upper bound of one export packet, below the usual 1500 byte MTU.
+pub const MAX_PACKET_SIZE: usize = 1400;
+
+const NETFLOW_V9_VERSION: u16 = 9;
+const IPFIX_VERSION: u16 = 10;
+const NETFLOW_V9_HEADER_LEN: usize = 20;
+const IPFIX_HEADER_LEN: usize = 16;
+const NETFLOW_V9_TEMPLATE_SET_ID: u16 = 0;
+const IPFIX_TEMPLATE_SET_ID: u16 = 2;
+const SET_HEADER_LEN: usize = 4;
+const IPV4_TEMPLATE_ID: u16 = 256;
+const IPV6_TEMPLATE_ID: u16 = 257;
+
+// Field types. The NetFlow v9 numbers are the same as the IPFIX information elements.
+const IE_OCTET_DELTA_COUNT: u16 = 1;
+const IE_PACKET_DELTA_COUNT: u16 = 2;
+const IE_PROTOCOL_IDENTIFIER: u16 = 4;
+const IE_SOURCE_TRANSPORT_PORT: u16 = 7;
+const IE_SOURCE_IPV4_ADDRESS: u16 = 8;
+const IE_INGRESS_INTERFACE: u16 = 10;
+const IE_DESTINATION_TRANSPORT_PORT: u16 = 11;
+const IE_DESTINATION_IPV4_ADDRESS: u16 = 12;
+const IE_EGRESS_INTERFACE: u16 = 14;
+const IE_BGP_SOURCE_AS_NUMBER: u16 = 16;
+const IE_BGP_DESTINATION_AS_NUMBER: u16 = 17;
+const IE_FLOW_END_SYS_UP_TIME: u16 = 21;
+const IE_FLOW_START_SYS_UP_TIME: u16 = 22;
+const IE_SOURCE_IPV6_ADDRESS: u16 = 27;
+const IE_DESTINATION_IPV6_ADDRESS: u16 = 28;
+const IE_FLOW_DIRECTION: u16 = 61;
+const IE_FLOW_START_MILLISECONDS: u16 = 152;
+const IE_FLOW_END_MILLISECONDS: u16 = 153;
+
+/// Key of a unidirectional flow. A connection has up to one flow per direction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub conn: SocketConnection,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlowRecord {
+    pub key: FlowKey,
+    pub packets: u64,
+    pub bytes: u64,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// ASN of the remote host. 0 if unknown.
+    pub remote_asn: u32,
+}
+
+impl FlowRecord {
+    fn is_ipv6(&self) -> bool {
+        self.key.conn.remote_ip_addr.is_ipv6()
+    }
+    // Source and destination socket in the direction of the flow
+    fn endpoints(&self) -> ((IpAddr, u16), (IpAddr, u16)) {
+        let conn = &self.key.conn;
+        let local = (conn.local_ip_addr, conn.local_port);
+        let remote = (conn.remote_ip_addr, conn.remote_port);
+        match self.key.direction {
+            Direction::Egress => (local, remote),
+            Direction::Ingress => (remote, local),
+        }
+    }
+}
+
+/// Flows built from the per-tick connection traffic.
+pub struct FlowCache {
+    flows: HashMap<FlowKey, FlowRecord>,
+    active_timeout: Duration,
+    inactive_timeout: Duration,
+    /// Local port and collector of the export socket. Its own traffic is not exported.
+    export_socket: Option<(u16, SocketAddr)>,
+}
+
+impl FlowCache {
+    pub fn new(active_timeout: Duration, inactive_timeout: Duration) -> Self {
+        FlowCache {
+            flows: HashMap::new(),
+            active_timeout,
+            inactive_timeout,
+            export_socket: None,
+        }
+    }
+    pub fn set_export_socket(&mut self, local_port: u16, collector: SocketAddr) {
+        self.export_socket = Some((local_port, collector));
+    }
+    fn is_export_traffic(&self, conn: &SocketConnection) -> bool {
+        match self.export_socket {
+            Some((local_port, collector)) => {
+                conn.protocol == TransportProtocol::UDP
+                    && conn.local_port == local_port
+                    && conn.remote_port == collector.port()
+                    && conn.remote_ip_addr == collector.ip()
+            }
+            None => false,
+        }
+    }
+    /// Add the traffic of one tick.
+    /// netstat_data is the accumulated data, used for the ASN of the remote host.
+    pub fn update(&mut self, delta: &NetStatData, netstat_data: &NetStatData, now: SystemTime) {
+        for (conn, traffic) in &delta.connection_map {
+            // A record holds both addresses in one template
+            if conn.local_ip_addr.is_ipv6() != conn.remote_ip_addr.is_ipv6() {
+                continue;
+            }
+            if self.is_export_traffic(conn) {
+                continue;
+            }
+            let remote_asn = netstat_data
+                .remote_hosts
+                .get(&conn.remote_ip_addr)
+                .map_or(0, |host| host.asn);
+            let directions = [
+                (
+                    Direction::Ingress,
+                    traffic.packet_received,
+                    traffic.bytes_received,
+                ),
+                (Direction::Egress, traffic.packet_sent, traffic.bytes_sent),
+            ];
+            for (direction, packets, bytes) in directions {
+                if packets == 0 {
+                    continue;
+                }
+                let key = FlowKey {
+                    conn: conn.clone(),
+                    direction,
+                };
+                let flow = self.flows.entry(key.clone()).or_insert(FlowRecord {
+                    key,
+                    packets: 0,
+                    bytes: 0,
+                    start: traffic.first_seen,
+                    end: now,
+                    remote_asn,
+                });
+                flow.packets += packets as u64;
+                flow.bytes += bytes as u64;
+                flow.end = now;
+                if flow.remote_asn == 0 {
+                    flow.remote_asn = remote_asn;
+                }
+            }
+        }
+    }
+    /// Remove and return the flows that are idle, active for too long, or whose TCP connection closed.
+    pub fn expire(&mut self, netstat_data: &NetStatData, now: SystemTime) -> Vec<FlowRecord> {
+        let expired: Vec<FlowKey> = self
+            .flows
+            .values()
+            .filter(|flow| {
+                let idle = now.duration_since(flow.end).unwrap_or(Duration::ZERO);
+                let age = now.duration_since(flow.start).unwrap_or(Duration::ZERO);
+                let closed = match netstat_data.tcp_flow_map.get(&flow.key.conn) {
+                    Some(tcp_flow) => tcp_flow.is_closed(),
+                    None => false,
+                };
+                idle >= self.inactive_timeout || age >= self.active_timeout || closed
+            })
+            .map(|flow| flow.key.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|key| self.flows.remove(key))
+            .collect()
+    }
+}
+
+// Template fields (field type, length) in record order
+fn template_fields(protocol: FlowProtocol, ipv6: bool) -> Vec<(u16, u16)> {
+    let (source_address, destination_address, address_len) = if ipv6 {
+        (IE_SOURCE_IPV6_ADDRESS, IE_DESTINATION_IPV6_ADDRESS, 16)
+    } else {
+        (IE_SOURCE_IPV4_ADDRESS, IE_DESTINATION_IPV4_ADDRESS, 4)
+    };
+    let mut fields = vec![
+        (source_address, address_len),
+        (destination_address, address_len),
+        (IE_SOURCE_TRANSPORT_PORT, 2),
+        (IE_DESTINATION_TRANSPORT_PORT, 2),
+        (IE_PROTOCOL_IDENTIFIER, 1),
+        (IE_OCTET_DELTA_COUNT, 8),
+        (IE_PACKET_DELTA_COUNT, 8),
+        (IE_INGRESS_INTERFACE, 4),
+        (IE_EGRESS_INTERFACE, 4),
+        (IE_BGP_SOURCE_AS_NUMBER, 4),
+        (IE_BGP_DESTINATION_AS_NUMBER, 4),
+        (IE_FLOW_DIRECTION, 1),
+    ];
+    // NetFlow v9 times are relative to the exporter uptime, IPFIX times are absolute
+    match protocol {
+        FlowProtocol::NetflowV9 => {
+            fields.push((IE_FLOW_START_SYS_UP_TIME, 4));
+            fields.push((IE_FLOW_END_SYS_UP_TIME, 4));
+        }
+        FlowProtocol::Ipfix => {
+            fields.push((IE_FLOW_START_MILLISECONDS, 8));
+            fields.push((IE_FLOW_END_MILLISECONDS, 8));
+        }
+    }
+    fields
+}
+
+fn record_len(protocol: FlowProtocol, ipv6: bool) -> usize {
+    template_fields(protocol, ipv6)
+        .iter()
+        .map(|(_, len)| *len as usize)
+        .sum()
+}
+
+// One export packet being built
+struct ExportPacket {
+    sets: Vec<u8>,
+    /// Template and data records (NetFlow v9 header count)
+    records: u16,
+    data_records: u32,
+}
+
+impl ExportPacket {
+    fn new() -> Self {
+        ExportPacket {
+            sets: Vec::new(),
+            records: 0,
+            data_records: 0,
+        }
+    }
+}
+
+/// Encodes flows into NetFlow v9 or IPFIX packets.
+pub struct FlowEncoder {
+    protocol: FlowProtocol,
+    observation_domain_id: u32,
+    template_interval: Duration,
+    /// Packets sent (NetFlow v9) or data records sent (IPFIX)
+    sequence: u32,
+    /// Exporter start, the zero of the NetFlow v9 uptime
+    boot_time: SystemTime,
+    last_template: Option<Instant>,
+    /// Interface Name -> Interface Index
+    if_index_map: HashMap<String, u32>,
+}
+
+impl FlowEncoder {
+    pub fn new(config: &ExportConfig) -> Self {
+        let if_index_map = netdev::get_interfaces()
+            .into_iter()
+            .map(|iface| (iface.name, iface.index))
+            .collect();
+        FlowEncoder {
+            protocol: config.protocol,
+            observation_domain_id: config.observation_domain_id,
+            template_interval: Duration::from_secs(config.template_interval),
+            sequence: 0,
+            boot_time: SystemTime::now(),
+            last_template: None,
+            if_index_map,
+        }
+    }
+    fn header_len(&self) -> usize {
+        match self.protocol {
+            FlowProtocol::NetflowV9 => NETFLOW_V9_HEADER_LEN,
+            FlowProtocol::Ipfix => IPFIX_HEADER_LEN,
+        }
+    }
+    /// Encode the flows into export packets. The templates are sent first when they are due,
+    /// also if there is no flow.
+    pub fn encode(&mut self, flows: &[FlowRecord], now: SystemTime) -> Vec<Vec<u8>> {
+        let mut packets: Vec<Vec<u8>> = Vec::new();
+        let mut packet = ExportPacket::new();
+        let template_due = match self.last_template {
+            Some(last_template) => last_template.elapsed() >= self.template_interval,
+            None => true,
+        };
+        if template_due {
+            self.write_template_set(&mut packet);
+            self.last_template = Some(Instant::now());
+        }
+        for ipv6 in [false, true] {
+            let group: Vec<&FlowRecord> =
+                flows.iter().filter(|flow| flow.is_ipv6() == ipv6).collect();
+            let record_len = record_len(self.protocol, ipv6);
+            let mut remaining = group.as_slice();
+            while !remaining.is_empty() {
+                // Room for a data set with padding in the current packet
+                let used = self.header_len() + packet.sets.len() + SET_HEADER_LEN + 3;
+                let count = MAX_PACKET_SIZE.saturating_sub(used) / record_len;
+                if count == 0 {
+                    packets.push(self.finish_packet(packet, now));
+                    packet = ExportPacket::new();
+                    continue;
+                }
+                let count = count.min(remaining.len());
+                self.write_data_set(&mut packet, &remaining[..count], ipv6);
+                remaining = &remaining[count..];
+            }
+        }
+        if packet.records > 0 {
+            packets.push(self.finish_packet(packet, now));
+        }
+        packets
+    }
+    fn write_template_set(&self, packet: &mut ExportPacket) {
+        let set_id = match self.protocol {
+            FlowProtocol::NetflowV9 => NETFLOW_V9_TEMPLATE_SET_ID,
+            FlowProtocol::Ipfix => IPFIX_TEMPLATE_SET_ID,
+        };
+        let mut body: Vec<u8> = Vec::new();
+        for (template_id, ipv6) in [(IPV4_TEMPLATE_ID, false), (IPV6_TEMPLATE_ID, true)] {
+            let fields = template_fields(self.protocol, ipv6);
+            body.extend_from_slice(&template_id.to_be_bytes());
+            body.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+            for (field_type, len) in fields {
+                body.extend_from_slice(&field_type.to_be_bytes());
+                body.extend_from_slice(&len.to_be_bytes());
+            }
+            packet.records += 1;
+        }
+        write_set(&mut packet.sets, set_id, &body);
+    }
+    fn write_data_set(&self, packet: &mut ExportPacket, flows: &[&FlowRecord], ipv6: bool) {
+        let template_id = if ipv6 {
+            IPV6_TEMPLATE_ID
+        } else {
+            IPV4_TEMPLATE_ID
+        };
+        let mut body: Vec<u8> = Vec::new();
+        for flow in flows {
+            self.write_record(&mut body, flow);
+            packet.records += 1;
+            packet.data_records += 1;
+        }
+        // Pad the set to a 4 byte boundary
+        while !(SET_HEADER_LEN + body.len()).is_multiple_of(4) {
+            body.push(0);
+        }
+        write_set(&mut packet.sets, template_id, &body);
+    }
+    fn write_record(&self, body: &mut Vec<u8>, flow: &FlowRecord) {
+        let ((source_addr, source_port), (destination_addr, destination_port)) = flow.endpoints();
+        write_ip_addr(body, source_addr);
+        write_ip_addr(body, destination_addr);
+        body.extend_from_slice(&source_port.to_be_bytes());
+        body.extend_from_slice(&destination_port.to_be_bytes());
+        let protocol: u8 = match flow.key.conn.protocol {
+            TransportProtocol::TCP => 6,
+            TransportProtocol::UDP => 17,
+        };
+        body.push(protocol);
+        body.extend_from_slice(&flow.bytes.to_be_bytes());
+        body.extend_from_slice(&flow.packets.to_be_bytes());
+        let if_index = self
+            .if_index_map
+            .get(&flow.key.conn.interface_name)
+            .copied()
+            .unwrap_or(0);
+        // The remote side is the source of ingress flows and the destination of egress flows
+        let (ingress_interface, egress_interface, source_as, destination_as, flow_direction) =
+            match flow.key.direction {
+                Direction::Ingress => (if_index, 0, flow.remote_asn, 0, 0u8),
+                Direction::Egress => (0, if_index, 0, flow.remote_asn, 1u8),
+            };
+        body.extend_from_slice(&ingress_interface.to_be_bytes());
+        body.extend_from_slice(&egress_interface.to_be_bytes());
+        body.extend_from_slice(&source_as.to_be_bytes());
+        body.extend_from_slice(&destination_as.to_be_bytes());
+        body.push(flow_direction);
+        match self.protocol {
+            FlowProtocol::NetflowV9 => {
+                body.extend_from_slice(&self.uptime_millis(flow.start).to_be_bytes());
+                body.extend_from_slice(&self.uptime_millis(flow.end).to_be_bytes());
+            }
+            FlowProtocol::Ipfix => {
+                body.extend_from_slice(&unix_millis(flow.start).to_be_bytes());
+                body.extend_from_slice(&unix_millis(flow.end).to_be_bytes());
+            }
+        }
+    }
+    // Milliseconds since the exporter start. Wraps after about 49 days as in NetFlow v9.
+    fn uptime_millis(&self, time: SystemTime) -> u32 {
+        time.duration_since(self.boot_time)
+            .unwrap_or(Duration::ZERO)
+            .as_millis() as u32
+    }
+    fn finish_packet(&mut self, packet: ExportPacket, now: SystemTime) -> Vec<u8> {
+        let export_secs = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs() as u32;
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.header_len() + packet.sets.len());
+        match self.protocol {
+            FlowProtocol::NetflowV9 => {
+                bytes.extend_from_slice(&NETFLOW_V9_VERSION.to_be_bytes());
+                bytes.extend_from_slice(&packet.records.to_be_bytes());
+                bytes.extend_from_slice(&self.uptime_millis(now).to_be_bytes());
+                bytes.extend_from_slice(&export_secs.to_be_bytes());
+                bytes.extend_from_slice(&self.sequence.to_be_bytes());
+                bytes.extend_from_slice(&self.observation_domain_id.to_be_bytes());
+                self.sequence = self.sequence.wrapping_add(1);
+            }
+            FlowProtocol::Ipfix => {
+                let len = (IPFIX_HEADER_LEN + packet.sets.len()) as u16;
+                bytes.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+                bytes.extend_from_slice(&len.to_be_bytes());
+                bytes.extend_from_slice(&export_secs.to_be_bytes());
+                bytes.extend_from_slice(&self.sequence.to_be_bytes());
+                bytes.extend_from_slice(&self.observation_domain_id.to_be_bytes());
+                self.sequence = self.sequence.wrapping_add(packet.data_records);
+            }
+        }
+        bytes.extend_from_slice(&packet.sets);
+        bytes
+    }
+}
+
+fn write_set(out: &mut Vec<u8>, set_id: u16, body: &[u8]) {
+    out.extend_from_slice(&set_id.to_be_bytes());
+    out.extend_from_slice(&((SET_HEADER_LEN + body.len()) as u16).to_be_bytes());
+    out.extend_from_slice(body);
+}
+
+fn write_ip_addr(out: &mut Vec<u8>, ip_addr: IpAddr) {
+    match ip_addr {
+        IpAddr::V4(ipv4) => out.extend_from_slice(&ipv4.octets()),
+        IpAddr::V6(ipv6) => out.extend_from_slice(&ipv6.octets()),
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as u64
+}
+
+/// Build flows from the captured traffic at each interval and send them to the collector.
+/// Blocks the current thread.
+pub fn start_flow_export(
+    netstat_strage: &mut Arc<NetStatStrage>,
+    config: &ExportConfig,
+    collector: SocketAddr,
+    interval: Duration,
+    entry_ttl: Duration,
+) -> io::Result<()> {
+    let bind_addr = if collector.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.connect(collector)?;
+    thread_log!(
+        info,
+        "exporting {} flows to {}",
+        config.protocol.name(),
+        collector
+    );
+    let mut cache = FlowCache::new(
+        Duration::from_secs(config.active_timeout),
+        Duration::from_secs(config.inactive_timeout),
+    );
+    cache.set_export_socket(socket.local_addr()?.port(), collector);
+    let mut encoder = FlowEncoder::new(config);
+    let mut netstat_data = NetStatData::new();
+    let mut last_clear = Instant::now();
+    loop {
+        thread::sleep(interval);
+        if last_clear.elapsed() >= entry_ttl {
+            netstat_data.remove_old_entries(entry_ttl);
+            last_clear = Instant::now();
+        }
+        let delta = netstat_strage.clone_data_and_reset();
+        netstat_data.merge(delta.clone(), interval);
+        let now = SystemTime::now();
+        cache.update(&delta, &netstat_data, now);
+        let flows = cache.expire(&netstat_data, now);
+        for packet in encoder.encode(&flows, now) {
+            if let Err(e) = socket.send(&packet) {
+                thread_log!(error, "flow export send error: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::host::RemoteHostInfo;
+    use crate::net::tcp::{TcpFlowInfo, TcpSegment};
+    use crate::net::traffic::TrafficInfo;
+    use nex::packet::tcp::TcpFlags;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const LOCAL_V4: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+    const REMOTE_V4: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+    const LOCAL_V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x10));
+    const REMOTE_V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2606, 0x4700, 0, 0, 0, 0, 0, 0x1111));
+
+    fn config(protocol: FlowProtocol) -> ExportConfig {
+        let mut config = ExportConfig::new();
+        config.protocol = protocol;
+        config.observation_domain_id = 7;
+        config
+    }
+
+    fn conn(
+        local_ip_addr: IpAddr,
+        local_port: u16,
+        remote_ip_addr: IpAddr,
+        protocol: TransportProtocol,
+    ) -> SocketConnection {
+        SocketConnection {
+            interface_name: String::from("test0"),
+            local_ip_addr,
+            local_port,
+            remote_ip_addr,
+            remote_port: 443,
+            protocol,
+        }
+    }
+
+    fn traffic(
+        first_seen: SystemTime,
+        sent: (usize, usize),
+        received: (usize, usize),
+    ) -> TrafficInfo {
+        let mut traffic = TrafficInfo::new();
+        traffic.first_seen = first_seen;
+        (traffic.packet_sent, traffic.bytes_sent) = sent;
+        (traffic.packet_received, traffic.bytes_received) = received;
+        traffic
+    }
+
+    // Receive every packet through a local UDP socket as the collector would
+    fn send_to_collector(packets: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+        exporter.connect(collector.local_addr().unwrap()).unwrap();
+        let mut received = Vec::new();
+        for packet in packets {
+            exporter.send(packet).unwrap();
+            let mut buf = [0u8; 65535];
+            let (len, from) = collector.recv_from(&mut buf).unwrap();
+            assert_eq!(from, exporter.local_addr().unwrap());
+            received.push(buf[..len].to_vec());
+        }
+        received
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_be_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    struct Header {
+        version: u16,
+        /// Record count (NetFlow v9) or packet length (IPFIX)
+        count_or_len: u16,
+        sequence: u32,
+        domain_id: u32,
+    }
+
+    // Split a packet into the header and its (set id, set body) pairs
+    fn parse_packet(packet: &[u8]) -> (Header, Vec<(u16, Vec<u8>)>) {
+        let version = u16_at(packet, 0);
+        let (header, header_len) = match version {
+            NETFLOW_V9_VERSION => (
+                Header {
+                    version,
+                    count_or_len: u16_at(packet, 2),
+                    sequence: u32_at(packet, 12),
+                    domain_id: u32_at(packet, 16),
+                },
+                NETFLOW_V9_HEADER_LEN,
+            ),
+            IPFIX_VERSION => (
+                Header {
+                    version,
+                    count_or_len: u16_at(packet, 2),
+                    sequence: u32_at(packet, 8),
+                    domain_id: u32_at(packet, 12),
+                },
+                IPFIX_HEADER_LEN,
+            ),
+            _ => panic!("unknown version {}", version),
+        };
+        let mut sets = Vec::new();
+        let mut offset = header_len;
+        while offset < packet.len() {
+            let set_id = u16_at(packet, offset);
+            let set_len = u16_at(packet, offset + 2) as usize;
+            assert_eq!(set_len % 4, 0, "set {} is not padded", set_id);
+            sets.push((
+                set_id,
+                packet[offset + SET_HEADER_LEN..offset + set_len].to_vec(),
+            ));
+            offset += set_len;
+        }
+        assert_eq!(offset, packet.len());
+        (header, sets)
+    }
+
+    // Template ID -> field list of a template set
+    fn parse_templates(body: &[u8]) -> HashMap<u16, Vec<(u16, u16)>> {
+        let mut templates = HashMap::new();
+        let mut offset = 0;
+        while offset < body.len() {
+            let template_id = u16_at(body, offset);
+            let field_count = u16_at(body, offset + 2) as usize;
+            offset += 4;
+            let fields = (0..field_count)
+                .map(|i| {
+                    (
+                        u16_at(body, offset + i * 4),
+                        u16_at(body, offset + i * 4 + 2),
+                    )
+                })
+                .collect();
+            offset += field_count * 4;
+            templates.insert(template_id, fields);
+        }
+        templates
+    }
+
+    // Field type -> value bytes of each record in a data set. Padding is dropped.
+    fn parse_records(fields: &[(u16, u16)], body: &[u8]) -> Vec<HashMap<u16, Vec<u8>>> {
+        let record_len: usize = fields.iter().map(|(_, len)| *len as usize).sum();
+        body.chunks_exact(record_len)
+            .map(|record| {
+                let mut offset = 0;
+                fields
+                    .iter()
+                    .map(|(field_type, len)| {
+                        let value = record[offset..offset + *len as usize].to_vec();
+                        offset += *len as usize;
+                        (*field_type, value)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // One TCP connection in both directions, one IPv6 UDP connection with egress traffic only
+    fn cache_with_flows(start: SystemTime) -> (FlowCache, NetStatData) {
+        let mut cache = FlowCache::new(Duration::from_secs(60), Duration::from_secs(15));
+        let mut netstat_data = NetStatData::new();
+        let mut host = RemoteHostInfo::new(String::new(), REMOTE_V4);
+        host.asn = 13335;
+        netstat_data.remote_hosts.insert(REMOTE_V4, host);
+        let mut delta = NetStatData::new();
+        delta.connection_map.insert(
+            conn(LOCAL_V4, 50000, REMOTE_V4, TransportProtocol::TCP),
+            traffic(start, (10, 1000), (20, 30000)),
+        );
+        delta.connection_map.insert(
+            conn(LOCAL_V6, 50001, REMOTE_V6, TransportProtocol::UDP),
+            traffic(start, (3, 300), (0, 0)),
+        );
+        cache.update(&delta, &netstat_data, start + Duration::from_secs(1));
+        cache.update(&delta, &netstat_data, start + Duration::from_secs(2));
+        (cache, netstat_data)
+    }
+
+    fn check_export(protocol: FlowProtocol) {
+        let start = SystemTime::now();
+        let (mut cache, netstat_data) = cache_with_flows(start);
+        // Nothing is due before the inactive timeout
+        assert!(cache
+            .expire(&netstat_data, start + Duration::from_secs(3))
+            .is_empty());
+        let now = start + Duration::from_secs(30);
+        let mut flows = cache.expire(&netstat_data, now);
+        assert_eq!(flows.len(), 3);
+        assert!(cache.expire(&netstat_data, now).is_empty());
+        flows.sort_by_key(|flow| (flow.is_ipv6(), flow.key.direction == Direction::Egress));
+
+        let mut encoder = FlowEncoder::new(&config(protocol));
+        let packets = send_to_collector(&encoder.encode(&flows, now));
+        assert_eq!(packets.len(), 1);
+        let (header, sets) = parse_packet(&packets[0]);
+        let template_set_id = match protocol {
+            FlowProtocol::NetflowV9 => {
+                assert_eq!(header.version, 9);
+                // 2 template records and 3 data records
+                assert_eq!(header.count_or_len, 5);
+                NETFLOW_V9_TEMPLATE_SET_ID
+            }
+            FlowProtocol::Ipfix => {
+                assert_eq!(header.version, 10);
+                assert_eq!(header.count_or_len as usize, packets[0].len());
+                IPFIX_TEMPLATE_SET_ID
+            }
+        };
+        assert_eq!(header.sequence, 0);
+        assert_eq!(header.domain_id, 7);
+        let set_ids: Vec<u16> = sets.iter().map(|(set_id, _)| *set_id).collect();
+        assert_eq!(
+            set_ids,
+            vec![template_set_id, IPV4_TEMPLATE_ID, IPV6_TEMPLATE_ID]
+        );
+
+        let templates = parse_templates(&sets[0].1);
+        let v4_fields = &templates[&IPV4_TEMPLATE_ID];
+        let v6_fields = &templates[&IPV6_TEMPLATE_ID];
+        assert_eq!(v4_fields, &template_fields(protocol, false));
+        assert_eq!(v6_fields, &template_fields(protocol, true));
+        assert!(v4_fields.contains(&(IE_SOURCE_IPV4_ADDRESS, 4)));
+        assert!(v6_fields.contains(&(IE_SOURCE_IPV6_ADDRESS, 16)));
+        let (start_field, end_field) = match protocol {
+            FlowProtocol::NetflowV9 => {
+                ((IE_FLOW_START_SYS_UP_TIME, 4), (IE_FLOW_END_SYS_UP_TIME, 4))
+            }
+            FlowProtocol::Ipfix => (
+                (IE_FLOW_START_MILLISECONDS, 8),
+                (IE_FLOW_END_MILLISECONDS, 8),
+            ),
+        };
+        assert!(v4_fields.contains(&start_field) && v4_fields.contains(&end_field));
+
+        // Ingress then egress of the TCP connection
+        let v4_records = parse_records(v4_fields, &sets[1].1);
+        assert_eq!(v4_records.len(), 2);
+        let ingress = &v4_records[0];
+        assert_eq!(ingress[&IE_SOURCE_IPV4_ADDRESS], vec![1, 1, 1, 1]);
+        assert_eq!(ingress[&IE_DESTINATION_IPV4_ADDRESS], vec![192, 168, 1, 10]);
+        assert_eq!(u16_at(&ingress[&IE_SOURCE_TRANSPORT_PORT], 0), 443);
+        assert_eq!(u16_at(&ingress[&IE_DESTINATION_TRANSPORT_PORT], 0), 50000);
+        assert_eq!(ingress[&IE_PROTOCOL_IDENTIFIER], vec![6]);
+        assert_eq!(u64_at(&ingress[&IE_PACKET_DELTA_COUNT], 0), 40);
+        assert_eq!(u64_at(&ingress[&IE_OCTET_DELTA_COUNT], 0), 60000);
+        assert_eq!(u32_at(&ingress[&IE_BGP_SOURCE_AS_NUMBER], 0), 13335);
+        assert_eq!(u32_at(&ingress[&IE_BGP_DESTINATION_AS_NUMBER], 0), 0);
+        assert_eq!(ingress[&IE_FLOW_DIRECTION], vec![0]);
+        let egress = &v4_records[1];
+        assert_eq!(egress[&IE_SOURCE_IPV4_ADDRESS], vec![192, 168, 1, 10]);
+        assert_eq!(egress[&IE_DESTINATION_IPV4_ADDRESS], vec![1, 1, 1, 1]);
+        assert_eq!(u16_at(&egress[&IE_SOURCE_TRANSPORT_PORT], 0), 50000);
+        assert_eq!(u64_at(&egress[&IE_PACKET_DELTA_COUNT], 0), 20);
+        assert_eq!(u64_at(&egress[&IE_OCTET_DELTA_COUNT], 0), 2000);
+        assert_eq!(u32_at(&egress[&IE_BGP_SOURCE_AS_NUMBER], 0), 0);
+        assert_eq!(u32_at(&egress[&IE_BGP_DESTINATION_AS_NUMBER], 0), 13335);
+        assert_eq!(egress[&IE_FLOW_DIRECTION], vec![1]);
+        if protocol == FlowProtocol::Ipfix {
+            let start_millis = u64_at(&egress[&IE_FLOW_START_MILLISECONDS], 0);
+            let end_millis = u64_at(&egress[&IE_FLOW_END_MILLISECONDS], 0);
+            assert_eq!(start_millis, unix_millis(start));
+            assert_eq!(end_millis, unix_millis(start + Duration::from_secs(2)));
+        }
+
+        // The IPv6 record is 71 or 79 bytes, so the set carries padding
+        let v6_records = parse_records(v6_fields, &sets[2].1);
+        assert_eq!(v6_records.len(), 1);
+        assert_ne!(record_len(protocol, true) % 4, 0);
+        assert_eq!(
+            v6_records[0][&IE_SOURCE_IPV6_ADDRESS],
+            Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x10)
+                .octets()
+                .to_vec()
+        );
+        assert_eq!(v6_records[0][&IE_PROTOCOL_IDENTIFIER], vec![17]);
+        assert_eq!(u64_at(&v6_records[0][&IE_PACKET_DELTA_COUNT], 0), 6);
+
+        // The templates are not due again, the sequence number moves on
+        let packets = encoder.encode(&flows[..1], now);
+        assert_eq!(packets.len(), 1);
+        let (header, sets) = parse_packet(&packets[0]);
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].0, IPV4_TEMPLATE_ID);
+        match protocol {
+            FlowProtocol::NetflowV9 => assert_eq!(header.sequence, 1),
+            FlowProtocol::Ipfix => assert_eq!(header.sequence, 3),
+        }
+    }
+
+    #[test]
+    fn export_netflow_v9() {
+        check_export(FlowProtocol::NetflowV9);
+    }
+
+    #[test]
+    fn export_ipfix() {
+        check_export(FlowProtocol::Ipfix);
+    }
+
+    fn check_split(protocol: FlowProtocol) {
+        let now = SystemTime::now();
+        let flows: Vec<FlowRecord> = (0..100)
+            .map(|i| FlowRecord {
+                key: FlowKey {
+                    conn: conn(LOCAL_V4, 40000 + i, REMOTE_V4, TransportProtocol::TCP),
+                    direction: Direction::Egress,
+                },
+                packets: 1,
+                bytes: 100,
+                start: now,
+                end: now,
+                remote_asn: 0,
+            })
+            .collect();
+        let mut encoder = FlowEncoder::new(&config(protocol));
+        let packets = send_to_collector(&encoder.encode(&flows, now));
+        assert!(packets.len() > 1);
+        let mut ports = Vec::new();
+        let mut sequence = 0;
+        for (i, packet) in packets.iter().enumerate() {
+            assert!(packet.len() <= MAX_PACKET_SIZE);
+            let (header, sets) = parse_packet(packet);
+            let mut data_records = 0;
+            for (set_id, body) in &sets {
+                if *set_id != IPV4_TEMPLATE_ID {
+                    continue;
+                }
+                for record in parse_records(&template_fields(protocol, false), body) {
+                    ports.push(u16_at(&record[&IE_SOURCE_TRANSPORT_PORT], 0));
+                    data_records += 1;
+                }
+            }
+            match protocol {
+                FlowProtocol::NetflowV9 => assert_eq!(header.sequence, i as u32),
+                FlowProtocol::Ipfix => {
+                    assert_eq!(header.sequence, sequence);
+                    assert_eq!(header.count_or_len as usize, packet.len());
+                }
+            }
+            sequence += data_records;
+            // A packet is only cut when the next record does not fit
+            if i + 1 < packets.len() {
+                let record_len = record_len(protocol, false);
+                assert!(packet.len() + record_len + 3 > MAX_PACKET_SIZE);
+            }
+        }
+        let expected: Vec<u16> = (0..100).map(|i| 40000 + i).collect();
+        assert_eq!(ports, expected);
+    }
+
+    #[test]
+    fn split_netflow_v9() {
+        check_split(FlowProtocol::NetflowV9);
+    }
+
+    #[test]
+    fn split_ipfix() {
+        check_split(FlowProtocol::Ipfix);
+    }
+
+    #[test]
+    fn expire_active_closed_and_export_traffic() {
+        let start = SystemTime::now();
+        let mut cache = FlowCache::new(Duration::from_secs(60), Duration::from_secs(15));
+        let collector: SocketAddr = "192.0.2.10:4739".parse().unwrap();
+        cache.set_export_socket(50100, collector);
+        let busy = conn(LOCAL_V4, 50000, REMOTE_V4, TransportProtocol::TCP);
+        let closed = conn(LOCAL_V4, 50001, REMOTE_V4, TransportProtocol::TCP);
+        let mut export = conn(LOCAL_V4, 50100, collector.ip(), TransportProtocol::UDP);
+        export.remote_port = collector.port();
+        let mixed = conn(LOCAL_V4, 50002, REMOTE_V6, TransportProtocol::UDP);
+        let mut delta = NetStatData::new();
+        for conn in [&busy, &closed, &export, &mixed] {
+            delta
+                .connection_map
+                .insert(conn.clone(), traffic(start, (1, 100), (1, 100)));
+        }
+        let mut netstat_data = NetStatData::new();
+        cache.update(&delta, &netstat_data, start);
+        assert_eq!(cache.flows.len(), 4);
+
+        // The RST closes one connection
+        let mut tcp_flow = TcpFlowInfo::new();
+        let rst = TcpSegment {
+            flags: TcpFlags::RST,
+            sequence: 0,
+            acknowledgement: 0,
+            window: 0,
+            payload_len: 0,
+        };
+        tcp_flow.update(Direction::Ingress, &rst, Duration::ZERO);
+        netstat_data.tcp_flow_map.insert(closed.clone(), tcp_flow);
+        let expired = cache.expire(&netstat_data, start + Duration::from_secs(1));
+        assert_eq!(expired.len(), 2);
+        assert!(expired.iter().all(|flow| flow.key.conn == closed));
+
+        // Traffic every 10 seconds keeps the flow until the active timeout
+        let mut delta = NetStatData::new();
+        delta
+            .connection_map
+            .insert(busy.clone(), traffic(start, (1, 100), (1, 100)));
+        for secs in (10..60).step_by(10) {
+            let now = start + Duration::from_secs(secs);
+            cache.update(&delta, &netstat_data, now);
+            assert!(cache.expire(&netstat_data, now).is_empty());
+        }
+        let expired = cache.expire(&netstat_data, start + Duration::from_secs(60));
+        assert_eq!(expired.len(), 2);
+        let egress = expired
+            .iter()
+            .find(|flow| flow.key.direction == Direction::Egress)
+            .unwrap();
+        assert_eq!(egress.packets, 6);
+        assert!(cache.flows.is_empty());
+    }
+}
@@ -26,3 +26,14 @@ pub struct Notification {
     pub notification_type: NotificationType,
     pub timestamp: String,
 }
+
+impl Notification {
+    pub fn new(title: String, body: String, notification_type: NotificationType) -> Self {
+        Notification {
+            title,
+            body,
+            notification_type,
+            timestamp: crate::sys::get_sysdate(),
+        }
+    }
+}
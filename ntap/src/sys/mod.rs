@@ -36,6 +36,28 @@ pub fn get_os_type() -> String {
     "macos".to_owned()
 }
 
+/// Command line of a process from /proc/<pid>/cmdline. None if it can not be read.
+#[cfg(target_os = "linux")]
+pub fn get_process_command_line(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let args: Vec<String> = cmdline
+        .split(|b| *b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).to_string())
+        .collect();
+    if args.is_empty() {
+        None
+    } else {
+        Some(args.join(" "))
+    }
+}
+
+/// Command line of a process. Not available on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn get_process_command_line(_pid: u32) -> Option<String> {
+    None
+}
+
 pub fn get_sysdate() -> String {
     let now = chrono::Local::now();
     now.to_rfc3339()
@@ -5,6 +5,7 @@ use std::time::Duration;
 use crate::{
     config::AppConfig,
     net::{
+        decode::{self, DecodedLayer},
         host::HostDisplayInfo,
         packet::{PacketFrame, PacketStorage},
         service::ServiceDisplayInfo,
@@ -12,6 +13,7 @@ use crate::{
         stat::NetStatData,
     },
     process::ProcessDisplayInfo,
+    tui::search::{self, SearchState},
 };
 use ratatui::widgets::TableState;
 
@@ -44,7 +46,18 @@ pub struct App<'a> {
     pub tabs: TabsState<'a>,
     pub talbe_state: TableState,
     pub row_selecting: bool,
+    /// Captured packets before the search filter
+    pub captured_packets: Vec<PacketFrame>,
+    /// Packets shown in the table
     pub packets: Vec<PacketFrame>,
+    /// Search prompt and pinned filters of the table
+    pub search: SearchState,
+    /// Packet opened in the detail view
+    pub detail: Option<PacketFrame>,
+    /// Decoded layers of the opened packet
+    pub detail_layers: Vec<DecodedLayer>,
+    /// Selected (expanded) layer of the opened packet
+    pub detail_layer_index: usize,
     pub enhanced_graphics: bool,
     pub config: AppConfig,
 }
@@ -58,16 +71,29 @@ impl<'a> App<'a> {
             tabs: TabsState::new(vec!["PacketCapture"]),
             talbe_state: TableState::default(),
             row_selecting: false,
+            captured_packets: Vec::new(),
             packets: Vec::new(),
+            search: SearchState::new(config.display.pinned_filters.clone()),
+            detail: None,
+            detail_layers: Vec::new(),
+            detail_layer_index: 0,
             enhanced_graphics: enhanced_graphics,
             config: config,
         }
     }
 
     pub fn on_up(&mut self) {
+        if self.detail.is_some() {
+            // Select the previous layer
+            self.detail_layer_index = self.detail_layer_index.saturating_sub(1);
+            return;
+        }
         // Select the previous row
         self.row_selecting = true;
         let row_count = self.packets.len();
+        if row_count == 0 {
+            return;
+        }
         let i = match self.talbe_state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -82,9 +108,19 @@ impl<'a> App<'a> {
     }
 
     pub fn on_down(&mut self) {
+        if self.detail.is_some() {
+            // Select the next layer
+            if self.detail_layer_index + 1 < self.detail_layers.len() {
+                self.detail_layer_index += 1;
+            }
+            return;
+        }
         // Select the next row
         self.row_selecting = true;
         let row_count = self.packets.len();
+        if row_count == 0 {
+            return;
+        }
         let i = match self.talbe_state.selected() {
             Some(i) => {
                 if i >= row_count - 1 {
@@ -118,7 +154,52 @@ impl<'a> App<'a> {
         self.tabs.previous();
     }
 
+    pub fn on_enter(&mut self) {
+        if self.search.editing {
+            // Apply the query and leave the prompt
+            self.search.confirm();
+            return;
+        }
+        if self.detail.is_none() {
+            // Open the selected packet
+            let packet = self
+                .talbe_state
+                .selected()
+                .and_then(|i| self.packets.get(i))
+                .cloned();
+            if let Some(packet) = packet {
+                self.detail_layers = decode::decode_packet(&packet);
+                self.detail_layer_index = 0;
+                self.detail = Some(packet);
+            }
+        }
+    }
+
+    pub fn on_esc(&mut self) {
+        if self.detail.is_some() && !self.search.editing {
+            // Close the packet detail
+            self.detail = None;
+            self.detail_layers.clear();
+            return;
+        }
+        // Clear the query. Pinned filters stay.
+        self.search.cancel();
+        self.update_packets();
+    }
+
+    pub fn on_backspace(&mut self) {
+        if self.search.editing {
+            self.search.pop();
+            self.update_packets();
+        }
+    }
+
     pub fn on_key(&mut self, c: char) {
+        if self.search.editing {
+            self.search.push(c);
+            self.update_packets();
+            return;
+        }
         match c {
             'q' => {
                 // Quit the application
@@ -130,9 +211,23 @@ impl<'a> App<'a> {
             }
             'b' => {
                 // Scroll to the bottom
-                self.talbe_state.select(Some(self.packets.len() - 1));
+                self.talbe_state.select(self.packets.len().checked_sub(1));
                 self.row_selecting = false;
             }
+            '/' => {
+                // Open the search prompt
+                self.search.start();
+            }
+            'p' => {
+                // Pin the current query
+                self.search.pin();
+                self.update_packets();
+            }
+            'u' => {
+                // Unpin the last pinned filter
+                self.search.unpin();
+                self.update_packets();
+            }
             _ => {}
         }
     }
@@ -140,10 +235,28 @@ impl<'a> App<'a> {
     pub fn on_tick(&mut self, packets: Vec<PacketFrame>) {
         // Update the state of the application
         // Set the latest packets
-        self.packets = packets;
+        self.captured_packets = packets;
+        self.update_packets();
+    }
+
+    /// Apply the search filter to the captured packets.
+    fn update_packets(&mut self) {
+        if self.search.is_active() {
+            let filter = &self.search;
+            self.packets = self
+                .captured_packets
+                .iter()
+                .filter(|packet| filter.matches(&search::packet_fields(packet)))
+                .cloned()
+                .collect();
+        } else {
+            self.packets = self.captured_packets.clone();
+        }
         // If the user is not selecting a row, scroll to the bottom
-        if !self.row_selecting {
-            self.talbe_state.select(Some(self.packets.len() - 1));
+        let last = self.packets.len().checked_sub(1);
+        match self.talbe_state.selected() {
+            Some(i) if self.row_selecting && i < self.packets.len() => {}
+            _ => self.talbe_state.select(last),
         }
     }
 }
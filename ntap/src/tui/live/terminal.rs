@@ -70,7 +70,16 @@ fn run_app<B: Backend>(
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
+                if key.kind == KeyEventKind::Press && app.search.editing {
+                    // The search prompt takes the key input
+                    match key.code {
+                        KeyCode::Enter => app.on_enter(),
+                        KeyCode::Esc => app.on_esc(),
+                        KeyCode::Backspace => app.on_backspace(),
+                        KeyCode::Char(c) => app.on_key(c),
+                        _ => {}
+                    }
+                } else if key.kind == KeyEventKind::Press {
                     match key.code {
                         KeyCode::Left | KeyCode::Char('a') => app.on_left(),
                         KeyCode::Up | KeyCode::Char('w') => app.on_up(),
@@ -78,6 +87,8 @@ fn run_app<B: Backend>(
                         KeyCode::Down | KeyCode::Char('s') => app.on_down(),
                         KeyCode::Tab => app.on_tab(),
                         KeyCode::BackTab => app.on_shift_tab(),
+                        KeyCode::Enter => app.on_enter(),
+                        KeyCode::Esc => app.on_esc(),
                         KeyCode::Char(c) => app.on_key(c),
                         _ => {}
                     }
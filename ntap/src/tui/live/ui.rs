@@ -1,6 +1,11 @@
 use super::app::App;
+use crate::net::decode::{DecodedField, DecodedLayer};
+use crate::net::packet::PacketFrame;
 use ratatui::{prelude::*, widgets::*};
 
+/// Number of bytes in one line of the hex dump
+const HEX_DUMP_WIDTH: usize = 16;
+
 pub fn draw(f: &mut Frame, app: &mut App) {
     let chunks = Layout::default()
         .constraints([
@@ -34,17 +39,35 @@ pub fn draw(f: &mut Frame, app: &mut App) {
     };
     f.render_widget(tabs, chunks[0]);
     match app.tabs.index {
-        0 => draw_live_capture_tab(f, app, chunks[1]),
+        0 => match app.detail.clone() {
+            Some(packet) => draw_packet_detail(f, app, &packet, chunks[1]),
+            None => draw_live_capture_tab(f, app, chunks[1]),
+        },
         _ => {}
     };
     // Draw footer
-    let footer = format!(
-        "Press <Q> to quit, <SPACE> to pause, <Up>/<Down> to scroll, <B> to scroll to the bottom"
-    );
-    let footer = Paragraph::new(text::Line::from(Span::styled(
-        footer,
-        Style::default().fg(Color::DarkGray),
-    )));
+    let footer = if app.search.editing {
+        Paragraph::new(text::Line::from(vec![
+            Span::styled("/", Style::default().fg(Color::Yellow)),
+            Span::raw(app.search.query.clone()),
+            Span::styled("█", Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                "  <Enter> to apply, <Esc> to clear",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]))
+    } else if app.detail.is_some() {
+        Paragraph::new(text::Line::from(Span::styled(
+            "Press <Esc> to go back, <Up>/<Down> to select a layer, <SPACE> to pause, <Q> to quit",
+            Style::default().fg(Color::DarkGray),
+        )))
+    } else {
+        let footer = format!("Press <Q> to quit, </> to search, <P>/<U> to pin/unpin the search, <SPACE> to pause, <Up>/<Down> to scroll, <B> to scroll to the bottom, <Enter> to open the packet");
+        Paragraph::new(text::Line::from(Span::styled(
+            footer,
+            Style::default().fg(Color::DarkGray),
+        )))
+    };
     f.render_widget(footer, chunks[2]);
 }
 
@@ -89,6 +112,8 @@ fn draw_packet_table(f: &mut Frame, app: &mut App, area: Rect) {
         );
     }
 
+    let table_title = format!("{}{}", table_title, app.search.title_suffix());
+
     //let mut table_state = TableState::default();
     let table = Table::new(rows, widths)
         .column_spacing(1)
@@ -121,3 +146,155 @@ fn draw_live_capture_tab(f: &mut Frame, app: &mut App, area: Rect) {
         .split(area);
     draw_packet_table(f, app, chunks[0]);
 }
+
+fn draw_packet_detail(f: &mut Frame, app: &App, packet: &PacketFrame, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    let selected_layer = app.detail_layers.get(app.detail_layer_index);
+    draw_decode_tree(f, app, packet, chunks[0]);
+    draw_hex_dump(
+        f,
+        &packet.raw_packet,
+        packet.packet_len,
+        selected_layer,
+        chunks[1],
+    );
+}
+
+fn field_lines(field: &DecodedField, depth: usize, lines: &mut Vec<Line>) {
+    // Fields without a value (Example: the NOP option) are shown by name only
+    let name = if field.value.is_empty() {
+        field.name.clone()
+    } else {
+        format!("{}: ", field.name)
+    };
+    lines.push(Line::from(vec![
+        Span::raw("    ".repeat(depth)),
+        Span::styled(name, Style::default().fg(Color::Cyan)),
+        Span::raw(field.value.clone()),
+    ]));
+    for child in &field.children {
+        field_lines(child, depth + 1, lines);
+    }
+}
+
+/// Layers of the packet. The selected layer is expanded.
+fn draw_decode_tree(f: &mut Frame, app: &App, packet: &PacketFrame, area: Rect) {
+    let title = format!(
+        "Packet {} - {} bytes on {} at {}",
+        packet.capture_no,
+        packet.packet_len,
+        packet.if_name,
+        packet.get_time()
+    );
+    let mut lines: Vec<Line> = Vec::new();
+    // Line index of the selected layer, to keep it on the screen
+    let mut selected_line = 0;
+    for (i, layer) in app.detail_layers.iter().enumerate() {
+        let header = format!("{}, {}", layer.name, layer.summary);
+        if i == app.detail_layer_index {
+            selected_line = lines.len();
+            lines.push(Line::from(Span::styled(
+                format!("▼ {}", header),
+                Style::default().fg(Color::LightBlue).bold(),
+            )));
+            for field in &layer.fields {
+                field_lines(field, 1, &mut lines);
+            }
+        } else {
+            lines.push(Line::from(format!("▶ {}", header)));
+        }
+    }
+    if lines.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "Raw bytes were not kept. Set --snaplen or capture.snaplen in the config above 0.",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    let height = area.height.saturating_sub(2) as usize;
+    let scroll = if lines.len() <= height {
+        0
+    } else {
+        std::cmp::min(selected_line, lines.len() - height)
+    };
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .scroll((scroll as u16, 0));
+    f.render_widget(paragraph, area);
+}
+
+/// Hex and ASCII dump of the raw bytes. The bytes of the selected layer are highlighted.
+fn draw_hex_dump(
+    f: &mut Frame,
+    bytes: &[u8],
+    packet_len: usize,
+    selected_layer: Option<&DecodedLayer>,
+    area: Rect,
+) {
+    let title = if bytes.len() < packet_len {
+        format!("Bytes ({} of {} bytes captured)", bytes.len(), packet_len)
+    } else {
+        format!("Bytes ({} bytes)", bytes.len())
+    };
+    let highlight = |offset: usize| match selected_layer {
+        Some(layer) => offset >= layer.offset && offset < layer.offset + layer.len,
+        None => false,
+    };
+    let highlight_style = Style::default().fg(Color::LightBlue).reversed();
+    let lines: Vec<Line> = bytes
+        .chunks(HEX_DUMP_WIDTH)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let row_offset = row * HEX_DUMP_WIDTH;
+            let mut spans = vec![Span::styled(
+                format!("{:04x}  ", row_offset),
+                Style::default().fg(Color::DarkGray),
+            )];
+            for i in 0..HEX_DUMP_WIDTH {
+                let text = match chunk.get(i) {
+                    Some(b) => format!("{:02x}", b),
+                    None => "  ".to_string(),
+                };
+                if chunk.get(i).is_some() && highlight(row_offset + i) {
+                    spans.push(Span::styled(text, highlight_style));
+                } else {
+                    spans.push(Span::raw(text));
+                }
+                // Space between bytes, and an extra one in the middle of the line
+                if i == HEX_DUMP_WIDTH / 2 - 1 {
+                    spans.push(Span::raw("  "));
+                } else {
+                    spans.push(Span::raw(" "));
+                }
+            }
+            spans.push(Span::raw(" "));
+            for (i, b) in chunk.iter().enumerate() {
+                let c = if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                };
+                if highlight(row_offset + i) {
+                    spans.push(Span::styled(c.to_string(), highlight_style));
+                } else {
+                    spans.push(Span::raw(c.to_string()));
+                }
+            }
+            Line::from(spans)
+        })
+        .collect();
+    // Scroll to the first line of the selected layer
+    let height = area.height.saturating_sub(2) as usize;
+    let scroll = match selected_layer {
+        Some(layer) if lines.len() > height => {
+            std::cmp::min(layer.offset / HEX_DUMP_WIDTH, lines.len() - height)
+        }
+        _ => 0,
+    };
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .scroll((scroll as u16, 0));
+    f.render_widget(paragraph, area);
+}
@@ -1,3 +1,5 @@
 pub mod live;
 pub mod monitor;
+pub mod search;
+pub mod sort;
 pub mod stat;
@@ -1,17 +1,28 @@
 #![allow(unused)]
 
+use std::net::IpAddr;
 use std::time::Duration;
 
 use crate::{
+    alert::{AlertEngine, TickTraffic},
     config::AppConfig,
     net::{
-        host::HostDisplayInfo, service::ServiceDisplayInfo, socket::SocketDisplayInfo,
+        history::{BandwidthHistory, HistoryResolution},
+        host::{AsDisplayInfo, CountryDisplayInfo, HostDisplayInfo},
+        service::ServiceDisplayInfo,
+        socket::{SocketConnection, SocketDisplayInfo},
         stat::NetStatData,
+        traffic::{TrafficDisplayInfo, TrafficInfo},
     },
     process::ProcessDisplayInfo,
+    tui::search::{self, SearchState},
+    tui::sort::{SortTable, TableSort},
 };
 use ratatui::widgets::TableState;
 
+/// Number of one-second samples in the Trend column of the tables.
+const TREND_SAMPLES: usize = 10;
+
 pub struct TabsState<'a> {
     pub titles: Vec<&'a str>,
     pub index: usize,
@@ -34,6 +45,13 @@ impl<'a> TabsState<'a> {
     }
 }
 
+/// Detail pane opened with Enter on a table row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DetailView {
+    Connection(SocketConnection),
+    RemoteHost(IpAddr),
+}
+
 pub struct App<'a> {
     pub title: &'a str,
     pub should_pause: bool,
@@ -45,7 +63,28 @@ pub struct App<'a> {
     pub processes: Vec<ProcessDisplayInfo>,
     pub connections: Vec<SocketDisplayInfo>,
     pub app_protocols: Vec<ServiceDisplayInfo>,
+    pub countries: Vec<CountryDisplayInfo>,
+    pub autonomous_systems: Vec<AsDisplayInfo>,
+    pub remote_host_sort: TableSort,
+    pub connection_sort: TableSort,
+    pub country_sort: TableSort,
+    pub autonomous_system_sort: TableSort,
+    /// Table of the Statistics tab that the sort keys apply to
+    pub focused_table: SortTable,
+    /// Open detail panes. The last one is shown and Esc goes back to the previous one.
+    pub details: Vec<DetailView>,
+    /// Selected row in the connection list of the remote host detail
+    pub detail_table_state: TableState,
+    /// Connections of the remote host in the detail pane
+    pub host_connections: Vec<SocketDisplayInfo>,
+    /// Index of the interface shown in the summary. None shows the total of all interfaces.
+    pub focused_interface: Option<usize>,
+    /// Resolution of the bandwidth graphs
+    pub history_resolution: HistoryResolution,
+    /// Search prompt and pinned filters of the tables
+    pub search: SearchState,
     pub enhanced_graphics: bool,
+    pub alert_engine: AlertEngine,
     pub config: AppConfig,
 }
 
@@ -55,28 +94,65 @@ impl<'a> App<'a> {
             title,
             should_pause: false,
             should_quit: false,
-            tabs: TabsState::new(vec!["Statistics", "RemoteAddresses", "Connections"]),
+            tabs: TabsState::new(vec![
+                "Statistics",
+                "RemoteAddresses",
+                "Connections",
+                "Countries",
+                "AutonomousSystems",
+            ]),
             talbe_state: TableState::default(),
             netstat_data: NetStatData::new(),
             remote_hosts: vec![],
             processes: vec![],
             connections: vec![],
             app_protocols: vec![],
+            countries: vec![],
+            autonomous_systems: vec![],
+            remote_host_sort: TableSort::new(SortTable::RemoteHosts, &config.display.default_sort),
+            connection_sort: TableSort::new(SortTable::Connections, &config.display.default_sort),
+            country_sort: TableSort::new(SortTable::Countries, &config.display.default_sort),
+            autonomous_system_sort: TableSort::new(
+                SortTable::AutonomousSystems,
+                &config.display.default_sort,
+            ),
+            focused_table: SortTable::RemoteHosts,
+            details: vec![],
+            detail_table_state: TableState::default(),
+            host_connections: vec![],
+            focused_interface: None,
+            history_resolution: HistoryResolution::Second,
+            search: SearchState::new(config.display.pinned_filters.clone()),
             enhanced_graphics: enhanced_graphics,
+            alert_engine: AlertEngine::new(&config.alerts),
             config: config,
         }
     }
 
+    /// Number of rows in the table of the current tab
+    fn row_count(&self) -> usize {
+        match self.tabs.index {
+            1 => self.remote_hosts.len(),
+            2 => self.connections.len(),
+            3 => self.countries.len(),
+            4 => self.autonomous_systems.len(),
+            _ => 0,
+        }
+    }
+
     pub fn on_up(&mut self) {
+        if !self.details.is_empty() {
+            self.select_host_connection(false);
+            return;
+        }
         if self.tabs.index == 0 {
             return;
         }
         // Select the previous row
-        let row_count = match self.tabs.index {
-            1 => self.remote_hosts.len(),
-            2 => self.connections.len(),
-            _ => 0,
-        };
+        let row_count = self.row_count();
+        if row_count == 0 {
+            return;
+        }
         let i = match self.talbe_state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -91,15 +167,18 @@ impl<'a> App<'a> {
     }
 
     pub fn on_down(&mut self) {
+        if !self.details.is_empty() {
+            self.select_host_connection(true);
+            return;
+        }
         if self.tabs.index == 0 {
             return;
         }
         // Select the next row
-        let row_count = match self.tabs.index {
-            1 => self.remote_hosts.len(),
-            2 => self.connections.len(),
-            _ => 0,
-        };
+        let row_count = self.row_count();
+        if row_count == 0 {
+            return;
+        }
         let i = match self.talbe_state.selected() {
             Some(i) => {
                 if i >= row_count - 1 {
@@ -115,25 +194,89 @@ impl<'a> App<'a> {
 
     pub fn on_right(&mut self) {
         // Select the next tab
+        self.details.clear();
         self.tabs.next();
     }
 
     pub fn on_left(&mut self) {
         // Select the previous tab
+        self.details.clear();
         self.tabs.previous();
     }
 
     pub fn on_tab(&mut self) {
         // Select the next tab
+        self.details.clear();
         self.tabs.next();
     }
 
     pub fn on_shift_tab(&mut self) {
         // Select the previous tab
+        self.details.clear();
         self.tabs.previous();
     }
 
+    pub fn on_enter(&mut self) {
+        if self.search.editing {
+            // Apply the query and leave the prompt
+            self.search.confirm();
+            return;
+        }
+        // Open the detail pane of the selected row
+        let detail = match self.details.last() {
+            Some(DetailView::RemoteHost(_)) => self
+                .detail_table_state
+                .selected()
+                .and_then(|i| self.host_connections.get(i))
+                .and_then(|conn| conn.to_socket_connection())
+                .map(DetailView::Connection),
+            Some(DetailView::Connection(_)) => None,
+            None => {
+                let selected = self.talbe_state.selected();
+                match self.tabs.index {
+                    1 => selected
+                        .and_then(|i| self.remote_hosts.get(i))
+                        .map(|host| DetailView::RemoteHost(host.ip_addr)),
+                    2 => selected
+                        .and_then(|i| self.connections.get(i))
+                        .and_then(|conn| conn.to_socket_connection())
+                        .map(DetailView::Connection),
+                    _ => None,
+                }
+            }
+        };
+        if let Some(detail) = detail {
+            if let DetailView::RemoteHost(_) = detail {
+                self.detail_table_state = TableState::default();
+            }
+            self.details.push(detail);
+            self.update_host_connections();
+        }
+    }
+
+    pub fn on_esc(&mut self) {
+        // Close the detail pane
+        if self.details.pop().is_some() {
+            return;
+        }
+        // Clear the query. Pinned filters stay.
+        self.search.cancel();
+        self.update_tables();
+    }
+
+    pub fn on_backspace(&mut self) {
+        if self.search.editing {
+            self.search.pop();
+            self.update_tables();
+        }
+    }
+
     pub fn on_key(&mut self, c: char) {
+        if self.search.editing {
+            self.search.push(c);
+            self.update_tables();
+            return;
+        }
         match c {
             'q' => {
                 // Quit the application
@@ -146,19 +289,204 @@ impl<'a> App<'a> {
             't' => {
                 // Switch display mode (total/bandwidth)
                 self.config.display.show_bandwidth = !self.config.display.show_bandwidth;
+                self.sort_tables();
+            }
+            'o' => {
+                // Change the sort column of the current table
+                self.sort_target().next_key();
+                self.sort_tables();
+            }
+            'r' => {
+                // Reverse the sort direction of the current table
+                self.sort_target().reverse();
+                self.sort_tables();
+            }
+            'f' => {
+                // Focus the next table of the Statistics tab
+                self.focused_table = match self.focused_table {
+                    SortTable::RemoteHosts => SortTable::Connections,
+                    _ => SortTable::RemoteHosts,
+                };
+            }
+            'h' => {
+                // Switch the resolution of the bandwidth graphs (1s/1m)
+                self.history_resolution = self.history_resolution.next();
+            }
+            '/' => {
+                // Open the search prompt
+                self.search.start();
+            }
+            'p' => {
+                // Pin the current query
+                self.search.pin();
+                self.update_tables();
+            }
+            'u' => {
+                // Unpin the last pinned filter
+                self.search.unpin();
+                self.update_tables();
+            }
+            'i' => {
+                // Focus the next interface. All interfaces after the last one.
+                let interface_count = self.netstat_data.interface_traffic.len();
+                self.focused_interface = match self.focused_interface {
+                    None if interface_count > 0 => Some(0),
+                    Some(i) if i + 1 < interface_count => Some(i + 1),
+                    _ => None,
+                };
             }
             _ => {}
         }
     }
 
+    /// Title, traffic and bandwidth history of the focused interface, or the total of all interfaces.
+    pub fn focused_traffic(&self) -> (String, &TrafficInfo, &BandwidthHistory) {
+        match self
+            .focused_interface
+            .and_then(|i| self.netstat_data.interface_traffic.iter().nth(i))
+        {
+            Some((interface_name, interface_traffic)) => (
+                interface_name.clone(),
+                &interface_traffic.traffic,
+                &interface_traffic.history,
+            ),
+            None => (
+                String::from("Total"),
+                &self.netstat_data.traffic,
+                &self.netstat_data.traffic_history,
+            ),
+        }
+    }
+
+    /// Text graph of the recent bandwidth of a remote host.
+    pub fn host_trend(&self, ip_addr: &IpAddr) -> String {
+        match self.netstat_data.host_history.get(ip_addr) {
+            Some(history) => history.trend(TREND_SAMPLES),
+            None => String::new(),
+        }
+    }
+
+    /// Text graph of the recent bandwidth of a process.
+    pub fn process_trend(&self, pid: u32) -> String {
+        match self.netstat_data.process_history.get(&pid) {
+            Some(history) => history.trend(TREND_SAMPLES),
+            None => String::new(),
+        }
+    }
+
     pub fn on_tick(&mut self, netstat_data: NetStatData) {
+        let interval = Duration::from_millis(self.config.display.tick_rate);
+        let tick_traffic = TickTraffic::from_netstat_data(&netstat_data);
         // Update the state of the application
-        self.netstat_data.merge(
-            netstat_data,
-            Duration::from_millis(self.config.display.tick_rate),
-        );
+        self.netstat_data.merge(netstat_data, interval);
+        // Evaluate alert rules
+        let notifications = self
+            .alert_engine
+            .evaluate(&tick_traffic, &self.netstat_data, interval);
+        self.netstat_data
+            .add_notifications(notifications, self.config.alerts.max_notifications);
+        self.update_tables();
+    }
+
+    /// Rebuild the tables from the merged data and apply the search filter.
+    fn update_tables(&mut self) {
         self.remote_hosts = self.netstat_data.get_remote_hosts(None);
         //self.top_processes = app.netstat_data.get_top_processes();
         self.connections = self.netstat_data.get_connections(None);
+        self.countries = self.netstat_data.get_countries(None);
+        self.autonomous_systems = self.netstat_data.get_autonomous_systems(None);
+        if self.search.is_active() {
+            let filter = &self.search;
+            let remote_hosts = &self.netstat_data.remote_hosts;
+            self.remote_hosts
+                .retain(|host| filter.matches(&search::host_fields(host)));
+            self.connections.retain(|conn| {
+                let remote_host = conn.remote_ip_addr.and_then(|ip| remote_hosts.get(&ip));
+                filter.matches(&search::connection_fields(conn, remote_host))
+            });
+        }
+        self.sort_tables();
+        self.update_host_connections();
+        // Keep the selection inside the filtered table
+        let row_count = self.row_count();
+        if let Some(i) = self.talbe_state.selected() {
+            if i >= row_count {
+                self.talbe_state.select(row_count.checked_sub(1));
+            }
+        }
+    }
+
+    /// List the connections of the remote host in the detail pane
+    fn update_host_connections(&mut self) {
+        let ip_addr = match self.details.first() {
+            Some(DetailView::RemoteHost(ip_addr)) => *ip_addr,
+            _ => {
+                self.host_connections.clear();
+                return;
+            }
+        };
+        self.host_connections = self
+            .netstat_data
+            .get_connections(None)
+            .into_iter()
+            .filter(|conn| conn.remote_ip_addr == Some(ip_addr))
+            .collect();
+        self.connection_sort.sort_connections(
+            &mut self.host_connections,
+            &self.netstat_data.remote_hosts,
+            self.config.display.show_bandwidth,
+        );
+        let row_count = self.host_connections.len();
+        if let Some(i) = self.detail_table_state.selected() {
+            if i >= row_count {
+                self.detail_table_state.select(row_count.checked_sub(1));
+            }
+        }
+    }
+
+    // Move the selection in the connection list of the remote host detail
+    fn select_host_connection(&mut self, next: bool) {
+        if !matches!(self.details.last(), Some(DetailView::RemoteHost(_))) {
+            return;
+        }
+        let row_count = self.host_connections.len();
+        if row_count == 0 {
+            return;
+        }
+        let i = match self.detail_table_state.selected() {
+            Some(i) if next => (i + 1) % row_count,
+            Some(i) => (i + row_count - 1) % row_count,
+            None => 0,
+        };
+        self.detail_table_state.select(Some(i));
+    }
+
+    /// Sort of the table in the current tab, or the focused table of the Statistics tab
+    fn sort_target(&mut self) -> &mut TableSort {
+        match self.tabs.index {
+            1 => &mut self.remote_host_sort,
+            2 => &mut self.connection_sort,
+            3 => &mut self.country_sort,
+            4 => &mut self.autonomous_system_sort,
+            _ => match self.focused_table {
+                SortTable::Connections => &mut self.connection_sort,
+                _ => &mut self.remote_host_sort,
+            },
+        }
+    }
+
+    fn sort_tables(&mut self) {
+        let show_bandwidth = self.config.display.show_bandwidth;
+        self.remote_host_sort
+            .sort_hosts(&mut self.remote_hosts, show_bandwidth);
+        self.connection_sort.sort_connections(
+            &mut self.connections,
+            &self.netstat_data.remote_hosts,
+            show_bandwidth,
+        );
+        self.country_sort
+            .sort_countries(&mut self.countries, show_bandwidth);
+        self.autonomous_system_sort
+            .sort_autonomous_systems(&mut self.autonomous_systems, show_bandwidth);
     }
 }
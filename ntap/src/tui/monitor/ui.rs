@@ -1,6 +1,15 @@
 use ratatui::{prelude::*, widgets::*};
 
-use crate::tui::monitor::app::App;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use crate::config::SortKey;
+use crate::net::history::{BandwidthHistory, HistoryResolution};
+use crate::net::socket::{LocalSocket, SocketConnection, SocketDisplayInfo};
+use crate::net::tcp::TcpMetrics;
+use crate::net::traffic::TrafficInfo;
+use crate::tui::monitor::app::{App, DetailView};
+use crate::tui::sort::SortTable;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let chunks = Layout::default()
@@ -34,18 +43,42 @@ pub fn draw(f: &mut Frame, app: &mut App) {
             .select(app.tabs.index)
     };
     f.render_widget(tabs, chunks[0]);
-    match app.tabs.index {
-        0 => draw_overview_tab(f, app, chunks[1]),
-        1 => draw_remotehosts_tab(f, app, chunks[1]),
-        2 => draw_connections_tab(f, app, chunks[1]),
-        _ => {}
+    match app.details.last().cloned() {
+        Some(DetailView::Connection(conn)) => draw_connection_detail(f, app, &conn, chunks[1]),
+        Some(DetailView::RemoteHost(ip_addr)) => draw_host_detail(f, app, ip_addr, chunks[1]),
+        None => match app.tabs.index {
+            0 => draw_overview_tab(f, app, chunks[1]),
+            1 => draw_remotehosts_tab(f, app, chunks[1]),
+            2 => draw_connections_tab(f, app, chunks[1]),
+            3 => draw_countries_tab(f, app, chunks[1]),
+            4 => draw_autonomous_systems_tab(f, app, chunks[1]),
+            _ => {}
+        },
     };
     // Draw footer
-    let footer = format!("Press <Q> to quit, <TAB> to switch tabs, <SPACE> to pause, <T> to toggle bandwidth display, <Up>/<Down> to scroll");
-    let footer = Paragraph::new(text::Line::from(Span::styled(
-        footer,
-        Style::default().fg(Color::DarkGray),
-    )));
+    let footer = if app.search.editing {
+        Paragraph::new(text::Line::from(vec![
+            Span::styled("/", Style::default().fg(Color::Yellow)),
+            Span::raw(app.search.query.clone()),
+            Span::styled("█", Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                "  <Enter> to apply, <Esc> to clear",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]))
+    } else if !app.details.is_empty() {
+        let footer = "Press <Esc> to go back, <Up>/<Down> to select a connection, <Enter> to open it, <H> to switch graph resolution, <SPACE> to pause, <Q> to quit";
+        Paragraph::new(text::Line::from(Span::styled(
+            footer,
+            Style::default().fg(Color::DarkGray),
+        )))
+    } else {
+        let footer = format!("Press <Q> to quit, <Enter> to open details, </> to search, <P>/<U> to pin/unpin the search, <TAB> to switch tabs, <SPACE> to pause, <T> to toggle bandwidth display, <I> to focus an interface, <H> to switch graph resolution, <O>/<R> to change sort column/direction, <F> to focus a table, <Up>/<Down> to scroll");
+        Paragraph::new(text::Line::from(Span::styled(
+            footer,
+            Style::default().fg(Color::DarkGray),
+        )))
+    };
     f.render_widget(footer, chunks[2]);
 }
 
@@ -53,18 +86,23 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
     // Draw network interface
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+        ])
         .split(area);
+    let (focus_title, traffic, _) = app.focused_traffic();
     // Draw total ingress
     let ingress_packets: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_ingress_packets_per_sec()
+        traffic.formatted_ingress_packets_per_sec()
     } else {
-        app.netstat_data.traffic.packet_received.to_string()
+        traffic.packet_received.to_string()
     };
     let ingress_traffic: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_ingress_bytes_per_sec()
+        traffic.formatted_ingress_bytes_per_sec()
     } else {
-        app.netstat_data.traffic.formatted_received_bytes()
+        traffic.formatted_received_bytes()
     };
     let ingress_text = vec![
         text::Line::from(format!("Packets: {}", ingress_packets)),
@@ -72,7 +110,7 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
     ];
     let ingress_block = Block::default()
         .borders(Borders::ALL)
-        .title("↓ Total Ingress");
+        .title(format!("↓ {} Ingress", focus_title));
     let ingress_paragraph = Paragraph::new(ingress_text)
         .block(ingress_block)
         .wrap(Wrap { trim: true });
@@ -80,14 +118,14 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
 
     // Draw total egress
     let egress_packets: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_egress_packets_per_sec()
+        traffic.formatted_egress_packets_per_sec()
     } else {
-        app.netstat_data.traffic.packet_sent.to_string()
+        traffic.packet_sent.to_string()
     };
     let eggress_traffic: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_egress_bytes_per_sec()
+        traffic.formatted_egress_bytes_per_sec()
     } else {
-        app.netstat_data.traffic.formatted_sent_bytes()
+        traffic.formatted_sent_bytes()
     };
     let eggress_text = vec![
         text::Line::from(format!("Packets: {}", egress_packets)),
@@ -95,11 +133,113 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
     ];
     let eggress_block = Block::default()
         .borders(Borders::ALL)
-        .title("↑ Total Egress");
+        .title(format!("↑ {} Egress", focus_title));
     let eggress_paragraph = Paragraph::new(eggress_text)
         .block(eggress_block)
         .wrap(Wrap { trim: true });
     f.render_widget(eggress_paragraph, chunks[1]);
+    draw_bandwidth(f, app, chunks[2]);
+}
+
+fn draw_bandwidth(f: &mut Frame, app: &mut App, area: Rect) {
+    let (focus_title, _, history) = app.focused_traffic();
+    draw_bandwidth_history(f, &focus_title, history, app.history_resolution, area);
+}
+
+fn draw_bandwidth_history(
+    f: &mut Frame,
+    title: &str,
+    history: &BandwidthHistory,
+    resolution: HistoryResolution,
+    area: Rect,
+) {
+    let series = history.series(resolution);
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        "{} Bandwidth ({}) <H> to switch",
+        title,
+        resolution.name()
+    ));
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+    let inner_chunks = Layout::default()
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner_area);
+    let stats_text = match series.total_stats() {
+        Some(stats) => format!(
+            "min {}ps avg {}ps max {}ps p95 {}ps",
+            TrafficInfo::format_bytes(stats.min as usize),
+            TrafficInfo::format_bytes(stats.avg as usize),
+            TrafficInfo::format_bytes(stats.max as usize),
+            TrafficInfo::format_bytes(stats.p95 as usize),
+        ),
+        None => String::from("Collecting samples..."),
+    };
+    f.render_widget(
+        Paragraph::new(text::Line::from(Span::styled(
+            stats_text,
+            Style::default().fg(Color::DarkGray),
+        ))),
+        inner_chunks[0],
+    );
+    let data = series.latest_total(inner_chunks[1].width as usize);
+    let sparkline = Sparkline::default()
+        .data(&data)
+        .style(Style::default().fg(Color::Green));
+    f.render_widget(sparkline, inner_chunks[1]);
+}
+
+fn draw_interfaces(f: &mut Frame, app: &mut App, area: Rect) {
+    // Draw the captured interfaces side by side
+    let interface_count = app.netstat_data.interface_traffic.len() as u32;
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            (0..interface_count)
+                .map(|_| Constraint::Ratio(1, interface_count))
+                .collect::<Vec<Constraint>>(),
+        )
+        .split(area);
+    for (i, (interface_name, interface_traffic)) in
+        app.netstat_data.interface_traffic.iter().enumerate()
+    {
+        let traffic = &interface_traffic.traffic;
+        let (ingress_traffic, egress_traffic) = if app.config.display.show_bandwidth {
+            (
+                traffic.formatted_ingress_bytes_per_sec(),
+                traffic.formatted_egress_bytes_per_sec(),
+            )
+        } else {
+            (
+                traffic.formatted_received_bytes(),
+                traffic.formatted_sent_bytes(),
+            )
+        };
+        let block_style = if app.focused_interface == Some(i) {
+            Style::default().fg(Color::LightBlue)
+        } else {
+            Style::default()
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(interface_name.as_str())
+            .style(block_style);
+        let inner_area = block.inner(chunks[i]);
+        f.render_widget(block, chunks[i]);
+        let inner_chunks = Layout::default()
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(inner_area);
+        let text = text::Line::from(format!("↓ {} ↑ {}", ingress_traffic, egress_traffic));
+        f.render_widget(Paragraph::new(text), inner_chunks[0]);
+        // Total bandwidth of the latest samples that fit in the width
+        let history: Vec<u64> = interface_traffic
+            .history
+            .series(app.history_resolution)
+            .latest_total(inner_chunks[1].width as usize);
+        let sparkline = Sparkline::default()
+            .data(&history)
+            .style(Style::default().fg(Color::Green));
+        f.render_widget(sparkline, inner_chunks[1]);
+    }
 }
 
 fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
@@ -132,9 +272,12 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
                     host.ip_addr.to_string(),
                     ingress_traffic,
                     egress_traffic,
+                    app.host_trend(&host.ip_addr),
                     host.country_code.clone(),
                     host.asn.to_string(),
                     host.as_name.clone(),
+                    host.label.clone(),
+                    host.vendor_name.clone(),
                 ])
             })
             .collect::<Vec<Row>>();
@@ -142,29 +285,41 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
             Constraint::Length(40),
             Constraint::Length(11),
             Constraint::Length(11),
+            Constraint::Length(10),
             Constraint::Length(8),
             Constraint::Length(8),
             Constraint::Length(24),
+            Constraint::Length(16),
+            Constraint::Length(24),
         ];
 
         //let mut table_state = TableState::default();
+        let sort = app.remote_host_sort;
         let table = Table::new(rows, widths)
             .column_spacing(1)
             .header(
                 Row::new(vec![
-                    "IP Address",
-                    "↓ Bytes",
-                    "↑ Bytes",
-                    "Country",
-                    "ASN",
-                    "AS Name",
+                    String::from("IP Address"),
+                    sort.header("↓ Bytes", SortKey::BytesReceived),
+                    sort.header("↑ Bytes", SortKey::BytesSent),
+                    String::from("Trend"),
+                    sort.header("Country", SortKey::Country),
+                    sort.header("ASN", SortKey::Asn),
+                    String::from("AS Name"),
+                    String::from("Label"),
+                    String::from("Vendor"),
                 ])
                 .style(Style::new().bold()), //.bottom_margin(1),
             )
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Top Remote Addresses"),
+                    .title(format!(
+                        "Top Remote Addresses{}{}",
+                        sort.title_suffix(),
+                        app.search.title_suffix()
+                    ))
+                    .border_style(table_border_style(app, SortTable::RemoteHosts)),
             )
             .highlight_style(Style::new().reversed())
             .highlight_symbol(">>");
@@ -192,6 +347,12 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
                     process_id_string = process.pid.to_string();
                     process_name_string = process.name.clone();
                 }
+                let (server_name_string, alpn_string) = match &conn.server_name {
+                    Some(server_name) => {
+                        (server_name.server_name.clone(), server_name.alpn_string())
+                    }
+                    None => (String::new(), String::new()),
+                };
                 let ingress_traffic: String = if app.config.display.show_bandwidth {
                     conn.traffic.formatted_ingress_bytes_per_sec.clone()
                 } else {
@@ -210,6 +371,8 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
                         conn.local_port.to_string()
                     ),
                     format!("{}:{}", remote_ip_string, remote_port_string),
+                    server_name_string,
+                    alpn_string,
                     ingress_traffic,
                     egress_traffic,
                     process_id_string,
@@ -221,29 +384,39 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
             Constraint::Length(8),
             Constraint::Length(46),
             Constraint::Length(46),
+            Constraint::Length(30),
+            Constraint::Length(12),
             Constraint::Length(11),
             Constraint::Length(11),
             Constraint::Length(5),
             Constraint::Length(20),
         ];
+        let sort = app.connection_sort;
         let table = Table::new(rows, widths)
             .column_spacing(1)
             .header(
                 Row::new(vec![
-                    "Protocol",
-                    "Local Socket",
-                    "Remote Socket",
-                    "↓ Bytes",
-                    "↑ Bytes",
-                    "PID",
-                    "Process Name",
+                    String::from("Protocol"),
+                    String::from("Local Socket"),
+                    String::from("Remote Socket"),
+                    String::from("Server Name"),
+                    String::from("ALPN"),
+                    sort.header("↓ Bytes", SortKey::BytesReceived),
+                    sort.header("↑ Bytes", SortKey::BytesSent),
+                    String::from("PID"),
+                    sort.header("Process Name", SortKey::ProcessName),
                 ])
                 .style(Style::new().bold()), //.bottom_margin(1),
             )
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Top Connections"),
+                    .title(format!(
+                        "Top Connections{}{}",
+                        sort.title_suffix(),
+                        app.search.title_suffix()
+                    ))
+                    .border_style(table_border_style(app, SortTable::Connections)),
             )
             .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
             .highlight_symbol(">>");
@@ -252,6 +425,15 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
     }
 }
 
+// Border of the table that the sort keys apply to
+fn table_border_style(app: &App, table: SortTable) -> Style {
+    if app.focused_table == table {
+        Style::default().fg(Color::LightBlue)
+    } else {
+        Style::default()
+    }
+}
+
 fn draw_remotehosts_table(f: &mut Frame, app: &mut App, area: Rect) {
     // Draw top Remote Address Table
     let rows = app
@@ -272,9 +454,14 @@ fn draw_remotehosts_table(f: &mut Frame, app: &mut App, area: Rect) {
                 host.ip_addr.to_string(),
                 ingress_traffic,
                 egress_traffic,
+                app.host_trend(&host.ip_addr),
+                host.traffic.tcp.formatted_avg_rtt(),
+                host.traffic.tcp.formatted_retransmissions(),
                 host.country_code.clone(),
                 host.asn.to_string(),
                 host.as_name.clone(),
+                host.label.clone(),
+                host.vendor_name.clone(),
             ])
         })
         .collect::<Vec<Row>>();
@@ -282,31 +469,42 @@ fn draw_remotehosts_table(f: &mut Frame, app: &mut App, area: Rect) {
         Constraint::Length(40),
         Constraint::Length(11),
         Constraint::Length(11),
+        Constraint::Length(10),
+        Constraint::Length(9),
+        Constraint::Length(12),
         Constraint::Length(8),
         Constraint::Length(8),
         Constraint::Length(24),
+        Constraint::Length(16),
+        Constraint::Length(24),
     ];
 
     //let mut table_state = TableState::default();
+    let sort = app.remote_host_sort;
     let table = Table::new(rows, widths)
         .column_spacing(1)
         //.style(Style::new().blue())
         .header(
             Row::new(vec![
-                "IP Address",
-                "↓ Bytes",
-                "↑ Bytes",
-                "Country",
-                "ASN",
-                "AS Name",
+                String::from("IP Address"),
+                sort.header("↓ Bytes", SortKey::BytesReceived),
+                sort.header("↑ Bytes", SortKey::BytesSent),
+                String::from("Trend"),
+                sort.header("RTT", SortKey::Rtt),
+                String::from("Retrans"),
+                sort.header("Country", SortKey::Country),
+                sort.header("ASN", SortKey::Asn),
+                String::from("AS Name"),
+                String::from("Label"),
+                String::from("Vendor"),
             ])
             .style(Style::new().bold()), //.bottom_margin(1),
         )
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Remote Addresses"),
-        )
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Remote Addresses{}{}",
+            sort.title_suffix(),
+            app.search.title_suffix()
+        )))
         .highlight_style(Style::new().reversed())
         .highlight_symbol(">>");
 
@@ -335,6 +533,10 @@ fn draw_connection_table(f: &mut Frame, app: &mut App, area: Rect) {
                 process_id_string = process.pid.to_string();
                 process_name_string = process.name.clone();
             }
+            let (server_name_string, alpn_string) = match &conn.server_name {
+                Some(server_name) => (server_name.server_name.clone(), server_name.alpn_string()),
+                None => (String::new(), String::new()),
+            };
             let ingress_traffic: String = if app.config.display.show_bandwidth {
                 conn.traffic.formatted_ingress_bytes_per_sec.clone()
             } else {
@@ -347,14 +549,18 @@ fn draw_connection_table(f: &mut Frame, app: &mut App, area: Rect) {
             };
             Row::new(vec![
                 conn.protocol.as_str().to_string(),
+                conn.tcp_state_string(),
                 format!(
                     "{}:{}",
                     conn.local_ip_addr.to_string(),
                     conn.local_port.to_string()
                 ),
                 format!("{}:{}", remote_ip_string, remote_port_string),
+                server_name_string,
+                alpn_string,
                 ingress_traffic,
                 egress_traffic,
+                conn.traffic.tcp.formatted_avg_rtt(),
                 process_id_string,
                 process_name_string,
             ])
@@ -362,40 +568,277 @@ fn draw_connection_table(f: &mut Frame, app: &mut App, area: Rect) {
         .collect::<Vec<Row>>();
     let widths = [
         Constraint::Length(8),
+        Constraint::Length(11),
         Constraint::Length(46),
         Constraint::Length(46),
+        Constraint::Length(24),
+        Constraint::Length(12),
         Constraint::Length(11),
         Constraint::Length(11),
+        Constraint::Length(9),
         Constraint::Length(5),
         Constraint::Length(20),
     ];
+    let sort = app.connection_sort;
     let table = Table::new(rows, widths)
         .column_spacing(1)
         .header(
             Row::new(vec![
-                "Protocol",
-                "Local Socket",
-                "Remote Socket",
-                "↓ Bytes",
-                "↑ Bytes",
-                "PID",
-                "Process Name",
+                String::from("Protocol"),
+                String::from("State"),
+                String::from("Local Socket"),
+                String::from("Remote Socket"),
+                String::from("Server Name"),
+                String::from("ALPN"),
+                sort.header("↓ Bytes", SortKey::BytesReceived),
+                sort.header("↑ Bytes", SortKey::BytesSent),
+                sort.header("RTT", SortKey::Rtt),
+                String::from("PID"),
+                sort.header("Process Name", SortKey::ProcessName),
             ])
             .style(Style::new().bold()), //.bottom_margin(1),
         )
-        .block(Block::default().borders(Borders::ALL).title("Connections"))
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Connections{}{}",
+            sort.title_suffix(),
+            app.search.title_suffix()
+        )))
         .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
         .highlight_symbol(">>");
     //f.render_widget(table, area);
     f.render_stateful_widget(table, area, &mut app.talbe_state);
 }
 
+fn draw_country_table(f: &mut Frame, app: &mut App, area: Rect) {
+    let rows = app
+        .countries
+        .iter()
+        .map(|country| {
+            let ingress_traffic: String = if app.config.display.show_bandwidth {
+                country.traffic.formatted_ingress_bytes_per_sec.clone()
+            } else {
+                country.traffic.formatted_received_bytes.clone()
+            };
+            let egress_traffic: String = if app.config.display.show_bandwidth {
+                country.traffic.formatted_egress_bytes_per_sec.clone()
+            } else {
+                country.traffic.formatted_sent_bytes.clone()
+            };
+            let total_traffic: String = if app.config.display.show_bandwidth {
+                format!(
+                    "{}ps",
+                    TrafficInfo::format_bytes(
+                        country.traffic.ingress_bytes_per_sec
+                            + country.traffic.egress_bytes_per_sec
+                    )
+                )
+            } else {
+                country.traffic.formatted_total_bytes.clone()
+            };
+            let country_code = if country.country_code.is_empty() {
+                String::from("-")
+            } else {
+                country.country_code.clone()
+            };
+            Row::new(vec![
+                country_code,
+                country.country_name.clone(),
+                ingress_traffic,
+                egress_traffic,
+                total_traffic,
+                country.host_count.to_string(),
+                country.connection_count.to_string(),
+            ])
+        })
+        .collect::<Vec<Row>>();
+    let widths = [
+        Constraint::Length(8),
+        Constraint::Length(32),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(8),
+        Constraint::Length(12),
+    ];
+    let sort = app.country_sort;
+    let table = Table::new(rows, widths)
+        .column_spacing(1)
+        .header(
+            Row::new(vec![
+                sort.header("Country", SortKey::Country),
+                String::from("Country Name"),
+                sort.header("↓ Bytes", SortKey::BytesReceived),
+                sort.header("↑ Bytes", SortKey::BytesSent),
+                sort.header("Total", SortKey::Total),
+                sort.header("Hosts", SortKey::Hosts),
+                sort.header("Connections", SortKey::Connections),
+            ])
+            .style(Style::new().bold()),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Countries{}", sort.title_suffix())),
+        )
+        .highlight_style(Style::new().reversed())
+        .highlight_symbol(">>");
+    f.render_stateful_widget(table, area, &mut app.talbe_state);
+}
+
+fn draw_autonomous_system_table(f: &mut Frame, app: &mut App, area: Rect) {
+    let rows = app
+        .autonomous_systems
+        .iter()
+        .map(|autonomous_system| {
+            let ingress_traffic: String = if app.config.display.show_bandwidth {
+                autonomous_system
+                    .traffic
+                    .formatted_ingress_bytes_per_sec
+                    .clone()
+            } else {
+                autonomous_system.traffic.formatted_received_bytes.clone()
+            };
+            let egress_traffic: String = if app.config.display.show_bandwidth {
+                autonomous_system
+                    .traffic
+                    .formatted_egress_bytes_per_sec
+                    .clone()
+            } else {
+                autonomous_system.traffic.formatted_sent_bytes.clone()
+            };
+            let total_traffic: String = if app.config.display.show_bandwidth {
+                format!(
+                    "{}ps",
+                    TrafficInfo::format_bytes(
+                        autonomous_system.traffic.ingress_bytes_per_sec
+                            + autonomous_system.traffic.egress_bytes_per_sec
+                    )
+                )
+            } else {
+                autonomous_system.traffic.formatted_total_bytes.clone()
+            };
+            let asn = if autonomous_system.asn == 0 {
+                String::from("-")
+            } else {
+                autonomous_system.asn.to_string()
+            };
+            Row::new(vec![
+                asn,
+                autonomous_system.as_name.clone(),
+                ingress_traffic,
+                egress_traffic,
+                total_traffic,
+                autonomous_system.host_count.to_string(),
+                autonomous_system.connection_count.to_string(),
+                autonomous_system.traffic.tcp.formatted_avg_rtt(),
+                autonomous_system.traffic.tcp.formatted_retransmissions(),
+            ])
+        })
+        .collect::<Vec<Row>>();
+    let widths = [
+        Constraint::Length(10),
+        Constraint::Length(32),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(8),
+        Constraint::Length(12),
+        Constraint::Length(9),
+        Constraint::Length(12),
+    ];
+    let sort = app.autonomous_system_sort;
+    let table = Table::new(rows, widths)
+        .column_spacing(1)
+        .header(
+            Row::new(vec![
+                sort.header("ASN", SortKey::Asn),
+                String::from("AS Name"),
+                sort.header("↓ Bytes", SortKey::BytesReceived),
+                sort.header("↑ Bytes", SortKey::BytesSent),
+                sort.header("Total", SortKey::Total),
+                sort.header("Hosts", SortKey::Hosts),
+                sort.header("Connections", SortKey::Connections),
+                sort.header("RTT", SortKey::Rtt),
+                String::from("Retrans"),
+            ])
+            .style(Style::new().bold()),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Autonomous Systems{}", sort.title_suffix())),
+        )
+        .highlight_style(Style::new().reversed())
+        .highlight_symbol(">>");
+    f.render_stateful_widget(table, area, &mut app.talbe_state);
+}
+
+fn draw_notifications(f: &mut Frame, app: &mut App, area: Rect) {
+    let lines: Vec<text::Line> = app
+        .netstat_data
+        .notifications
+        .iter()
+        .rev()
+        .take(area.height.saturating_sub(2) as usize)
+        .map(|notification| {
+            let time = match chrono::DateTime::parse_from_rfc3339(&notification.timestamp) {
+                Ok(timestamp) => timestamp.format("%H:%M:%S").to_string(),
+                Err(_) => notification.timestamp.clone(),
+            };
+            text::Line::from(vec![
+                Span::styled(format!("{} ", time), Style::default().fg(Color::DarkGray)),
+                Span::styled(
+                    format!("[{}] ", notification.notification_type.name()),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    format!("{}: ", notification.title),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(notification.body.clone()),
+            ])
+        })
+        .collect();
+    let title = format!("Notifications ({})", app.netstat_data.notifications.len());
+    let paragraph =
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(paragraph, area);
+}
+
 fn draw_overview_tab(f: &mut Frame, app: &mut App, area: Rect) {
-    let chunks = Layout::default()
-        .constraints([Constraint::Length(4), Constraint::Min(8)])
-        .split(area);
-    draw_summary(f, app, chunks[0]);
-    draw_top_data(f, app, chunks[1]);
+    // Interface name, traffic and a 2 line bandwidth graph
+    let interface_rows: u16 = if app.netstat_data.interface_traffic.is_empty() {
+        0
+    } else {
+        5
+    };
+    if app.netstat_data.notifications.is_empty() {
+        let chunks = Layout::default()
+            .constraints([
+                Constraint::Length(5),
+                Constraint::Length(interface_rows),
+                Constraint::Min(8),
+            ])
+            .split(area);
+        draw_summary(f, app, chunks[0]);
+        draw_interfaces(f, app, chunks[1]);
+        draw_top_data(f, app, chunks[2]);
+    } else {
+        // Show up to 3 latest notifications between the summary and the tables
+        let notification_rows = app.netstat_data.notifications.len().min(3) as u16;
+        let chunks = Layout::default()
+            .constraints([
+                Constraint::Length(5),
+                Constraint::Length(interface_rows),
+                Constraint::Length(notification_rows + 2),
+                Constraint::Min(8),
+            ])
+            .split(area);
+        draw_summary(f, app, chunks[0]);
+        draw_interfaces(f, app, chunks[1]);
+        draw_notifications(f, app, chunks[2]);
+        draw_top_data(f, app, chunks[3]);
+    }
 }
 
 fn draw_remotehosts_tab(f: &mut Frame, app: &mut App, area: Rect) {
@@ -411,3 +854,344 @@ fn draw_connections_tab(f: &mut Frame, app: &mut App, area: Rect) {
         .split(area);
     draw_connection_table(f, app, chunks[0]);
 }
+
+fn draw_countries_tab(f: &mut Frame, app: &mut App, area: Rect) {
+    let chunks = Layout::default()
+        .constraints(vec![Constraint::Percentage(100)])
+        .split(area);
+    draw_country_table(f, app, chunks[0]);
+}
+
+fn draw_autonomous_systems_tab(f: &mut Frame, app: &mut App, area: Rect) {
+    let chunks = Layout::default()
+        .constraints(vec![Constraint::Percentage(100)])
+        .split(area);
+    draw_autonomous_system_table(f, app, chunks[0]);
+}
+
+fn format_system_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+// Name and value line of the detail panes
+fn detail_line(name: &str, value: String) -> text::Line<'static> {
+    text::Line::from(vec![
+        Span::styled(
+            format!("{:<12}", name),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::raw(value),
+    ])
+}
+
+// Bytes, packets and current rate of one direction
+fn traffic_lines(traffic: &TrafficInfo) -> Vec<text::Line<'static>> {
+    vec![
+        detail_line(
+            "↓ Ingress",
+            format!(
+                "{}  {} packets  {}",
+                traffic.formatted_received_bytes(),
+                traffic.packet_received,
+                traffic.formatted_ingress_bytes_per_sec()
+            ),
+        ),
+        detail_line(
+            "↑ Egress",
+            format!(
+                "{}  {} packets  {}",
+                traffic.formatted_sent_bytes(),
+                traffic.packet_sent,
+                traffic.formatted_egress_bytes_per_sec()
+            ),
+        ),
+        detail_line("First seen", format_system_time(traffic.first_seen)),
+        detail_line("Last seen", format_system_time(traffic.last_seen)),
+    ]
+}
+
+// Passive TCP metrics. Empty if no TCP segment was measured.
+fn tcp_metrics_lines(tcp: &TcpMetrics) -> Vec<text::Line<'static>> {
+    if tcp.data_segments == 0 && tcp.handshake_rtt.samples == 0 {
+        return Vec::new();
+    }
+    vec![
+        detail_line("RTT", tcp.rtt.summary()),
+        detail_line("SYN RTT", tcp.handshake_rtt.summary()),
+        detail_line(
+            "Retrans",
+            format!(
+                "{} of {} segments  out of order {}  dup ACKs {}  zero window {}",
+                tcp.retransmissions,
+                tcp.data_segments,
+                tcp.out_of_order,
+                tcp.dup_acks,
+                tcp.zero_windows
+            ),
+        ),
+    ]
+}
+
+fn draw_connection_detail(f: &mut Frame, app: &mut App, conn: &SocketConnection, area: Rect) {
+    let title = format!(
+        "{} {}:{} - {}:{} <Esc> to go back",
+        conn.protocol.as_str(),
+        conn.local_ip_addr,
+        conn.local_port,
+        conn.remote_ip_addr,
+        conn.remote_port
+    );
+    let traffic = match app.netstat_data.connection_map.get(conn) {
+        Some(traffic) => traffic,
+        None => {
+            let paragraph = Paragraph::new("The connection is no longer tracked.")
+                .block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(paragraph, area);
+            return;
+        }
+    };
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(14), Constraint::Min(5)])
+        .split(area);
+    let info_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[0]);
+    // Connection and process
+    let socket_process = app.netstat_data.local_socket_map.get(&LocalSocket {
+        interface_name: conn.interface_name.clone(),
+        port: conn.local_port,
+        protocol: conn.protocol,
+    });
+    let status = match socket_process {
+        Some(socket_process) => socket_process.status.to_string(),
+        None => String::from("-"),
+    };
+    let (process, command_line) = match socket_process
+        .and_then(|socket_process| socket_process.process.as_ref())
+    {
+        Some(process) => (
+            format!("{} (PID {})", process.name, process.pid),
+            crate::sys::get_process_command_line(process.pid).unwrap_or_else(|| String::from("-")),
+        ),
+        None => (String::from("-"), String::from("-")),
+    };
+    let tcp_flow = app.netstat_data.tcp_flow_map.get(conn);
+    let server_name = match app.netstat_data.server_name_map.get(conn) {
+        Some(server_name) if server_name.alpn.is_empty() => server_name.server_name.clone(),
+        Some(server_name) => format!(
+            "{} ({})",
+            server_name.server_name,
+            server_name.alpn_string()
+        ),
+        None => String::from("-"),
+    };
+    let mut lines = vec![
+        detail_line("Protocol", conn.protocol.as_str().to_string()),
+        detail_line(
+            "Local",
+            format!("{}:{}", conn.local_ip_addr, conn.local_port),
+        ),
+        detail_line(
+            "Remote",
+            format!("{}:{}", conn.remote_ip_addr, conn.remote_port),
+        ),
+        detail_line("Interface", conn.interface_name.clone()),
+        detail_line("Status", status),
+        detail_line("Process", process),
+        detail_line("Command", command_line),
+        detail_line("Server Name", server_name),
+    ];
+    // TCP state from the captured flags
+    if let Some(tcp_flow) = tcp_flow {
+        lines.push(detail_line("TCP State", tcp_flow.state.to_string()));
+        lines.push(detail_line("Handshake", tcp_flow.handshake_string()));
+        if tcp_flow.close_kind.is_some() {
+            lines.push(detail_line("Closed", tcp_flow.close_string()));
+        }
+    }
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, info_chunks[0]);
+    // Traffic and remote host
+    let mut lines = traffic_lines(traffic);
+    lines.extend(tcp_metrics_lines(&traffic.tcp));
+    lines.push(text::Line::from(""));
+    match app.netstat_data.remote_hosts.get(&conn.remote_ip_addr) {
+        Some(host) => {
+            lines.push(detail_line("Hostname", host.hostname.clone()));
+            lines.push(detail_line(
+                "Country",
+                format!("{} {}", host.country_code, host.country_name),
+            ));
+            lines.push(detail_line("AS", format!("{} {}", host.asn, host.as_name)));
+            lines.push(detail_line("Label", host.label.clone()));
+        }
+        None => {
+            lines.push(detail_line("Remote Host", String::from("-")));
+        }
+    }
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Traffic and Remote Host"),
+        )
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, info_chunks[1]);
+    match app.netstat_data.connection_history.get(conn) {
+        Some(history) => {
+            draw_bandwidth_history(f, "Connection", history, app.history_resolution, chunks[1])
+        }
+        None => draw_bandwidth_history(
+            f,
+            "Connection",
+            &BandwidthHistory::new(),
+            app.history_resolution,
+            chunks[1],
+        ),
+    }
+}
+
+fn draw_host_detail(f: &mut Frame, app: &mut App, ip_addr: IpAddr, area: Rect) {
+    let title = format!("Remote Host {} <Esc> to go back", ip_addr);
+    let host = match app.netstat_data.remote_hosts.get(&ip_addr) {
+        Some(host) => host,
+        None => {
+            let paragraph = Paragraph::new("The remote host is no longer tracked.")
+                .block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(paragraph, area);
+            return;
+        }
+    };
+    let chunks = Layout::default()
+        .constraints([
+            Constraint::Length(9),
+            Constraint::Length(8),
+            Constraint::Min(5),
+        ])
+        .split(area);
+    let info_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[0]);
+    let lines = vec![
+        detail_line("IP Address", host.ip_addr.to_string()),
+        detail_line("Hostname", host.hostname.clone()),
+        detail_line(
+            "Country",
+            format!("{} {}", host.country_code, host.country_name),
+        ),
+        detail_line("AS", format!("{} {}", host.asn, host.as_name)),
+        detail_line("Label", host.label.clone()),
+        detail_line("MAC", host.mac_addr.clone()),
+        detail_line("Vendor", host.vendor_name.clone()),
+    ];
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, info_chunks[0]);
+    let mut lines = traffic_lines(&host.traffic_info);
+    lines.extend(tcp_metrics_lines(&host.traffic_info.tcp));
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Traffic"))
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, info_chunks[1]);
+    match app.netstat_data.host_history.get(&ip_addr) {
+        Some(history) => {
+            draw_bandwidth_history(f, "Host", history, app.history_resolution, chunks[1])
+        }
+        None => draw_bandwidth_history(
+            f,
+            "Host",
+            &BandwidthHistory::new(),
+            app.history_resolution,
+            chunks[1],
+        ),
+    }
+    draw_host_connection_table(f, app, chunks[2]);
+}
+
+fn connection_row(conn: &SocketDisplayInfo, show_bandwidth: bool) -> Row<'static> {
+    let remote_socket = match (conn.remote_ip_addr, conn.remote_port) {
+        (Some(remote_ip_addr), Some(remote_port)) => format!("{}:{}", remote_ip_addr, remote_port),
+        _ => String::new(),
+    };
+    let (process_id, process_name) = match &conn.process {
+        Some(process) => (process.pid.to_string(), process.name.clone()),
+        None => (String::new(), String::new()),
+    };
+    let server_name = match &conn.server_name {
+        Some(server_name) => server_name.server_name.clone(),
+        None => String::new(),
+    };
+    let (ingress_traffic, egress_traffic) = if show_bandwidth {
+        (
+            conn.traffic.formatted_ingress_bytes_per_sec.clone(),
+            conn.traffic.formatted_egress_bytes_per_sec.clone(),
+        )
+    } else {
+        (
+            conn.traffic.formatted_received_bytes.clone(),
+            conn.traffic.formatted_sent_bytes.clone(),
+        )
+    };
+    Row::new(vec![
+        conn.protocol.as_str().to_string(),
+        conn.tcp_state_string(),
+        format!("{}:{}", conn.local_ip_addr, conn.local_port),
+        remote_socket,
+        server_name,
+        ingress_traffic,
+        egress_traffic,
+        process_id,
+        process_name,
+    ])
+}
+
+fn draw_host_connection_table(f: &mut Frame, app: &mut App, area: Rect) {
+    let rows = app
+        .host_connections
+        .iter()
+        .map(|conn| connection_row(conn, app.config.display.show_bandwidth))
+        .collect::<Vec<Row>>();
+    let widths = [
+        Constraint::Length(8),
+        Constraint::Length(11),
+        Constraint::Length(46),
+        Constraint::Length(46),
+        Constraint::Length(24),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(8),
+        Constraint::Length(20),
+    ];
+    let sort = app.connection_sort;
+    let table = Table::new(rows, widths)
+        .column_spacing(1)
+        .header(
+            Row::new(vec![
+                String::from("Protocol"),
+                String::from("State"),
+                String::from("Local Socket"),
+                String::from("Remote Socket"),
+                String::from("Server Name"),
+                sort.header("↓ Bytes", SortKey::BytesReceived),
+                sort.header("↑ Bytes", SortKey::BytesSent),
+                String::from("PID"),
+                sort.header("Process Name", SortKey::ProcessName),
+            ])
+            .style(Style::new().bold()),
+        )
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Connections ({}){} <Enter> to open",
+            app.host_connections.len(),
+            sort.title_suffix()
+        )))
+        .highlight_style(Style::new().reversed())
+        .highlight_symbol(">>");
+    f.render_stateful_widget(table, area, &mut app.detail_table_state);
+}
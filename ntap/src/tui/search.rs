@@ -0,0 +1,151 @@
+use crate::net::host::{HostDisplayInfo, RemoteHostInfo};
+use crate::net::packet::PacketFrame;
+use crate::net::socket::SocketDisplayInfo;
+use crate::process::ProcessDisplayInfo;
+
+/// `/` search prompt and pinned filters of the TUI tables.
+/// A row is shown if every term (the query and each pinned filter) is found in one of its fields.
+pub struct SearchState {
+    /// Text typed at the prompt
+    pub query: String,
+    /// True while the prompt takes the key input
+    pub editing: bool,
+    /// Pinned filters. They stay applied when the query is cleared.
+    pub pinned: Vec<String>,
+}
+
+impl SearchState {
+    pub fn new(pinned: Vec<String>) -> SearchState {
+        SearchState {
+            query: String::new(),
+            editing: false,
+            pinned: pinned
+                .into_iter()
+                .map(|filter| filter.trim().to_string())
+                .filter(|filter| !filter.is_empty())
+                .collect(),
+        }
+    }
+    pub fn start(&mut self) {
+        self.editing = true;
+    }
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+    }
+    pub fn pop(&mut self) {
+        self.query.pop();
+    }
+    /// Keep the query and leave the prompt
+    pub fn confirm(&mut self) {
+        self.editing = false;
+    }
+    /// Clear the query and leave the prompt
+    pub fn cancel(&mut self) {
+        self.query.clear();
+        self.editing = false;
+    }
+    /// Move the query to the pinned filters
+    pub fn pin(&mut self) {
+        let query = self.query.trim().to_string();
+        self.query.clear();
+        if !query.is_empty() && !self.pinned.contains(&query) {
+            self.pinned.push(query);
+        }
+    }
+    /// Remove the last pinned filter
+    pub fn unpin(&mut self) {
+        self.pinned.pop();
+    }
+    pub fn is_active(&self) -> bool {
+        !self.query.trim().is_empty() || !self.pinned.is_empty()
+    }
+    pub fn matches(&self, fields: &[String]) -> bool {
+        let fields: Vec<String> = fields.iter().map(|field| field.to_lowercase()).collect();
+        self.terms().all(|term| {
+            let term = term.to_lowercase();
+            fields.iter().any(|field| field.contains(&term))
+        })
+    }
+    fn terms(&self) -> impl Iterator<Item = &str> {
+        self.pinned
+            .iter()
+            .map(|filter| filter.as_str())
+            .chain(Some(self.query.trim()).filter(|query| !query.is_empty()))
+    }
+    /// Suffix for the title of a filtered table. Example: ` [Filter: 443] [Pinned: firefox]`
+    pub fn title_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if !self.query.trim().is_empty() {
+            suffix.push_str(&format!(" [Filter: {}]", self.query.trim()));
+        }
+        if !self.pinned.is_empty() {
+            suffix.push_str(&format!(" [Pinned: {}]", self.pinned.join(", ")));
+        }
+        suffix
+    }
+}
+
+pub fn host_fields(host: &HostDisplayInfo) -> Vec<String> {
+    vec![
+        host.ip_addr.to_string(),
+        host.host_name.clone(),
+        host.country_code.clone(),
+        host.country_name.clone(),
+        host.asn.to_string(),
+        host.as_name.clone(),
+        host.label.clone(),
+    ]
+}
+
+/// Fields of a connection. The remote host adds its hostname, country and AS.
+pub fn connection_fields(
+    conn: &SocketDisplayInfo,
+    remote_host: Option<&RemoteHostInfo>,
+) -> Vec<String> {
+    let mut fields = vec![
+        conn.interface_name.clone(),
+        conn.local_ip_addr.to_string(),
+        conn.local_port.to_string(),
+        conn.protocol.as_str().to_string(),
+    ];
+    if let Some(remote_ip_addr) = conn.remote_ip_addr {
+        fields.push(remote_ip_addr.to_string());
+    }
+    if let Some(remote_port) = conn.remote_port {
+        fields.push(remote_port.to_string());
+    }
+    if let Some(process) = &conn.process {
+        fields.push(process.pid.to_string());
+        fields.push(process.name.clone());
+    }
+    if let Some(server_name) = &conn.server_name {
+        fields.push(server_name.server_name.clone());
+    }
+    if let Some(tcp_flow) = &conn.tcp_flow {
+        fields.push(tcp_flow.state.to_string());
+    }
+    if let Some(host) = remote_host {
+        fields.push(host.hostname.clone());
+        fields.push(host.country_code.clone());
+        fields.push(host.country_name.clone());
+        fields.push(host.asn.to_string());
+        fields.push(host.as_name.clone());
+        fields.push(host.label.clone());
+    }
+    fields
+}
+
+pub fn process_fields(process: &ProcessDisplayInfo) -> Vec<String> {
+    vec![process.pid.to_string(), process.name.clone()]
+}
+
+pub fn packet_fields(packet: &PacketFrame) -> Vec<String> {
+    vec![
+        packet.if_name.clone(),
+        packet.get_src_addr(),
+        packet.get_dst_addr(),
+        packet.get_src_port(),
+        packet.get_dst_port(),
+        packet.get_protocol(),
+    ]
+}
@@ -0,0 +1,272 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::config::{SortConfig, SortKey, SortOrder};
+use crate::net::host::{AsDisplayInfo, CountryDisplayInfo, HostDisplayInfo, RemoteHostInfo};
+use crate::net::socket::SocketDisplayInfo;
+use crate::net::traffic::TrafficDisplayInfo;
+use crate::process::ProcessDisplayInfo;
+
+/// Tables of the TUIs that can be sorted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortTable {
+    RemoteHosts,
+    Connections,
+    Processes,
+    Countries,
+    AutonomousSystems,
+}
+
+impl SortTable {
+    /// Sort keys of the table in the order they are cycled.
+    pub fn keys(&self) -> &'static [SortKey] {
+        match self {
+            SortTable::RemoteHosts => &[
+                SortKey::Total,
+                SortKey::BytesReceived,
+                SortKey::BytesSent,
+                SortKey::Rate,
+                SortKey::Packets,
+                SortKey::FirstSeen,
+                SortKey::LastSeen,
+                SortKey::Country,
+                SortKey::Asn,
+                SortKey::Rtt,
+            ],
+            SortTable::Connections => &[
+                SortKey::Total,
+                SortKey::BytesReceived,
+                SortKey::BytesSent,
+                SortKey::Rate,
+                SortKey::Packets,
+                SortKey::FirstSeen,
+                SortKey::LastSeen,
+                SortKey::Country,
+                SortKey::Asn,
+                SortKey::ProcessName,
+                SortKey::Rtt,
+            ],
+            SortTable::Processes => &[
+                SortKey::Total,
+                SortKey::BytesReceived,
+                SortKey::BytesSent,
+                SortKey::Rate,
+                SortKey::Packets,
+                SortKey::FirstSeen,
+                SortKey::LastSeen,
+                SortKey::ProcessName,
+            ],
+            SortTable::Countries => &[
+                SortKey::Total,
+                SortKey::BytesReceived,
+                SortKey::BytesSent,
+                SortKey::Rate,
+                SortKey::Hosts,
+                SortKey::Connections,
+                SortKey::Country,
+            ],
+            SortTable::AutonomousSystems => &[
+                SortKey::Total,
+                SortKey::BytesReceived,
+                SortKey::BytesSent,
+                SortKey::Rate,
+                SortKey::Hosts,
+                SortKey::Connections,
+                SortKey::Asn,
+                SortKey::Rtt,
+            ],
+        }
+    }
+}
+
+/// Sort column and direction of one table.
+#[derive(Debug, Clone, Copy)]
+pub struct TableSort {
+    pub table: SortTable,
+    pub key: SortKey,
+    pub order: SortOrder,
+}
+
+impl TableSort {
+    /// Start with the configured default sort. Tables without the key sort by total bytes.
+    pub fn new(table: SortTable, config: &SortConfig) -> TableSort {
+        if table.keys().contains(&config.key) {
+            TableSort {
+                table,
+                key: config.key,
+                order: config.order,
+            }
+        } else {
+            TableSort {
+                table,
+                key: SortKey::Total,
+                order: SortKey::Total.default_order(),
+            }
+        }
+    }
+    /// Select the next sort key of the table
+    pub fn next_key(&mut self) {
+        let keys = self.table.keys();
+        let i = keys.iter().position(|key| *key == self.key).unwrap_or(0);
+        self.key = keys[(i + 1) % keys.len()];
+        self.order = self.key.default_order();
+    }
+    pub fn reverse(&mut self) {
+        self.order = match self.order {
+            SortOrder::Ascending => SortOrder::Descending,
+            SortOrder::Descending => SortOrder::Ascending,
+        };
+    }
+    fn marker(&self) -> &str {
+        match self.order {
+            SortOrder::Ascending => "▲",
+            SortOrder::Descending => "▼",
+        }
+    }
+    /// Column header with a marker if the table is sorted by the column
+    pub fn header(&self, name: &str, key: SortKey) -> String {
+        if key == self.key {
+            format!("{} {}", name, self.marker())
+        } else {
+            name.to_string()
+        }
+    }
+    /// Suffix for the table title. Example: ` [Sort: Rate ▼]`
+    pub fn title_suffix(&self) -> String {
+        format!(" [Sort: {} {}]", self.key.name(), self.marker())
+    }
+    fn apply_order(&self, ordering: Ordering) -> Ordering {
+        match self.order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+    // Compare by traffic. None if the key is not a traffic key.
+    fn cmp_traffic(
+        &self,
+        a: &TrafficDisplayInfo,
+        b: &TrafficDisplayInfo,
+        show_bandwidth: bool,
+    ) -> Option<Ordering> {
+        let ordering = match self.key {
+            SortKey::Total => {
+                if show_bandwidth {
+                    (a.ingress_bytes_per_sec + a.egress_bytes_per_sec)
+                        .cmp(&(b.ingress_bytes_per_sec + b.egress_bytes_per_sec))
+                } else {
+                    (a.bytes_received + a.bytes_sent).cmp(&(b.bytes_received + b.bytes_sent))
+                }
+            }
+            SortKey::BytesReceived => {
+                if show_bandwidth {
+                    a.ingress_bytes_per_sec.cmp(&b.ingress_bytes_per_sec)
+                } else {
+                    a.bytes_received.cmp(&b.bytes_received)
+                }
+            }
+            SortKey::BytesSent => {
+                if show_bandwidth {
+                    a.egress_bytes_per_sec.cmp(&b.egress_bytes_per_sec)
+                } else {
+                    a.bytes_sent.cmp(&b.bytes_sent)
+                }
+            }
+            SortKey::Rate => (a.ingress_bytes_per_sec + a.egress_bytes_per_sec)
+                .cmp(&(b.ingress_bytes_per_sec + b.egress_bytes_per_sec)),
+            SortKey::Packets => {
+                (a.packet_received + a.packet_sent).cmp(&(b.packet_received + b.packet_sent))
+            }
+            SortKey::FirstSeen => a.first_seen.cmp(&b.first_seen),
+            SortKey::LastSeen => a.last_seen.cmp(&b.last_seen),
+            // Entries without an RTT sample sort as the smallest
+            SortKey::Rtt => a.tcp.avg_rtt().cmp(&b.tcp.avg_rtt()),
+            _ => return None,
+        };
+        Some(ordering)
+    }
+    pub fn sort_hosts(&self, hosts: &mut [HostDisplayInfo], show_bandwidth: bool) {
+        hosts.sort_by(|a, b| {
+            let ordering = match self.key {
+                SortKey::Country => a.country_code.cmp(&b.country_code),
+                SortKey::Asn => a.asn.cmp(&b.asn),
+                _ => self
+                    .cmp_traffic(&a.traffic, &b.traffic, show_bandwidth)
+                    .unwrap_or(Ordering::Equal),
+            };
+            self.apply_order(ordering)
+        });
+    }
+    /// Country and ASN of a connection are taken from the remote host.
+    pub fn sort_connections(
+        &self,
+        connections: &mut [SocketDisplayInfo],
+        remote_hosts: &HashMap<IpAddr, RemoteHostInfo>,
+        show_bandwidth: bool,
+    ) {
+        let remote_host = |conn: &SocketDisplayInfo| {
+            conn.remote_ip_addr
+                .and_then(|ip_addr| remote_hosts.get(&ip_addr))
+        };
+        let process_name = |conn: &SocketDisplayInfo| match &conn.process {
+            Some(process) => process.name.clone(),
+            None => String::new(),
+        };
+        connections.sort_by(|a, b| {
+            let ordering = match self.key {
+                SortKey::Country => remote_host(a)
+                    .map(|host| host.country_code.clone())
+                    .cmp(&remote_host(b).map(|host| host.country_code.clone())),
+                SortKey::Asn => remote_host(a)
+                    .map(|host| host.asn)
+                    .cmp(&remote_host(b).map(|host| host.asn)),
+                SortKey::ProcessName => process_name(a).cmp(&process_name(b)),
+                _ => self
+                    .cmp_traffic(&a.traffic, &b.traffic, show_bandwidth)
+                    .unwrap_or(Ordering::Equal),
+            };
+            self.apply_order(ordering)
+        });
+    }
+    pub fn sort_processes(&self, processes: &mut [ProcessDisplayInfo], show_bandwidth: bool) {
+        processes.sort_by(|a, b| {
+            let ordering = match self.key {
+                SortKey::ProcessName => a.name.cmp(&b.name),
+                _ => self
+                    .cmp_traffic(&a.traffic, &b.traffic, show_bandwidth)
+                    .unwrap_or(Ordering::Equal),
+            };
+            self.apply_order(ordering)
+        });
+    }
+    pub fn sort_countries(&self, countries: &mut [CountryDisplayInfo], show_bandwidth: bool) {
+        countries.sort_by(|a, b| {
+            let ordering = match self.key {
+                SortKey::Country => a.country_code.cmp(&b.country_code),
+                SortKey::Hosts => a.host_count.cmp(&b.host_count),
+                SortKey::Connections => a.connection_count.cmp(&b.connection_count),
+                _ => self
+                    .cmp_traffic(&a.traffic, &b.traffic, show_bandwidth)
+                    .unwrap_or(Ordering::Equal),
+            };
+            self.apply_order(ordering)
+        });
+    }
+    pub fn sort_autonomous_systems(
+        &self,
+        autonomous_systems: &mut [AsDisplayInfo],
+        show_bandwidth: bool,
+    ) {
+        autonomous_systems.sort_by(|a, b| {
+            let ordering = match self.key {
+                SortKey::Asn => a.asn.cmp(&b.asn),
+                SortKey::Hosts => a.host_count.cmp(&b.host_count),
+                SortKey::Connections => a.connection_count.cmp(&b.connection_count),
+                _ => self
+                    .cmp_traffic(&a.traffic, &b.traffic, show_bandwidth)
+                    .unwrap_or(Ordering::Equal),
+            };
+            self.apply_order(ordering)
+        });
+    }
+}
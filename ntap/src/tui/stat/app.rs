@@ -1,17 +1,28 @@
 #![allow(unused)]
 
+use std::net::IpAddr;
 use std::time::Duration;
 
 use crate::{
+    alert::{AlertEngine, TickTraffic},
     config::AppConfig,
     net::{
-        host::HostDisplayInfo, service::ServiceDisplayInfo, socket::SocketDisplayInfo,
+        history::{BandwidthHistory, HistoryResolution},
+        host::HostDisplayInfo,
+        service::ServiceDisplayInfo,
+        socket::SocketDisplayInfo,
         stat::NetStatData,
+        traffic::TrafficInfo,
     },
     process::ProcessDisplayInfo,
+    tui::search::{self, SearchState},
+    tui::sort::{SortTable, TableSort},
 };
 use ratatui::widgets::TableState;
 
+/// Number of one-second samples in the Trend column of the tables.
+const TREND_SAMPLES: usize = 10;
+
 pub struct TabsState<'a> {
     pub titles: Vec<&'a str>,
     pub index: usize,
@@ -45,7 +56,19 @@ pub struct App<'a> {
     pub processes: Vec<ProcessDisplayInfo>,
     pub connections: Vec<SocketDisplayInfo>,
     pub app_protocols: Vec<ServiceDisplayInfo>,
+    /// Index of the interface shown in the summary. None shows the total of all interfaces.
+    pub focused_interface: Option<usize>,
+    /// Resolution of the bandwidth graphs
+    pub history_resolution: HistoryResolution,
+    /// Search prompt and pinned filters of the tables
+    pub search: SearchState,
+    pub remote_host_sort: TableSort,
+    pub process_sort: TableSort,
+    pub connection_sort: TableSort,
+    /// Table that the sort keys apply to
+    pub focused_table: SortTable,
     pub enhanced_graphics: bool,
+    pub alert_engine: AlertEngine,
     pub config: AppConfig,
 }
 
@@ -62,7 +85,15 @@ impl<'a> App<'a> {
             processes: vec![],
             connections: vec![],
             app_protocols: vec![],
+            focused_interface: None,
+            history_resolution: HistoryResolution::Second,
+            search: SearchState::new(config.display.pinned_filters.clone()),
+            remote_host_sort: TableSort::new(SortTable::RemoteHosts, &config.display.default_sort),
+            process_sort: TableSort::new(SortTable::Processes, &config.display.default_sort),
+            connection_sort: TableSort::new(SortTable::Connections, &config.display.default_sort),
+            focused_table: SortTable::RemoteHosts,
             enhanced_graphics: enhanced_graphics,
+            alert_engine: AlertEngine::new(&config.alerts),
             config: config,
         }
     }
@@ -133,7 +164,32 @@ impl<'a> App<'a> {
         self.tabs.previous();
     }
 
+    pub fn on_enter(&mut self) {
+        if self.search.editing {
+            // Apply the query and leave the prompt
+            self.search.confirm();
+        }
+    }
+
+    pub fn on_esc(&mut self) {
+        // Clear the query. Pinned filters stay.
+        self.search.cancel();
+        self.update_tables();
+    }
+
+    pub fn on_backspace(&mut self) {
+        if self.search.editing {
+            self.search.pop();
+            self.update_tables();
+        }
+    }
+
     pub fn on_key(&mut self, c: char) {
+        if self.search.editing {
+            self.search.push(c);
+            self.update_tables();
+            return;
+        }
         match c {
             'q' => {
                 // Quit the application
@@ -146,19 +202,144 @@ impl<'a> App<'a> {
             't' => {
                 // Switch display mode (total/bandwidth)
                 self.config.display.show_bandwidth = !self.config.display.show_bandwidth;
+                self.sort_tables();
+            }
+            'o' => {
+                // Change the sort column of the focused table
+                self.sort_target().next_key();
+                self.sort_tables();
+            }
+            'r' => {
+                // Reverse the sort direction of the focused table
+                self.sort_target().reverse();
+                self.sort_tables();
+            }
+            'f' => {
+                // Focus the next table
+                self.focused_table = match self.focused_table {
+                    SortTable::RemoteHosts => SortTable::Processes,
+                    SortTable::Processes => SortTable::Connections,
+                    _ => SortTable::RemoteHosts,
+                };
+            }
+            'h' => {
+                // Switch the resolution of the bandwidth graphs (1s/1m)
+                self.history_resolution = self.history_resolution.next();
+            }
+            '/' => {
+                // Open the search prompt
+                self.search.start();
+            }
+            'p' => {
+                // Pin the current query
+                self.search.pin();
+                self.update_tables();
+            }
+            'u' => {
+                // Unpin the last pinned filter
+                self.search.unpin();
+                self.update_tables();
+            }
+            'i' => {
+                // Focus the next interface. All interfaces after the last one.
+                let interface_count = self.netstat_data.interface_traffic.len();
+                self.focused_interface = match self.focused_interface {
+                    None if interface_count > 0 => Some(0),
+                    Some(i) if i + 1 < interface_count => Some(i + 1),
+                    _ => None,
+                };
             }
             _ => {}
         }
     }
 
+    /// Title, traffic and bandwidth history of the focused interface, or the total of all interfaces.
+    pub fn focused_traffic(&self) -> (String, &TrafficInfo, &BandwidthHistory) {
+        match self
+            .focused_interface
+            .and_then(|i| self.netstat_data.interface_traffic.iter().nth(i))
+        {
+            Some((interface_name, interface_traffic)) => (
+                interface_name.clone(),
+                &interface_traffic.traffic,
+                &interface_traffic.history,
+            ),
+            None => (
+                String::from("Total"),
+                &self.netstat_data.traffic,
+                &self.netstat_data.traffic_history,
+            ),
+        }
+    }
+
+    /// Text graph of the recent bandwidth of a remote host.
+    pub fn host_trend(&self, ip_addr: &IpAddr) -> String {
+        match self.netstat_data.host_history.get(ip_addr) {
+            Some(history) => history.trend(TREND_SAMPLES),
+            None => String::new(),
+        }
+    }
+
+    /// Text graph of the recent bandwidth of a process.
+    pub fn process_trend(&self, pid: u32) -> String {
+        match self.netstat_data.process_history.get(&pid) {
+            Some(history) => history.trend(TREND_SAMPLES),
+            None => String::new(),
+        }
+    }
+
     pub fn on_tick(&mut self, netstat_data: NetStatData) {
+        let interval = Duration::from_millis(self.config.display.tick_rate);
+        let tick_traffic = TickTraffic::from_netstat_data(&netstat_data);
         // Update the state of the application
-        self.netstat_data.merge(
-            netstat_data,
-            Duration::from_millis(self.config.display.tick_rate),
-        );
+        self.netstat_data.merge(netstat_data, interval);
+        // Evaluate alert rules
+        let notifications = self
+            .alert_engine
+            .evaluate(&tick_traffic, &self.netstat_data, interval);
+        self.netstat_data
+            .add_notifications(notifications, self.config.alerts.max_notifications);
+        self.update_tables();
+    }
+
+    /// Rebuild the tables from the merged data and apply the search filter.
+    fn update_tables(&mut self) {
         self.remote_hosts = self.netstat_data.get_remote_hosts(None);
         self.processes = self.netstat_data.get_processes(None);
         self.connections = self.netstat_data.get_connections(None);
+        if self.search.is_active() {
+            let filter = &self.search;
+            let remote_hosts = &self.netstat_data.remote_hosts;
+            self.remote_hosts
+                .retain(|host| filter.matches(&search::host_fields(host)));
+            self.processes
+                .retain(|process| filter.matches(&search::process_fields(process)));
+            self.connections.retain(|conn| {
+                let remote_host = conn.remote_ip_addr.and_then(|ip| remote_hosts.get(&ip));
+                filter.matches(&search::connection_fields(conn, remote_host))
+            });
+        }
+        self.sort_tables();
+    }
+
+    fn sort_target(&mut self) -> &mut TableSort {
+        match self.focused_table {
+            SortTable::Processes => &mut self.process_sort,
+            SortTable::Connections => &mut self.connection_sort,
+            _ => &mut self.remote_host_sort,
+        }
+    }
+
+    fn sort_tables(&mut self) {
+        let show_bandwidth = self.config.display.show_bandwidth;
+        self.remote_host_sort
+            .sort_hosts(&mut self.remote_hosts, show_bandwidth);
+        self.process_sort
+            .sort_processes(&mut self.processes, show_bandwidth);
+        self.connection_sort.sort_connections(
+            &mut self.connections,
+            &self.netstat_data.remote_hosts,
+            show_bandwidth,
+        );
     }
 }
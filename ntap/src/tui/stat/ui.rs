@@ -1,5 +1,8 @@
 use ratatui::{prelude::*, widgets::*};
 
+use crate::config::SortKey;
+use crate::net::traffic::TrafficInfo;
+use crate::tui::sort::SortTable;
 use crate::tui::stat::app::App;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
@@ -39,11 +42,23 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         _ => {}
     };
     // Draw footer
-    let footer = format!("Press <Q> to quit, <SPACE> to pause, <T> to toggle bandwidth display");
-    let footer = Paragraph::new(text::Line::from(Span::styled(
-        footer,
-        Style::default().fg(Color::DarkGray),
-    )));
+    let footer = if app.search.editing {
+        Paragraph::new(text::Line::from(vec![
+            Span::styled("/", Style::default().fg(Color::Yellow)),
+            Span::raw(app.search.query.clone()),
+            Span::styled("█", Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                "  <Enter> to apply, <Esc> to clear",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]))
+    } else {
+        let footer = format!("Press <Q> to quit, </> to search, <P>/<U> to pin/unpin the search, <SPACE> to pause, <T> to toggle bandwidth display, <I> to focus an interface, <H> to switch graph resolution, <O>/<R> to change sort column/direction, <F> to focus a table");
+        Paragraph::new(text::Line::from(Span::styled(
+            footer,
+            Style::default().fg(Color::DarkGray),
+        )))
+    };
     f.render_widget(footer, chunks[2]);
 }
 
@@ -51,18 +66,23 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
     // Draw network interface
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+        ])
         .split(area);
+    let (focus_title, traffic, _) = app.focused_traffic();
     // Draw total ingress
     let ingress_packets: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_ingress_packets_per_sec()
+        traffic.formatted_ingress_packets_per_sec()
     } else {
-        app.netstat_data.traffic.packet_received.to_string()
+        traffic.packet_received.to_string()
     };
     let ingress_traffic: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_ingress_bytes_per_sec()
+        traffic.formatted_ingress_bytes_per_sec()
     } else {
-        app.netstat_data.traffic.formatted_received_bytes()
+        traffic.formatted_received_bytes()
     };
     let ingress_text = vec![
         text::Line::from(format!("Packets: {}", ingress_packets)),
@@ -70,7 +90,7 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
     ];
     let ingress_block = Block::default()
         .borders(Borders::ALL)
-        .title("↓ Total Ingress");
+        .title(format!("↓ {} Ingress", focus_title));
     let ingress_paragraph = Paragraph::new(ingress_text)
         .block(ingress_block)
         .wrap(Wrap { trim: true });
@@ -78,14 +98,14 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
 
     // Draw total egress
     let egress_packets: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_egress_packets_per_sec()
+        traffic.formatted_egress_packets_per_sec()
     } else {
-        app.netstat_data.traffic.packet_sent.to_string()
+        traffic.packet_sent.to_string()
     };
     let eggress_traffic: String = if app.config.display.show_bandwidth {
-        app.netstat_data.traffic.formatted_egress_bytes_per_sec()
+        traffic.formatted_egress_bytes_per_sec()
     } else {
-        app.netstat_data.traffic.formatted_sent_bytes()
+        traffic.formatted_sent_bytes()
     };
     let eggress_text = vec![
         text::Line::from(format!("Packets: {}", egress_packets)),
@@ -93,11 +113,103 @@ fn draw_summary(f: &mut Frame, app: &mut App, area: Rect) {
     ];
     let eggress_block = Block::default()
         .borders(Borders::ALL)
-        .title("↑ Total Egress");
+        .title(format!("↑ {} Egress", focus_title));
     let eggress_paragraph = Paragraph::new(eggress_text)
         .block(eggress_block)
         .wrap(Wrap { trim: true });
     f.render_widget(eggress_paragraph, chunks[1]);
+    draw_bandwidth(f, app, chunks[2]);
+}
+
+fn draw_bandwidth(f: &mut Frame, app: &mut App, area: Rect) {
+    let (focus_title, _, history) = app.focused_traffic();
+    let series = history.series(app.history_resolution);
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        "{} Bandwidth ({}) <H> to switch",
+        focus_title,
+        app.history_resolution.name()
+    ));
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+    let inner_chunks = Layout::default()
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner_area);
+    let stats_text = match series.total_stats() {
+        Some(stats) => format!(
+            "min {}ps avg {}ps max {}ps p95 {}ps",
+            TrafficInfo::format_bytes(stats.min as usize),
+            TrafficInfo::format_bytes(stats.avg as usize),
+            TrafficInfo::format_bytes(stats.max as usize),
+            TrafficInfo::format_bytes(stats.p95 as usize),
+        ),
+        None => String::from("Collecting samples..."),
+    };
+    f.render_widget(
+        Paragraph::new(text::Line::from(Span::styled(
+            stats_text,
+            Style::default().fg(Color::DarkGray),
+        ))),
+        inner_chunks[0],
+    );
+    let data = series.latest_total(inner_chunks[1].width as usize);
+    let sparkline = Sparkline::default()
+        .data(&data)
+        .style(Style::default().fg(Color::Green));
+    f.render_widget(sparkline, inner_chunks[1]);
+}
+
+fn draw_interfaces(f: &mut Frame, app: &mut App, area: Rect) {
+    // Draw the captured interfaces side by side
+    let interface_count = app.netstat_data.interface_traffic.len() as u32;
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            (0..interface_count)
+                .map(|_| Constraint::Ratio(1, interface_count))
+                .collect::<Vec<Constraint>>(),
+        )
+        .split(area);
+    for (i, (interface_name, interface_traffic)) in
+        app.netstat_data.interface_traffic.iter().enumerate()
+    {
+        let traffic = &interface_traffic.traffic;
+        let (ingress_traffic, egress_traffic) = if app.config.display.show_bandwidth {
+            (
+                traffic.formatted_ingress_bytes_per_sec(),
+                traffic.formatted_egress_bytes_per_sec(),
+            )
+        } else {
+            (
+                traffic.formatted_received_bytes(),
+                traffic.formatted_sent_bytes(),
+            )
+        };
+        let block_style = if app.focused_interface == Some(i) {
+            Style::default().fg(Color::LightBlue)
+        } else {
+            Style::default()
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(interface_name.as_str())
+            .style(block_style);
+        let inner_area = block.inner(chunks[i]);
+        f.render_widget(block, chunks[i]);
+        let inner_chunks = Layout::default()
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(inner_area);
+        let text = text::Line::from(format!("↓ {} ↑ {}", ingress_traffic, egress_traffic));
+        f.render_widget(Paragraph::new(text), inner_chunks[0]);
+        // Total bandwidth of the latest samples that fit in the width
+        let history: Vec<u64> = interface_traffic
+            .history
+            .series(app.history_resolution)
+            .latest_total(inner_chunks[1].width as usize);
+        let sparkline = Sparkline::default()
+            .data(&history)
+            .style(Style::default().fg(Color::Green));
+        f.render_widget(sparkline, inner_chunks[1]);
+    }
 }
 
 fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
@@ -136,6 +248,10 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
                     host.ip_addr.to_string(),
                     ingress_traffic,
                     egress_traffic,
+                    app.host_trend(&host.ip_addr),
+                    host.traffic.tcp.formatted_avg_rtt(),
+                    host.label.clone(),
+                    host.vendor_name.clone(),
                 ])
             })
             .collect::<Vec<Row>>();
@@ -143,15 +259,36 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
             Constraint::Length(40),
             Constraint::Length(11),
             Constraint::Length(11),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(16),
+            Constraint::Length(24),
         ];
 
+        let sort = app.remote_host_sort;
         let table = Table::new(rows, widths)
             .column_spacing(1)
-            .header(Row::new(vec!["IP Address", "↓ Bytes", "↑ Bytes"]).style(Style::new().bold()))
+            .header(
+                Row::new(vec![
+                    String::from("IP Address"),
+                    sort.header("↓ Bytes", SortKey::BytesReceived),
+                    sort.header("↑ Bytes", SortKey::BytesSent),
+                    String::from("Trend"),
+                    sort.header("RTT", SortKey::Rtt),
+                    String::from("Label"),
+                    String::from("Vendor"),
+                ])
+                .style(Style::new().bold()),
+            )
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Top Remote Addresses"),
+                    .title(format!(
+                        "Top Remote Addresses{}{}",
+                        sort.title_suffix(),
+                        app.search.title_suffix()
+                    ))
+                    .border_style(table_border_style(app, SortTable::RemoteHosts)),
             )
             .highlight_style(Style::new().reversed())
             .highlight_symbol(">>");
@@ -179,6 +316,7 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
                     proc.name.clone(),
                     ingress_traffic,
                     egress_traffic,
+                    app.process_trend(proc.pid),
                 ])
             })
             .collect::<Vec<Row>>();
@@ -187,17 +325,30 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
             Constraint::Length(20),
             Constraint::Length(11),
             Constraint::Length(11),
+            Constraint::Length(10),
         ];
+        let sort = app.process_sort;
         let table = Table::new(rows, widths)
             .column_spacing(1)
             .header(
-                Row::new(vec!["PID", "Process Name", "↓ Bytes", "↑ Bytes"])
-                    .style(Style::new().bold()),
+                Row::new(vec![
+                    String::from("PID"),
+                    sort.header("Process Name", SortKey::ProcessName),
+                    sort.header("↓ Bytes", SortKey::BytesReceived),
+                    sort.header("↑ Bytes", SortKey::BytesSent),
+                    String::from("Trend"),
+                ])
+                .style(Style::new().bold()),
             )
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Top Processes"),
+                    .title(format!(
+                        "Top Processes{}{}",
+                        sort.title_suffix(),
+                        app.search.title_suffix()
+                    ))
+                    .border_style(table_border_style(app, SortTable::Processes)),
             )
             .highlight_style(Style::new().reversed())
             .highlight_symbol(">>");
@@ -225,6 +376,12 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
                     process_id_string = process.pid.to_string();
                     process_name_string = process.name.clone();
                 }
+                let (server_name_string, alpn_string) = match &conn.server_name {
+                    Some(server_name) => {
+                        (server_name.server_name.clone(), server_name.alpn_string())
+                    }
+                    None => (String::new(), String::new()),
+                };
                 let ingress_traffic: String = if app.config.display.show_bandwidth {
                     conn.traffic.formatted_ingress_bytes_per_sec.clone()
                 } else {
@@ -237,14 +394,18 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
                 };
                 Row::new(vec![
                     conn.protocol.as_str().to_string(),
+                    conn.tcp_state_string(),
                     format!(
                         "{}:{}",
                         conn.local_ip_addr.to_string(),
                         conn.local_port.to_string()
                     ),
                     format!("{}:{}", remote_ip_string, remote_port_string),
+                    server_name_string,
+                    alpn_string,
                     ingress_traffic,
                     egress_traffic,
+                    conn.traffic.tcp.formatted_avg_rtt(),
                     process_id_string,
                     process_name_string,
                 ])
@@ -252,31 +413,45 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
             .collect::<Vec<Row>>();
         let widths = [
             Constraint::Length(8),
+            Constraint::Length(11),
             Constraint::Length(46),
             Constraint::Length(46),
+            Constraint::Length(24),
+            Constraint::Length(12),
             Constraint::Length(11),
             Constraint::Length(11),
+            Constraint::Length(9),
             Constraint::Length(5),
             Constraint::Length(20),
         ];
+        let sort = app.connection_sort;
         let table = Table::new(rows, widths)
             .column_spacing(1)
             .header(
                 Row::new(vec![
-                    "Protocol",
-                    "Local Socket",
-                    "Remote Socket",
-                    "↓ Bytes",
-                    "↑ Bytes",
-                    "PID",
-                    "Process Name",
+                    String::from("Protocol"),
+                    String::from("State"),
+                    String::from("Local Socket"),
+                    String::from("Remote Socket"),
+                    String::from("Server Name"),
+                    String::from("ALPN"),
+                    sort.header("↓ Bytes", SortKey::BytesReceived),
+                    sort.header("↑ Bytes", SortKey::BytesSent),
+                    sort.header("RTT", SortKey::Rtt),
+                    String::from("PID"),
+                    sort.header("Process Name", SortKey::ProcessName),
                 ])
                 .style(Style::new().bold()),
             )
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Top Connections"),
+                    .title(format!(
+                        "Top Connections{}{}",
+                        sort.title_suffix(),
+                        app.search.title_suffix()
+                    ))
+                    .border_style(table_border_style(app, SortTable::Connections)),
             )
             .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
             .highlight_symbol(">>");
@@ -284,10 +459,79 @@ fn draw_top_data(f: &mut Frame, app: &mut App, area: Rect) {
     }
 }
 
+// Border of the table that the sort keys apply to
+fn table_border_style(app: &App, table: SortTable) -> Style {
+    if app.focused_table == table {
+        Style::default().fg(Color::LightBlue)
+    } else {
+        Style::default()
+    }
+}
+
+fn draw_notifications(f: &mut Frame, app: &mut App, area: Rect) {
+    let lines: Vec<text::Line> = app
+        .netstat_data
+        .notifications
+        .iter()
+        .rev()
+        .take(area.height.saturating_sub(2) as usize)
+        .map(|notification| {
+            let time = match chrono::DateTime::parse_from_rfc3339(&notification.timestamp) {
+                Ok(timestamp) => timestamp.format("%H:%M:%S").to_string(),
+                Err(_) => notification.timestamp.clone(),
+            };
+            text::Line::from(vec![
+                Span::styled(format!("{} ", time), Style::default().fg(Color::DarkGray)),
+                Span::styled(
+                    format!("[{}] ", notification.notification_type.name()),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    format!("{}: ", notification.title),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(notification.body.clone()),
+            ])
+        })
+        .collect();
+    let title = format!("Notifications ({})", app.netstat_data.notifications.len());
+    let paragraph =
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(paragraph, area);
+}
+
 fn draw_overview_tab(f: &mut Frame, app: &mut App, area: Rect) {
-    let chunks = Layout::default()
-        .constraints([Constraint::Length(4), Constraint::Min(8)])
-        .split(area);
-    draw_summary(f, app, chunks[0]);
-    draw_top_data(f, app, chunks[1]);
+    // Interface name, traffic and a 2 line bandwidth graph
+    let interface_rows: u16 = if app.netstat_data.interface_traffic.is_empty() {
+        0
+    } else {
+        5
+    };
+    if app.netstat_data.notifications.is_empty() {
+        let chunks = Layout::default()
+            .constraints([
+                Constraint::Length(5),
+                Constraint::Length(interface_rows),
+                Constraint::Min(8),
+            ])
+            .split(area);
+        draw_summary(f, app, chunks[0]);
+        draw_interfaces(f, app, chunks[1]);
+        draw_top_data(f, app, chunks[2]);
+    } else {
+        // Show up to 3 latest notifications between the summary and the tables
+        let notification_rows = app.netstat_data.notifications.len().min(3) as u16;
+        let chunks = Layout::default()
+            .constraints([
+                Constraint::Length(5),
+                Constraint::Length(interface_rows),
+                Constraint::Length(notification_rows + 2),
+                Constraint::Min(8),
+            ])
+            .split(area);
+        draw_summary(f, app, chunks[0]);
+        draw_interfaces(f, app, chunks[1]);
+        draw_notifications(f, app, chunks[2]);
+        draw_top_data(f, app, chunks[3]);
+    }
 }